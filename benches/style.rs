@@ -0,0 +1,145 @@
+//! Styles generated documents of about 10k elements with 5k rules: a deep
+//! one with the default thread pool, and a wide and a flat one with pools of
+//! 1, 2, 4, ... threads up to the number of CPUs.
+//!
+//! Run with `cargo bench --bench style`.
+
+use serval::css::media::Environment;
+use serval::css::parser::parse_stylesheet;
+use serval::css::Stylesheet;
+use serval::dom::Node;
+use serval::style::style_tree;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// The tag names of the elements at each depth below `body`.
+const TAG_NAMES: &[&str] = &["section", "div", "p"];
+/// The numbers of children at each depth: 100 sections of 10 divs of 10
+/// paragraphs.
+const DEEP: &[usize] = &[100, 10, 10];
+/// 2500 sections of 4 divs.
+const WIDE: &[usize] = &[2500, 4];
+/// 10000 sections.
+const FLAT: &[usize] = &[10000];
+const RULES: usize = 5000;
+const CLASSES: usize = 500;
+const ITERATIONS: u32 = 5;
+
+/// A linear congruential generator, so that the documents are the same on
+/// every run.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self, n: usize) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        ((self.0 >> 33) as usize) % n
+    }
+}
+
+/// Returns a `body` whose elements at each depth have `shape[depth]`
+/// children, and the number of elements.
+fn document(shape: &[usize], random: &mut Random) -> (Node, usize) {
+    fn element(depth: usize, shape: &[usize], random: &mut Random, count: &mut usize) -> Node {
+        *count += 1;
+        let mut attrs = BTreeMap::new();
+        attrs.insert("id".to_string(), format!("e{}", count));
+        attrs.insert(
+            "class".to_string(),
+            format!("c{} c{}", random.next(CLASSES), random.next(CLASSES)),
+        );
+        let children = match shape.first() {
+            Some(&n) => (0..n)
+                .map(|_| element(depth + 1, &shape[1..], random, count))
+                .collect(),
+            None => vec![],
+        };
+        let tag_name = if depth == 0 {
+            "body"
+        } else {
+            TAG_NAMES[depth - 1]
+        };
+        Node::element(tag_name.to_string(), attrs, children)
+    }
+    let mut count = 0;
+    let root = element(0, shape, random, &mut count);
+    (root, count)
+}
+
+/// Returns rules with ids, classes, tag names, and descendant and child
+/// combinators.
+fn generate_stylesheet(elements: usize, random: &mut Random) -> Stylesheet {
+    let text: String = (0..RULES)
+        .map(|i| {
+            let class = random.next(CLASSES);
+            let selector = match i % 6 {
+                0 => format!(".c{}", class),
+                1 => format!("#e{}", random.next(elements)),
+                2 => format!("div.c{}", class),
+                3 => format!("section .c{} p", class),
+                4 => format!(".c{} > p.c{}", class, random.next(CLASSES)),
+                _ => format!("#e{} .c{}", random.next(elements), class),
+            };
+            format!("{} {{ margin-left: {}px; }}\n", selector, i)
+        })
+        .collect();
+    parse_stylesheet(&text).unwrap()
+}
+
+/// Returns the median and the minimum time of styling `root`.
+fn measure(root: &Node, stylesheet: &Stylesheet) -> (Duration, Duration) {
+    let env = Environment::default();
+    let mut times: Vec<Duration> = (0..ITERATIONS)
+        .map(|_| {
+            let start = Instant::now();
+            style_tree(root, &[stylesheet], &env);
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    (times[times.len() / 2], times[0])
+}
+
+fn main() {
+    let mut random = Random(0);
+    let (root, elements) = document(DEEP, &mut random);
+    let stylesheet = generate_stylesheet(elements, &mut random);
+    let (median, min) = measure(&root, &stylesheet);
+    println!(
+        "style_tree: {} elements, {} rules: median {:?} (min {:?})",
+        elements, RULES, median, min
+    );
+
+    for &(name, shape) in &[("wide", WIDE), ("flat", FLAT)] {
+        let (root, elements) = document(shape, &mut random);
+        let stylesheet = generate_stylesheet(elements, &mut random);
+        println!("{} document: {} elements, {} rules", name, elements, RULES);
+        scale(&root, &stylesheet);
+    }
+}
+
+/// Prints the times of styling `root` with pools of 1, 2, 4, ... threads up
+/// to the number of CPUs.
+fn scale(root: &Node, stylesheet: &Stylesheet) {
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut threads = 1;
+    let mut sequential = None;
+    while threads <= cpus {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        let (median, min) = pool.install(|| measure(root, stylesheet));
+        let sequential = *sequential.get_or_insert(median);
+        println!(
+            "  {} threads: median {:?} (min {:?}), speedup {:.2}",
+            threads,
+            median,
+            min,
+            sequential.as_secs_f64() / median.as_secs_f64()
+        );
+        threads *= 2;
+    }
+}
@@ -1,42 +1,210 @@
+pub mod animation;
+pub mod calc;
+pub mod color;
+pub mod container;
+pub mod counter;
+pub mod font_face;
+pub mod import;
+pub mod media;
+pub mod nesting;
 pub mod parser;
+pub mod property;
+pub mod serializer;
+pub mod shorthand;
+pub mod supports;
+pub mod variable;
 use lazy_static::*;
 
 // use ordered_float::OrderedFloat;
 use std::collections::BTreeSet;
+use std::fmt;
 
 // https://limpet.net/mbrubeck/2014/08/13/toy-layout-engine-3-css.html
 
 // pub type Num = OrderedFloat<f32>;
 // pub type Num = f32;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Stylesheet {
-    pub rules: Vec<Rule>,
+    pub origin: Origin,
+    pub rules: Vec<CssRule>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CssRule {
+    Style(Rule),
+    Media(MediaRule),
+    Import(ImportRule),
+    /// `@layer a, b;`, which declares the order of layers.
+    LayerStatement(Vec<LayerName>),
+    /// `@layer name { }`
+    Layer(LayerRule),
+    Supports(SupportsRule),
+    Keyframes(animation::KeyframesRule),
+    FontFace(font_face::FontFaceRule),
+    Container(container::ContainerRule),
+}
+
+/// A dotted layer name, e.g. `framework.base`.
+pub type LayerName = Vec<String>;
+
+/// `@import`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportRule {
+    pub url: String,
+    /// `layer` or `layer(name)`. `Some(None)` is an anonymous layer.
+    pub layer: Option<Option<LayerName>>,
+    pub queries: media::MediaQueryList,
+    /// The rules of the imported stylesheet. They are empty until the import
+    /// is resolved by `import::load_stylesheet`.
+    pub rules: Vec<CssRule>,
+}
+
+/// `@layer name { }`. The rules are in an anonymous layer if `name` is
+/// `None`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerRule {
+    pub name: Option<LayerName>,
+    pub rules: Vec<CssRule>,
+}
+
+/// `@media`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaRule {
+    pub queries: media::MediaQueryList,
+    pub rules: Vec<CssRule>,
+}
+
+/// `@supports`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SupportsRule {
+    pub condition: supports::SupportsCondition,
+    pub rules: Vec<CssRule>,
+}
+
+/// https://drafts.csswg.org/css-cascade-4/#cascading-origins
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Origin {
+    UserAgent,
+    User,
+    Author,
+}
+
+/// Returns the built-in user-agent stylesheet.
+pub fn user_agent_stylesheet() -> &'static Stylesheet {
+    lazy_static! {
+        static ref USER_AGENT_STYLESHEET: Stylesheet = {
+            let mut stylesheet = parser::parse_stylesheet(include_str!("css/user_agent.css"))
+                .expect("the user-agent stylesheet should be valid");
+            stylesheet.origin = Origin::UserAgent;
+            set_source_file(&mut stylesheet.rules, "user-agent stylesheet");
+            stylesheet
+        };
+    }
+    &USER_AGENT_STYLESHEET
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Rule {
     // TODO: Assert selectors should br sorted by their specifities
     // pub selectors: Vec<Selector>,
     pub selectors: SortedSelectors,
     pub declarations: Vec<Declaration>,
+    pub location: SourceLocation,
+}
+
+/// Where a rule starts in its stylesheet. It is not part of the rule's
+/// value, so it is ignored by `==`: the same rules parsed from different
+/// text are equal.
+#[derive(Debug, Clone, Default)]
+pub struct SourceLocation {
+    pub file: Option<String>,
+    /// The line and the column, both starting at 1. They are 0 if unknown.
+    pub line: usize,
+    pub column: usize,
+}
+
+impl PartialEq for SourceLocation {
+    fn eq(&self, _: &SourceLocation) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.as_deref().unwrap_or("<anonymous>"))?;
+        if self.line > 0 {
+            write!(f, ":{}:{}", self.line, self.column)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sets `file` as the file of the rules which don't have one. The rules of
+/// `@import` are left as they are, because they come from another file.
+pub fn set_source_file(rules: &mut [CssRule], file: &str) {
+    for rule in rules {
+        match rule {
+            CssRule::Style(rule) => {
+                if rule.location.file.is_none() {
+                    rule.location.file = Some(file.to_string());
+                }
+            }
+            CssRule::Media(MediaRule { rules, .. })
+            | CssRule::Layer(LayerRule { rules, .. })
+            | CssRule::Supports(SupportsRule { rules, .. })
+            | CssRule::Container(container::ContainerRule { rules, .. }) => {
+                set_source_file(rules, file)
+            }
+            CssRule::Import(_)
+            | CssRule::LayerStatement(_)
+            | CssRule::Keyframes(_)
+            | CssRule::FontFace(_) => {}
+        }
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct SortedSelectors {
     pub selectors: Vec<Selector>,
+    /// The indices into `selectors` in the order of appearance.
+    source_order: Vec<usize>,
 }
 
 impl SortedSelectors {
-    pub fn new(mut selectors: Vec<Selector>) -> SortedSelectors {
-        selectors.sort_by(|a, b| b.specifity().cmp(&a.specifity()));
-        SortedSelectors { selectors }
+    pub fn new(selectors: Vec<Selector>) -> SortedSelectors {
+        let mut indexed: Vec<_> = selectors.into_iter().enumerate().collect();
+        indexed.sort_by_key(|(_, selector)| std::cmp::Reverse(selector.specifity()));
+        let mut source_order = vec![0; indexed.len()];
+        for (sorted, (source, _)) in indexed.iter().enumerate() {
+            source_order[*source] = sorted;
+        }
+        SortedSelectors {
+            selectors: indexed.into_iter().map(|(_, selector)| selector).collect(),
+            source_order,
+        }
+    }
+
+    /// Returns the selectors in the order of appearance.
+    pub fn in_source_order(&self) -> impl Iterator<Item = &Selector> {
+        self.source_order.iter().map(move |&i| &self.selectors[i])
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Selector {
     Simple(SimpleSelector),
+    /// A selector of an ancestor or the parent (the first field), a
+    /// combinator, and a compound selector of the element itself.
+    Complex(Box<Selector>, Combinator, SimpleSelector),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Combinator {
+    /// ` `
+    Descendant,
+    /// `>`
+    Child,
 }
 
 impl Selector {
@@ -66,6 +234,36 @@ pub struct SimpleSelector {
     pub tag_name: Option<String>,
     pub id: Option<String>,
     pub classes: BTreeSet<String>,
+    /// The arguments of `:is()`. Each of the lists must have a matching
+    /// selector.
+    pub is: Vec<Vec<Selector>>,
+    /// `&`, the nesting selector. A nested rule replaces it with `:is()`
+    /// of the parent's selectors, so it remains only at the top level, where
+    /// it matches the root element as `:scope` does.
+    pub nesting: bool,
+    /// A pseudo-element, which comes at the end of the compound selector.
+    pub pseudo_element: Option<PseudoElement>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PseudoElement {
+    /// `::marker`, the marker box of a list item.
+    Marker,
+}
+
+impl PseudoElement {
+    pub fn from_name(name: &str) -> Option<PseudoElement> {
+        match name.to_ascii_lowercase().as_str() {
+            "marker" => Some(PseudoElement::Marker),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PseudoElement::Marker => "marker",
+        }
+    }
 }
 
 impl SimpleSelector {
@@ -103,6 +301,8 @@ impl SimpleSelector {
 pub struct Declaration {
     pub name: String,
     pub value: Value,
+    /// Whether the declaration has `!important`.
+    pub important: bool,
 }
 
 impl Declaration {
@@ -111,6 +311,7 @@ impl Declaration {
         Declaration {
             name: "color".to_string(),
             value: Value::color(rgb),
+            important: false,
         }
     }
 }
@@ -118,14 +319,43 @@ impl Declaration {
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Keyword(String),
+    Number(f32),
+    Percentage(f32),
     Length(f32, Unit),
     ColorValue(Color),
+    /// A quoted string, e.g. a font family name.
+    String(String),
+    /// Space separated components, e.g. `1px solid red`.
+    List(Vec<Value>),
+    /// Comma separated values, e.g. `"Helvetica", sans-serif`. Each of them
+    /// may be a `List`.
+    CommaList(Vec<Value>),
+    /// A `/` between components, e.g. `12px/1.5` in `font`.
+    Slash,
+    /// `calc()`, `min()`, `max()` or `clamp()`.
+    Calc(Box<calc::CalcNode>),
+    /// A time in seconds, e.g. `1.5s` or `200ms`.
+    Time(f32),
+    /// `cubic-bezier()` or `steps()`. Keywords such as `ease` are kept as
+    /// keywords.
+    Easing(animation::EasingFunction),
+    /// `counter()` or `counters()`.
+    Counter(counter::CounterFunction),
+    /// The value of a custom property. It is not parsed until it is
+    /// substituted into another property by `var()`.
+    Tokens(variable::TokenStream),
+    /// A value which contains `var()`. It is parsed after substitution, at
+    /// computed-value time.
+    WithVariables(variable::TokenStream),
+    /// A longhand of a shorthand whose value contains `var()`. The shorthand
+    /// (the first field) is expanded after substitution.
+    PendingSubstitution(String, variable::TokenStream),
 }
 
 impl Value {
     #[cfg(test)]
     pub(crate) fn color((r, g, b): Rgb) -> Value {
-        Value::ColorValue(Color { r, g, b })
+        Value::ColorValue(Color::rgb(r, g, b))
     }
 
     pub fn keyword_auto() -> &'static Value {
@@ -142,17 +372,175 @@ impl Value {
         &LENGTH_ZERO
     }
 
-    pub fn to_px(&self) -> f32 {
+    /// Resolves a length or a percentage to px. `percentage_basis` is the
+    /// size a percentage refers to, which depends on the property (e.g. the
+    /// containing block's width for `width`, `margin` and `padding`).
+    ///
+    /// Returns `None` if the value is not a length.
+    pub fn resolve_length(&self, context: &LengthContext, percentage_basis: f32) -> Option<f32> {
+        match *self {
+            Value::Length(n, unit) => Some(unit.to_px(n, context)),
+            Value::Percentage(n) => Some(n * percentage_basis / 100.0),
+            // Unitless zero is a valid length.
+            Value::Number(0.0) => Some(0.0),
+            Value::Calc(ref node) => match node.calc_type() {
+                Some(calc::CalcType::Number) | None => None,
+                Some(_) => Some(node.resolve(context, percentage_basis)),
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns the computed value: lengths are converted to px and math
+    /// functions are simplified. Percentages are kept as is because they can
+    /// be resolved only at layout time.
+    pub fn to_computed(&self, context: &LengthContext) -> Value {
         match *self {
-            Value::Length(px, Unit::Px) => px,
-            _ => 0.0,
+            Value::Length(n, unit) => Value::Length(unit.to_px(n, context), Unit::Px),
+            Value::Calc(ref node) => node.simplify(context).into_value(),
+            _ => self.clone(),
         }
     }
+
+    /// Same as `resolve_length`, but treats non-length values, such as
+    /// `auto`, as 0.
+    pub fn to_px(&self, context: &LengthContext, percentage_basis: f32) -> f32 {
+        self.resolve_length(context, percentage_basis)
+            .unwrap_or(0.0)
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Unit {
+    // Absolute lengths
     Px,
+    Pt,
+    Pc,
+    Cm,
+    Mm,
+    In,
+    Q,
+    // Font-relative lengths
+    Em,
+    Rem,
+    Ex,
+    Ch,
+    // Viewport-percentage lengths
+    Vw,
+    Vh,
+    Vmin,
+    Vmax,
+}
+
+impl Unit {
+    pub const ALL: [Unit; 15] = [
+        Unit::Px,
+        Unit::Pt,
+        Unit::Pc,
+        Unit::Cm,
+        Unit::Mm,
+        Unit::In,
+        Unit::Q,
+        Unit::Em,
+        Unit::Rem,
+        Unit::Ex,
+        Unit::Ch,
+        Unit::Vw,
+        Unit::Vh,
+        Unit::Vmin,
+        Unit::Vmax,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Unit::Px => "px",
+            Unit::Pt => "pt",
+            Unit::Pc => "pc",
+            Unit::Cm => "cm",
+            Unit::Mm => "mm",
+            Unit::In => "in",
+            Unit::Q => "Q",
+            Unit::Em => "em",
+            Unit::Rem => "rem",
+            Unit::Ex => "ex",
+            Unit::Ch => "ch",
+            Unit::Vw => "vw",
+            Unit::Vh => "vh",
+            Unit::Vmin => "vmin",
+            Unit::Vmax => "vmax",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Unit> {
+        Unit::ALL
+            .iter()
+            .find(|unit| unit.name().eq_ignore_ascii_case(name))
+            .cloned()
+    }
+
+    pub fn is_absolute(self) -> bool {
+        matches!(
+            self,
+            Unit::Px | Unit::Pt | Unit::Pc | Unit::Cm | Unit::Mm | Unit::In | Unit::Q
+        )
+    }
+
+    pub fn to_px(self, n: f32, context: &LengthContext) -> f32 {
+        // https://drafts.csswg.org/css-values-3/#absolute-lengths
+        const PX_PER_IN: f32 = 96.0;
+        const PX_PER_CM: f32 = PX_PER_IN / 2.54;
+        match self {
+            Unit::Px => n,
+            Unit::Pt => n * PX_PER_IN / 72.0,
+            Unit::Pc => n * PX_PER_IN / 6.0,
+            Unit::Cm => n * PX_PER_CM,
+            Unit::Mm => n * PX_PER_CM / 10.0,
+            Unit::In => n * PX_PER_IN,
+            Unit::Q => n * PX_PER_CM / 40.0,
+            Unit::Em => n * context.font_size,
+            Unit::Rem => n * context.root_font_size,
+            // We don't have font metrics. Use 0.5em, which the spec allows
+            // when it is impossible or impractical to determine the x-height.
+            Unit::Ex | Unit::Ch => n * context.font_size / 2.0,
+            Unit::Vw => n * context.viewport_width / 100.0,
+            Unit::Vh => n * context.viewport_height / 100.0,
+            Unit::Vmin => n * context.viewport_width.min(context.viewport_height) / 100.0,
+            Unit::Vmax => n * context.viewport_width.max(context.viewport_height) / 100.0,
+        }
+    }
+}
+
+/// Everything a relative length needs to be resolved, except for the
+/// percentage basis, which depends on the property.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LengthContext {
+    pub font_size: f32,
+    pub root_font_size: f32,
+    pub viewport_width: f32,
+    pub viewport_height: f32,
+}
+
+impl LengthContext {
+    pub const DEFAULT_FONT_SIZE: f32 = 16.0;
+
+    pub fn new(viewport_width: f32, viewport_height: f32) -> LengthContext {
+        LengthContext {
+            font_size: LengthContext::DEFAULT_FONT_SIZE,
+            root_font_size: LengthContext::DEFAULT_FONT_SIZE,
+            viewport_width,
+            viewport_height,
+        }
+    }
+
+    /// Returns the context for an element whose `font-size` is `font_size`.
+    /// Font-relative units in `font-size` itself refer to the parent's font
+    /// size, so `font_size` should be resolved against `self`.
+    pub fn with_font_size(&self, font_size: Option<&Value>) -> LengthContext {
+        let font_size = font_size
+            .and_then(|v| v.resolve_length(self, self.font_size))
+            .unwrap_or(self.font_size);
+        LengthContext { font_size, ..*self }
+    }
 }
 
 pub type Rgb = (u8, u8, u8);
@@ -162,18 +550,40 @@ pub struct Color {
     pub r: u8,
     pub g: u8,
     pub b: u8,
-    // pub a: u8, // alpha
+    pub a: u8,
 }
 
 pub type Specifity = (usize, usize, usize);
 
 impl Selector {
     pub fn specifity(&self) -> Specifity {
-        let Selector::Simple(ref simple) = *self;
-        let a = if simple.id.is_some() { 1 } else { 0 };
-        let b = simple.classes.len();
-        let c = if simple.tag_name.is_some() { 1 } else { 0 };
-        (a, b, c)
+        match self {
+            Selector::Simple(simple) => simple.specifity(),
+            Selector::Complex(ancestor, _, simple) => {
+                let (a, b, c) = ancestor.specifity();
+                let (x, y, z) = simple.specifity();
+                (a + x, b + y, c + z)
+            }
+        }
+    }
+}
+
+impl SimpleSelector {
+    pub fn specifity(&self) -> Specifity {
+        let a = if self.id.is_some() { 1 } else { 0 };
+        // `&` at the top level is `:scope`, which is a pseudo-class.
+        let b = self.classes.len() + if self.nesting { 1 } else { 0 };
+        let c = if self.tag_name.is_some() { 1 } else { 0 }
+            + if self.pseudo_element.is_some() { 1 } else { 0 };
+        // `:is()` takes the specificity of its most specific argument.
+        self.is.iter().fold((a, b, c), |(a, b, c), selectors| {
+            let (x, y, z) = selectors
+                .iter()
+                .map(Selector::specifity)
+                .max()
+                .unwrap_or_default();
+            (a + x, b + y, c + z)
+        })
     }
 }
 
@@ -182,6 +592,53 @@ mod test {
     use super::*;
     use maplit::btreeset;
 
+    #[test]
+    fn resolve_length_test() {
+        let context = LengthContext {
+            font_size: 20.0,
+            root_font_size: 10.0,
+            viewport_width: 400.0,
+            viewport_height: 200.0,
+        };
+        let px = |v: Value| v.resolve_length(&context, 50.0);
+
+        assert_eq!(px(Value::Length(1.5, Unit::Px)), Some(1.5));
+        assert_eq!(px(Value::Length(-2.0, Unit::Px)), Some(-2.0));
+        assert_eq!(px(Value::Length(1.0, Unit::In)), Some(96.0));
+        assert_eq!(px(Value::Length(72.0, Unit::Pt)), Some(96.0));
+        assert_eq!(px(Value::Length(1.0, Unit::Pc)), Some(16.0));
+        assert_eq!(px(Value::Length(2.54, Unit::Cm)), Some(96.0));
+        assert_eq!(px(Value::Length(2.0, Unit::Em)), Some(40.0));
+        assert_eq!(px(Value::Length(2.0, Unit::Rem)), Some(20.0));
+        assert_eq!(px(Value::Length(1.0, Unit::Ex)), Some(10.0));
+        assert_eq!(px(Value::Length(10.0, Unit::Vw)), Some(40.0));
+        assert_eq!(px(Value::Length(10.0, Unit::Vh)), Some(20.0));
+        assert_eq!(px(Value::Length(10.0, Unit::Vmin)), Some(20.0));
+        assert_eq!(px(Value::Length(10.0, Unit::Vmax)), Some(40.0));
+        assert_eq!(px(Value::Percentage(10.0)), Some(5.0));
+        assert_eq!(px(Value::Number(0.0)), Some(0.0));
+        assert_eq!(px(Value::Number(1.0)), None);
+        assert_eq!(px(Value::Keyword("auto".to_string())), None);
+    }
+
+    #[test]
+    fn with_font_size_test() {
+        let context = LengthContext::new(800.0, 600.0);
+        assert_eq!(context.with_font_size(None).font_size, 16.0);
+        assert_eq!(
+            context
+                .with_font_size(Some(&Value::Length(2.0, Unit::Em)))
+                .font_size,
+            32.0
+        );
+        assert_eq!(
+            context
+                .with_font_size(Some(&Value::Percentage(50.0)))
+                .font_size,
+            8.0
+        );
+    }
+
     #[test]
     fn sorted_selectors_test() {
         let selectors = vec![
@@ -219,6 +676,13 @@ mod test {
                 Selector::Simple(Default::default()),
             ]
         );
+        assert_eq!(
+            sorted_selectors
+                .in_source_order()
+                .cloned()
+                .collect::<Vec<_>>(),
+            selectors
+        );
     }
 
 }
@@ -0,0 +1,347 @@
+//! `@keyframes`, easing functions and the interpolation of values.
+//!
+//! https://drafts.csswg.org/css-animations-1/
+//! https://drafts.csswg.org/css-easing-1/
+
+use crate::css::calc::{CalcNode, CalcType};
+use crate::css::{Color, Declaration, Unit, Value};
+
+/// `@keyframes name { }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyframesRule {
+    pub name: String,
+    pub keyframes: Vec<Keyframe>,
+}
+
+/// A keyframe, e.g. `from, 50% { }`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyframe {
+    /// The positions of the keyframe in an iteration, in percent.
+    pub offsets: Vec<f32>,
+    /// `!important` declarations are ignored in keyframes.
+    pub declarations: Vec<Declaration>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EasingFunction {
+    /// `cubic-bezier(x1, y1, x2, y2)`
+    CubicBezier(f32, f32, f32, f32),
+    /// `steps(n, position)`
+    Steps(u32, StepPosition),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StepPosition {
+    JumpStart,
+    JumpEnd,
+    JumpNone,
+    JumpBoth,
+}
+
+impl StepPosition {
+    pub fn name(self) -> &'static str {
+        match self {
+            StepPosition::JumpStart => "jump-start",
+            StepPosition::JumpEnd => "jump-end",
+            StepPosition::JumpNone => "jump-none",
+            StepPosition::JumpBoth => "jump-both",
+        }
+    }
+
+    /// `start` and `end` are the same as `jump-start` and `jump-end`.
+    pub fn from_name(name: &str) -> Option<StepPosition> {
+        match name.to_ascii_lowercase().as_str() {
+            "jump-start" | "start" => Some(StepPosition::JumpStart),
+            "jump-end" | "end" => Some(StepPosition::JumpEnd),
+            "jump-none" => Some(StepPosition::JumpNone),
+            "jump-both" => Some(StepPosition::JumpBoth),
+            _ => None,
+        }
+    }
+}
+
+impl EasingFunction {
+    pub const LINEAR: EasingFunction = EasingFunction::CubicBezier(0.0, 0.0, 1.0, 1.0);
+    pub const EASE: EasingFunction = EasingFunction::CubicBezier(0.25, 0.1, 0.25, 1.0);
+
+    /// Returns the easing function of a keyword or a function value.
+    pub fn from_value(value: &Value) -> Option<EasingFunction> {
+        let keyword = match value {
+            Value::Easing(easing) => return Some(*easing),
+            Value::Keyword(keyword) => keyword.to_ascii_lowercase(),
+            _ => return None,
+        };
+        Some(match keyword.as_str() {
+            "linear" => EasingFunction::LINEAR,
+            "ease" => EasingFunction::EASE,
+            "ease-in" => EasingFunction::CubicBezier(0.42, 0.0, 1.0, 1.0),
+            "ease-out" => EasingFunction::CubicBezier(0.0, 0.0, 0.58, 1.0),
+            "ease-in-out" => EasingFunction::CubicBezier(0.42, 0.0, 0.58, 1.0),
+            "step-start" => EasingFunction::Steps(1, StepPosition::JumpStart),
+            "step-end" => EasingFunction::Steps(1, StepPosition::JumpEnd),
+            _ => return None,
+        })
+    }
+
+    /// Returns the output progress for an input progress from 0 to 1. It can
+    /// be out of the range for a cubic Bézier curve.
+    pub fn apply(&self, t: f32) -> f32 {
+        match *self {
+            EasingFunction::CubicBezier(x1, y1, x2, y2) => cubic_bezier(x1, y1, x2, y2, t),
+            EasingFunction::Steps(steps, position) => {
+                // https://drafts.csswg.org/css-easing-1/#step-easing-algo
+                let steps = steps as f32;
+                let jumps = match position {
+                    StepPosition::JumpStart | StepPosition::JumpEnd => steps,
+                    StepPosition::JumpNone => steps - 1.0,
+                    StepPosition::JumpBoth => steps + 1.0,
+                };
+                let mut step = (t * steps).floor();
+                if let StepPosition::JumpStart | StepPosition::JumpBoth = position {
+                    step += 1.0;
+                }
+                if t >= 0.0 && step < 0.0 {
+                    step = 0.0;
+                }
+                if t <= 1.0 && step > jumps {
+                    step = jumps;
+                }
+                step / jumps
+            }
+        }
+    }
+}
+
+/// Returns y for x on the curve from (0, 0) to (1, 1) with the control
+/// points (x1, y1) and (x2, y2).
+fn cubic_bezier(x1: f32, y1: f32, x2: f32, y2: f32, x: f32) -> f32 {
+    // Each coordinate is 3(1-s)^2 s p1 + 3(1-s) s^2 p2 + s^3.
+    let curve = |s: f32, p1: f32, p2: f32| {
+        ((1.0 - 3.0 * p2 + 3.0 * p1) * s + (3.0 * p2 - 6.0 * p1)) * s * s + 3.0 * p1 * s
+    };
+    let slope = |s: f32, p1: f32, p2: f32| {
+        3.0 * (1.0 - 3.0 * p2 + 3.0 * p1) * s * s + 2.0 * (3.0 * p2 - 6.0 * p1) * s + 3.0 * p1
+    };
+    if x1 == y1 && x2 == y2 {
+        return x;
+    }
+    // The curve is extended linearly outside of [0, 1].
+    if x <= 0.0 || x >= 1.0 {
+        let (s, x0, y0) = if x <= 0.0 {
+            (0.0, 0.0, 0.0)
+        } else {
+            (1.0, 1.0, 1.0)
+        };
+        let dx = slope(s, x1, x2);
+        let gradient = if dx != 0.0 {
+            slope(s, y1, y2) / dx
+        } else {
+            0.0
+        };
+        return y0 + gradient * (x - x0);
+    }
+
+    // Find s for x by Newton's method, falling back to bisection.
+    let mut s = x;
+    for _ in 0..8 {
+        let error = curve(s, x1, x2) - x;
+        if error.abs() < 1e-6 {
+            return curve(s, y1, y2);
+        }
+        let dx = slope(s, x1, x2);
+        if dx.abs() < 1e-6 {
+            break;
+        }
+        s -= error / dx;
+    }
+    let (mut low, mut high) = (0.0, 1.0);
+    s = x;
+    for _ in 0..32 {
+        let current = curve(s, x1, x2);
+        if (current - x).abs() < 1e-6 {
+            break;
+        }
+        if current < x {
+            low = s;
+        } else {
+            high = s;
+        }
+        s = (low + high) / 2.0;
+    }
+    curve(s, y1, y2)
+}
+
+/// Returns the value at `progress` between `from` (0) and `to` (1).
+///
+/// Numbers, lengths, percentages, colors and lists of them are interpolated.
+/// A length and a percentage, or lengths of different units, are
+/// interpolated as `calc()`. Other values are discrete: they flip at 0.5.
+///
+/// https://drafts.csswg.org/css-values-4/#interpolation
+pub fn interpolate(from: &Value, to: &Value, progress: f32) -> Value {
+    let lerp = |a: f32, b: f32| a + (b - a) * progress;
+    match (from, to) {
+        (Value::Number(a), Value::Number(b)) => Value::Number(lerp(*a, *b)),
+        (Value::Percentage(a), Value::Percentage(b)) => Value::Percentage(lerp(*a, *b)),
+        (Value::Length(a, unit_a), Value::Length(b, unit_b)) if unit_a == unit_b => {
+            Value::Length(lerp(*a, *b), *unit_a)
+        }
+        (Value::ColorValue(a), Value::ColorValue(b)) => {
+            Value::ColorValue(interpolate_color(*a, *b, progress))
+        }
+        (Value::List(a), Value::List(b)) if a.len() == b.len() => Value::List(
+            a.iter()
+                .zip(b)
+                .map(|(a, b)| interpolate(a, b, progress))
+                .collect(),
+        ),
+        _ => match (length_percentage(from), length_percentage(to)) {
+            (Some(a), Some(b)) => {
+                let scale = |node, factor| {
+                    CalcNode::Mul(Box::new(node), Box::new(CalcNode::Number(factor)))
+                };
+                Value::Calc(Box::new(CalcNode::Add(
+                    Box::new(scale(a, 1.0 - progress)),
+                    Box::new(scale(b, progress)),
+                )))
+            }
+            _ if progress < 0.5 => from.clone(),
+            _ => to.clone(),
+        },
+    }
+}
+
+/// Returns true if `interpolate` interpolates `from` and `to`, rather than
+/// flipping from one to the other.
+pub fn is_interpolable(from: &Value, to: &Value) -> bool {
+    match (from, to) {
+        (Value::Number(_), Value::Number(_))
+        | (Value::Percentage(_), Value::Percentage(_))
+        | (Value::ColorValue(_), Value::ColorValue(_)) => true,
+        (Value::List(a), Value::List(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| is_interpolable(a, b))
+        }
+        _ => length_percentage(from).is_some() && length_percentage(to).is_some(),
+    }
+}
+
+/// Returns a length or a percentage as a math expression. A unitless zero
+/// is a length.
+fn length_percentage(value: &Value) -> Option<CalcNode> {
+    let node = match value {
+        Value::Number(n) if *n == 0.0 => return Some(CalcNode::Length(0.0, Unit::Px)),
+        value => CalcNode::from_value(value.clone())?,
+    };
+    match node.calc_type()? {
+        CalcType::Number => None,
+        _ => Some(node),
+    }
+}
+
+/// Interpolates colors with premultiplied alpha.
+///
+/// https://drafts.csswg.org/css-color-4/#interpolation-alpha
+fn interpolate_color(from: Color, to: Color, progress: f32) -> Color {
+    let lerp = |a: f32, b: f32| a + (b - a) * progress;
+    let alpha = lerp(from.a as f32, to.a as f32).clamp(0.0, 255.0);
+    let component = |a: u8, b: u8| {
+        if alpha == 0.0 {
+            return 0;
+        }
+        let premultiplied = lerp(a as f32 * from.a as f32, b as f32 * to.a as f32);
+        (premultiplied / alpha).round().clamp(0.0, 255.0) as u8
+    };
+    Color {
+        r: component(from.r, to.r),
+        g: component(from.g, to.g),
+        b: component(from.b, to.b),
+        a: alpha.round() as u8,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::css::LengthContext;
+
+    #[test]
+    fn easing_test() {
+        let near = |a: f32, b: f32| (a - b).abs() < 1e-3;
+        let linear = EasingFunction::LINEAR;
+        assert!(near(linear.apply(0.3), 0.3));
+        let ease = EasingFunction::EASE;
+        assert!(near(ease.apply(0.0), 0.0));
+        assert!(near(ease.apply(1.0), 1.0));
+        assert!(near(ease.apply(0.5), 0.8024));
+        let ease_in = EasingFunction::from_value(&Value::Keyword("ease-in".to_string())).unwrap();
+        assert!(near(ease_in.apply(0.5), 0.3153));
+
+        let steps = |n, position| EasingFunction::Steps(n, position);
+        assert_eq!(steps(4, StepPosition::JumpEnd).apply(0.3), 0.25);
+        assert_eq!(steps(4, StepPosition::JumpEnd).apply(1.0), 1.0);
+        assert_eq!(steps(4, StepPosition::JumpStart).apply(0.0), 0.25);
+        assert_eq!(steps(3, StepPosition::JumpNone).apply(0.5), 0.5);
+        assert_eq!(steps(3, StepPosition::JumpBoth).apply(0.0), 0.25);
+    }
+
+    #[test]
+    fn interpolate_test() {
+        assert_eq!(
+            interpolate(&Value::Number(1.0), &Value::Number(3.0), 0.25),
+            Value::Number(1.5)
+        );
+        assert_eq!(
+            interpolate(
+                &Value::Length(10.0, Unit::Px),
+                &Value::Length(20.0, Unit::Px),
+                0.5
+            ),
+            Value::Length(15.0, Unit::Px)
+        );
+        let mixed = interpolate(&Value::Length(1.0, Unit::Em), &Value::Percentage(50.0), 0.5);
+        let context = LengthContext::new(800.0, 600.0);
+        assert_eq!(mixed.resolve_length(&context, 100.0), Some(33.0));
+        assert_eq!(
+            interpolate(&Value::Number(0.0), &Value::Length(8.0, Unit::Px), 0.5)
+                .resolve_length(&context, 0.0),
+            Some(4.0)
+        );
+
+        assert_eq!(
+            interpolate(
+                &Value::ColorValue(Color::rgb(0, 0, 0)),
+                &Value::ColorValue(Color::rgb(255, 100, 0)),
+                0.5
+            ),
+            Value::ColorValue(Color::rgb(128, 50, 0))
+        );
+        // A transparent color doesn't darken the other one.
+        assert_eq!(
+            interpolate(
+                &Value::ColorValue(Color::TRANSPARENT),
+                &Value::ColorValue(Color::rgb(255, 0, 0)),
+                0.5
+            ),
+            Value::ColorValue(Color {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 128
+            })
+        );
+
+        let block = Value::Keyword("block".to_string());
+        let none = Value::Keyword("none".to_string());
+        assert_eq!(interpolate(&block, &none, 0.4), block);
+        assert_eq!(interpolate(&block, &none, 0.5), none);
+        assert!(!is_interpolable(&block, &none));
+        assert!(is_interpolable(
+            &Value::Length(1.0, Unit::Em),
+            &Value::Percentage(2.0)
+        ));
+        assert!(!is_interpolable(
+            &Value::Number(1.0),
+            &Value::Length(1.0, Unit::Px)
+        ));
+    }
+}
@@ -0,0 +1,322 @@
+// https://drafts.csswg.org/css-values-4/#math
+
+use super::{LengthContext, Unit, Value};
+
+/// An expression tree of `calc()`, `min()`, `max()` and `clamp()`.
+///
+/// Parentheses and nested `calc()`s are not represented; the shape of the
+/// tree encodes the precedence.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CalcNode {
+    Number(f32),
+    Percentage(f32),
+    Length(f32, Unit),
+    Add(Box<CalcNode>, Box<CalcNode>),
+    Sub(Box<CalcNode>, Box<CalcNode>),
+    Mul(Box<CalcNode>, Box<CalcNode>),
+    Div(Box<CalcNode>, Box<CalcNode>),
+    Min(Vec<CalcNode>),
+    Max(Vec<CalcNode>),
+    Clamp(Box<CalcNode>, Box<CalcNode>, Box<CalcNode>),
+}
+
+/// The type of a math expression.
+///
+/// A percentage is kept distinct from a length until it is combined with
+/// one, because whether a percentage resolves to a length depends on the
+/// property.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CalcType {
+    Number,
+    Length,
+    Percentage,
+    LengthPercentage,
+}
+
+impl CalcType {
+    fn add(self, other: CalcType) -> Option<CalcType> {
+        match (self, other) {
+            (a, b) if a == b => Some(a),
+            (CalcType::Number, _) | (_, CalcType::Number) => None,
+            _ => Some(CalcType::LengthPercentage),
+        }
+    }
+
+    fn mul(self, other: CalcType) -> Option<CalcType> {
+        match (self, other) {
+            (CalcType::Number, t) | (t, CalcType::Number) => Some(t),
+            _ => None,
+        }
+    }
+
+    fn div(self, other: CalcType) -> Option<CalcType> {
+        match (self, other) {
+            (t, CalcType::Number) => Some(t),
+            _ => None,
+        }
+    }
+}
+
+impl CalcNode {
+    pub fn from_value(value: Value) -> Option<CalcNode> {
+        match value {
+            Value::Number(n) => Some(CalcNode::Number(n)),
+            Value::Percentage(n) => Some(CalcNode::Percentage(n)),
+            Value::Length(n, unit) => Some(CalcNode::Length(n, unit)),
+            Value::Calc(node) => Some(*node),
+            _ => None,
+        }
+    }
+
+    /// Type-checks the expression. Returns `None` if the expression is
+    /// invalid, e.g. a length is multiplied by a length or a number is added
+    /// to a length.
+    pub fn calc_type(&self) -> Option<CalcType> {
+        match self {
+            CalcNode::Number(_) => Some(CalcType::Number),
+            CalcNode::Percentage(_) => Some(CalcType::Percentage),
+            CalcNode::Length(..) => Some(CalcType::Length),
+            CalcNode::Add(a, b) | CalcNode::Sub(a, b) => a.calc_type()?.add(b.calc_type()?),
+            CalcNode::Mul(a, b) => a.calc_type()?.mul(b.calc_type()?),
+            CalcNode::Div(a, b) => a.calc_type()?.div(b.calc_type()?),
+            CalcNode::Min(args) | CalcNode::Max(args) => {
+                let (first, rest) = args.split_first()?;
+                rest.iter()
+                    .try_fold(first.calc_type()?, |t, arg| t.add(arg.calc_type()?))
+            }
+            CalcNode::Clamp(min, value, max) => min
+                .calc_type()?
+                .add(value.calc_type()?)?
+                .add(max.calc_type()?),
+        }
+    }
+
+    /// Evaluates the expression. Lengths are resolved to px and percentages
+    /// against `percentage_basis`.
+    pub fn resolve(&self, context: &LengthContext, percentage_basis: f32) -> f32 {
+        let resolve = |node: &CalcNode| node.resolve(context, percentage_basis);
+        match self {
+            CalcNode::Number(n) => *n,
+            CalcNode::Percentage(n) => n * percentage_basis / 100.0,
+            CalcNode::Length(n, unit) => unit.to_px(*n, context),
+            CalcNode::Add(a, b) => resolve(a) + resolve(b),
+            CalcNode::Sub(a, b) => resolve(a) - resolve(b),
+            CalcNode::Mul(a, b) => resolve(a) * resolve(b),
+            CalcNode::Div(a, b) => resolve(a) / resolve(b),
+            CalcNode::Min(args) => args.iter().map(resolve).fold(f32::INFINITY, f32::min),
+            CalcNode::Max(args) => args.iter().map(resolve).fold(f32::NEG_INFINITY, f32::max),
+            // The lower bound wins if it is greater than the upper bound.
+            CalcNode::Clamp(min, value, max) => resolve(min).max(resolve(value).min(resolve(max))),
+        }
+    }
+
+    /// Simplifies the expression at computed-value time: every length is
+    /// converted to px and the terms which do not involve percentages are
+    /// folded. What remains can only be resolved at layout time, when the
+    /// percentage basis is known.
+    pub fn simplify(&self, context: &LengthContext) -> CalcNode {
+        match self {
+            CalcNode::Number(_) | CalcNode::Percentage(_) => self.clone(),
+            CalcNode::Length(n, unit) => CalcNode::Length(unit.to_px(*n, context), Unit::Px),
+            CalcNode::Add(a, b) => {
+                let (a, b) = (a.simplify(context), b.simplify(context));
+                fold(&a, &b, |x, y| x + y).unwrap_or_else(|| CalcNode::Add(a.into(), b.into()))
+            }
+            CalcNode::Sub(a, b) => {
+                let (a, b) = (a.simplify(context), b.simplify(context));
+                fold(&a, &b, |x, y| x - y).unwrap_or_else(|| CalcNode::Sub(a.into(), b.into()))
+            }
+            CalcNode::Mul(a, b) => match (a.simplify(context), b.simplify(context)) {
+                (CalcNode::Number(n), node) | (node, CalcNode::Number(n)) => node.scale(n),
+                (a, b) => CalcNode::Mul(a.into(), b.into()),
+            },
+            CalcNode::Div(a, b) => match (a.simplify(context), b.simplify(context)) {
+                (node, CalcNode::Number(n)) => node.scale(1.0 / n),
+                (a, b) => CalcNode::Div(a.into(), b.into()),
+            },
+            CalcNode::Min(args) => {
+                let args: Vec<_> = args.iter().map(|arg| arg.simplify(context)).collect();
+                fold_all(&args, f32::min).unwrap_or(CalcNode::Min(args))
+            }
+            CalcNode::Max(args) => {
+                let args: Vec<_> = args.iter().map(|arg| arg.simplify(context)).collect();
+                fold_all(&args, f32::max).unwrap_or(CalcNode::Max(args))
+            }
+            CalcNode::Clamp(min, value, max) => {
+                let args = vec![
+                    min.simplify(context),
+                    value.simplify(context),
+                    max.simplify(context),
+                ];
+                fold_all(&args[1..], f32::min)
+                    .and_then(|upper| fold(&args[0], &upper, f32::max))
+                    .unwrap_or_else(|| {
+                        let mut args = args.into_iter();
+                        CalcNode::Clamp(
+                            args.next().unwrap().into(),
+                            args.next().unwrap().into(),
+                            args.next().unwrap().into(),
+                        )
+                    })
+            }
+        }
+    }
+
+    /// Multiplies a simplified expression by `factor`.
+    fn scale(self, factor: f32) -> CalcNode {
+        match self {
+            CalcNode::Number(n) => CalcNode::Number(n * factor),
+            CalcNode::Percentage(n) => CalcNode::Percentage(n * factor),
+            CalcNode::Length(n, unit) => CalcNode::Length(n * factor, unit),
+            CalcNode::Add(a, b) => CalcNode::Add(a.scale(factor).into(), b.scale(factor).into()),
+            CalcNode::Sub(a, b) => CalcNode::Sub(a.scale(factor).into(), b.scale(factor).into()),
+            node => CalcNode::Mul(node.into(), CalcNode::Number(factor).into()),
+        }
+    }
+
+    /// Converts a fully simplified expression back into a plain value.
+    pub fn into_value(self) -> Value {
+        match self {
+            CalcNode::Number(n) => Value::Number(n),
+            CalcNode::Percentage(n) => Value::Percentage(n),
+            CalcNode::Length(n, unit) => Value::Length(n, unit),
+            node => Value::Calc(Box::new(node)),
+        }
+    }
+}
+
+/// Folds two leaves of the same kind. Lengths must already be in px.
+fn fold(a: &CalcNode, b: &CalcNode, f: impl Fn(f32, f32) -> f32) -> Option<CalcNode> {
+    match (a, b) {
+        (CalcNode::Number(a), CalcNode::Number(b)) => Some(CalcNode::Number(f(*a, *b))),
+        (CalcNode::Percentage(a), CalcNode::Percentage(b)) => Some(CalcNode::Percentage(f(*a, *b))),
+        (CalcNode::Length(a, Unit::Px), CalcNode::Length(b, Unit::Px)) => {
+            Some(CalcNode::Length(f(*a, *b), Unit::Px))
+        }
+        _ => None,
+    }
+}
+
+fn fold_all(args: &[CalcNode], f: impl Fn(f32, f32) -> f32 + Copy) -> Option<CalcNode> {
+    let (first, rest) = args.split_first()?;
+    rest.iter()
+        .try_fold(first.clone(), |acc, arg| fold(&acc, arg, f))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn px(n: f32) -> Box<CalcNode> {
+        Box::new(CalcNode::Length(n, Unit::Px))
+    }
+
+    fn em(n: f32) -> Box<CalcNode> {
+        Box::new(CalcNode::Length(n, Unit::Em))
+    }
+
+    fn percent(n: f32) -> Box<CalcNode> {
+        Box::new(CalcNode::Percentage(n))
+    }
+
+    fn number(n: f32) -> Box<CalcNode> {
+        Box::new(CalcNode::Number(n))
+    }
+
+    #[test]
+    fn calc_type_test() {
+        assert_eq!(
+            CalcNode::Add(px(1.0), em(1.0)).calc_type(),
+            Some(CalcType::Length)
+        );
+        assert_eq!(
+            CalcNode::Add(px(1.0), percent(1.0)).calc_type(),
+            Some(CalcType::LengthPercentage)
+        );
+        assert_eq!(
+            CalcNode::Mul(px(1.0), number(2.0)).calc_type(),
+            Some(CalcType::Length)
+        );
+        assert_eq!(
+            CalcNode::Div(percent(1.0), number(2.0)).calc_type(),
+            Some(CalcType::Percentage)
+        );
+        assert_eq!(CalcNode::Add(px(1.0), number(1.0)).calc_type(), None);
+        assert_eq!(CalcNode::Mul(px(1.0), px(1.0)).calc_type(), None);
+        assert_eq!(CalcNode::Div(number(1.0), px(1.0)).calc_type(), None);
+        assert_eq!(
+            CalcNode::Min(vec![*px(1.0), *percent(1.0)]).calc_type(),
+            Some(CalcType::LengthPercentage)
+        );
+        assert_eq!(
+            CalcNode::Max(vec![*px(1.0), *number(1.0)]).calc_type(),
+            None
+        );
+        assert_eq!(
+            CalcNode::Clamp(px(1.0), percent(50.0), em(3.0)).calc_type(),
+            Some(CalcType::LengthPercentage)
+        );
+    }
+
+    #[test]
+    fn resolve_test() {
+        let context = LengthContext::new(800.0, 600.0);
+        let resolve = |node: CalcNode| node.resolve(&context, 200.0);
+
+        assert_eq!(resolve(CalcNode::Add(percent(50.0), px(10.0))), 110.0);
+        assert_eq!(resolve(CalcNode::Sub(em(1.0), px(1.0))), 15.0);
+        assert_eq!(resolve(CalcNode::Mul(number(2.0), px(3.0))), 6.0);
+        assert_eq!(resolve(CalcNode::Div(px(3.0), number(2.0))), 1.5);
+        assert_eq!(
+            resolve(CalcNode::Min(vec![*percent(10.0), *px(30.0)])),
+            20.0
+        );
+        assert_eq!(
+            resolve(CalcNode::Max(vec![*percent(10.0), *px(30.0)])),
+            30.0
+        );
+        assert_eq!(
+            resolve(CalcNode::Clamp(px(10.0), percent(1.0), px(30.0))),
+            10.0
+        );
+        assert_eq!(
+            resolve(CalcNode::Clamp(px(10.0), percent(50.0), px(30.0))),
+            30.0
+        );
+        assert_eq!(resolve(CalcNode::Clamp(px(30.0), px(20.0), px(10.0))), 30.0);
+    }
+
+    #[test]
+    fn simplify_test() {
+        let context = LengthContext::new(800.0, 600.0);
+        let simplify = |node: CalcNode| node.simplify(&context);
+
+        assert_eq!(
+            simplify(CalcNode::Add(em(1.0), px(1.0))),
+            CalcNode::Length(17.0, Unit::Px)
+        );
+        assert_eq!(
+            simplify(CalcNode::Mul(
+                Box::new(CalcNode::Add(percent(10.0), em(1.0))),
+                number(2.0)
+            )),
+            CalcNode::Add(percent(20.0), px(32.0))
+        );
+        assert_eq!(
+            simplify(CalcNode::Div(em(1.0), number(4.0))),
+            CalcNode::Length(4.0, Unit::Px)
+        );
+        assert_eq!(
+            simplify(CalcNode::Min(vec![*em(1.0), *px(10.0)])),
+            CalcNode::Length(10.0, Unit::Px)
+        );
+        assert_eq!(
+            simplify(CalcNode::Max(vec![*em(1.0), *percent(10.0)])),
+            CalcNode::Max(vec![*px(16.0), *percent(10.0)])
+        );
+        assert_eq!(
+            simplify(CalcNode::Clamp(px(1.0), em(2.0), px(20.0))),
+            CalcNode::Length(20.0, Unit::Px)
+        );
+    }
+}
@@ -0,0 +1,523 @@
+// https://drafts.csswg.org/css-color-4/
+
+use super::Color;
+
+impl Color {
+    pub const TRANSPARENT: Color = Color {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 0,
+    };
+
+    pub fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b, a: 255 }
+    }
+
+    fn from_hex(rgb: u32) -> Color {
+        Color::rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+    }
+
+    /// Creates a color from components in the range [0, 1].
+    fn from_unit(r: f32, g: f32, b: f32, a: f32) -> Color {
+        let to_u8 = |x: f32| (x.clamp(0.0, 1.0) * 255.0).round() as u8;
+        Color {
+            r: to_u8(r),
+            g: to_u8(g),
+            b: to_u8(b),
+            a: to_u8(a),
+        }
+    }
+
+    pub fn is_opaque(self) -> bool {
+        self.a == 255
+    }
+
+    /// Composites `self` over `backdrop` with the source-over operator.
+    pub fn over(self, backdrop: Color) -> Color {
+        let alpha = f32::from(self.a) / 255.0;
+        let backdrop_alpha = f32::from(backdrop.a) / 255.0 * (1.0 - alpha);
+        let out_alpha = alpha + backdrop_alpha;
+        if out_alpha == 0.0 {
+            return Color::TRANSPARENT;
+        }
+        let mix = |source: u8, backdrop: u8| {
+            (f32::from(source) * alpha + f32::from(backdrop) * backdrop_alpha) / out_alpha / 255.0
+        };
+        Color::from_unit(
+            mix(self.r, backdrop.r),
+            mix(self.g, backdrop.g),
+            mix(self.b, backdrop.b),
+            out_alpha,
+        )
+    }
+}
+
+/// Returns the color for a named color or `transparent`.
+pub fn named_color(name: &str) -> Option<Color> {
+    let name = name.to_ascii_lowercase();
+    if name == "transparent" {
+        return Some(Color::TRANSPARENT);
+    }
+    NAMED_COLORS
+        .binary_search_by(|(n, _)| n.cmp(&name.as_str()))
+        .ok()
+        .map(|i| Color::from_hex(NAMED_COLORS[i].1))
+}
+
+/// Creates a color from the hex digits of `#rgb`, `#rgba`, `#rrggbb` or
+/// `#rrggbbaa`.
+pub fn hex_color(digits: &str) -> Option<Color> {
+    let digits = digits
+        .chars()
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<Vec<u8>>>()?;
+    let channels: Vec<u8> = match digits.len() {
+        3 | 4 => digits.iter().map(|d| d * 17).collect(),
+        6 | 8 => digits.chunks(2).map(|d| d[0] * 16 + d[1]).collect(),
+        _ => return None,
+    };
+    Some(Color {
+        r: channels[0],
+        g: channels[1],
+        b: channels[2],
+        a: channels.get(3).cloned().unwrap_or(255),
+    })
+}
+
+/// An argument of a color function.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ColorComponent {
+    Number(f32),
+    Percentage(f32),
+    /// An angle in degrees.
+    Angle(f32),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Separator {
+    Comma,
+    Space,
+    Slash,
+}
+
+/// Evaluates `rgb()`, `rgba()`, `hsl()`, `hsla()` or `hwb()`.
+///
+/// The legacy syntax separates every argument with a comma. The modern
+/// syntax separates them with white space, and the alpha with a slash.
+/// `hwb()` supports only the modern syntax.
+pub fn color_function(
+    name: &str,
+    args: &[ColorComponent],
+    separators: &[Separator],
+) -> Option<Color> {
+    let name = name.to_ascii_lowercase();
+    let legacy = match separators {
+        [Separator::Comma, Separator::Comma]
+        | [Separator::Comma, Separator::Comma, Separator::Comma] => true,
+        [Separator::Space, Separator::Space]
+        | [Separator::Space, Separator::Space, Separator::Slash] => false,
+        _ => return None,
+    };
+    let alpha = match args.get(3) {
+        None => 1.0,
+        Some(ColorComponent::Number(n)) => *n,
+        Some(ColorComponent::Percentage(p)) => p / 100.0,
+        Some(ColorComponent::Angle(_)) => return None,
+    };
+    match name.as_str() {
+        "rgb" | "rgba" => {
+            if legacy && !(args[..3].iter().all(is_number) || args[..3].iter().all(is_percentage)) {
+                return None;
+            }
+            let channel = |c: &ColorComponent| match *c {
+                ColorComponent::Number(n) => Some(n / 255.0),
+                ColorComponent::Percentage(p) => Some(p / 100.0),
+                ColorComponent::Angle(_) => None,
+            };
+            Some(Color::from_unit(
+                channel(&args[0])?,
+                channel(&args[1])?,
+                channel(&args[2])?,
+                alpha,
+            ))
+        }
+        "hsl" | "hsla" => {
+            if legacy && !args[1..3].iter().all(is_percentage) {
+                return None;
+            }
+            let (r, g, b) = hsl_to_rgb(hue(&args[0])?, fraction(&args[1])?, fraction(&args[2])?);
+            Some(Color::from_unit(r, g, b, alpha))
+        }
+        "hwb" if !legacy => {
+            let (r, g, b) = hwb_to_rgb(hue(&args[0])?, fraction(&args[1])?, fraction(&args[2])?);
+            Some(Color::from_unit(r, g, b, alpha))
+        }
+        _ => None,
+    }
+}
+
+fn is_number(c: &ColorComponent) -> bool {
+    matches!(c, ColorComponent::Number(_))
+}
+
+fn is_percentage(c: &ColorComponent) -> bool {
+    matches!(c, ColorComponent::Percentage(_))
+}
+
+/// A hue is a number of degrees or an angle.
+fn hue(c: &ColorComponent) -> Option<f32> {
+    match *c {
+        ColorComponent::Number(deg) | ColorComponent::Angle(deg) => Some(deg),
+        ColorComponent::Percentage(_) => None,
+    }
+}
+
+/// Saturation, lightness, whiteness and blackness are percentages. The
+/// modern syntax also allows numbers, where 100 is 100%.
+fn fraction(c: &ColorComponent) -> Option<f32> {
+    match *c {
+        ColorComponent::Number(n) | ColorComponent::Percentage(n) => Some(n / 100.0),
+        ColorComponent::Angle(_) => None,
+    }
+}
+
+// https://drafts.csswg.org/css-color-4/#hsl-to-rgb
+fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> (f32, f32, f32) {
+    let hue = hue.rem_euclid(360.0);
+    let saturation = saturation.clamp(0.0, 1.0);
+    let lightness = lightness.clamp(0.0, 1.0);
+    let f = |n: f32| {
+        let k = (n + hue / 30.0) % 12.0;
+        let a = saturation * lightness.min(1.0 - lightness);
+        lightness - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+    };
+    (f(0.0), f(8.0), f(4.0))
+}
+
+// https://drafts.csswg.org/css-color-4/#hwb-to-rgb
+fn hwb_to_rgb(hue: f32, whiteness: f32, blackness: f32) -> (f32, f32, f32) {
+    let (whiteness, blackness) = (whiteness.max(0.0), blackness.max(0.0));
+    if whiteness + blackness >= 1.0 {
+        let gray = whiteness / (whiteness + blackness);
+        return (gray, gray, gray);
+    }
+    let (r, g, b) = hsl_to_rgb(hue, 1.0, 0.5);
+    let scale = 1.0 - whiteness - blackness;
+    (
+        r * scale + whiteness,
+        g * scale + whiteness,
+        b * scale + whiteness,
+    )
+}
+
+// https://drafts.csswg.org/css-color-4/#named-colors
+// Sorted by name for binary search.
+static NAMED_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    #[test]
+    fn named_color_test() {
+        assert_eq!(named_color("red"), Some(Color::rgb(255, 0, 0)));
+        assert_eq!(
+            named_color("RebeccaPurple"),
+            Some(Color::rgb(0x66, 0x33, 0x99))
+        );
+        assert_eq!(named_color("aliceblue"), Some(Color::rgb(0xf0, 0xf8, 0xff)));
+        assert_eq!(
+            named_color("yellowgreen"),
+            Some(Color::rgb(0x9a, 0xcd, 0x32))
+        );
+        assert_eq!(named_color("transparent"), Some(Color::TRANSPARENT));
+        assert_eq!(named_color("currentcolor"), None);
+        assert_eq!(named_color("block"), None);
+    }
+
+    #[test]
+    fn hex_color_test() {
+        assert_eq!(hex_color("f00"), Some(Color::rgb(255, 0, 0)));
+        assert_eq!(hex_color("f008"), Some(rgba(255, 0, 0, 0x88)));
+        assert_eq!(hex_color("010203"), Some(Color::rgb(1, 2, 3)));
+        assert_eq!(hex_color("01020304"), Some(rgba(1, 2, 3, 4)));
+        assert_eq!(hex_color("01020"), None);
+        assert_eq!(hex_color("0g0"), None);
+    }
+
+    #[test]
+    fn color_function_test() {
+        use self::ColorComponent::*;
+        use self::Separator::*;
+
+        let legacy = [Comma, Comma];
+        let legacy_alpha = [Comma, Comma, Comma];
+        let modern = [Space, Space];
+        let modern_alpha = [Space, Space, Slash];
+
+        assert_eq!(
+            color_function("rgb", &[Number(255.0), Number(0.0), Number(0.0)], &legacy),
+            Some(Color::rgb(255, 0, 0))
+        );
+        assert_eq!(
+            color_function(
+                "rgba",
+                &[Number(255.0), Number(0.0), Number(0.0), Number(0.5)],
+                &legacy_alpha
+            ),
+            Some(rgba(255, 0, 0, 128))
+        );
+        assert_eq!(
+            color_function(
+                "rgb",
+                &[
+                    Percentage(100.0),
+                    Number(0.0),
+                    Number(300.0),
+                    Percentage(25.0)
+                ],
+                &modern_alpha
+            ),
+            Some(rgba(255, 0, 255, 64))
+        );
+        // The legacy syntax doesn't allow mixing numbers and percentages.
+        assert_eq!(
+            color_function(
+                "rgb",
+                &[Percentage(100.0), Number(0.0), Number(0.0)],
+                &legacy
+            ),
+            None
+        );
+        assert_eq!(
+            color_function(
+                "rgb",
+                &[Number(0.0), Number(0.0), Number(0.0)],
+                &[Comma, Space]
+            ),
+            None
+        );
+
+        assert_eq!(
+            color_function(
+                "hsl",
+                &[Number(120.0), Percentage(100.0), Percentage(50.0)],
+                &legacy
+            ),
+            Some(Color::rgb(0, 255, 0))
+        );
+        assert_eq!(
+            color_function(
+                "hsla",
+                &[
+                    Angle(240.0),
+                    Percentage(100.0),
+                    Percentage(25.0),
+                    Number(1.0)
+                ],
+                &legacy_alpha
+            ),
+            Some(Color::rgb(0, 0, 128))
+        );
+        assert_eq!(
+            color_function(
+                "hsl",
+                &[Number(-360.0), Percentage(0.0), Percentage(100.0)],
+                &modern
+            ),
+            Some(Color::rgb(255, 255, 255))
+        );
+
+        assert_eq!(
+            color_function(
+                "hwb",
+                &[Number(0.0), Percentage(0.0), Percentage(0.0)],
+                &modern
+            ),
+            Some(Color::rgb(255, 0, 0))
+        );
+        assert_eq!(
+            color_function(
+                "hwb",
+                &[Number(0.0), Percentage(60.0), Percentage(60.0)],
+                &modern
+            ),
+            Some(Color::rgb(128, 128, 128))
+        );
+        assert_eq!(
+            color_function(
+                "hwb",
+                &[Number(0.0), Percentage(0.0), Percentage(0.0)],
+                &legacy
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn over_test() {
+        let red = Color::rgb(255, 0, 0);
+        let blue = Color::rgb(0, 0, 255);
+        assert_eq!(red.over(blue), red);
+        assert_eq!(Color::TRANSPARENT.over(blue), blue);
+        assert_eq!(rgba(255, 0, 0, 128).over(blue), Color::rgb(128, 0, 127));
+        assert_eq!(
+            rgba(255, 0, 0, 128).over(Color::TRANSPARENT),
+            rgba(255, 0, 0, 128)
+        );
+    }
+}
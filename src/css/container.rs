@@ -0,0 +1,283 @@
+//! Container queries, which are evaluated against the size of an ancestor
+//! query container instead of the viewport.
+//!
+//! https://drafts.csswg.org/css-contain-3/#container-queries
+
+use crate::css::media::{self, Comparison, MediaCondition, MediaFeature, MediaValue};
+use crate::css::{CssRule, LengthContext, Value};
+
+/// `@container name (condition) { }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContainerRule {
+    /// Only the containers with this name are queried if it is given.
+    pub name: Option<String>,
+    /// The syntax is the same as a media condition, but the features are
+    /// the ones of the container.
+    pub condition: MediaCondition,
+    pub rules: Vec<CssRule>,
+}
+
+impl ContainerRule {
+    /// Returns true if the condition queries the block axis, which only a
+    /// `size` container can answer.
+    pub fn queries_block_axis(&self) -> bool {
+        fn visit(condition: &MediaCondition) -> bool {
+            match condition {
+                MediaCondition::Feature(feature) => {
+                    let name = match feature {
+                        MediaFeature::Boolean(name)
+                        | MediaFeature::Plain(name, _)
+                        | MediaFeature::Range(name, _) => name.to_ascii_lowercase(),
+                    };
+                    let (name, _) = media::range_prefix(&name);
+                    ["height", "block-size", "aspect-ratio", "orientation"].contains(&name)
+                }
+                MediaCondition::Not(condition) => visit(condition),
+                MediaCondition::And(conditions) | MediaCondition::Or(conditions) => {
+                    conditions.iter().any(visit)
+                }
+            }
+        }
+        visit(&self.condition)
+    }
+}
+
+/// `container-type`. A `normal` element is not a query container for size
+/// queries.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ContainerType {
+    Normal,
+    /// Queries the width and the height. Its height doesn't depend on its
+    /// content.
+    Size,
+    /// Queries the width only.
+    InlineSize,
+}
+
+impl ContainerType {
+    /// Returns `None` if `value` is invalid.
+    pub fn from_value(value: &Value) -> Option<ContainerType> {
+        match value {
+            Value::Keyword(keyword) => match keyword.to_ascii_lowercase().as_str() {
+                "normal" => Some(ContainerType::Normal),
+                "size" => Some(ContainerType::Size),
+                "inline-size" => Some(ContainerType::InlineSize),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Returns the names in `container-name`, which are empty for `none`, or
+/// `None` if `value` is invalid.
+pub fn container_names(value: &Value) -> Option<Vec<String>> {
+    let values = match value {
+        Value::List(values) => &values[..],
+        _ => std::slice::from_ref(value),
+    };
+    match values {
+        [Value::Keyword(keyword)] if keyword.eq_ignore_ascii_case("none") => Some(vec![]),
+        _ => values
+            .iter()
+            .map(|value| match value {
+                Value::Keyword(name) if is_container_name(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect(),
+    }
+}
+
+/// `none` and the keywords of conditions can't be names.
+pub fn is_container_name(name: &str) -> bool {
+    !["none", "not", "and", "or"]
+        .iter()
+        .any(|keyword| keyword.eq_ignore_ascii_case(name))
+}
+
+/// The size of the content box of a query container, which layout gives it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ContainerSize {
+    pub width: f32,
+    /// `None` for an `inline-size` container.
+    pub height: Option<f32>,
+    /// The length context of the container. Relative lengths in conditions
+    /// refer to it.
+    pub context: LengthContext,
+}
+
+impl ContainerSize {
+    /// Evaluates `condition` with three-valued logic. It is unknown
+    /// (`None`) if it queries an unknown feature, a feature which the
+    /// container doesn't have, or a value of a wrong type. The `not` of
+    /// unknown is unknown, and a rule applies only if its condition is true.
+    pub fn evaluate(&self, condition: &MediaCondition) -> Option<bool> {
+        match condition {
+            MediaCondition::Feature(feature) => self.evaluate_feature(feature),
+            MediaCondition::Not(condition) => self.evaluate(condition).map(|result| !result),
+            MediaCondition::And(conditions) => all(conditions.iter().map(|c| self.evaluate(c))),
+            MediaCondition::Or(conditions) => {
+                // `a or b` is `not (not a and not b)`.
+                let negated = conditions.iter().map(|c| self.evaluate(c).map(|r| !r));
+                all(negated).map(|result| !result)
+            }
+        }
+    }
+
+    fn evaluate_feature(&self, feature: &MediaFeature) -> Option<bool> {
+        match feature {
+            MediaFeature::Boolean(name) => match name.to_ascii_lowercase().as_str() {
+                // Both orientations are true in a boolean context.
+                "orientation" => self.height.map(|_| true),
+                name => self.size_feature(name).map(|value| value != 0.0),
+            },
+            MediaFeature::Plain(name, value) => {
+                let name = name.to_ascii_lowercase();
+                let (name, comparison) = media::range_prefix(&name);
+                self.compare(name, comparison, value)
+            }
+            MediaFeature::Range(name, comparisons) => {
+                let name = name.to_ascii_lowercase();
+                all(comparisons
+                    .iter()
+                    .map(|(comparison, value)| self.compare(&name, *comparison, value)))
+            }
+        }
+    }
+
+    fn size_feature(&self, name: &str) -> Option<f32> {
+        match name {
+            "width" | "inline-size" => Some(self.width),
+            "height" | "block-size" => self.height,
+            "aspect-ratio" => self.height.map(|height| self.width / height),
+            _ => None,
+        }
+    }
+
+    fn compare(&self, name: &str, comparison: Comparison, value: &MediaValue) -> Option<bool> {
+        if name == "orientation" {
+            let orientation = if self.height? >= self.width {
+                "portrait"
+            } else {
+                "landscape"
+            };
+            return match value {
+                MediaValue::Ident(ident) if comparison == Comparison::Eq => {
+                    Some(ident.eq_ignore_ascii_case(orientation))
+                }
+                _ => None,
+            };
+        }
+        let actual = self.size_feature(name)?;
+        let expected = match (name, value) {
+            ("aspect-ratio", MediaValue::Ratio(width, height)) => width / height,
+            ("aspect-ratio", MediaValue::Number(n)) => *n,
+            ("aspect-ratio", _) => return None,
+            (_, MediaValue::Length(n, unit)) => unit.to_px(*n, &self.context),
+            (_, MediaValue::Number(n)) if *n == 0.0 => 0.0,
+            _ => return None,
+        };
+        Some(comparison.compare(actual, expected))
+    }
+}
+
+/// The `and` of three-valued results: false if any of them is false, and
+/// otherwise unknown if any of them is unknown.
+fn all(results: impl Iterator<Item = Option<bool>>) -> Option<bool> {
+    let mut unknown = false;
+    for result in results {
+        match result {
+            Some(false) => return Some(false),
+            Some(true) => {}
+            None => unknown = true,
+        }
+    }
+    if unknown {
+        None
+    } else {
+        Some(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::css::parser::parse_stylesheet;
+
+    fn container_rule(text: &str) -> ContainerRule {
+        match parse_stylesheet(text).unwrap().rules.pop() {
+            Some(CssRule::Container(rule)) => rule,
+            rule => panic!("not @container: {:?}", rule),
+        }
+    }
+
+    fn evaluate(condition: &str, width: f32, height: Option<f32>) -> Option<bool> {
+        let rule = container_rule(&format!("@container {} {{ }}", condition));
+        let size = ContainerSize {
+            width,
+            height,
+            context: LengthContext {
+                font_size: 20.0,
+                ..LengthContext::new(800.0, 600.0)
+            },
+        };
+        size.evaluate(&rule.condition)
+    }
+
+    #[test]
+    fn evaluate_test() {
+        assert_eq!(evaluate("(width > 300px)", 400.0, None), Some(true));
+        assert_eq!(
+            evaluate("(min-inline-size: 500px)", 400.0, None),
+            Some(false)
+        );
+        // `em` refers to the font size of the container.
+        assert_eq!(evaluate("(width = 20em)", 400.0, None), Some(true));
+        assert_eq!(evaluate("(50vw <= width < 60vw)", 400.0, None), Some(true));
+        assert_eq!(evaluate("(width)", 0.0, None), Some(false));
+        assert_eq!(evaluate("(height > 100px)", 400.0, Some(200.0)), Some(true));
+        assert_eq!(
+            evaluate("(aspect-ratio: 2/1)", 400.0, Some(200.0)),
+            Some(true)
+        );
+        assert_eq!(
+            evaluate("(orientation: landscape)", 400.0, Some(200.0)),
+            Some(true)
+        );
+        // The height of an inline-size container is unknown.
+        assert_eq!(evaluate("(height > 100px)", 400.0, None), None);
+        assert_eq!(evaluate("(orientation: portrait)", 400.0, None), None);
+        assert_eq!(evaluate("(unknown)", 400.0, None), None);
+        assert_eq!(evaluate("(width: portrait)", 400.0, None), None);
+        // Unknown is neither true nor false.
+        assert_eq!(evaluate("not (unknown)", 400.0, None), None);
+        assert_eq!(
+            evaluate("(unknown) or (width > 1px)", 400.0, None),
+            Some(true)
+        );
+        assert_eq!(
+            evaluate("(unknown) and (width < 1px)", 400.0, None),
+            Some(false)
+        );
+        assert_eq!(evaluate("(unknown) or (width < 1px)", 400.0, None), None);
+    }
+
+    #[test]
+    fn queries_block_axis_test() {
+        assert!(!container_rule("@container (width > 1px) { }").queries_block_axis());
+        assert!(
+            container_rule("@container a (width > 1px) or (not (min-height: 1px)) { }")
+                .queries_block_axis()
+        );
+        assert!(container_rule("@container (orientation: portrait) { }").queries_block_axis());
+    }
+
+    #[test]
+    fn container_names_test() {
+        let names = |text| container_names(&crate::css::parser::parse_value(text).unwrap());
+        assert_eq!(names("none"), Some(vec![]));
+        assert_eq!(names("a b"), Some(vec!["a".to_string(), "b".to_string()]));
+        assert_eq!(names("a none"), None);
+        assert_eq!(names("\"a\""), None);
+    }
+}
@@ -0,0 +1,220 @@
+//! Counters, `counter()`, `counters()` and the predefined counter styles.
+//!
+//! https://drafts.csswg.org/css-lists-3/
+//! https://drafts.csswg.org/css-counter-styles-3/#predefined-counters
+
+use crate::css::Value;
+
+/// `counter(name, style)`, or `counters(name, separator, style)` if it has
+/// a separator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CounterFunction {
+    pub name: String,
+    pub separator: Option<String>,
+    pub style: CounterStyle,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CounterStyle {
+    Decimal,
+    DecimalLeadingZero,
+    LowerRoman,
+    UpperRoman,
+    LowerAlpha,
+    UpperAlpha,
+    LowerGreek,
+    Disc,
+    Circle,
+    Square,
+    None,
+}
+
+impl CounterStyle {
+    /// `lower-latin` and `upper-latin` are the same as `lower-alpha` and
+    /// `upper-alpha`.
+    pub fn from_name(name: &str) -> Option<CounterStyle> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "decimal" => CounterStyle::Decimal,
+            "decimal-leading-zero" => CounterStyle::DecimalLeadingZero,
+            "lower-roman" => CounterStyle::LowerRoman,
+            "upper-roman" => CounterStyle::UpperRoman,
+            "lower-alpha" | "lower-latin" => CounterStyle::LowerAlpha,
+            "upper-alpha" | "upper-latin" => CounterStyle::UpperAlpha,
+            "lower-greek" => CounterStyle::LowerGreek,
+            "disc" => CounterStyle::Disc,
+            "circle" => CounterStyle::Circle,
+            "square" => CounterStyle::Square,
+            "none" => CounterStyle::None,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CounterStyle::Decimal => "decimal",
+            CounterStyle::DecimalLeadingZero => "decimal-leading-zero",
+            CounterStyle::LowerRoman => "lower-roman",
+            CounterStyle::UpperRoman => "upper-roman",
+            CounterStyle::LowerAlpha => "lower-alpha",
+            CounterStyle::UpperAlpha => "upper-alpha",
+            CounterStyle::LowerGreek => "lower-greek",
+            CounterStyle::Disc => "disc",
+            CounterStyle::Circle => "circle",
+            CounterStyle::Square => "square",
+            CounterStyle::None => "none",
+        }
+    }
+
+    /// Returns the representation of `value`. A value out of the range of a
+    /// style is represented in `decimal`.
+    pub fn format(self, value: i32) -> String {
+        let formatted = match self {
+            CounterStyle::Decimal => None,
+            CounterStyle::DecimalLeadingZero => Some(if value < 0 {
+                format!("-{:02}", -i64::from(value))
+            } else {
+                format!("{:02}", value)
+            }),
+            CounterStyle::LowerRoman => roman(value).map(|s| s.to_ascii_lowercase()),
+            CounterStyle::UpperRoman => roman(value),
+            CounterStyle::LowerAlpha => alphabetic(value, &('a'..='z').collect::<Vec<_>>()),
+            CounterStyle::UpperAlpha => alphabetic(value, &('A'..='Z').collect::<Vec<_>>()),
+            // Without the final sigma.
+            CounterStyle::LowerGreek => alphabetic(
+                value,
+                &('α'..='ω').filter(|&c| c != 'ς').collect::<Vec<_>>(),
+            ),
+            CounterStyle::Disc => Some("•".to_string()),
+            CounterStyle::Circle => Some("◦".to_string()),
+            CounterStyle::Square => Some("▪".to_string()),
+            CounterStyle::None => Some(String::new()),
+        };
+        formatted.unwrap_or_else(|| value.to_string())
+    }
+
+    /// Returns the text of a list item's marker, e.g. `1. ` or `• `.
+    pub fn marker(self, value: i32) -> String {
+        match self {
+            CounterStyle::None => String::new(),
+            CounterStyle::Disc | CounterStyle::Circle | CounterStyle::Square => {
+                format!("{} ", self.format(value))
+            }
+            _ => format!("{}. ", self.format(value)),
+        }
+    }
+}
+
+/// https://drafts.csswg.org/css-counter-styles-3/#upper-roman
+fn roman(value: i32) -> Option<String> {
+    const SYMBOLS: &[(i32, &str)] = &[
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    if !(1..=3999).contains(&value) {
+        return None;
+    }
+    let mut value = value;
+    let mut s = String::new();
+    for &(weight, symbol) in SYMBOLS {
+        while value >= weight {
+            s.push_str(symbol);
+            value -= weight;
+        }
+    }
+    Some(s)
+}
+
+/// The alphabetic system: a, b, ..., z, aa, ab, ...
+///
+/// https://drafts.csswg.org/css-counter-styles-3/#alphabetic-system
+fn alphabetic(value: i32, symbols: &[char]) -> Option<String> {
+    if value < 1 {
+        return None;
+    }
+    let n = symbols.len() as i32;
+    let mut value = value;
+    let mut s = vec![];
+    while value > 0 {
+        value -= 1;
+        s.push(symbols[(value % n) as usize]);
+        value /= n;
+    }
+    Some(s.into_iter().rev().collect())
+}
+
+/// Returns the counters and their values in a value of `counter-reset`,
+/// `counter-increment` or `counter-set`, e.g. `a 2 b`. A counter without a
+/// value has `default`. Invalid items are ignored.
+pub fn counter_changes(value: &Value, default: i32) -> Vec<(String, i32)> {
+    let items = match value {
+        Value::List(items) => items.as_slice(),
+        _ => std::slice::from_ref(value),
+    };
+    let mut changes: Vec<(String, i32)> = vec![];
+    for item in items {
+        match item {
+            Value::Keyword(name) if name.eq_ignore_ascii_case("none") => {}
+            Value::Keyword(name) => changes.push((name.clone(), default)),
+            Value::Number(n) if n.fract() == 0.0 => {
+                if let Some(last) = changes.last_mut() {
+                    last.1 = *n as i32;
+                }
+            }
+            _ => {}
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::css::parser::parse_value;
+
+    #[test]
+    fn counter_style_test() {
+        let format = |name, value| CounterStyle::from_name(name).unwrap().format(value);
+        assert_eq!(format("decimal", -3), "-3");
+        assert_eq!(format("decimal-leading-zero", 7), "07");
+        assert_eq!(format("decimal-leading-zero", -7), "-07");
+        assert_eq!(format("lower-roman", 1994), "mcmxciv");
+        assert_eq!(format("upper-roman", 4), "IV");
+        // Out of range.
+        assert_eq!(format("upper-roman", 0), "0");
+        assert_eq!(format("lower-latin", 1), "a");
+        assert_eq!(format("upper-alpha", 28), "AB");
+        assert_eq!(format("lower-alpha", 702), "zz");
+        assert_eq!(format("lower-greek", 25), "αα");
+        assert_eq!(format("disc", 3), "•");
+
+        assert_eq!(CounterStyle::LowerRoman.marker(3), "iii. ");
+        assert_eq!(CounterStyle::Square.marker(3), "▪ ");
+        assert_eq!(CounterStyle::None.marker(3), "");
+    }
+
+    #[test]
+    fn counter_changes_test() {
+        let changes = |s| counter_changes(&parse_value(s).unwrap(), 1);
+        assert_eq!(
+            changes("a b -2 c"),
+            vec![
+                ("a".to_string(), 1),
+                ("b".to_string(), -2),
+                ("c".to_string(), 1)
+            ]
+        );
+        assert_eq!(changes("none"), vec![]);
+        assert_eq!(changes("a 1.5"), vec![("a".to_string(), 1)]);
+    }
+}
@@ -0,0 +1,72 @@
+//! `@font-face`, which defines a font family from font files.
+//!
+//! https://drafts.csswg.org/css-fonts-4/#font-face-rule
+
+/// `@font-face { }`. A rule without `font-family` or `src` is invalid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontFaceRule {
+    pub family: String,
+    /// The sources in the order of preference.
+    pub sources: Vec<FontSource>,
+    /// The range of `font-weight`. The weight of the font file is used if it
+    /// is `None`.
+    pub weight: Option<(f32, f32)>,
+    /// `font-style`. The style of the font file is used if it is `None`.
+    pub style: Option<FontStyle>,
+    /// The ranges of code points in `unicode-range`. All code points are in
+    /// the range if it is empty.
+    pub unicode_range: Vec<(u32, u32)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FontSource {
+    /// `url(a.ttf) format("truetype")`. A URL is a local file, which is
+    /// relative to the stylesheet once `import::load_stylesheet` resolves it.
+    Url { url: String, format: Option<String> },
+    /// `local(Name)`, the full name or the PostScript name of a font in the
+    /// font registry.
+    Local(String),
+}
+
+impl FontSource {
+    /// Only TrueType and OpenType fonts are supported. A source without a
+    /// format hint is tried anyway.
+    pub fn is_supported(&self) -> bool {
+        match self {
+            FontSource::Url {
+                format: Some(format),
+                ..
+            } => ["truetype", "opentype"]
+                .iter()
+                .any(|supported| supported.eq_ignore_ascii_case(format)),
+            _ => true,
+        }
+    }
+}
+
+/// The angle of `oblique` is not supported.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FontStyle {
+    Normal,
+    Italic,
+    Oblique,
+}
+
+impl FontStyle {
+    pub fn from_name(name: &str) -> Option<FontStyle> {
+        match name.to_ascii_lowercase().as_str() {
+            "normal" => Some(FontStyle::Normal),
+            "italic" => Some(FontStyle::Italic),
+            "oblique" => Some(FontStyle::Oblique),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FontStyle::Normal => "normal",
+            FontStyle::Italic => "italic",
+            FontStyle::Oblique => "oblique",
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! `@import`, which is resolved from the local filesystem.
+//!
+//! https://drafts.csswg.org/css-cascade-5/#at-import
+
+use crate::css::font_face::FontSource;
+use crate::css::{self, parser, CssRule, Origin, Stylesheet};
+use crate::prelude::*;
+use log::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Loads an author stylesheet from `path`, and the stylesheets which it
+/// imports. A URL of `@import` is relative to the importing file, and so is
+/// a URL in `src` of `@font-face`.
+///
+/// The imported rules are stored in their `ImportRule`s, so they come before
+/// the rules of the importing stylesheet in the cascade order.
+pub fn load_stylesheet(path: impl AsRef<Path>) -> Result<Stylesheet> {
+    Ok(Stylesheet {
+        origin: Origin::Author,
+        rules: load_rules(path.as_ref(), &mut vec![])?,
+    })
+}
+
+/// An importing file. `canonical` is used to detect cycles.
+struct Importer {
+    path: PathBuf,
+    canonical: PathBuf,
+}
+
+/// `chain` is the files which import `path`, directly or indirectly.
+fn load_rules(path: &Path, chain: &mut Vec<Importer>) -> Result<Vec<CssRule>> {
+    let error = |chain: &[Importer], message: String| -> failure::Error {
+        let chain = chain
+            .iter()
+            .map(|importer| importer.path.display().to_string())
+            .chain(Some(path.display().to_string()))
+            .collect::<Vec<_>>()
+            .join(" -> ");
+        EngineError::ImportError { chain, message }.into()
+    };
+
+    let canonical = fs::canonicalize(path).map_err(|e| error(chain, e.to_string()))?;
+    if chain.iter().any(|importer| importer.canonical == canonical) {
+        return Err(error(chain, "import cycle".to_string()));
+    }
+    let source = fs::read_to_string(path).map_err(|e| error(chain, e.to_string()))?;
+    let mut rules = parser::parse_stylesheet(&source)
+        .map_err(|e| error(chain, e.to_string()))?
+        .rules;
+    css::set_source_file(&mut rules, &path.display().to_string());
+
+    chain.push(Importer {
+        path: path.to_path_buf(),
+        canonical,
+    });
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    resolve_font_urls(&mut rules, base);
+    // @import must precede all other rules except for @layer statements.
+    let mut preceded = false;
+    for rule in &mut rules {
+        match rule {
+            CssRule::Import(import) if !preceded => {
+                import.rules = load_rules(&base.join(&import.url), chain)?;
+            }
+            CssRule::Import(import) => {
+                warn!(
+                    "Ignored @import {:?}, which is not at the top of {}",
+                    import.url,
+                    path.display()
+                );
+            }
+            CssRule::LayerStatement(_) => {}
+            _ => preceded = true,
+        }
+    }
+    chain.pop();
+    Ok(rules)
+}
+
+/// Makes the URLs of font files relative to `base`. The rules of `@import`
+/// are resolved against their own files.
+fn resolve_font_urls(rules: &mut [CssRule], base: &Path) {
+    for rule in rules {
+        match rule {
+            CssRule::FontFace(font_face) => {
+                for source in &mut font_face.sources {
+                    if let FontSource::Url { url, .. } = source {
+                        *url = base.join(&url).display().to_string();
+                    }
+                }
+            }
+            CssRule::Media(css::MediaRule { rules, .. })
+            | CssRule::Layer(css::LayerRule { rules, .. })
+            | CssRule::Supports(css::SupportsRule { rules, .. })
+            | CssRule::Container(css::container::ContainerRule { rules, .. }) => {
+                resolve_font_urls(rules, base)
+            }
+            CssRule::Style(_)
+            | CssRule::Import(_)
+            | CssRule::LayerStatement(_)
+            | CssRule::Keyframes(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::css::media::Environment;
+    use crate::dom;
+    use crate::style;
+
+    /// Creates the files in a new temporary directory.
+    fn create_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("serval-{}-{}", name, std::process::id()));
+        for (file, content) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn load_stylesheet_test() {
+        let dir = create_files(
+            "import",
+            &[
+                (
+                    "main.css",
+                    "@import 'partials/a.css'; @import url(print.css) print; \
+                     div { width: 1px; height: 1px }",
+                ),
+                (
+                    "partials/a.css",
+                    "@import url(\"b.css\"); div { width: 2px; color: #000000 }",
+                ),
+                ("partials/b.css", "div { width: 3px; margin-top: 3px }"),
+                ("print.css", "div { height: 4px }"),
+                ("late.css", "div { width: 1px } @import 'missing.css';"),
+            ],
+        );
+        let stylesheet = load_stylesheet(dir.join("main.css")).unwrap();
+        let node = dom::parser::parse_html("(div)").unwrap();
+        let style_tree = style::style_tree(&node, &[&stylesheet], &Environment::default());
+        let px = |n| Some(crate::css::Value::Length(n, crate::css::Unit::Px));
+
+        // Imported rules come first in the cascade order.
+        assert_eq!(style_tree.value("width"), px(1.0));
+        assert_eq!(style_tree.value("margin-top"), px(3.0));
+        assert!(style_tree.value("color").is_some());
+        assert_eq!(style_tree.value("height"), px(1.0));
+
+        // An @import after other rules is ignored.
+        assert!(load_stylesheet(dir.join("late.css")).is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn font_url_test() {
+        let dir = create_files(
+            "import-font",
+            &[
+                (
+                    "main.css",
+                    "@import 'fonts/fonts.css'; \
+                     @font-face { font-family: a; src: url(a.ttf), local(A) }",
+                ),
+                (
+                    "fonts/fonts.css",
+                    "@media screen { @font-face { font-family: b; src: url('b.otf') } }",
+                ),
+            ],
+        );
+        let stylesheet = load_stylesheet(dir.join("main.css")).unwrap();
+        let sources: Vec<_> = style::font_face_rules(&[&stylesheet], &Environment::default())
+            .into_iter()
+            .flat_map(|rule| rule.sources.clone())
+            .collect();
+        assert_eq!(
+            sources,
+            vec![
+                FontSource::Url {
+                    url: dir.join("fonts/b.otf").display().to_string(),
+                    format: None,
+                },
+                FontSource::Url {
+                    url: dir.join("a.ttf").display().to_string(),
+                    format: None,
+                },
+                FontSource::Local("A".to_string()),
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn import_error_test() {
+        let dir = create_files(
+            "import-error",
+            &[
+                ("a.css", "@import 'sub/b.css';"),
+                ("sub/b.css", "@import '../a.css';"),
+                ("c.css", "@import 'd.css';"),
+                ("d.css", "@import 'missing.css';"),
+            ],
+        );
+        let error = |file: &str| load_stylesheet(dir.join(file)).unwrap_err().to_string();
+        let path = |file: &str| dir.join(file).display().to_string();
+
+        let cycle = error("a.css");
+        assert!(cycle.contains("import cycle"), "{}", cycle);
+        assert!(
+            cycle.contains(&format!(
+                "{} -> {} -> {}",
+                path("a.css"),
+                path("sub/b.css"),
+                dir.join("sub/../a.css").display()
+            )),
+            "{}",
+            cycle
+        );
+
+        let missing = error("c.css");
+        assert!(
+            missing.contains(&format!(
+                "{} -> {} -> {}",
+                path("c.css"),
+                path("d.css"),
+                path("missing.css")
+            )),
+            "{}",
+            missing
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,332 @@
+//! Media queries.
+//!
+//! https://drafts.csswg.org/mediaqueries-4/
+
+use crate::css::{LengthContext, Unit};
+use crate::prelude::*;
+use std::str::FromStr;
+
+/// The environment which media queries are evaluated against, and the
+/// moment which animations are rendered at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Environment {
+    pub media_type: MediaType,
+    /// The width of the viewport in px.
+    pub width: f32,
+    /// The height of the viewport in px.
+    pub height: f32,
+    /// The number of device pixels per px.
+    pub resolution: f32,
+    pub color_scheme: ColorScheme,
+    /// The time of the document timeline in seconds. It is a virtual
+    /// clock: animations are sampled at this time.
+    pub time: f32,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Environment {
+            media_type: MediaType::Screen,
+            width: 800.0,
+            height: 800.0,
+            resolution: 1.0,
+            color_scheme: ColorScheme::Light,
+            time: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MediaType {
+    Screen,
+    Print,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+impl ColorScheme {
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorScheme::Light => "light",
+            ColorScheme::Dark => "dark",
+        }
+    }
+}
+
+impl FromStr for ColorScheme {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<ColorScheme> {
+        match s {
+            "light" => Ok(ColorScheme::Light),
+            "dark" => Ok(ColorScheme::Dark),
+            _ => Err(failure::err_msg(format!("unknown color scheme: {}", s))),
+        }
+    }
+}
+
+/// A comma separated list of media queries. It matches if any of them
+/// matches, or if it is empty.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MediaQueryList(pub Vec<MediaQuery>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQuery {
+    pub qualifier: Option<Qualifier>,
+    /// `None` if the query is a media condition only, which is the same as
+    /// `all`.
+    pub media_type: Option<String>,
+    pub condition: Option<MediaCondition>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Qualifier {
+    Not,
+    Only,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaCondition {
+    Feature(MediaFeature),
+    Not(Box<MediaCondition>),
+    And(Vec<MediaCondition>),
+    Or(Vec<MediaCondition>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaFeature {
+    /// `(name)`, which matches if the feature's value is not zero or
+    /// `none`.
+    Boolean(String),
+    /// `(name: value)`. `name` may have a `min-` or `max-` prefix.
+    Plain(String, MediaValue),
+    /// `(name < value)`, `(value < name)` or `(value < name < value)`. The
+    /// comparisons are normalized so that the feature is on the left side.
+    Range(String, Vec<(Comparison, MediaValue)>),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Comparison {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl Comparison {
+    /// Returns the comparison with the operands swapped.
+    pub fn flip(self) -> Comparison {
+        match self {
+            Comparison::Lt => Comparison::Gt,
+            Comparison::Le => Comparison::Ge,
+            Comparison::Eq => Comparison::Eq,
+            Comparison::Ge => Comparison::Le,
+            Comparison::Gt => Comparison::Lt,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Eq => "=",
+            Comparison::Ge => ">=",
+            Comparison::Gt => ">",
+        }
+    }
+
+    pub(crate) fn compare(self, lhs: f32, rhs: f32) -> bool {
+        match self {
+            Comparison::Lt => lhs < rhs,
+            Comparison::Le => lhs <= rhs,
+            Comparison::Eq => (lhs - rhs).abs() < f32::EPSILON,
+            Comparison::Ge => lhs >= rhs,
+            Comparison::Gt => lhs > rhs,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaValue {
+    Number(f32),
+    Length(f32, Unit),
+    /// `width / height`
+    Ratio(f32, f32),
+    /// Resolution in dppx.
+    Resolution(f32),
+    Ident(String),
+}
+
+impl MediaQueryList {
+    pub fn matches(&self, env: &Environment) -> bool {
+        self.0.is_empty() || self.0.iter().any(|query| query.matches(env))
+    }
+}
+
+impl MediaQuery {
+    pub fn matches(&self, env: &Environment) -> bool {
+        let media_type = match self.media_type.as_ref() {
+            None => true,
+            Some(name) => match name.to_ascii_lowercase().as_str() {
+                "all" => true,
+                "screen" => env.media_type == MediaType::Screen,
+                "print" => env.media_type == MediaType::Print,
+                _ => false,
+            },
+        };
+        let matches = media_type
+            && self
+                .condition
+                .as_ref()
+                .is_none_or(|condition| condition.matches(env));
+        match self.qualifier {
+            Some(Qualifier::Not) => !matches,
+            _ => matches,
+        }
+    }
+}
+
+impl MediaCondition {
+    pub fn matches(&self, env: &Environment) -> bool {
+        match self {
+            MediaCondition::Feature(feature) => feature.matches(env),
+            MediaCondition::Not(condition) => !condition.matches(env),
+            MediaCondition::And(conditions) => conditions.iter().all(|c| c.matches(env)),
+            MediaCondition::Or(conditions) => conditions.iter().any(|c| c.matches(env)),
+        }
+    }
+}
+
+/// The value of a media feature in the environment.
+enum FeatureValue {
+    /// Lengths, ratios and resolutions are compared as numbers.
+    Number(f32),
+    Ident(&'static str),
+}
+
+impl MediaFeature {
+    /// Unknown features and values of a wrong type never match.
+    pub fn matches(&self, env: &Environment) -> bool {
+        match self {
+            MediaFeature::Boolean(name) => match feature_value(name, env) {
+                Some(FeatureValue::Number(n)) => n != 0.0,
+                Some(FeatureValue::Ident(ident)) => ident != "none",
+                None => false,
+            },
+            MediaFeature::Plain(name, value) => {
+                let name = name.to_ascii_lowercase();
+                let (name, comparison) = range_prefix(&name);
+                compare(name, comparison, value, env)
+            }
+            MediaFeature::Range(name, comparisons) => {
+                let name = name.to_ascii_lowercase();
+                comparisons
+                    .iter()
+                    .all(|(comparison, value)| compare(&name, *comparison, value, env))
+            }
+        }
+    }
+}
+
+/// Splits the `min-` or `max-` prefix of a feature name, which is lower
+/// case, into a comparison.
+pub(crate) fn range_prefix(name: &str) -> (&str, Comparison) {
+    if let Some(name) = name.strip_prefix("min-") {
+        (name, Comparison::Ge)
+    } else if let Some(name) = name.strip_prefix("max-") {
+        (name, Comparison::Le)
+    } else {
+        (name, Comparison::Eq)
+    }
+}
+
+fn feature_value(name: &str, env: &Environment) -> Option<FeatureValue> {
+    Some(match name.to_ascii_lowercase().as_str() {
+        "width" => FeatureValue::Number(env.width),
+        "height" => FeatureValue::Number(env.height),
+        "aspect-ratio" => FeatureValue::Number(env.width / env.height),
+        "resolution" => FeatureValue::Number(env.resolution),
+        "orientation" => FeatureValue::Ident(if env.height >= env.width {
+            "portrait"
+        } else {
+            "landscape"
+        }),
+        "prefers-color-scheme" => FeatureValue::Ident(env.color_scheme.name()),
+        _ => return None,
+    })
+}
+
+fn compare(name: &str, comparison: Comparison, value: &MediaValue, env: &Environment) -> bool {
+    // Relative lengths in media queries refer to the initial values.
+    let context = LengthContext::new(env.width, env.height);
+    let expected = match (name, value) {
+        ("width", MediaValue::Length(n, unit)) | ("height", MediaValue::Length(n, unit)) => {
+            unit.to_px(*n, &context)
+        }
+        ("width", MediaValue::Number(n)) | ("height", MediaValue::Number(n)) if *n == 0.0 => 0.0,
+        ("aspect-ratio", MediaValue::Ratio(w, h)) => w / h,
+        ("aspect-ratio", MediaValue::Number(n)) => *n,
+        ("resolution", MediaValue::Resolution(dppx)) => *dppx,
+        (_, MediaValue::Ident(ident)) if comparison == Comparison::Eq => {
+            return match feature_value(name, env) {
+                Some(FeatureValue::Ident(actual)) => ident.eq_ignore_ascii_case(actual),
+                _ => false,
+            };
+        }
+        _ => return false,
+    };
+    match feature_value(name, env) {
+        Some(FeatureValue::Number(actual)) => comparison.compare(actual, expected),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::css::parser::parse_media_query_list;
+
+    fn matches(query: &str, env: &Environment) -> bool {
+        parse_media_query_list(query).unwrap().matches(env)
+    }
+
+    #[test]
+    fn matches_test() {
+        let env = Environment {
+            width: 375.0,
+            height: 667.0,
+            resolution: 2.0,
+            color_scheme: ColorScheme::Dark,
+            ..Default::default()
+        };
+        assert!(matches("", &env));
+        assert!(matches("screen", &env));
+        assert!(matches("all and (orientation: portrait)", &env));
+        assert!(!matches("print", &env));
+        assert!(matches("not print", &env));
+        assert!(matches("only screen and (max-width: 400px)", &env));
+        assert!(!matches("screen and (min-width: 400px)", &env));
+        assert!(matches("print, (width: 375px)", &env));
+        assert!(matches("(width <= 30em)", &env));
+        assert!(matches("(300px < width <= 375px)", &env));
+        assert!(!matches("(400px <= width <= 800px)", &env));
+        assert!(matches("(400px > width)", &env));
+        assert!(matches("(aspect-ratio < 1/1)", &env));
+        assert!(matches(
+            "(min-resolution: 2dppx) and (resolution < 200dpi)",
+            &env
+        ));
+        assert!(matches("(prefers-color-scheme: dark)", &env));
+        assert!(!matches("(prefers-color-scheme: light)", &env));
+        assert!(matches("not (orientation: landscape)", &env));
+        assert!(matches("(color-gamut: p3) or (width)", &env));
+        assert!(matches("(not (color-gamut: p3))", &env));
+        assert!(!matches("(unknown)", &env));
+        assert!(!matches("(width: portrait)", &env));
+    }
+}
@@ -0,0 +1,167 @@
+//! Nested style rules, which are desugared into flat rules at parse time.
+//!
+//! A nested selector is relative to the parent's selectors: `&` is replaced
+//! with `:is()` of them, and a selector without `&` is a descendant (or a
+//! child, with a leading `>`) of `:is()` of them.
+//!
+//! https://drafts.csswg.org/css-nesting-1/
+
+use crate::css::media::MediaQueryList;
+use crate::css::supports::SupportsCondition;
+use crate::css::*;
+
+/// The contents of a style rule's block, in the order of appearance.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockItem {
+    Declarations(Vec<Declaration>),
+    Rule(NestedRule),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NestedRule {
+    Style(Vec<RelativeSelector>, Vec<BlockItem>, SourceLocation),
+    /// The declarations directly in a nested group rule apply to the
+    /// parent's selectors.
+    Media(MediaQueryList, Vec<BlockItem>),
+    Supports(SupportsCondition, Vec<BlockItem>),
+    Layer(Option<LayerName>, Vec<BlockItem>),
+}
+
+/// A selector of a nested rule, which may start with a combinator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelativeSelector {
+    pub combinator: Option<Combinator>,
+    pub selector: Selector,
+}
+
+/// Returns the flat rules of a style rule at `location`. The rule itself
+/// comes first, followed by its nested rules. Declarations after a nested
+/// rule keep their position, in a rule with the same selectors.
+pub fn desugar(
+    selectors: Vec<Selector>,
+    items: Vec<BlockItem>,
+    location: &SourceLocation,
+) -> Vec<CssRule> {
+    let has_nested_rules = items.iter().any(|item| matches!(item, BlockItem::Rule(_)));
+    let mut rules = vec![];
+    for item in items {
+        match item {
+            BlockItem::Declarations(declarations) => {
+                if !declarations.is_empty() || !has_nested_rules {
+                    rules.push(CssRule::Style(Rule {
+                        selectors: SortedSelectors::new(selectors.clone()),
+                        declarations,
+                        location: location.clone(),
+                    }));
+                }
+            }
+            BlockItem::Rule(NestedRule::Style(relative_selectors, items, location)) => {
+                let nested_selectors = relative_selectors
+                    .into_iter()
+                    .map(|relative| resolve(relative, &selectors))
+                    .collect();
+                rules.extend(desugar(nested_selectors, items, &location));
+            }
+            BlockItem::Rule(NestedRule::Media(queries, items)) => {
+                rules.push(CssRule::Media(MediaRule {
+                    queries,
+                    rules: desugar(selectors.clone(), items, location),
+                }));
+            }
+            BlockItem::Rule(NestedRule::Supports(condition, items)) => {
+                rules.push(CssRule::Supports(SupportsRule {
+                    condition,
+                    rules: desugar(selectors.clone(), items, location),
+                }));
+            }
+            BlockItem::Rule(NestedRule::Layer(name, items)) => {
+                rules.push(CssRule::Layer(LayerRule {
+                    name,
+                    rules: desugar(selectors.clone(), items, location),
+                }));
+            }
+        }
+    }
+    rules
+}
+
+/// Returns the absolute selector of a nested selector.
+fn resolve(relative: RelativeSelector, parent: &[Selector]) -> Selector {
+    let mut selector = relative.selector;
+    if relative.combinator.is_none() && replace_nesting(&mut selector, parent) {
+        return selector;
+    }
+    let parent = SimpleSelector {
+        is: vec![parent.to_vec()],
+        ..Default::default()
+    };
+    let combinator = relative.combinator.unwrap_or(Combinator::Descendant);
+    prepend(selector, parent, combinator)
+}
+
+/// Replaces `&` with `:is()` of `parent`. Returns true if `selector` has `&`.
+fn replace_nesting(selector: &mut Selector, parent: &[Selector]) -> bool {
+    let (ancestor, simple) = match selector {
+        Selector::Simple(simple) => (None, simple),
+        Selector::Complex(ancestor, _, simple) => (Some(ancestor), simple),
+    };
+    let mut replaced = false;
+    for selectors in &mut simple.is {
+        for selector in selectors {
+            replaced |= replace_nesting(selector, parent);
+        }
+    }
+    if simple.nesting {
+        simple.nesting = false;
+        simple.is.push(parent.to_vec());
+        replaced = true;
+    }
+    if let Some(ancestor) = ancestor {
+        replaced |= replace_nesting(ancestor, parent);
+    }
+    replaced
+}
+
+/// Puts `first` and `combinator` before the leftmost compound selector.
+fn prepend(selector: Selector, first: SimpleSelector, combinator: Combinator) -> Selector {
+    match selector {
+        Selector::Simple(simple) => {
+            Selector::Complex(Box::new(Selector::Simple(first)), combinator, simple)
+        }
+        Selector::Complex(ancestor, c, simple) => {
+            Selector::Complex(Box::new(prepend(*ancestor, first, combinator)), c, simple)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::css::parser::parse_stylesheet;
+
+    #[test]
+    fn desugar_test() {
+        let nested = parse_stylesheet(
+            ".a, #b { \
+               color: #000000; \
+               & .c { width: 1px } \
+               .d& { } \
+               > p { } \
+               @media print { color: #010101; span { } } \
+               height: 2px \
+             } \
+             .e { .f { .g { } } }",
+        )
+        .unwrap();
+        let flat = parse_stylesheet(
+            ".a, #b { color: #000000 } \
+             :is(.a, #b) .c { width: 1px } \
+             .d:is(.a, #b) { } \
+             :is(.a, #b) > p { } \
+             @media print { .a, #b { color: #010101 } :is(.a, #b) span { } } \
+             .a, #b { height: 2px } \
+             :is(:is(.e) .f) .g { }",
+        )
+        .unwrap();
+        assert_eq!(nested, flat);
+    }
+}
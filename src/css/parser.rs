@@ -1,43 +1,682 @@
 // use combine::parser::char::{char, letter, space, spaces};
 use combine::parser::char;
 use combine::parser::char::{digit, letter, spaces};
+use combine::parser::combinator;
+use combine::parser::error::unexpected_any;
 use combine::parser::item;
+use combine::stream::state::State;
 use combine::*;
+use log::*;
 
+use crate::css::animation::{EasingFunction, Keyframe, KeyframesRule, StepPosition};
+use crate::css::calc::CalcNode;
+use crate::css::color as css_color;
+use crate::css::color::{ColorComponent, Separator};
+use crate::css::container::{self, ContainerRule};
+use crate::css::counter::{CounterFunction, CounterStyle};
+use crate::css::font_face::{FontFaceRule, FontSource, FontStyle};
+use crate::css::media::*;
+use crate::css::nesting::{BlockItem, NestedRule, RelativeSelector};
+use crate::css::shorthand;
+use crate::css::supports::SupportsCondition;
+use crate::css::variable::{Token, TokenStream};
 use crate::css::*;
 use crate::prelude::*;
 
+// A rule which is invalid or not supported is skipped with a warning.
 def_parser! {
     pub fn stylesheet() -> Stylesheet {
-        sep_by(rule(), spaces()).map(|rules| Stylesheet { rules })
+        many(
+            choice((
+                attempt(import_rule()).map(|rule| vec![CssRule::Import(rule)]),
+                attempt(css_rule()),
+                invalid_item("rule", true).map(|_| vec![]),
+            )).skip(spaces()),
+        ).map(|rules: Vec<Vec<CssRule>>| Stylesheet {
+            origin: Origin::Author,
+            rules: rules.into_iter().flatten().collect(),
+        })
+    }
+}
+
+// A style rule is desugared into more than one rule if it has nested rules.
+def_parser! {
+    fn css_rule() -> Vec<CssRule> {
+        media_rule().map(|rule| vec![CssRule::Media(rule)])
+            .or(supports_rule().map(|rule| vec![CssRule::Supports(rule)]))
+            .or(container_rule().map(|rule| vec![CssRule::Container(rule)]))
+            .or(layer_rule().map(|rule| vec![rule]))
+            .or(keyframes_rule().map(|rule| vec![CssRule::Keyframes(rule)]))
+            .or(font_face_rule().map(|rule| vec![CssRule::FontFace(rule)]))
+            .or(rule())
+    }
+}
+
+// The rules in a block of an at-rule.
+def_parser! {
+    fn rule_list() -> Vec<CssRule> {
+        many(attempt(css_rule()).or(invalid_item("rule", false).map(|_| vec![])).skip(spaces()))
+            .map(|rules: Vec<Vec<CssRule>>| rules.into_iter().flatten().collect())
+    }
+}
+
+def_parser! {
+    fn import_rule() -> ImportRule {
+        (
+            at_keyword("import"),
+            spaces(),
+            import_url(),
+            spaces(),
+            optional(attempt(import_layer().skip(spaces()))),
+            media_query_list(),
+            spaces(),
+            char::char(';'),
+        ).map(|(_, _, url, _, layer, queries, _, _)| ImportRule {
+            url,
+            layer,
+            queries,
+            rules: vec![],
+        })
+    }
+}
+
+def_parser! {
+    fn import_url() -> String {
+        string_token().or(url_function())
+    }
+}
+
+def_parser! {
+    fn url_function() -> String {
+        (
+            function_start("url"),
+            string_token().or(many1(item::satisfy(|c: char| {
+                !c.is_whitespace() && !"()\"'".contains(c)
+            }))),
+            spaces(),
+            char::char(')'),
+        ).map(|(_, url, _, _)| url)
+    }
+}
+
+def_parser! {
+    fn import_layer() -> Option<LayerName> {
+        (function_start("layer"), layer_name(), spaces(), char::char(')'))
+            .map(|(_, name, _, _)| Some(name))
+            .or(keyword_ignore_case("layer").skip(not_followed_by(name_char())).map(|_| None))
+    }
+}
+
+def_parser! {
+    pub fn layer_name() -> LayerName {
+        sep_by1(ident(), char::char('.'))
+    }
+}
+
+// A statement (`@layer a, b;`) or a block (`@layer a { }`).
+def_parser! {
+    fn layer_rule() -> CssRule {
+        (
+            at_keyword("layer"),
+            spaces(),
+            sep_by(layer_name(), attempt((spaces(), char::char(','), spaces()))),
+            spaces(),
+            char::char(';').map(|_| None).or(
+                (char::char('{'), spaces(), rule_list(), char::char('}'))
+                    .map(|(_, _, rules, _)| Some(rules))
+            ),
+        ).then(|(_, _, mut names, _, rules): (_, _, Vec<LayerName>, _, _)| match rules {
+            None if !names.is_empty() => item::value(CssRule::LayerStatement(names)).left(),
+            Some(rules) if names.len() <= 1 => item::value(CssRule::Layer(LayerRule {
+                name: names.pop(),
+                rules,
+            })).left(),
+            _ => unexpected_any("invalid @layer").right(),
+        })
+    }
+}
+
+// https://drafts.csswg.org/css-animations-1/#keyframes
+def_parser! {
+    fn keyframes_rule() -> KeyframesRule {
+        (
+            at_keyword("keyframes"),
+            spaces(),
+            ident().or(string_token()),
+            spaces(),
+            char::char('{'),
+            spaces(),
+            many(keyframe().skip(spaces())),
+            char::char('}'),
+        ).map(|(_, _, name, _, _, _, keyframes, _)| KeyframesRule { name, keyframes })
+    }
+}
+
+def_parser! {
+    fn keyframe() -> Keyframe {
+        (
+            sep_by1(keyframe_selector(), attempt((spaces(), char::char(','), spaces()))),
+            spaces(),
+            char::char('{'),
+            spaces(),
+            declarations(),
+            spaces(),
+            char::char('}'),
+        ).map(|(offsets, _, _, _, declarations, _, _): (_, _, _, _, Vec<Declaration>, _, _)| {
+            Keyframe {
+                offsets,
+                declarations: declarations.into_iter().filter(|d| !d.important).collect(),
+            }
+        })
+    }
+}
+
+// `from`, `to` or a percentage, which is returned in percent.
+def_parser! {
+    fn keyframe_selector() -> f32 {
+        keyword_ignore_case("from").map(|_| 0.0)
+            .or(keyword_ignore_case("to").map(|_| 100.0))
+            .or((number(), char::char('%')).then(|(n, _)| {
+                if (0.0..=100.0).contains(&n) {
+                    item::value(n).left()
+                } else {
+                    unexpected_any("keyframe selector out of range").right()
+                }
+            }))
+    }
+}
+
+// https://drafts.csswg.org/css-fonts-4/#font-face-rule
+//
+// A descriptor with an invalid value is ignored, but the rule is invalid
+// without `font-family` or `src`.
+def_parser! {
+    fn font_face_rule() -> FontFaceRule {
+        (
+            at_keyword("font-face"),
+            spaces(),
+            char::char('{'),
+            spaces(),
+            sep_end_by(font_descriptor(), (char::char(';'), spaces())),
+            char::char('}'),
+        ).then(|(_, _, _, _, descriptors, _)| match font_face(descriptors) {
+            Some(rule) => item::value(rule).left(),
+            None => unexpected_any("@font-face without font-family or src").right(),
+        })
+    }
+}
+
+// The name and the text of the value of a descriptor.
+def_parser! {
+    fn font_descriptor() -> (String, String) {
+        (
+            ident(),
+            spaces(),
+            char::char(':'),
+            spaces(),
+            many(item::satisfy(|c| c != ';' && c != '}')),
+        ).map(|(name, _, _, _, value): (String, _, _, _, String)| {
+            (name.to_ascii_lowercase(), value.trim().to_string())
+        })
+    }
+}
+
+fn font_face(descriptors: Vec<(String, String)>) -> Option<FontFaceRule> {
+    fn parse_all<'a, P: Parser<Input = &'a str>>(parser: P, text: &'a str) -> Option<P::Output> {
+        parser
+            .skip(eof())
+            .parse(text)
+            .ok()
+            .map(|(output, _)| output)
+    }
+    let comma = || attempt((spaces(), char::char(','), spaces()));
+
+    let mut family = None;
+    let mut sources = None;
+    let mut rule = FontFaceRule {
+        family: String::new(),
+        sources: vec![],
+        weight: None,
+        style: None,
+        unicode_range: vec![],
+    };
+    for (name, text) in &descriptors {
+        match name.as_str() {
+            "font-family" => family = parse_all(font_family_name(), text).or(family),
+            "src" => sources = parse_all(sep_by1(font_source(), comma()), text).or(sources),
+            "font-weight" => rule.weight = parse_all(font_weight_range(), text).or(rule.weight),
+            "font-style" => rule.style = FontStyle::from_name(text).or(rule.style),
+            "unicode-range" => {
+                if let Some(ranges) = parse_all(sep_by1(unicode_range(), comma()), text) {
+                    rule.unicode_range = ranges;
+                }
+            }
+            _ => {}
+        }
+    }
+    rule.family = family?;
+    rule.sources = sources?;
+    Some(rule)
+}
+
+// A family name is a string or a sequence of identifiers.
+def_parser! {
+    pub fn font_family_name() -> String {
+        string_token().or(
+            sep_by1(ident(), attempt(skip_many1(char::space()).skip(look_ahead(ident()))))
+                .map(|names: Vec<String>| names.join(" "))
+        )
+    }
+}
+
+def_parser! {
+    fn font_source() -> FontSource {
+        (function_start("local"), font_family_name(), spaces(), char::char(')'))
+            .map(|(_, name, _, _)| FontSource::Local(name))
+            .or((
+                url_function(),
+                optional(attempt((
+                    spaces(),
+                    function_start("format"),
+                    string_token().or(ident()),
+                    spaces(),
+                    char::char(')'),
+                ))),
+            ).map(|(url, format)| FontSource::Url {
+                url,
+                format: format.map(|(_, _, format, _, _)| format),
+            }))
+    }
+}
+
+// One weight or a range of two weights, in 1 to 1000.
+def_parser! {
+    fn font_weight_range() -> (f32, f32) {
+        (font_weight(), optional(attempt((skip_many1(char::space()), font_weight()))))
+            .map(|(start, end)| {
+                let end = end.map_or(start, |(_, end)| end);
+                (start.min(end), start.max(end))
+            })
+    }
+}
+
+def_parser! {
+    fn font_weight() -> f32 {
+        number().then(|weight| if (1.0..=1000.0).contains(&weight) {
+            item::value(weight).left()
+        } else {
+            unexpected_any("font-weight out of range").right()
+        })
+            .or(keyword_ignore_case("normal").map(|_| 400.0))
+            .or(keyword_ignore_case("bold").map(|_| 700.0))
+    }
+}
+
+// `U+26`, `U+0-7F` or `U+4??`.
+//
+// https://drafts.csswg.org/css-fonts-4/#unicode-range-desc
+def_parser! {
+    fn unicode_range() -> (u32, u32) {
+        (
+            keyword_ignore_case("u+"),
+            many1(item::satisfy(|c: char| c.is_ascii_hexdigit() || c == '?')),
+            optional((
+                char::char('-'),
+                many1(item::satisfy(|c: char| c.is_ascii_hexdigit())),
+            )),
+        ).then(|(_, start, end): (_, String, Option<(char, String)>)| {
+            match unicode_range_bounds(&start, end.as_ref().map(|(_, end)| end.as_str())) {
+                Some(range) => item::value(range).left(),
+                None => unexpected_any("invalid unicode-range").right(),
+            }
+        })
+    }
+}
+
+fn unicode_range_bounds(start: &str, end: Option<&str>) -> Option<(u32, u32)> {
+    let hex = |s: &str| {
+        if s.len() <= 6 {
+            u32::from_str_radix(s, 16).ok()
+        } else {
+            None
+        }
+    };
+    let (low, high) = match end {
+        // Wildcards are only at the end.
+        None if start.contains('?') => {
+            if start.trim_end_matches('?').contains('?') {
+                return None;
+            }
+            (
+                hex(&start.replace('?', "0"))?,
+                hex(&start.replace('?', "F"))?,
+            )
+        }
+        None => (hex(start)?, hex(start)?),
+        Some(end) => (hex(start)?, hex(end)?),
+    };
+    if low <= high && high <= 0x10_FFFF {
+        Some((low, high))
+    } else {
+        None
+    }
+}
+
+def_parser! {
+    fn media_rule() -> MediaRule {
+        (
+            at_keyword("media"),
+            spaces(),
+            media_query_list(),
+            spaces(),
+            char::char('{'),
+            spaces(),
+            rule_list(),
+            char::char('}'),
+        ).map(|(_, _, queries, _, _, _, rules, _)| MediaRule { queries, rules })
+    }
+}
+
+def_parser! {
+    fn supports_rule() -> SupportsRule {
+        (
+            at_keyword("supports"),
+            spaces(),
+            supports_condition(),
+            spaces(),
+            char::char('{'),
+            spaces(),
+            rule_list(),
+            char::char('}'),
+        ).map(|(_, _, condition, _, _, _, rules, _)| SupportsRule { condition, rules })
+    }
+}
+
+// https://drafts.csswg.org/css-contain-3/#container-rule
+def_parser! {
+    fn container_rule() -> ContainerRule {
+        (
+            at_keyword("container"),
+            spaces(),
+            optional(attempt(container_name().skip(spaces()))),
+            media_condition(),
+            spaces(),
+            char::char('{'),
+            spaces(),
+            rule_list(),
+            char::char('}'),
+        ).map(|(_, _, name, condition, _, _, _, rules, _)| ContainerRule {
+            name,
+            condition,
+            rules,
+        })
+    }
+}
+
+def_parser! {
+    fn container_name() -> String {
+        ident().then(|name: String| {
+            if container::is_container_name(&name) {
+                item::value(name).left()
+            } else {
+                unexpected_any("not a container name").right()
+            }
+        })
+    }
+}
+
+// https://drafts.csswg.org/css-conditional-3/#typedef-supports-condition
+def_parser! {
+    pub fn supports_condition() -> SupportsCondition {
+        attempt((keyword_ignore_case("not"), skip_many1(char::space())))
+            .with(supports_in_parens())
+            .map(|condition| SupportsCondition::Not(Box::new(condition)))
+            .or((
+                supports_in_parens(),
+                many(attempt((
+                    skip_many1(char::space()),
+                    keyword_ignore_case("and").or(keyword_ignore_case("or")),
+                    skip_many1(char::space()),
+                    supports_in_parens(),
+                ))),
+            ).then(|(first, rest): (SupportsCondition, Vec<(_, &str, _, SupportsCondition)>)| {
+                let is_and = rest.first().map(|(_, op, _, _)| op.eq_ignore_ascii_case("and"));
+                // `and` and `or` can't be mixed without parentheses.
+                if rest.iter().any(|(_, op, _, _)| Some(op.eq_ignore_ascii_case("and")) != is_and) {
+                    return unexpected_any("mixed and and or").right();
+                }
+                let mut conditions = vec![first];
+                conditions.extend(rest.into_iter().map(|(_, _, _, condition)| condition));
+                item::value(match is_and {
+                    None => conditions.pop().unwrap(),
+                    Some(true) => SupportsCondition::And(conditions),
+                    Some(false) => SupportsCondition::Or(conditions),
+                }).left()
+            }))
+    }
+}
+
+def_parser! {
+    fn supports_in_parens() -> SupportsCondition {
+        choice((
+            attempt(between(
+                (char::char('('), spaces()),
+                (spaces(), char::char(')')),
+                supports_condition(),
+            )),
+            attempt((
+                char::char('('),
+                spaces(),
+                property_name(),
+                spaces(),
+                char::char(':'),
+                spaces(),
+                balanced_text(),
+                char::char(')'),
+            )).map(|(_, _, name, _, _, _, value, _): (_, _, _, _, _, _, String, _)| {
+                SupportsCondition::Declaration(name, value.trim_end().to_string())
+            }),
+            (function_start("selector"), balanced_text(), char::char(')'))
+                .map(|(_, selector, _): (_, String, _)| {
+                    SupportsCondition::Selector(selector.trim_end().to_string())
+                }),
+            (
+                optional(ident()),
+                char::char('('),
+                balanced_text(),
+                char::char(')'),
+            ).map(|(name, _, text, _): (Option<String>, _, String, _)| {
+                SupportsCondition::General(format!("{}({})", name.unwrap_or_default(), text))
+            }),
+        ))
+    }
+}
+
+// A block in brackets, which is skipped as a whole.
+def_parser! {
+    fn simple_block() -> () {
+        choice((
+            (char::char('('), skip_many(block_content()), char::char(')')).map(|_| ()),
+            (char::char('['), skip_many(block_content()), char::char(']')).map(|_| ()),
+            curly_block(),
+        ))
+    }
+}
+
+def_parser! {
+    fn curly_block() -> () {
+        (char::char('{'), skip_many(block_content()), char::char('}')).map(|_| ())
+    }
+}
+
+def_parser! {
+    fn block_content() -> () {
+        simple_block()
+            .or(attempt(string_token()).map(|_| ()))
+            .or(item::satisfy(|c| !"()[]{}".contains(c)).map(|_| ()))
+    }
+}
+
+/// Skips an item which is invalid or not supported, as CSS error recovery
+/// does, and logs a warning. The item ends after a `;`, or a `{}` block at
+/// its level, or before the `}` which closes the enclosing block. At the top
+/// level, a `}` is skipped as well.
+fn invalid_item<I>(kind: &'static str, top_level: bool) -> impl Parser<Input = I, Output = ()>
+where
+    I: Stream<Item = char>,
+    I::Position: InputPosition,
+    I::Error: ParseError<I::Item, I::Range, I::Position>,
+{
+    let part = choice((
+        attempt(string_token()).map(|_| ()),
+        look_ahead(item::one_of("([".chars())).with(simple_block()),
+        item::satisfy(move |c| !"([{;}".contains(c) || (top_level && c == '}')).map(|_| ()),
+    ));
+    let end = choice((
+        char::char(';').map(|_| ()),
+        curly_block(),
+        look_ahead(char::char('}')).map(|_| ()),
+        eof(),
+    ));
+    (
+        item::position(),
+        combinator::recognize::<String, _>((
+            look_ahead(item::satisfy(move |c| top_level || c != '}')),
+            skip_many(part),
+            end,
+        )),
+    )
+        .map(move |(position, text)| {
+            let text = text.trim_end_matches(';').trim();
+            if !text.is_empty() {
+                warn!(
+                    "{}: Ignored an invalid or unsupported {}: {}",
+                    source_location(position),
+                    kind,
+                    text
+                );
+            }
+        })
+}
+
+// Text with balanced parentheses, as written.
+def_parser! {
+    fn balanced_text() -> String {
+        many(
+            many1(item::satisfy(|c| c != '(' && c != ')' && c != '{' && c != '}'))
+                .or((char::char('('), balanced_text(), char::char(')'))
+                    .map(|(_, text, _): (_, String, _)| format!("({})", text)))
+        ).map(|texts: Vec<String>| texts.concat())
     }
 }
 
 def_parser! {
-    fn rule() -> Rule {
-        (selectors(),
+    fn rule() -> Vec<CssRule> {
+        (item::position(),
+         selectors(),
          spaces(),
          char::char('{'),
          spaces(),
-         declarations(),
+         style_block(),
          spaces(),
          char::char('}'),
-        ).map(|(selectors, _, _, _, declarations, _, _)| Rule {
-            selectors: SortedSelectors::new(selectors),
-            declarations
+        ).map(|(position, selectors, _, _, _, items, _, _)| {
+            nesting::desugar(selectors, items, &source_location(position))
+        })
+    }
+}
+
+// The declarations and nested rules in a style rule, which start and end
+// with declarations.
+// https://drafts.csswg.org/css-nesting-1/#syntax
+def_parser! {
+    fn style_block() -> Vec<BlockItem> {
+        many(
+            choice((
+                attempt(terminated_declaration()).map(|declaration| Some(Ok(declaration))),
+                attempt(nested_rule()).map(|rule| Some(Err(rule))),
+                invalid_item("declaration", false).map(|_| None),
+            )).skip(spaces()),
+        ).map(|items: Vec<Option<std::result::Result<Declaration, NestedRule>>>| {
+            let mut block = vec![];
+            let mut declarations = vec![];
+            for item in items.into_iter().flatten() {
+                match item {
+                    Ok(declaration) => declarations.push(declaration),
+                    Err(rule) => {
+                        let declarations = std::mem::take(&mut declarations);
+                        block.push(BlockItem::Declarations(expand_declarations(declarations)));
+                        block.push(BlockItem::Rule(rule));
+                    }
+                }
+            }
+            block.push(BlockItem::Declarations(expand_declarations(declarations)));
+            block
         })
     }
 }
 
+def_parser! {
+    fn nested_rule() -> NestedRule {
+        choice((
+            (at_keyword("media"), spaces(), media_query_list(), spaces(), nested_block())
+                .map(|(_, _, queries, _, items)| NestedRule::Media(queries, items)),
+            (at_keyword("supports"), spaces(), supports_condition(), spaces(), nested_block())
+                .map(|(_, _, condition, _, items)| NestedRule::Supports(condition, items)),
+            (at_keyword("layer"), spaces(), optional(layer_name()), spaces(), nested_block())
+                .map(|(_, _, name, _, items)| NestedRule::Layer(name, items)),
+            (
+                item::position(),
+                sep_by1(relative_selector(), attempt((spaces(), char::char(','), spaces()))),
+                spaces(),
+                nested_block(),
+            ).map(|(position, selectors, _, items)| {
+                NestedRule::Style(selectors, items, source_location(position))
+            }),
+        ))
+    }
+}
+
+def_parser! {
+    fn nested_block() -> Vec<BlockItem> {
+        (char::char('{'), spaces(), style_block(), spaces(), char::char('}'))
+            .map(|(_, _, items, _, _)| items)
+    }
+}
+
+def_parser! {
+    fn relative_selector() -> RelativeSelector {
+        (optional(attempt(char::char('>').skip(spaces()))), selector())
+            .map(|(child, selector)| RelativeSelector {
+                combinator: child.map(|_| Combinator::Child),
+                selector,
+            })
+    }
+}
+
 def_parser! {
     pub fn selectors() -> Vec<Selector> {
-        sep_by(selector(), (char::char(','), spaces()))
+        sep_by(selector(), attempt((spaces(), char::char(','), spaces())))
     }
 }
 
 def_parser! {
     fn selector() -> Selector {
-        simple_selector().map(Selector::Simple)
+        (
+            simple_selector(),
+            many(attempt((selector_combinator(), compound_selector()))),
+        ).map(|(first, rest): (SimpleSelector, Vec<(Combinator, SimpleSelector)>)| {
+            rest.into_iter().fold(Selector::Simple(first), |selector, (combinator, simple)| {
+                Selector::Complex(Box::new(selector), combinator, simple)
+            })
+        })
+    }
+}
+
+def_parser! {
+    fn selector_combinator() -> Combinator {
+        attempt((spaces(), char::char('>'), spaces())).map(|_| Combinator::Child)
+            .or(skip_many1(char::space()).map(|_| Combinator::Descendant))
     }
 }
 
@@ -46,28 +685,54 @@ enum SimpleSelectorPart {
     TagName(String),
     Id(String),
     Class(String),
+    Is(Vec<Selector>),
+    Nesting,
+    PseudoElement(PseudoElement),
 }
 
 def_parser! {
     fn simple_selector() -> SimpleSelector {
-        simple_selector_part().and(simple_selector()).map(|(x, mut xs)| {
+        compound_selector().or(item::value(Default::default()))
+    }
+}
+
+def_parser! {
+    fn compound_selector() -> SimpleSelector {
+        simple_selector_part().and(simple_selector()).then(|(x, mut xs)| {
             match x {
                 SimpleSelectorPart::Universal => {
                 }
                 SimpleSelectorPart::TagName(s) => {
-                    assert!(xs.tag_name.is_none());
+                    if xs.tag_name.is_some() {
+                        return unexpected_any("a second tag name").right();
+                    }
                     xs.tag_name = Some(s);
                 }
                 SimpleSelectorPart::Id(s) => {
-                    assert!(xs.id.is_none());
+                    if xs.id.is_some() {
+                        return unexpected_any("a second id").right();
+                    }
                     xs.id = Some(s);
                 }
                 SimpleSelectorPart::Class(s) => {
                     xs.classes.insert(s);
                 }
+                SimpleSelectorPart::Is(selectors) => {
+                    xs.is.insert(0, selectors);
+                }
+                SimpleSelectorPart::Nesting => {
+                    xs.nesting = true;
+                }
+                // A pseudo-element must be the last part.
+                SimpleSelectorPart::PseudoElement(pseudo_element) => {
+                    if xs != SimpleSelector::default() {
+                        return unexpected_any("a selector after a pseudo-element").right();
+                    }
+                    xs.pseudo_element = Some(pseudo_element);
+                }
             }
-            xs
-        }).or(item::value(Default::default()))
+            item::value(xs).left()
+        })
     }
 }
 
@@ -77,6 +742,23 @@ def_parser! {
             .or(tag_name().map(SimpleSelectorPart::TagName))
             .or(id().map(SimpleSelectorPart::Id))
                 .or(class().map(SimpleSelectorPart::Class))
+            .or(char::char('&').map(|_| SimpleSelectorPart::Nesting))
+            .or(pseudo_element().map(SimpleSelectorPart::PseudoElement))
+            .or(
+                (char::char(':'), function_start("is"), selectors(), spaces(), char::char(')'))
+                    .map(|(_, _, selectors, _, _)| SimpleSelectorPart::Is(selectors))
+            )
+    }
+}
+
+def_parser! {
+    fn pseudo_element() -> PseudoElement {
+        attempt((char::string("::"), ident())).then(|(_, name): (_, String)| {
+            match PseudoElement::from_name(&name) {
+                Some(pseudo_element) => item::value(pseudo_element).left(),
+                None => unexpected_any("unknown pseudo-element").right(),
+            }
+        })
     }
 }
 
@@ -98,173 +780,1335 @@ def_parser! {
     }
 }
 
+// A declaration which is invalid is skipped with a warning.
 def_parser! {
     fn declarations() -> Vec<Declaration> {
-        sep_by(declaration(), (char::char(';'), spaces()))
+        many(
+            attempt(terminated_declaration()).map(Some)
+                .or(invalid_item("declaration", false).map(|_| None))
+                .skip(spaces()),
+        ).map(|declarations: Vec<Option<Declaration>>| {
+            expand_declarations(declarations.into_iter().flatten().collect())
+        })
     }
 }
 
+// A declaration is followed by `;`, `}` or the end. Otherwise, it may be the start
+// of a nested rule, e.g. `a:is(.b) { }`.
+def_parser! {
+    fn terminated_declaration() -> Declaration {
+        declaration().skip(spaces()).skip(
+            char::char(';').map(|_| ()).or(look_ahead(char::char('}')).map(|_| ())).or(eof())
+        )
+    }
+}
+
+/// Expands the shorthands in `declarations`, and drops the invalid ones.
+fn expand_declarations(declarations: Vec<Declaration>) -> Vec<Declaration> {
+    declarations
+        .into_iter()
+        .flat_map(shorthand::expand)
+        .filter(property::validate)
+        .collect()
+}
+
 def_parser! {
     fn declaration() -> Declaration {
         (
-            identifier(),
+            property_name(),
+            spaces(),
             char::char(':'),
             spaces(),
-            value()
-        ).map(|(name, _, _, value)| {
-            Declaration {
-                name,
-                value
-            }
-
+        ).then(|(mut name, _, _, _): (String, _, _, _)| {
+            // Property names are ASCII case-insensitive, but custom ones are not.
+            let value = if name.starts_with("--") {
+                token_stream().map(Value::Tokens).left()
+            } else {
+                name.make_ascii_lowercase();
+                declaration_value().right()
+            };
+            value.map(move |value| (name.clone(), value))
+        }).and(important()).map(|((name, value), important)| Declaration {
+            name,
+            value,
+            important,
         })
     }
 }
 
 def_parser! {
-    fn identifier() -> String {
-        (letter(), many(char::alpha_num())).map(|(x, mut xs): (char, String)| {
-            xs.insert(0, x);
-            xs
-        })
+    fn important() -> bool {
+        optional(attempt((spaces(), char::char('!'), spaces(), keyword_ignore_case("important"))))
+            .map(|important| important.is_some())
     }
 }
 
 def_parser! {
-    fn value() -> Value {
-        // starts with [a-z] => keyword
-        // starts with [0-9] => Length
-        // starts with [#] => ColorValue
-        keyword_string().map(Value::Keyword)
-            .or(length().map(|(n, px)| Value::Length(n, px)))
-            .or(color().map(Value::ColorValue))
+    fn declaration_value() -> Value {
+        // A value with var() can be parsed only after substitution.
+        attempt(token_stream().then(|tokens| {
+            if tokens.has_references() {
+                item::value(Value::WithVariables(tokens)).left()
+            } else {
+                unexpected_any("no var()").right()
+            }
+        })).or(value_list())
     }
 }
 
 def_parser! {
-    fn keyword_string() -> String {
-        (letter(), many(char::alpha_num().or(item::token('-')))).map(|(x, mut xs): (char, String)| {
-            xs.insert(0, x);
-            xs
-        })
+    fn property_name() -> String {
+        attempt(custom_property_name()).or(ident())
     }
 }
 
 def_parser! {
-    fn length() -> (f32, Unit) {
-        // Todo: Supprt floating point number.
-        (many1(digit()), char::string("px")).map(|(digits, _): (String, _)| {
-            (digits.parse().unwrap(), Unit::Px)
-        })
+    fn custom_property_name() -> String {
+        (char::string("--"), many(name_char())).map(|(_, name): (_, String)| format!("--{}", name))
     }
 }
 
+// https://drafts.csswg.org/css-syntax-3/#ident-token-diagram
 def_parser! {
-    fn color() -> Color {
-        (char::char('#'),
-         count(3, hex_pair())).map(|(_, rgb): (_, Vec<u8>)| {
-             Color {
-                 r: rgb[0],
-                 g: rgb[1],
-                 b: rgb[2],
-             }
-         })
+    fn ident() -> String {
+        attempt(custom_property_name()).or(
+            (optional(char::char('-')), name_start_char(), many(name_char()))
+                .map(|(hyphen, start, rest): (Option<char>, char, String)| {
+                    let mut s: String = hyphen.into_iter().collect();
+                    s.push(start);
+                    s.push_str(&rest);
+                    s
+                })
+        )
     }
 }
 
 def_parser! {
-    fn hex_pair() -> u8 {
-        count(2, char::hex_digit()).map(|hex: String| {
-            u8::from_str_radix(&hex, 16).unwrap()
-        })
+    fn name_start_char() -> char {
+        item::satisfy(|c: char| c.is_alphabetic() || c == '_' || !c.is_ascii())
     }
 }
 
-pub fn parse_stylesheet(sheet: &str) -> Result<Stylesheet> {
-    Ok(stylesheet()
-        .parse(sheet.trim())
-        .map_err(EngineError::from)?
-        .0)
+def_parser! {
+    fn name_char() -> char {
+        item::satisfy(|c: char| c.is_alphanumeric() || c == '_' || c == '-' || !c.is_ascii())
+    }
+}
+
+// Tokens up to the end of a declaration.
+def_parser! {
+    pub fn token_stream() -> TokenStream {
+        many(token()).map(TokenStream::new)
+    }
+}
+
+def_parser! {
+    fn token() -> Token {
+        choice((
+            skip_many1(char::space()).map(|_| Token::Whitespace),
+            attempt(numeric_token()),
+            (ident(), optional(char::char('('))).map(|(name, paren)| match paren {
+                Some(_) => Token::Function(name),
+                None => Token::Ident(name),
+            }),
+            attempt((char::char('#'), many1(name_char()))).map(|(_, name)| Token::Hash(name)),
+            string_token().map(Token::String),
+            char::char(',').map(|_| Token::Comma),
+            char::char('(').map(|_| Token::OpenParen),
+            char::char(')').map(|_| Token::CloseParen),
+            item::satisfy(|c| !";{}!".contains(c)).map(Token::Delim),
+        ))
+    }
+}
+
+def_parser! {
+    fn numeric_token() -> Token {
+        (
+            combinator::recognize::<String, _>(number()),
+            optional(char::string("%").map(|s| s.to_string()).or(ident())),
+        ).map(|(mut number, suffix): (String, Option<String>)| {
+            number.extend(suffix);
+            Token::Number(number)
+        })
+    }
+}
+
+def_parser! {
+    fn string_token() -> String {
+        between(char::char('"'), char::char('"'), many(item::satisfy(|c| c != '"')))
+            .or(between(char::char('\''), char::char('\''), many(item::satisfy(|c| c != '\''))))
+    }
+}
+
+def_parser! {
+    fn identifier() -> String {
+        (letter(), many(char::alpha_num())).map(|(x, mut xs): (char, String)| {
+            xs.insert(0, x);
+            xs
+        })
+    }
+}
+
+// Comma separated lists of space or slash separated components. A single
+// component is returned as is.
+def_parser! {
+    pub fn value_list() -> Value {
+        sep_by1(component_list(), attempt((spaces(), char::char(','), spaces())))
+            .map(|mut lists: Vec<Value>| {
+                if lists.len() == 1 {
+                    lists.pop().unwrap()
+                } else {
+                    Value::CommaList(lists)
+                }
+            })
+    }
+}
+
+def_parser! {
+    fn component_list() -> Value {
+        (
+            value(),
+            many(attempt((
+                attempt((spaces(), char::char('/'), spaces())).map(|_| Some(Value::Slash))
+                    .or(skip_many1(char::space()).map(|_| None)),
+                value(),
+            ))),
+        ).map(|(first, rest): (Value, Vec<(Option<Value>, Value)>)| {
+            if rest.is_empty() {
+                return first;
+            }
+            let mut values = vec![first];
+            for (slash, value) in rest {
+                values.extend(slash);
+                values.push(value);
+            }
+            Value::List(values)
+        })
+    }
+}
+
+def_parser! {
+    fn value() -> Value {
+        // starts with calc(, min(, max( or clamp( => Calc
+        // starts with [a-z] => keyword, or ColorValue for a named color
+        // starts with [0-9+-.] => Number, Percentage or Length
+        // starts with rgb(, rgba(, hsl(, hsla( or hwb( => ColorValue
+        // starts with [#] => ColorValue
+        // starts with ["'] => String
+        math_function().map(|node| Value::Calc(Box::new(node)))
+            .or(color_function().map(Value::ColorValue))
+            .or(easing_function().map(Value::Easing))
+            .or(counter_function().map(Value::Counter))
+            .or(keyword_string().map(|keyword| match css_color::named_color(&keyword) {
+                Some(color) => Value::ColorValue(color),
+                None => Value::Keyword(keyword),
+            }))
+            .or(numeric())
+            .or(hex_color().map(Value::ColorValue))
+            .or(string_token().map(Value::String))
+    }
+}
+
+def_parser! {
+    fn keyword_string() -> String {
+        (letter(), many(char::alpha_num().or(item::token('-')))).map(|(x, mut xs): (char, String)| {
+            xs.insert(0, x);
+            xs
+        })
+    }
+}
+
+def_parser! {
+    fn numeric() -> Value {
+        (
+            number(),
+            optional(
+                char::char('%').map(|_| Dimension::Percentage)
+                    .or(unit().map(Dimension::Length))
+                    .or(time_unit().map(Dimension::Time)),
+            ),
+        )
+            .skip(not_followed_by(letter()))
+            .map(|(n, suffix)| match suffix {
+                None => Value::Number(n),
+                Some(Dimension::Percentage) => Value::Percentage(n),
+                Some(Dimension::Length(unit)) => Value::Length(n, unit),
+                Some(Dimension::Time(seconds)) => Value::Time(n * seconds),
+            })
+    }
+}
+
+/// The suffix of a number.
+#[derive(Clone)]
+enum Dimension {
+    Percentage,
+    Length(Unit),
+    /// A time unit, in seconds.
+    Time(f32),
+}
+
+def_parser! {
+    fn time_unit() -> f32 {
+        keyword_ignore_case("ms").map(|_| 0.001).or(keyword_ignore_case("s").map(|_| 1.0))
+    }
+}
+
+// https://drafts.csswg.org/css-easing-1/#easing-functions
+def_parser! {
+    fn easing_function() -> EasingFunction {
+        (
+            function_start("cubic-bezier"),
+            sep_by1(number(), attempt((spaces(), char::char(','), spaces()))),
+            spaces(),
+            char::char(')'),
+        ).then(|(_, args, _, _): (_, Vec<f32>, _, _)| match args[..] {
+            // The x coordinates must be in [0, 1].
+            [x1, y1, x2, y2] if (0.0..=1.0).contains(&x1) && (0.0..=1.0).contains(&x2) => {
+                item::value(EasingFunction::CubicBezier(x1, y1, x2, y2)).left()
+            }
+            _ => unexpected_any("invalid cubic-bezier()").right(),
+        }).or((
+            function_start("steps"),
+            number(),
+            optional(attempt((spaces(), char::char(','), spaces())).with(ident())),
+            spaces(),
+            char::char(')'),
+        ).then(|(_, steps, position, _, _): (_, f32, Option<String>, _, _)| {
+            let position = match position {
+                Some(name) => StepPosition::from_name(&name),
+                None => Some(StepPosition::JumpEnd),
+            };
+            let min_steps = if position == Some(StepPosition::JumpNone) { 2.0 } else { 1.0 };
+            match position {
+                Some(position) if steps.fract() == 0.0 && steps >= min_steps => {
+                    item::value(EasingFunction::Steps(steps as u32, position)).left()
+                }
+                _ => unexpected_any("invalid steps()").right(),
+            }
+        }))
+    }
+}
+
+// https://drafts.csswg.org/css-lists-3/#counter-functions
+def_parser! {
+    fn counter_function() -> CounterFunction {
+        (
+            function_start("counters"),
+            ident(),
+            attempt((spaces(), char::char(','), spaces())),
+            string_token(),
+            optional(attempt((spaces(), char::char(','), spaces())).with(counter_style())),
+            spaces(),
+            char::char(')'),
+        ).map(|(_, name, _, separator, style, _, _)| CounterFunction {
+            name,
+            separator: Some(separator),
+            style: style.unwrap_or(CounterStyle::Decimal),
+        }).or((
+            function_start("counter"),
+            ident(),
+            optional(attempt((spaces(), char::char(','), spaces())).with(counter_style())),
+            spaces(),
+            char::char(')'),
+        ).map(|(_, name, style, _, _)| CounterFunction {
+            name,
+            separator: None,
+            style: style.unwrap_or(CounterStyle::Decimal),
+        }))
+    }
+}
+
+def_parser! {
+    fn counter_style() -> CounterStyle {
+        ident().then(|name| match CounterStyle::from_name(&name) {
+            Some(style) => item::value(style).left(),
+            None => unexpected_any("unknown counter style").right(),
+        })
+    }
+}
+
+def_parser! {
+    fn length() -> (f32, Unit) {
+        (number(), unit()).skip(not_followed_by(letter()))
+    }
+}
+
+def_parser! {
+    fn number() -> f32 {
+        // https://drafts.csswg.org/css-syntax-3/#consume-number
+        (
+            optional(item::one_of("+-".chars())),
+            many1(digit()).and(optional(attempt((char::char('.'), many1(digit())))))
+                .map(|(int, frac): (String, Option<(char, String)>)| match frac {
+                    Some((_, frac)) => format!("{}.{}", int, frac),
+                    None => int,
+                })
+                .or((char::char('.'), many1(digit())).map(|(_, frac): (_, String)| format!("0.{}", frac))),
+            optional(exponent()),
+        ).map(|(sign, digits, exponent): (Option<char>, String, Option<String>)| {
+            let mut s: String = sign.into_iter().collect();
+            s.push_str(&digits);
+            s.extend(exponent);
+            s.parse().unwrap()
+        })
+    }
+}
+
+def_parser! {
+    fn exponent() -> String {
+        attempt((
+            item::one_of("eE".chars()),
+            optional(item::one_of("+-".chars())),
+            many1(digit()),
+        )).map(|(_, sign, digits): (_, Option<char>, String)| {
+            let mut s = "e".to_string();
+            s.extend(sign);
+            s.push_str(&digits);
+            s
+        })
+    }
+}
+
+def_parser! {
+    fn unit() -> Unit {
+        // Try longer names first so that "vmin" is not read as "vm" + "in".
+        choice([
+            keyword_ignore_case("vmin"),
+            keyword_ignore_case("vmax"),
+            keyword_ignore_case("rem"),
+            keyword_ignore_case("px"),
+            keyword_ignore_case("pt"),
+            keyword_ignore_case("pc"),
+            keyword_ignore_case("cm"),
+            keyword_ignore_case("mm"),
+            keyword_ignore_case("in"),
+            keyword_ignore_case("em"),
+            keyword_ignore_case("ex"),
+            keyword_ignore_case("ch"),
+            keyword_ignore_case("vw"),
+            keyword_ignore_case("vh"),
+            keyword_ignore_case("q"),
+        ]).map(|name| Unit::from_name(name).unwrap())
+    }
+}
+
+// https://drafts.csswg.org/css-values-4/#calc-syntax
+def_parser! {
+    fn math_function() -> CalcNode {
+        math_function_untyped().then(|node| {
+            if node.calc_type().is_some() {
+                item::value(node).left()
+            } else {
+                unexpected_any("invalid math expression").right()
+            }
+        })
+    }
+}
+
+def_parser! {
+    fn math_function_untyped() -> CalcNode {
+        choice((
+            (function_start("calc"), calc_sum()).map(|(_, node)| node),
+            (function_start("min"), calc_args()).map(|(_, args)| CalcNode::Min(args)),
+            (function_start("max"), calc_args()).map(|(_, args)| CalcNode::Max(args)),
+            (function_start("clamp"), calc_args()).then(|(_, mut args): (_, Vec<CalcNode>)| {
+                if args.len() == 3 {
+                    let max = args.pop().unwrap();
+                    let value = args.pop().unwrap();
+                    let min = args.pop().unwrap();
+                    item::value(CalcNode::Clamp(min.into(), value.into(), max.into())).left()
+                } else {
+                    unexpected_any("clamp() takes three arguments").right()
+                }
+            }),
+        )).skip((spaces(), char::char(')')))
+    }
+}
+
+def_parser! {
+    fn calc_args() -> Vec<CalcNode> {
+        sep_by1(calc_sum(), attempt((spaces(), char::char(','), spaces())))
+    }
+}
+
+def_parser! {
+    fn calc_sum() -> CalcNode {
+        // White space is required on both sides of the + and - operators.
+        (
+            calc_product(),
+            many(attempt((
+                skip_many1(char::space()),
+                item::one_of("+-".chars()),
+                skip_many1(char::space()),
+                calc_product(),
+            ))),
+        ).map(|(first, rest): (CalcNode, Vec<(_, char, _, CalcNode)>)| {
+            rest.into_iter().fold(first, |lhs, (_, op, _, rhs)| match op {
+                '+' => CalcNode::Add(lhs.into(), rhs.into()),
+                _ => CalcNode::Sub(lhs.into(), rhs.into()),
+            })
+        })
+    }
+}
+
+def_parser! {
+    fn calc_product() -> CalcNode {
+        (
+            calc_value(),
+            many(attempt((
+                spaces(),
+                item::one_of("*/".chars()),
+                spaces(),
+                calc_value(),
+            ))),
+        ).map(|(first, rest): (CalcNode, Vec<(_, char, _, CalcNode)>)| {
+            rest.into_iter().fold(first, |lhs, (_, op, _, rhs)| match op {
+                '*' => CalcNode::Mul(lhs.into(), rhs.into()),
+                _ => CalcNode::Div(lhs.into(), rhs.into()),
+            })
+        })
+    }
+}
+
+def_parser! {
+    fn calc_value() -> CalcNode {
+        numeric().map(|value| CalcNode::from_value(value).unwrap())
+            .or(math_function_untyped())
+            .or((char::char('('), spaces(), calc_sum(), spaces(), char::char(')'))
+                .map(|(_, _, node, _, _)| node))
+    }
+}
+
+fn source_location(position: impl InputPosition) -> SourceLocation {
+    let (line, column) = position.line_column().unwrap_or((0, 0));
+    SourceLocation {
+        file: None,
+        line,
+        column,
+    }
+}
+
+/// Parses `name(` and the white space after it.
+fn function_start<I>(name: &'static str) -> impl Parser<Input = I, Output = &'static str>
+where
+    I: Stream<Item = char>,
+    I::Error: ParseError<I::Item, I::Range, I::Position>,
+{
+    attempt((keyword_ignore_case(name), char::char('(')))
+        .skip(spaces())
+        .map(move |_| name)
+}
+
+/// Parses `@name`.
+fn at_keyword<I>(name: &'static str) -> impl Parser<Input = I, Output = &'static str>
+where
+    I: Stream<Item = char>,
+    I::Position: InputPosition,
+    I::Error: ParseError<I::Item, I::Range, I::Position>,
+{
+    attempt((
+        char::char('@'),
+        keyword_ignore_case(name),
+        not_followed_by(name_char()),
+    ))
+    .map(move |_| name)
+}
+
+fn keyword_ignore_case<I>(s: &'static str) -> impl Parser<Input = I, Output = &'static str>
+where
+    I: Stream<Item = char>,
+    I::Error: ParseError<I::Item, I::Range, I::Position>,
+{
+    attempt(char::string_cmp(s, |l: char, r: char| {
+        l.eq_ignore_ascii_case(&r)
+    }))
+}
+
+// https://drafts.csswg.org/mediaqueries-4/#mq-syntax
+def_parser! {
+    pub fn media_query_list() -> MediaQueryList {
+        sep_by(media_query(), attempt((spaces(), char::char(','), spaces()))).map(MediaQueryList)
+    }
+}
+
+def_parser! {
+    fn media_query() -> MediaQuery {
+        attempt(media_condition()).map(|condition| MediaQuery {
+            qualifier: None,
+            media_type: None,
+            condition: Some(condition),
+        }).or((
+            optional(attempt(media_qualifier().skip(skip_many1(char::space())))),
+            ident(),
+            optional(attempt((
+                skip_many1(char::space()),
+                keyword_ignore_case("and"),
+                skip_many1(char::space()),
+                media_condition(),
+            )).map(|(_, _, _, condition)| condition)),
+        ).then(|(qualifier, media_type, condition)| match condition {
+            // <media-condition-without-or>
+            Some(MediaCondition::Or(_)) => unexpected_any("or after a media type").right(),
+            _ => item::value(MediaQuery {
+                qualifier,
+                media_type: Some(media_type),
+                condition,
+            }).left(),
+        }))
+    }
+}
+
+def_parser! {
+    fn media_qualifier() -> Qualifier {
+        keyword_ignore_case("not").map(|_| Qualifier::Not)
+            .or(keyword_ignore_case("only").map(|_| Qualifier::Only))
+    }
+}
+
+def_parser! {
+    fn media_condition() -> MediaCondition {
+        attempt((keyword_ignore_case("not"), skip_many1(char::space())))
+            .with(media_in_parens())
+            .map(|condition| MediaCondition::Not(Box::new(condition)))
+            .or((
+                media_in_parens(),
+                many(attempt((
+                    skip_many1(char::space()),
+                    keyword_ignore_case("and").or(keyword_ignore_case("or")),
+                    skip_many1(char::space()),
+                    media_in_parens(),
+                ))),
+            ).then(|(first, rest): (MediaCondition, Vec<(_, &str, _, MediaCondition)>)| {
+                let is_and = rest.first().map(|(_, op, _, _)| op.eq_ignore_ascii_case("and"));
+                // `and` and `or` can't be mixed without parentheses.
+                if rest.iter().any(|(_, op, _, _)| Some(op.eq_ignore_ascii_case("and")) != is_and) {
+                    return unexpected_any("mixed and and or").right();
+                }
+                let mut conditions = vec![first];
+                conditions.extend(rest.into_iter().map(|(_, _, _, condition)| condition));
+                item::value(match is_and {
+                    None => conditions.pop().unwrap(),
+                    Some(true) => MediaCondition::And(conditions),
+                    Some(false) => MediaCondition::Or(conditions),
+                }).left()
+            }))
+    }
+}
+
+def_parser! {
+    fn media_in_parens() -> MediaCondition {
+        between(
+            (char::char('('), spaces()),
+            (spaces(), char::char(')')),
+            attempt(media_feature().skip(look_ahead((spaces(), char::char(')')))))
+                .map(MediaCondition::Feature)
+                .or(media_condition()),
+        )
+    }
+}
+
+def_parser! {
+    fn media_feature() -> MediaFeature {
+        choice((
+            attempt((
+                media_value(),
+                spaces(),
+                media_comparison(),
+                spaces(),
+                ident(),
+                optional(attempt((spaces(), media_comparison(), spaces(), media_value()))),
+            )).then(|(value, _, comparison, _, name, upper): (_, _, Comparison, _, _, Option<(_, Comparison, _, _)>)| {
+                let mut comparisons = vec![(comparison.flip(), value)];
+                if let Some((_, upper, _, upper_value)) = upper {
+                    let is_lt = |c| c == Comparison::Lt || c == Comparison::Le;
+                    let is_gt = |c| c == Comparison::Gt || c == Comparison::Ge;
+                    if !(is_lt(comparison) && is_lt(upper) || is_gt(comparison) && is_gt(upper)) {
+                        return unexpected_any("invalid range").right();
+                    }
+                    comparisons.push((upper, upper_value));
+                }
+                item::value(MediaFeature::Range(name, comparisons)).left()
+            }),
+            attempt((ident(), spaces(), char::char(':'), spaces(), media_value()))
+                .map(|(name, _, _, _, value)| MediaFeature::Plain(name, value)),
+            attempt((ident(), spaces(), media_comparison(), spaces(), media_value()))
+                .map(|(name, _, comparison, _, value)| MediaFeature::Range(name, vec![(comparison, value)])),
+            ident().map(MediaFeature::Boolean),
+        ))
+    }
+}
+
+def_parser! {
+    fn media_comparison() -> Comparison {
+        choice([
+            attempt(char::string("<=")),
+            attempt(char::string(">=")),
+            attempt(char::string("<")),
+            attempt(char::string(">")),
+            attempt(char::string("=")),
+        ]).map(|symbol| match symbol {
+            "<=" => Comparison::Le,
+            ">=" => Comparison::Ge,
+            "<" => Comparison::Lt,
+            ">" => Comparison::Gt,
+            _ => Comparison::Eq,
+        })
+    }
+}
+
+def_parser! {
+    fn media_value() -> MediaValue {
+        choice((
+            attempt((number(), spaces(), char::char('/'), spaces(), number()))
+                .map(|(width, _, _, _, height)| MediaValue::Ratio(width, height)),
+            attempt((number(), optional(ident()))).then(|(n, suffix): (f32, Option<String>)| {
+                let value = match suffix {
+                    None => Some(MediaValue::Number(n)),
+                    Some(suffix) => match suffix.to_ascii_lowercase().as_str() {
+                        "dppx" | "x" => Some(MediaValue::Resolution(n)),
+                        "dpi" => Some(MediaValue::Resolution(n / 96.0)),
+                        "dpcm" => Some(MediaValue::Resolution(n * 2.54 / 96.0)),
+                        unit => Unit::from_name(unit).map(|unit| MediaValue::Length(n, unit)),
+                    },
+                };
+                match value {
+                    Some(value) => item::value(value).left(),
+                    None => unexpected_any("unknown unit").right(),
+                }
+            }),
+            ident().map(MediaValue::Ident),
+        ))
+    }
+}
+
+def_parser! {
+    fn color() -> Color {
+        hex_color().or(color_function())
+    }
+}
+
+def_parser! {
+    fn hex_color() -> Color {
+        (char::char('#'), many1(char::hex_digit())).then(|(_, digits): (_, String)| {
+            match css_color::hex_color(&digits) {
+                Some(color) => item::value(color).left(),
+                None => unexpected_any("invalid hex color").right(),
+            }
+        })
+    }
+}
+
+// https://drafts.csswg.org/css-color-4/#funcdef-rgb
+def_parser! {
+    fn color_function() -> Color {
+        (
+            choice((
+                function_start("rgba"),
+                function_start("rgb"),
+                function_start("hsla"),
+                function_start("hsl"),
+                function_start("hwb"),
+            )),
+            color_component(),
+            many(attempt((color_separator(), color_component()))),
+            spaces(),
+            char::char(')'),
+        ).then(|(name, first, rest, _, _): (&str, _, Vec<_>, _, _)| {
+            let (separators, mut args): (Vec<_>, Vec<_>) = rest.into_iter().unzip();
+            args.insert(0, first);
+            match css_color::color_function(name, &args, &separators) {
+                Some(color) => item::value(color).left(),
+                None => unexpected_any("invalid color function").right(),
+            }
+        })
+    }
+}
+
+def_parser! {
+    fn color_component() -> ColorComponent {
+        (
+            number(),
+            optional(
+                char::char('%').map(|_| None)
+                    .or(angle_unit().map(Some))
+            ),
+        ).map(|(n, suffix)| match suffix {
+            None => ColorComponent::Number(n),
+            Some(None) => ColorComponent::Percentage(n),
+            Some(Some(degrees_per_unit)) => ColorComponent::Angle(n * degrees_per_unit),
+        })
+    }
+}
+
+def_parser! {
+    fn angle_unit() -> f32 {
+        choice([
+            keyword_ignore_case("deg"),
+            keyword_ignore_case("grad"),
+            keyword_ignore_case("rad"),
+            keyword_ignore_case("turn"),
+        ]).map(|unit| match unit {
+            "deg" => 1.0,
+            "grad" => 360.0 / 400.0,
+            "rad" => 180.0 / std::f32::consts::PI,
+            _ => 360.0,
+        })
+    }
+}
+
+def_parser! {
+    fn color_separator() -> Separator {
+        attempt((spaces(), char::char(','), spaces())).map(|_| Separator::Comma)
+            .or(attempt((spaces(), char::char('/'), spaces())).map(|_| Separator::Slash))
+            .or(skip_many1(char::space()).map(|_| Separator::Space))
+    }
+}
+
+/// Parses a value which is obtained by `var()` substitution.
+pub fn parse_value(s: &str) -> Result<Value> {
+    Ok(value_list()
+        .skip(eof())
+        .parse(s.trim())
+        .map_err(EngineError::from)?
+        .0)
+}
+
+/// Parses a comma separated list of selectors, e.g. in `selector()` of
+/// `@supports`.
+pub fn parse_selectors(s: &str) -> Result<Vec<Selector>> {
+    if s.trim().is_empty() {
+        return Err(failure::err_msg("empty selector"));
+    }
+    Ok(selectors()
+        .skip(eof())
+        .parse(s.trim())
+        .map_err(EngineError::from)?
+        .0)
+}
+
+/// Parses `name: value`. A shorthand is not expanded.
+pub fn parse_declaration(s: &str) -> Result<Declaration> {
+    Ok(declaration()
+        .skip((spaces(), eof()))
+        .parse(s.trim())
+        .map_err(EngineError::from)?
+        .0)
+}
+
+pub fn parse_supports_condition(s: &str) -> Result<SupportsCondition> {
+    Ok(supports_condition()
+        .skip(eof())
+        .parse(s.trim())
+        .map_err(EngineError::from)?
+        .0)
+}
+
+pub fn parse_media_query_list(s: &str) -> Result<MediaQueryList> {
+    Ok(media_query_list()
+        .skip(eof())
+        .parse(s.trim())
+        .map_err(EngineError::from)?
+        .0)
+}
+
+/// The rules have their lines and columns in `sheet`.
+pub fn parse_stylesheet(sheet: &str) -> Result<Stylesheet> {
+    Ok(spaces()
+        .with(stylesheet())
+        .skip(eof())
+        .parse(State::new(sheet.trim_end()))
+        .map_err(EngineError::from)?
+        .0)
 }
 
 #[cfg(test)]
 mod test {
 
-    use super::*;
-    use crate::css;
-    use crate::css::parser;
-    use maplit::btreeset;
+    use super::*;
+    use crate::css;
+    use crate::css::parser;
+    use maplit::btreeset;
+
+    fn color((r, g, b): Rgb) -> Color {
+        Color::rgb(r, g, b)
+    }
+
+    #[test]
+    #[ignore]
+    fn stylesheet_test() {
+        assert_parse!(
+            parser::stylesheet(),
+            "div { color: #000000 }",
+            Stylesheet {
+                origin: Origin::Author,
+                rules: vec![CssRule::Style(Rule {
+                    selectors: SortedSelectors::new(vec![Selector::tag("div")]),
+                    declarations: vec![Declaration::color((0, 0, 0))],
+                    location: Default::default(),
+                })],
+            }
+        );
+    }
+
+    #[test]
+    fn error_recovery_test() {
+        let recovered = |dirty: &str, clean: &str| {
+            let stylesheet =
+                parse_stylesheet(dirty).unwrap_or_else(|error| panic!("{}: {}", dirty, error));
+            assert_eq!(stylesheet, parse_stylesheet(clean).unwrap(), "{}", dirty);
+        };
+        let p = "p { color: #000000 }";
+
+        // Invalid and unsupported declarations.
+        recovered("p { transform: rotate(45deg); color: #000000; width: }", p);
+        recovered("p { x: \"a;}\" (b; [c]); y: {z;} ; color: #000000 }", p);
+        recovered("p { ;; color: #000000;; }", p);
+        recovered(
+            "p { color: #000000; @page { } div { } x { y } }",
+            "p { color: #000000; div { } x { } }",
+        );
+        // Unsupported at-rules, with a block or without one.
+        recovered("@charset \"utf-8\"; p { color: #000000 }", p);
+        recovered("@page { margin: 1in } p { color: #000000 }", p);
+        recovered(
+            "@media print { @page :first { } p { color: #000000 } }",
+            "@media print { p { color: #000000 } }",
+        );
+        // Invalid preludes and stray tokens.
+        recovered("@media (width: 1foo) { p { } } p { color: #000000 }", p);
+        recovered("p!? { } p { color: #000000 }", p);
+        // A stray `}` starts a rule, which ends after the next block.
+        recovered("} div { } p { color: #000000 }", p);
+        recovered("p { color: #000000 } ;", p);
+
+        // A block which is not closed is an error.
+        assert!(parse_stylesheet("p { color: #000000").is_err());
+        assert!(parse_stylesheet("@media print { p { }").is_err());
+    }
+
+    #[test]
+    fn import_rule_test() {
+        let mut parser = parser::import_rule();
+        let import = |url: &str, layer, queries| ImportRule {
+            url: url.to_string(),
+            layer,
+            queries,
+            rules: vec![],
+        };
+        assert_parse!(
+            parser,
+            "@import \"a.css\";",
+            import("a.css", None, MediaQueryList(vec![]))
+        );
+        assert_parse!(
+            parser,
+            "@import url( 'a b.css' ) ;",
+            import("a b.css", None, MediaQueryList(vec![]))
+        );
+        assert_parse!(
+            parser,
+            "@import url(../a.css) layer print;",
+            import(
+                "../a.css",
+                Some(None),
+                MediaQueryList(vec![MediaQuery {
+                    qualifier: None,
+                    media_type: Some("print".to_string()),
+                    condition: None,
+                }])
+            )
+        );
+        assert_parse!(
+            parser,
+            "@import 'a.css' layer(base.reset);",
+            import(
+                "a.css",
+                Some(Some(vec!["base".to_string(), "reset".to_string()])),
+                MediaQueryList(vec![])
+            )
+        );
+        assert_parse!(
+            parser,
+            "@import 'a.css' layers;",
+            import(
+                "a.css",
+                None,
+                MediaQueryList(vec![MediaQuery {
+                    qualifier: None,
+                    media_type: Some("layers".to_string()),
+                    condition: None,
+                }])
+            )
+        );
+        assert_parse_fail!(parser, "@import a.css;");
+    }
+
+    #[test]
+    fn layer_rule_test() {
+        let mut parser = parser::layer_rule();
+        let name = |s: &str| s.split('.').map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_parse!(
+            parser,
+            "@layer reset, framework.base;",
+            CssRule::LayerStatement(vec![name("reset"), name("framework.base")])
+        );
+        assert_parse!(
+            parser,
+            "@layer a { @layer { } div { color: #000000 } }",
+            CssRule::Layer(LayerRule {
+                name: Some(name("a")),
+                rules: vec![
+                    CssRule::Layer(LayerRule {
+                        name: None,
+                        rules: vec![],
+                    }),
+                    CssRule::Style(Rule {
+                        selectors: SortedSelectors::new(vec![Selector::tag("div")]),
+                        declarations: vec![Declaration::color((0, 0, 0))],
+                        location: Default::default(),
+                    }),
+                ],
+            })
+        );
+        assert_parse_fail!(parser, "@layer;");
+        assert_parse_fail!(parser, "@layer a, b { }");
+    }
+
+    #[test]
+    fn supports_rule_test() {
+        let declaration = |name: &str, value: &str| {
+            SupportsCondition::Declaration(name.to_string(), value.to_string())
+        };
+        assert_parse!(
+            parser::supports_rule(),
+            "@supports not (display: grid) { div { color: #000000 } }",
+            SupportsRule {
+                condition: SupportsCondition::Not(Box::new(declaration("display", "grid"))),
+                rules: vec![CssRule::Style(Rule {
+                    selectors: SortedSelectors::new(vec![Selector::tag("div")]),
+                    declarations: vec![Declaration::color((0, 0, 0))],
+                    location: Default::default(),
+                })],
+            }
+        );
+        assert_parse!(
+            parser::supports_condition(),
+            "(width: calc(1px + (2px)) ) or selector( div ) or ((x:y) and f(a))",
+            SupportsCondition::Or(vec![
+                declaration("width", "calc(1px + (2px))"),
+                SupportsCondition::Selector("div".to_string()),
+                SupportsCondition::And(vec![
+                    declaration("x", "y"),
+                    SupportsCondition::General("f(a)".to_string()),
+                ]),
+            ])
+        );
+        assert_parse_fail!(parser::supports_condition(), "(a: b) and (c: d) or (e: f)");
 
-    fn color((r, g, b): Rgb) -> Color {
-        Color {
-            r,
-            g,
-            b,
-            // a: 0,
+        // Conditions which serval doesn't support are false, and the rules
+        // in them are parsed as any other.
+        let stylesheet = parse_stylesheet(
+            "@supports (unknown-prop: x) or (transform: rotate(45deg)) or selector(a#b#c) { \
+                 p { transform: rotate(45deg); color: #000000 } \
+             }",
+        )
+        .unwrap();
+        match &stylesheet.rules[..] {
+            [CssRule::Supports(rule)] => {
+                assert!(!rule.condition.matches());
+                assert_eq!(rule.rules.len(), 1);
+            }
+            rules => panic!("{:?}", rules),
         }
     }
 
     #[test]
-    #[ignore]
-    fn stylesheet_test() {
+    fn container_rule_test() {
+        let width = |comparison, n| {
+            MediaCondition::Feature(MediaFeature::Range(
+                "width".to_string(),
+                vec![(comparison, MediaValue::Length(n, Unit::Px))],
+            ))
+        };
         assert_parse!(
-            parser::stylesheet(),
-            "div { color: #000000 }",
-            Stylesheet {
-                rules: vec![Rule {
+            parser::container_rule(),
+            "@container card (width > 400px) { div { color: #000000 } }",
+            ContainerRule {
+                name: Some("card".to_string()),
+                condition: width(Comparison::Gt, 400.0),
+                rules: vec![CssRule::Style(Rule {
                     selectors: SortedSelectors::new(vec![Selector::tag("div")]),
                     declarations: vec![Declaration::color((0, 0, 0))],
+                    location: Default::default(),
+                })],
+            }
+        );
+        // `not` is a part of the condition, not a name.
+        assert_parse!(
+            parser::container_rule(),
+            "@container not (width < 1px){}",
+            ContainerRule {
+                name: None,
+                condition: MediaCondition::Not(Box::new(width(Comparison::Lt, 1.0))),
+                rules: vec![],
+            }
+        );
+        assert_parse_fail!(parser::container_rule(), "@container card { }");
+        assert_parse_fail!(parser::container_rule(), "@container none (width) { }");
+    }
+
+    #[test]
+    fn keyframes_rule_test() {
+        assert_parse!(
+            parser::keyframes_rule(),
+            "@keyframes slide { from, 50% { color: #000000 } \
+             100% { color: #000000 !important; margin: 0 } }",
+            KeyframesRule {
+                name: "slide".to_string(),
+                keyframes: vec![
+                    Keyframe {
+                        offsets: vec![0.0, 50.0],
+                        declarations: vec![Declaration::color((0, 0, 0))],
+                    },
+                    Keyframe {
+                        offsets: vec![100.0],
+                        declarations: ["top", "right", "bottom", "left"]
+                            .iter()
+                            .map(|side| Declaration {
+                                name: format!("margin-{}", side),
+                                value: Value::Number(0.0),
+                                important: false,
+                            })
+                            .collect(),
+                    },
+                ],
+            }
+        );
+        assert_parse!(
+            parser::keyframes_rule(),
+            "@keyframes \"a b\" { }",
+            KeyframesRule {
+                name: "a b".to_string(),
+                keyframes: vec![],
+            }
+        );
+        assert_parse_fail!(parser::keyframes_rule(), "@keyframes a { 101% { } }");
+    }
+
+    #[test]
+    fn font_face_rule_test() {
+        assert_parse!(
+            parser::font_face_rule(),
+            "@font-face { font-family: Open  Sans; \
+             src: local('Open Sans'), url(fonts/a.ttf) format(\"truetype\"), url(a.woff2); \
+             font-weight: 700 100; font-style: Italic; unicode-range: U+26, u+0-7f, U+4??; }",
+            FontFaceRule {
+                family: "Open Sans".to_string(),
+                sources: vec![
+                    FontSource::Local("Open Sans".to_string()),
+                    FontSource::Url {
+                        url: "fonts/a.ttf".to_string(),
+                        format: Some("truetype".to_string()),
+                    },
+                    FontSource::Url {
+                        url: "a.woff2".to_string(),
+                        format: None,
+                    },
+                ],
+                weight: Some((100.0, 700.0)),
+                style: Some(FontStyle::Italic),
+                unicode_range: vec![(0x26, 0x26), (0, 0x7f), (0x400, 0x4ff)],
+            }
+        );
+        // Invalid descriptors are ignored.
+        assert_parse!(
+            parser::font_face_rule(),
+            "@font-face { font-family: \"x\"; src: url(x.otf); font-weight: 0; \
+             font-style: slanted; unicode-range: U+1?2; color: red }",
+            FontFaceRule {
+                family: "x".to_string(),
+                sources: vec![FontSource::Url {
+                    url: "x.otf".to_string(),
+                    format: None,
                 }],
+                weight: None,
+                style: None,
+                unicode_range: vec![],
+            }
+        );
+        assert_parse_fail!(parser::font_face_rule(), "@font-face { font-family: x }");
+        assert_parse_fail!(parser::font_face_rule(), "@font-face { src: url(x.ttf) }");
+    }
+
+    #[test]
+    fn media_rule_test() {
+        let div = || Rule {
+            selectors: SortedSelectors::new(vec![Selector::tag("div")]),
+            declarations: vec![Declaration::color((0, 0, 0))],
+            location: Default::default(),
+        };
+        assert_parse!(
+            parser::media_rule(),
+            "@media screen and (min-width: 400px) { div { color: #000000 } }",
+            MediaRule {
+                queries: MediaQueryList(vec![MediaQuery {
+                    qualifier: None,
+                    media_type: Some("screen".to_string()),
+                    condition: Some(MediaCondition::Feature(MediaFeature::Plain(
+                        "min-width".to_string(),
+                        MediaValue::Length(400.0, Unit::Px),
+                    ))),
+                }]),
+                rules: vec![CssRule::Style(div())],
+            }
+        );
+        assert_parse!(
+            parser::media_rule(),
+            "@MEDIA{@media print{div { color: #000000 }}}",
+            MediaRule {
+                queries: MediaQueryList(vec![]),
+                rules: vec![CssRule::Media(MediaRule {
+                    queries: MediaQueryList(vec![MediaQuery {
+                        qualifier: None,
+                        media_type: Some("print".to_string()),
+                        condition: None,
+                    }]),
+                    rules: vec![CssRule::Style(div())],
+                })],
             }
         );
     }
 
+    #[test]
+    fn media_query_list_test() {
+        let mut parser = parser::media_query_list();
+        let feature = |f| MediaCondition::Feature(f);
+        let range =
+            |name: &str, comparisons| feature(MediaFeature::Range(name.to_string(), comparisons));
+        let condition = |c| {
+            MediaQueryList(vec![MediaQuery {
+                qualifier: None,
+                media_type: None,
+                condition: Some(c),
+            }])
+        };
+        let px = |n| MediaValue::Length(n, Unit::Px);
+
+        assert_parse!(
+            parser,
+            "(400px <= width <= 800px)",
+            condition(range(
+                "width",
+                vec![(Comparison::Ge, px(400.0)), (Comparison::Le, px(800.0))]
+            ))
+        );
+        assert_parse!(
+            parser,
+            "(width > 10em)",
+            condition(range(
+                "width",
+                vec![(Comparison::Gt, MediaValue::Length(10.0, Unit::Em))]
+            ))
+        );
+        assert_parse!(
+            parser,
+            "(16/9 = aspect-ratio)",
+            condition(range(
+                "aspect-ratio",
+                vec![(Comparison::Eq, MediaValue::Ratio(16.0, 9.0))]
+            ))
+        );
+        assert_parse!(
+            parser,
+            "not ((color) or (resolution: 192dpi))",
+            condition(MediaCondition::Not(Box::new(MediaCondition::Or(vec![
+                feature(MediaFeature::Boolean("color".to_string())),
+                feature(MediaFeature::Plain(
+                    "resolution".to_string(),
+                    MediaValue::Resolution(2.0)
+                )),
+            ]))))
+        );
+        assert_parse!(
+            parser,
+            "not print, only screen and (orientation: landscape) and (hover)",
+            MediaQueryList(vec![
+                MediaQuery {
+                    qualifier: Some(Qualifier::Not),
+                    media_type: Some("print".to_string()),
+                    condition: None,
+                },
+                MediaQuery {
+                    qualifier: Some(Qualifier::Only),
+                    media_type: Some("screen".to_string()),
+                    condition: Some(MediaCondition::And(vec![
+                        feature(MediaFeature::Plain(
+                            "orientation".to_string(),
+                            MediaValue::Ident("landscape".to_string())
+                        )),
+                        feature(MediaFeature::Boolean("hover".to_string())),
+                    ])),
+                },
+            ])
+        );
+        assert!(parse_media_query_list("(a) and (b) or (c)").is_err());
+        assert!(parse_media_query_list("screen and (a) or (b)").is_err());
+        assert!(parse_media_query_list("(1px < width > 2px)").is_err());
+        assert!(parse_media_query_list("(width: 1foo)").is_err());
+    }
+
     #[test]
     fn rule_test() {
         assert_parse!(
             parser::rule(),
             "div { color: #000000 }",
-            Rule {
+            vec![CssRule::Style(Rule {
                 selectors: SortedSelectors::new(vec![Selector::tag("div")]),
                 declarations: vec![Declaration::color((0, 0, 0))],
-            }
+                location: Default::default(),
+            })]
         );
         assert_parse!(
             parser::rule(),
             "* { display: block }",
-            Rule {
+            vec![CssRule::Style(Rule {
                 selectors: SortedSelectors::new(vec![Selector::Simple(
                     css::SimpleSelector::universal(),
                 )]),
                 declarations: vec![Declaration {
                     name: "display".to_string(),
                     value: css::Value::Keyword("block".to_string()),
+                    important: false,
                 }],
-            }
+                location: Default::default(),
+            })]
         );
         assert_parse!(
             parser::rule(),
             "div, p { color: #000000; color: #010203 }",
-            Rule {
+            vec![CssRule::Style(Rule {
                 selectors: SortedSelectors::new(vec![Selector::tag("div"), Selector::tag("p")]),
                 declarations: vec![Declaration::color((0, 0, 0)), Declaration::color((1, 2, 3))],
-            }
+                location: Default::default(),
+            })]
         );
         assert_parse!(
             parser::rule(),
             "p, div { color: #000000; color: #010203 }",
-            Rule {
+            vec![CssRule::Style(Rule {
                 selectors: SortedSelectors::new(vec![Selector::tag("p"), Selector::tag("div")]),
                 declarations: vec![Declaration::color((0, 0, 0)), Declaration::color((1, 2, 3))],
-            }
+                location: Default::default(),
+            })]
         );
         assert_parse!(
             parser::rule(),
             "p, #foo { color: #000000; color: #010203 }",
-            Rule {
-                selectors: SortedSelectors::new(vec![Selector::id("foo"), Selector::tag("p")]),
+            vec![CssRule::Style(Rule {
+                selectors: SortedSelectors::new(vec![Selector::tag("p"), Selector::id("foo")]),
                 declarations: vec![Declaration::color((0, 0, 0)), Declaration::color((1, 2, 3))],
-            }
+                location: Default::default(),
+            })]
         );
     }
 
@@ -306,6 +2150,28 @@ mod test {
     #[test]
     fn selector_test() {
         assert_parse!(parser::selector(), "div", Selector::tag("div"));
+        assert_parse!(
+            parser::selector(),
+            "div  .a>#b",
+            Selector::Complex(
+                Box::new(Selector::Complex(
+                    Box::new(Selector::tag("div")),
+                    Combinator::Descendant,
+                    SimpleSelector::class(&["a"]),
+                )),
+                Combinator::Child,
+                SimpleSelector::id("b"),
+            )
+        );
+        assert_parse!(
+            parser::selector(),
+            "&:is(p, .a)",
+            Selector::Simple(SimpleSelector {
+                is: vec![vec![Selector::tag("p"), Selector::class(&["a"])]],
+                nesting: true,
+                ..Default::default()
+            })
+        );
     }
 
     #[test]
@@ -341,8 +2207,27 @@ mod test {
                 tag_name: Some("div".to_string()),
                 id: Some("foo".to_string()),
                 classes: btreeset! { "class1".to_string(), "class2".to_string() },
+                ..Default::default()
             }
         );
+
+        // A compound selector has at most one tag name and one id.
+        assert_parse_fail!(parser::simple_selector(), "a#b#c");
+        assert_parse_fail!(parser::simple_selector(), "div&p");
+        assert!(parse_selectors("a#b#c, p").is_err());
+    }
+
+    #[test]
+    fn pseudo_element_test() {
+        let marker = |tag_name: Option<&str>| SimpleSelector {
+            tag_name: tag_name.map(|s| s.to_string()),
+            pseudo_element: Some(PseudoElement::Marker),
+            ..Default::default()
+        };
+        assert_parse!(parser::simple_selector(), "li::marker", marker(Some("li")));
+        assert_parse!(parser::simple_selector(), "::MARKER", marker(None));
+        assert_parse_fail!(parser::selector(), "li::marker.a");
+        assert_parse_fail!(parser::selector(), "li::before");
     }
 
     #[test]
@@ -381,16 +2266,111 @@ mod test {
         assert_parse_fail!(parser, "-");
     }
 
+    #[test]
+    fn number_test() {
+        let mut parser = parser::number();
+        assert_parse!(parser, "1", 1.0);
+        assert_parse!(parser, "+1", 1.0);
+        assert_parse!(parser, "-1", -1.0);
+        assert_parse!(parser, "1.5", 1.5);
+        assert_parse!(parser, "-0.5", -0.5);
+        assert_parse!(parser, ".5", 0.5);
+        assert_parse!(parser, "1e2", 100.0);
+        assert_parse!(parser, "1.5E-1", 0.15);
+        assert_parse!(parser, "1.", 1.0, ".");
+        assert_parse!(parser, "1em", 1.0, "em");
+        assert_parse_fail!(parser, "a");
+        assert_parse_fail!(parser, "-");
+    }
+
     #[test]
     fn length_test() {
         let mut parser = parser::length();
         assert_parse!(parser, "1px", (1.0, Unit::Px));
         assert_parse!(parser, "123px", (123.0, Unit::Px));
-        // TODO: Support minus value.
-        // assert_eq!(parser.parse("-123px"), Ok(((-123, Unit::Px), "")));
+        assert_parse!(parser, "-123px", (-123.0, Unit::Px));
+        assert_parse!(parser, "1.5em", (1.5, Unit::Em));
+        assert_parse!(parser, "2rem", (2.0, Unit::Rem));
+        assert_parse!(parser, "1ex", (1.0, Unit::Ex));
+        assert_parse!(parser, "1ch", (1.0, Unit::Ch));
+        assert_parse!(parser, "10vw", (10.0, Unit::Vw));
+        assert_parse!(parser, "10vh", (10.0, Unit::Vh));
+        assert_parse!(parser, "10vmin", (10.0, Unit::Vmin));
+        assert_parse!(parser, "10vmax", (10.0, Unit::Vmax));
+        assert_parse!(parser, "12pt", (12.0, Unit::Pt));
+        assert_parse!(parser, "1pc", (1.0, Unit::Pc));
+        assert_parse!(parser, "1cm", (1.0, Unit::Cm));
+        assert_parse!(parser, "1mm", (1.0, Unit::Mm));
+        assert_parse!(parser, "1in", (1.0, Unit::In));
+        assert_parse!(parser, "4Q", (4.0, Unit::Q));
+        assert_parse!(parser, "1PX", (1.0, Unit::Px));
         assert_parse_fail!(parser, "1");
         assert_parse_fail!(parser, "apx");
         assert_parse_fail!(parser, "1pz");
+        assert_parse_fail!(parser, "1pxx");
+    }
+
+    #[test]
+    fn numeric_test() {
+        let mut parser = parser::numeric();
+        assert_parse!(parser, "1", Value::Number(1.0));
+        assert_parse!(parser, "-1.5", Value::Number(-1.5));
+        assert_parse!(parser, "50%", Value::Percentage(50.0));
+        assert_parse!(parser, "-2.5%", Value::Percentage(-2.5));
+        assert_parse!(parser, "1em", Value::Length(1.0, Unit::Em));
+        assert_parse!(parser, "1.5s", Value::Time(1.5));
+        assert_parse!(parser, "250MS", Value::Time(0.25));
+        assert_parse_fail!(parser, "1pz");
+        assert_parse_fail!(parser, "1sec");
+    }
+
+    #[test]
+    fn easing_function_test() {
+        let mut parser = parser::value();
+        assert_parse!(
+            parser,
+            "cubic-bezier(0.1, -0.6, 0.2, 1.5)",
+            Value::Easing(EasingFunction::CubicBezier(0.1, -0.6, 0.2, 1.5))
+        );
+        assert_parse!(
+            parser,
+            "steps( 3 )",
+            Value::Easing(EasingFunction::Steps(3, StepPosition::JumpEnd))
+        );
+        assert_parse!(
+            parser,
+            "steps(2, jump-none)",
+            Value::Easing(EasingFunction::Steps(2, StepPosition::JumpNone))
+        );
+        assert_parse_fail!(parser, "cubic-bezier(2, 0, 0, 1)");
+        assert_parse_fail!(parser, "cubic-bezier(0, 0, 1)");
+        assert_parse_fail!(parser, "steps(1, jump-none)");
+        assert_parse_fail!(parser, "steps(1.5)");
+    }
+
+    #[test]
+    fn counter_function_test() {
+        let mut parser = parser::value();
+        assert_parse!(
+            parser,
+            "counter(list-item)",
+            Value::Counter(CounterFunction {
+                name: "list-item".to_string(),
+                separator: None,
+                style: CounterStyle::Decimal,
+            })
+        );
+        assert_parse!(
+            parser,
+            "counters( a , \".\" , upper-roman )",
+            Value::Counter(CounterFunction {
+                name: "a".to_string(),
+                separator: Some(".".to_string()),
+                style: CounterStyle::UpperRoman,
+            })
+        );
+        assert_parse_fail!(parser, "counter(a, b)");
+        assert_parse_fail!(parser, "counters(a)");
     }
 
     #[test]
@@ -398,40 +2378,394 @@ mod test {
         let mut parser = parser::value();
         assert_parse!(parser, "div", Value::Keyword("div".to_string()));
         assert_parse!(parser, "1px", Value::Length(1.0, Unit::Px));
+        assert_parse!(parser, "-1px", Value::Length(-1.0, Unit::Px));
+        assert_parse!(parser, "0", Value::Number(0.0));
+        assert_parse!(parser, "10%", Value::Percentage(10.0));
+        assert_parse!(
+            parser,
+            "calc(10% + 1px)",
+            Value::Calc(Box::new(CalcNode::Add(
+                Box::new(CalcNode::Percentage(10.0)),
+                Box::new(CalcNode::Length(1.0, Unit::Px))
+            )))
+        );
+        assert_parse!(parser, "calculate", Value::Keyword("calculate".to_string()));
         assert_parse!(parser, "#000000", Value::ColorValue(color((0, 0, 0))));
+        assert_parse!(parser, "red", Value::ColorValue(color((255, 0, 0))));
+        assert_parse!(parser, "Transparent", Value::ColorValue(Color::TRANSPARENT));
+        assert_parse!(parser, "rgb(0 0 0)", Value::ColorValue(color((0, 0, 0))));
+        assert_parse!(
+            parser,
+            "currentcolor",
+            Value::Keyword("currentcolor".to_string())
+        );
+        assert_parse!(parser, "'a b'", Value::String("a b".to_string()));
+    }
+
+    #[test]
+    fn value_list_test() {
+        let mut parser = parser::value_list();
+        let px = |n| Value::Length(n, Unit::Px);
+        let keyword = |s: &str| Value::Keyword(s.to_string());
+        assert_parse!(parser, "1px", px(1.0));
+        assert_parse!(
+            parser,
+            "0 4px",
+            Value::List(vec![Value::Number(0.0), px(4.0)])
+        );
+        assert_parse!(
+            parser,
+            "1px  solid #000",
+            Value::List(vec![px(1.0), keyword("solid"), Value::color((0, 0, 0))])
+        );
+        assert_parse!(
+            parser,
+            "12px/1.5 a b, \"c\"",
+            Value::CommaList(vec![
+                Value::List(vec![
+                    px(12.0),
+                    Value::Slash,
+                    Value::Number(1.5),
+                    keyword("a"),
+                    keyword("b"),
+                ]),
+                Value::String("c".to_string()),
+            ])
+        );
+        assert_parse!(
+            parser,
+            "center / cover",
+            Value::List(vec![keyword("center"), Value::Slash, keyword("cover")])
+        );
+    }
+
+    #[test]
+    fn math_function_test() {
+        fn px(n: f32) -> Box<CalcNode> {
+            Box::new(CalcNode::Length(n, Unit::Px))
+        }
+        fn percent(n: f32) -> Box<CalcNode> {
+            Box::new(CalcNode::Percentage(n))
+        }
+        fn number(n: f32) -> Box<CalcNode> {
+            Box::new(CalcNode::Number(n))
+        }
+
+        let mut parser = parser::math_function();
+        assert_parse!(parser, "calc(1px)", *px(1.0));
+        assert_parse!(parser, "calc( 1px )", *px(1.0));
+        assert_parse!(
+            parser,
+            "calc(50% - 10px)",
+            CalcNode::Sub(percent(50.0), px(10.0))
+        );
+        assert_parse!(
+            parser,
+            "calc(1px + 2px * 3)",
+            CalcNode::Add(px(1.0), Box::new(CalcNode::Mul(px(2.0), number(3.0))))
+        );
+        assert_parse!(
+            parser,
+            "calc((1px + 2px)*3)",
+            CalcNode::Mul(Box::new(CalcNode::Add(px(1.0), px(2.0))), number(3.0))
+        );
+        assert_parse!(
+            parser,
+            "calc(1px - 2px - 3px)",
+            CalcNode::Sub(Box::new(CalcNode::Sub(px(1.0), px(2.0))), px(3.0))
+        );
+        assert_parse!(
+            parser,
+            "calc(100% / 3 - 2 * 1em)",
+            CalcNode::Sub(
+                Box::new(CalcNode::Div(percent(100.0), number(3.0))),
+                Box::new(CalcNode::Mul(
+                    number(2.0),
+                    Box::new(CalcNode::Length(1.0, Unit::Em))
+                ))
+            )
+        );
+        assert_parse!(
+            parser,
+            "min(10px, 5%)",
+            CalcNode::Min(vec![*px(10.0), *percent(5.0)])
+        );
+        assert_parse!(
+            parser,
+            "max(10px, calc(5% + 1px))",
+            CalcNode::Max(vec![*px(10.0), CalcNode::Add(percent(5.0), px(1.0))])
+        );
+        assert_parse!(
+            parser,
+            "clamp(1px, 50%, 100px)",
+            CalcNode::Clamp(px(1.0), percent(50.0), px(100.0))
+        );
+        assert_parse!(
+            parser,
+            "CALC(min(1px, 2px) + 1px)",
+            CalcNode::Add(Box::new(CalcNode::Min(vec![*px(1.0), *px(2.0)])), px(1.0))
+        );
+
+        // Type errors
+        assert_parse_fail!(parser, "calc(1px + 1)");
+        assert_parse_fail!(parser, "calc(1px * 1px)");
+        assert_parse_fail!(parser, "calc(1 / 1px)");
+        assert_parse_fail!(parser, "min(1px, 1)");
+        // Syntax errors
+        assert_parse_fail!(parser, "calc(1px+1px)");
+        assert_parse_fail!(parser, "calc()");
+        assert_parse_fail!(parser, "clamp(1px, 2px)");
+        assert_parse_fail!(parser, "calc(1px");
+    }
+
+    #[test]
+    fn token_stream_test() {
+        let mut parser = parser::token_stream();
+        assert_parse!(
+            parser,
+            " 1px  solid var(--color, #fff) ",
+            TokenStream::new(vec![
+                Token::Number("1px".to_string()),
+                Token::Whitespace,
+                Token::Ident("solid".to_string()),
+                Token::Whitespace,
+                Token::Function("var".to_string()),
+                Token::Ident("--color".to_string()),
+                Token::Comma,
+                Token::Whitespace,
+                Token::Hash("fff".to_string()),
+                Token::CloseParen,
+            ])
+        );
+        assert_parse!(
+            parser,
+            "-1.5em 50% 'a;b' / +2;",
+            TokenStream::new(vec![
+                Token::Number("-1.5em".to_string()),
+                Token::Whitespace,
+                Token::Number("50%".to_string()),
+                Token::Whitespace,
+                Token::String("a;b".to_string()),
+                Token::Whitespace,
+                Token::Delim('/'),
+                Token::Whitespace,
+                Token::Number("+2".to_string()),
+            ]),
+            ";"
+        );
+        assert_parse!(parser, "}", TokenStream::default(), "}");
+    }
+
+    #[test]
+    fn property_name_test() {
+        let mut parser = parser::property_name();
+        assert_parse!(parser, "color", "color".to_string());
+        assert_parse!(parser, "margin-left", "margin-left".to_string());
+        assert_parse!(parser, "-webkit-box", "-webkit-box".to_string());
+        assert_parse!(parser, "--x", "--x".to_string());
+        assert_parse!(parser, "--main-color2", "--main-color2".to_string());
+        assert_parse_fail!(parser, "1a");
+    }
+
+    #[test]
+    fn custom_property_declaration_test() {
+        let mut parser = parser::declaration();
+        assert_parse!(
+            parser,
+            "--x: 1px solid",
+            Declaration {
+                name: "--x".to_string(),
+                value: Value::Tokens(TokenStream::new(vec![
+                    Token::Number("1px".to_string()),
+                    Token::Whitespace,
+                    Token::Ident("solid".to_string()),
+                ])),
+                important: false,
+            }
+        );
+        assert_parse!(
+            parser,
+            "--empty:",
+            Declaration {
+                name: "--empty".to_string(),
+                value: Value::Tokens(TokenStream::default()),
+                important: false,
+            }
+        );
+        assert_parse!(
+            parser,
+            "margin-left: calc(var(--x) * 2)",
+            Declaration {
+                name: "margin-left".to_string(),
+                value: Value::WithVariables(TokenStream::new(vec![
+                    Token::Function("calc".to_string()),
+                    Token::Function("var".to_string()),
+                    Token::Ident("--x".to_string()),
+                    Token::CloseParen,
+                    Token::Whitespace,
+                    Token::Delim('*'),
+                    Token::Whitespace,
+                    Token::Number("2".to_string()),
+                    Token::CloseParen,
+                ])),
+                important: false,
+            }
+        );
+        assert_parse!(
+            parser,
+            "margin-left: 1px",
+            Declaration {
+                name: "margin-left".to_string(),
+                value: Value::Length(1.0, Unit::Px),
+                important: false,
+            }
+        );
     }
 
     #[test]
     fn declarations_test() {
         let mut parser = parser::declarations();
-        assert_parse!(parser, "color: #00000", vec![Declaration::color((0, 0, 0))]);
         assert_parse!(
             parser,
-            "color: #00000; color: #00000",
+            "color: #000000",
+            vec![Declaration::color((0, 0, 0))]
+        );
+        assert_parse!(
+            parser,
+            "color: #000000; color: #000000",
             vec![Declaration::color((0, 0, 0)), Declaration::color((0, 0, 0))]
         );
+        assert_parse!(
+            parser,
+            "color: #000000; ",
+            vec![Declaration::color((0, 0, 0))]
+        );
+        assert_parse!(
+            parser,
+            "color: #000000 ; padding: 0 4px",
+            vec![
+                Declaration::color((0, 0, 0)),
+                Declaration {
+                    name: "padding-top".to_string(),
+                    value: Value::Number(0.0),
+                    important: false,
+                },
+                Declaration {
+                    name: "padding-right".to_string(),
+                    value: Value::Length(4.0, Unit::Px),
+                    important: false,
+                },
+                Declaration {
+                    name: "padding-bottom".to_string(),
+                    value: Value::Number(0.0),
+                    important: false,
+                },
+                Declaration {
+                    name: "padding-left".to_string(),
+                    value: Value::Length(4.0, Unit::Px),
+                    important: false,
+                },
+            ]
+        );
+        // An invalid shorthand is dropped.
+        assert_parse!(
+            parser,
+            "margin: red; color: #000000",
+            vec![Declaration::color((0, 0, 0))]
+        );
+        // A shorthand is expanded whatever the case of its name.
+        assert_parse!(
+            parser,
+            "MARGIN: 0",
+            ["top", "right", "bottom", "left"]
+                .iter()
+                .map(|side| Declaration {
+                    name: format!("margin-{}", side),
+                    value: Value::Number(0.0),
+                    important: false,
+                })
+                .collect::<Vec<_>>()
+        );
     }
 
     #[test]
     fn declaration_test() {
         assert_parse!(
             parser::declaration(),
-            "color: #00000",
+            "color: #000000",
             Declaration::color((0, 0, 0))
         );
-    }
-
-    #[test]
-    fn hex_pair_test() {
-        assert_eq!(u8::from_str_radix("00", 16).unwrap(), 0);
-        assert_eq!(u8::from_str_radix("ff", 16).unwrap(), 255);
-        assert_parse!(parser::hex_pair(), "00", 0);
-        assert_parse!(parser::hex_pair(), "ff", 255);
+        let important = Declaration {
+            important: true,
+            ..Declaration::color((0, 0, 0))
+        };
+        assert_parse!(
+            parser::declaration(),
+            "color: #000000!important",
+            important.clone()
+        );
+        assert_parse!(
+            parser::declaration(),
+            "color: #000000 ! IMPORTANT",
+            important
+        );
+        assert_parse!(
+            parser::declaration(),
+            "COLOR: #000000",
+            Declaration::color((0, 0, 0))
+        );
+        assert_parse!(
+            parser::declaration(),
+            "--X: 1px",
+            Declaration {
+                name: "--X".to_string(),
+                value: Value::Tokens(TokenStream::new(vec![Token::Number("1px".to_string())])),
+                important: false,
+            }
+        );
+        assert_parse!(
+            parser::declaration(),
+            "--x: 1px !important",
+            Declaration {
+                name: "--x".to_string(),
+                value: Value::Tokens(TokenStream::new(vec![Token::Number("1px".to_string())])),
+                important: true,
+            }
+        );
     }
 
     #[test]
     fn color_test() {
-        assert_parse!(parser::color(), "#00000", color((0, 0, 0)));
+        let mut parser = parser::color();
+        let rgba = |r, g, b, a| Color { r, g, b, a };
+        assert_parse!(parser, "#000000", color((0, 0, 0)));
+        assert_parse!(parser, "#0f0", color((0, 255, 0)));
+        assert_parse!(parser, "#0f08", rgba(0, 255, 0, 0x88));
+        assert_parse!(parser, "#01020304", rgba(1, 2, 3, 4));
+        assert_parse_fail!(parser, "#00000");
+        assert_parse_fail!(parser, "#0000000");
+
+        assert_parse!(parser, "rgb(1, 2, 3)", color((1, 2, 3)));
+        assert_parse!(parser, "rgba(1,2,3,0)", rgba(1, 2, 3, 0));
+        assert_parse!(parser, "RGB( 1 2 3 )", color((1, 2, 3)));
+        assert_parse!(parser, "rgb(1 2 3 / 50%)", rgba(1, 2, 3, 128));
+        assert_parse!(parser, "rgba(100%, 0%, 0%, 1)", color((255, 0, 0)));
+        assert_parse!(parser, "hsl(0, 100%, 50%)", color((255, 0, 0)));
+        assert_parse!(
+            parser,
+            "hsl(0.5turn 100% 50% / 0.5)",
+            rgba(0, 255, 255, 128)
+        );
+        assert_parse!(
+            parser,
+            "hsla(3.14159rad, 100%, 50%, 1)",
+            color((0, 255, 255))
+        );
+        assert_parse!(parser, "hwb(120deg 0% 50%)", color((0, 128, 0)));
+        assert_parse_fail!(parser, "rgb(1, 2 3)");
+        assert_parse_fail!(parser, "rgb(1 2 3, 0.5)");
+        assert_parse_fail!(parser, "hwb(0, 0%, 0%)");
+        assert_parse_fail!(parser, "rgb(1 2)");
     }
 
 }
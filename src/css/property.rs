@@ -0,0 +1,748 @@
+//! The properties which serval supports: the grammar of their values, their
+//! initial values, whether they are inherited, and how they are animated.
+//!
+//! https://drafts.csswg.org/css-cascade-4/#property
+
+use crate::css::animation::EasingFunction;
+use crate::css::container::{container_names, ContainerType};
+use crate::css::counter::CounterStyle;
+use crate::css::shorthand::{
+    self, components, is_background_position, is_background_repeat, is_background_size, is_color,
+    is_font_family, is_font_size, is_font_stretch, is_font_weight, is_keyword,
+    is_length_percentage, is_line_height, is_line_style, is_line_width, is_one_of,
+};
+use crate::css::{parser, Declaration, Value};
+use lazy_static::*;
+use log::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Write;
+
+use self::AnimationType::*;
+
+/// How the values of a property are animated.
+///
+/// https://drafts.csswg.org/web-animations-1/#animation-type
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AnimationType {
+    NotAnimatable,
+    /// The value flips from one to the other halfway. A discrete property
+    /// doesn't transition.
+    Discrete,
+    /// The values are interpolated by `animation::interpolate`.
+    ByComputedValue,
+}
+
+impl fmt::Display for AnimationType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AnimationType::NotAnimatable => "not animatable",
+            AnimationType::Discrete => "discrete",
+            AnimationType::ByComputedValue => "by computed value",
+        })
+    }
+}
+
+/// The keywords which every property accepts.
+///
+/// https://drafts.csswg.org/css-cascade-4/#defaulting-keywords
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CssWideKeyword {
+    Initial,
+    Inherit,
+    /// `inherit` for an inherited property, and `initial` otherwise.
+    Unset,
+    /// Rolls back the cascade to the previous origin.
+    Revert,
+}
+
+impl CssWideKeyword {
+    pub fn from_value(value: &Value) -> Option<CssWideKeyword> {
+        match value {
+            Value::Keyword(keyword) => match keyword.to_ascii_lowercase().as_str() {
+                "initial" => Some(CssWideKeyword::Initial),
+                "inherit" => Some(CssWideKeyword::Inherit),
+                "unset" => Some(CssWideKeyword::Unset),
+                "revert" => Some(CssWideKeyword::Revert),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// A longhand property. Shorthands are in `shorthand::SHORTHANDS`.
+pub struct Property {
+    pub name: &'static str,
+    /// The grammar of the values in the notation of the specifications.
+    pub syntax: &'static str,
+    /// The text of the initial value.
+    pub initial: &'static str,
+    pub inherited: bool,
+    pub animation_type: AnimationType,
+    /// Returns true if a value matches `syntax`.
+    is_valid: fn(&Value) -> bool,
+}
+
+impl Property {
+    /// Returns true if `value` is valid for this property. A CSS-wide
+    /// keyword is valid for any property, and a value with `var()` is valid
+    /// until it is substituted.
+    pub fn is_valid(&self, value: &Value) -> bool {
+        match value {
+            Value::WithVariables(_) | Value::PendingSubstitution(..) => true,
+            _ => CssWideKeyword::from_value(value).is_some() || (self.is_valid)(value),
+        }
+    }
+
+    /// Returns the parsed initial value.
+    pub fn initial_value(&self) -> &'static Value {
+        lazy_static! {
+            static ref INITIAL_VALUES: HashMap<&'static str, Value> = properties()
+                .map(|property| {
+                    let value = parser::parse_value(property.initial);
+                    (property.name, value.expect("invalid initial value"))
+                })
+                .collect();
+        }
+        &INITIAL_VALUES[self.name]
+    }
+}
+
+const fn property(
+    name: &'static str,
+    syntax: &'static str,
+    initial: &'static str,
+    inherited: bool,
+    animation_type: AnimationType,
+    is_valid: fn(&Value) -> bool,
+) -> Property {
+    Property {
+        name,
+        syntax,
+        initial,
+        inherited,
+        animation_type,
+        is_valid,
+    }
+}
+
+macro_rules! sides {
+    ($prefix:expr, $suffix:expr, $syntax:expr, $initial:expr, $animation_type:expr, $is_valid:expr) => {
+        [
+            property(
+                concat!($prefix, "top", $suffix),
+                $syntax,
+                $initial,
+                false,
+                $animation_type,
+                $is_valid,
+            ),
+            property(
+                concat!($prefix, "right", $suffix),
+                $syntax,
+                $initial,
+                false,
+                $animation_type,
+                $is_valid,
+            ),
+            property(
+                concat!($prefix, "bottom", $suffix),
+                $syntax,
+                $initial,
+                false,
+                $animation_type,
+                $is_valid,
+            ),
+            property(
+                concat!($prefix, "left", $suffix),
+                $syntax,
+                $initial,
+                false,
+                $animation_type,
+                $is_valid,
+            ),
+        ]
+    };
+}
+
+static BOX: [Property; 3] = [
+    property(
+        "display",
+        "block | inline | list-item | none",
+        "inline",
+        false,
+        Discrete,
+        |v| is_one_of(v, &["block", "inline", "list-item", "none"]),
+    ),
+    property(
+        "width",
+        "auto | <length-percentage>",
+        "auto",
+        false,
+        ByComputedValue,
+        is_auto_or_length_percentage,
+    ),
+    property(
+        "height",
+        "auto | <length-percentage>",
+        "auto",
+        false,
+        ByComputedValue,
+        is_auto_or_length_percentage,
+    ),
+];
+
+static MARGIN: [Property; 4] = sides!(
+    "margin-",
+    "",
+    "auto | <length-percentage>",
+    "0",
+    ByComputedValue,
+    is_auto_or_length_percentage
+);
+
+static PADDING: [Property; 4] = sides!(
+    "padding-",
+    "",
+    "<length-percentage>",
+    "0",
+    ByComputedValue,
+    is_length_percentage
+);
+
+static BORDER_WIDTH: [Property; 4] = sides!(
+    "border-",
+    "-width",
+    "<line-width>",
+    "medium",
+    ByComputedValue,
+    is_line_width
+);
+
+static BORDER_STYLE: [Property; 4] = sides!(
+    "border-",
+    "-style",
+    "<line-style>",
+    "none",
+    Discrete,
+    is_line_style
+);
+
+static BORDER_COLOR: [Property; 4] = sides!(
+    "border-",
+    "-color",
+    "<color>",
+    "currentcolor",
+    ByComputedValue,
+    is_color
+);
+
+static OTHERS: [Property; 34] = [
+    property("color", "<color>", "black", true, ByComputedValue, is_color),
+    property(
+        "background-color",
+        "<color>",
+        "transparent",
+        false,
+        ByComputedValue,
+        is_color,
+    ),
+    property(
+        "background-image",
+        "none#",
+        "none",
+        false,
+        Discrete,
+        |v| is_comma_list(v, |v| is_keyword(v, "none")),
+    ),
+    property(
+        "background-repeat",
+        "[ repeat-x | repeat-y | [ repeat | space | round | no-repeat ]{1,2} ]#",
+        "repeat",
+        false,
+        Discrete,
+        |v| {
+            is_comma_list(v, |v| {
+                is_one_of(v, &["repeat-x", "repeat-y"]) || is_list(v, 2, is_background_repeat)
+            })
+        },
+    ),
+    property(
+        "background-attachment",
+        "[ scroll | fixed | local ]#",
+        "scroll",
+        false,
+        Discrete,
+        |v| is_comma_list(v, |v| is_one_of(v, &["scroll", "fixed", "local"])),
+    ),
+    property(
+        "background-position",
+        "[ left | center | right | top | bottom | <length-percentage> ]{1,2}#",
+        "0% 0%",
+        false,
+        ByComputedValue,
+        |v| is_comma_list(v, |v| is_list(v, 2, is_background_position)),
+    ),
+    property(
+        "background-size",
+        "[ <length-percentage> | auto | cover | contain ]{1,2}#",
+        "auto",
+        false,
+        ByComputedValue,
+        |v| is_comma_list(v, |v| is_list(v, 2, is_background_size)),
+    ),
+    property(
+        "font-style",
+        "normal | italic | oblique",
+        "normal",
+        true,
+        Discrete,
+        |v| is_one_of(v, &["normal", "italic", "oblique"]),
+    ),
+    property(
+        "font-variant",
+        "normal | small-caps",
+        "normal",
+        true,
+        Discrete,
+        |v| is_one_of(v, &["normal", "small-caps"]),
+    ),
+    property(
+        "font-weight",
+        "normal | bold | bolder | lighter | <number [1,1000]>",
+        "normal",
+        true,
+        ByComputedValue,
+        |v| is_keyword(v, "normal") || is_font_weight(v),
+    ),
+    property(
+        "font-stretch",
+        "normal | <font-stretch-keyword>",
+        "normal",
+        true,
+        ByComputedValue,
+        |v| is_keyword(v, "normal") || is_font_stretch(v),
+    ),
+    property(
+        "font-size",
+        "<absolute-size> | <relative-size> | <length-percentage>",
+        "medium",
+        true,
+        ByComputedValue,
+        is_font_size,
+    ),
+    property(
+        "line-height",
+        "normal | <number> | <length-percentage>",
+        "normal",
+        true,
+        ByComputedValue,
+        is_line_height,
+    ),
+    property(
+        "font-family",
+        "[ <family-name> | <generic-family> ]#",
+        "serif",
+        true,
+        Discrete,
+        |v| is_comma_list(v, is_font_family),
+    ),
+    property(
+        "animation-name",
+        "[ none | <keyframes-name> ]#",
+        "none",
+        false,
+        NotAnimatable,
+        |v| is_comma_list(v, |v| matches!(v, Value::Keyword(_) | Value::String(_))),
+    ),
+    property(
+        "animation-duration",
+        "<time [0s,∞]>#",
+        "0s",
+        false,
+        NotAnimatable,
+        |v| is_comma_list(v, is_non_negative_time),
+    ),
+    property(
+        "animation-timing-function",
+        "<easing-function>#",
+        "ease",
+        false,
+        NotAnimatable,
+        |v| is_comma_list(v, is_easing_function),
+    ),
+    property(
+        "animation-delay",
+        "<time>#",
+        "0s",
+        false,
+        NotAnimatable,
+        |v| is_comma_list(v, |v| matches!(v, Value::Time(_))),
+    ),
+    property(
+        "animation-iteration-count",
+        "[ infinite | <number [0,∞]> ]#",
+        "1",
+        false,
+        NotAnimatable,
+        |v| {
+            is_comma_list(v, |v| {
+                is_keyword(v, "infinite") || matches!(v, Value::Number(n) if *n >= 0.0)
+            })
+        },
+    ),
+    property(
+        "animation-direction",
+        "[ normal | reverse | alternate | alternate-reverse ]#",
+        "normal",
+        false,
+        NotAnimatable,
+        |v| {
+            is_comma_list(v, |v| {
+                is_one_of(v, &["normal", "reverse", "alternate", "alternate-reverse"])
+            })
+        },
+    ),
+    property(
+        "animation-fill-mode",
+        "[ none | forwards | backwards | both ]#",
+        "none",
+        false,
+        NotAnimatable,
+        |v| is_comma_list(v, |v| is_one_of(v, &["none", "forwards", "backwards", "both"])),
+    ),
+    property(
+        "animation-play-state",
+        "[ running | paused ]#",
+        "running",
+        false,
+        NotAnimatable,
+        |v| is_comma_list(v, |v| is_one_of(v, &["running", "paused"])),
+    ),
+    property(
+        "transition-property",
+        "none | [ all | <custom-ident> ]#",
+        "all",
+        false,
+        NotAnimatable,
+        |v| match v {
+            Value::CommaList(items) => items
+                .iter()
+                .all(|v| matches!(v, Value::Keyword(_)) && !is_keyword(v, "none")),
+            _ => matches!(v, Value::Keyword(_)),
+        },
+    ),
+    property(
+        "transition-duration",
+        "<time [0s,∞]>#",
+        "0s",
+        false,
+        NotAnimatable,
+        |v| is_comma_list(v, is_non_negative_time),
+    ),
+    property(
+        "transition-timing-function",
+        "<easing-function>#",
+        "ease",
+        false,
+        NotAnimatable,
+        |v| is_comma_list(v, is_easing_function),
+    ),
+    property(
+        "transition-delay",
+        "<time>#",
+        "0s",
+        false,
+        NotAnimatable,
+        |v| is_comma_list(v, |v| matches!(v, Value::Time(_))),
+    ),
+    property(
+        "content",
+        "normal | none | [ <string> | <counter> | open-quote | close-quote | no-open-quote | no-close-quote ]+",
+        "normal",
+        false,
+        Discrete,
+        is_content,
+    ),
+    property(
+        "counter-reset",
+        "none | [ <counter-name> <integer>? ]+",
+        "none",
+        false,
+        Discrete,
+        is_counter_changes,
+    ),
+    property(
+        "counter-increment",
+        "none | [ <counter-name> <integer>? ]+",
+        "none",
+        false,
+        Discrete,
+        is_counter_changes,
+    ),
+    property(
+        "counter-set",
+        "none | [ <counter-name> <integer>? ]+",
+        "none",
+        false,
+        Discrete,
+        is_counter_changes,
+    ),
+    property(
+        "list-style-type",
+        "<counter-style-name> | <string> | none",
+        "disc",
+        true,
+        Discrete,
+        |v| match v {
+            Value::Keyword(name) => CounterStyle::from_name(name).is_some(),
+            _ => matches!(v, Value::String(_)),
+        },
+    ),
+    property(
+        "list-style-position",
+        "inside | outside",
+        "outside",
+        true,
+        Discrete,
+        |v| is_one_of(v, &["inside", "outside"]),
+    ),
+    property(
+        "container-type",
+        "normal | size | inline-size",
+        "normal",
+        false,
+        NotAnimatable,
+        |v| ContainerType::from_value(v).is_some(),
+    ),
+    property(
+        "container-name",
+        "none | <custom-ident>+",
+        "none",
+        false,
+        NotAnimatable,
+        |v| container_names(v).is_some(),
+    ),
+];
+
+/// Returns all the longhand properties which serval supports.
+pub fn properties() -> impl Iterator<Item = &'static Property> {
+    BOX.iter()
+        .chain(&MARGIN)
+        .chain(&PADDING)
+        .chain(&BORDER_WIDTH)
+        .chain(&BORDER_STYLE)
+        .chain(&BORDER_COLOR)
+        .chain(&OTHERS)
+}
+
+/// Finds a longhand property. Property names are ASCII case-insensitive.
+pub fn find(name: &str) -> Option<&'static Property> {
+    properties().find(|property| property.name.eq_ignore_ascii_case(name))
+}
+
+/// Returns true if `name` is a custom property, a known longhand or a known
+/// shorthand.
+pub fn is_supported(name: &str) -> bool {
+    name.starts_with("--")
+        || find(name).is_some()
+        || shorthand::find(&name.to_ascii_lowercase()).is_some()
+}
+
+/// Returns true if `name` is inherited by default. Custom properties are
+/// inherited.
+pub fn is_inherited(name: &str) -> bool {
+    name.starts_with("--") || find(name).is_some_and(|property| property.inherited)
+}
+
+/// Returns the animation type of a longhand. Custom properties are
+/// discrete, and unknown properties are not animatable.
+pub fn animation_type(name: &str) -> AnimationType {
+    if name.starts_with("--") {
+        return Discrete;
+    }
+    find(name).map_or(NotAnimatable, |property| property.animation_type)
+}
+
+/// Returns true if a longhand or custom property declaration is valid.
+/// Otherwise, it is ignored with a warning.
+pub fn validate(declaration: &Declaration) -> bool {
+    if declaration.name.starts_with("--") {
+        return true;
+    }
+    match find(&declaration.name) {
+        Some(property) if property.is_valid(&declaration.value) => true,
+        Some(_) => {
+            warn!(
+                "Ignored an invalid value for {}: {:?}",
+                declaration.name, declaration.value
+            );
+            false
+        }
+        None => {
+            warn!("Ignored an unknown property: {}", declaration.name);
+            false
+        }
+    }
+}
+
+/// Returns the reference of the supported properties, which the
+/// `properties` command prints.
+pub fn documentation() -> String {
+    let mut text = String::new();
+    for property in properties() {
+        writeln!(text, "{}", property.name).unwrap();
+        writeln!(text, "  Value: {}", property.syntax).unwrap();
+        writeln!(text, "  Initial: {}", property.initial).unwrap();
+        let inherited = if property.inherited { "yes" } else { "no" };
+        writeln!(text, "  Inherited: {}", inherited).unwrap();
+        writeln!(text, "  Animation type: {}", property.animation_type).unwrap();
+    }
+    for shorthand in shorthand::shorthands() {
+        writeln!(text, "{}", shorthand.name).unwrap();
+        writeln!(text, "  Shorthand for: {}", shorthand.longhands.join(", ")).unwrap();
+    }
+    text
+}
+
+fn is_auto_or_length_percentage(value: &Value) -> bool {
+    is_keyword(value, "auto") || is_length_percentage(value)
+}
+
+/// Returns true if each comma separated item of `value` is valid.
+fn is_comma_list(value: &Value, is_valid: fn(&Value) -> bool) -> bool {
+    match value {
+        Value::CommaList(items) => items.iter().all(is_valid),
+        _ => is_valid(value),
+    }
+}
+
+/// Returns true if `value` has one to `max` space separated components,
+/// which are all valid.
+fn is_list(value: &Value, max: usize, is_valid: fn(&Value) -> bool) -> bool {
+    components(value).is_some_and(|values| values.len() <= max && values.iter().all(is_valid))
+}
+
+fn is_non_negative_time(value: &Value) -> bool {
+    matches!(value, Value::Time(t) if *t >= 0.0)
+}
+
+fn is_easing_function(value: &Value) -> bool {
+    EasingFunction::from_value(value).is_some()
+}
+
+fn is_content(value: &Value) -> bool {
+    if is_one_of(value, &["normal", "none"]) {
+        return true;
+    }
+    components(value).is_some_and(|values| {
+        values.iter().all(|value| match value {
+            Value::String(_) | Value::Counter(_) => true,
+            _ => is_one_of(
+                value,
+                &[
+                    "open-quote",
+                    "close-quote",
+                    "no-open-quote",
+                    "no-close-quote",
+                ],
+            ),
+        })
+    })
+}
+
+/// A counter name is followed by an optional integer.
+fn is_counter_changes(value: &Value) -> bool {
+    if is_keyword(value, "none") {
+        return true;
+    }
+    let values = match components(value) {
+        Some(values) => values,
+        None => return false,
+    };
+    let mut after_name = false;
+    for value in values {
+        after_name = match value {
+            Value::Keyword(name) if !name.eq_ignore_ascii_case("none") => true,
+            Value::Number(n) if after_name && n.fract() == 0.0 => false,
+            _ => return false,
+        };
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::css::parser::parse_value;
+
+    fn is_valid(name: &str, value: &str) -> bool {
+        find(name).unwrap().is_valid(&parse_value(value).unwrap())
+    }
+
+    #[test]
+    fn initial_value_test() {
+        for property in properties() {
+            assert!(
+                property.is_valid(property.initial_value()),
+                "{}",
+                property.name
+            );
+        }
+    }
+
+    #[test]
+    fn is_valid_test() {
+        assert!(is_valid("width", "calc(10px + 5%)"));
+        assert!(is_valid("margin-left", "auto"));
+        assert!(!is_valid("padding-top", "auto"));
+        assert!(!is_valid("display", "flex"));
+        assert!(is_valid("border-top-width", "thick"));
+        assert!(!is_valid("border-top-width", "10%"));
+        assert!(is_valid("color", "currentcolor"));
+        assert!(!is_valid("color", "10px"));
+        assert!(is_valid("background-position", "left 10px, 0 0"));
+        assert!(!is_valid("background-position", "left top 10px"));
+        assert!(is_valid("font-family", "\"Open Sans\", sans-serif"));
+        assert!(is_valid("animation-duration", "1s, 200ms"));
+        assert!(!is_valid("animation-duration", "-1s"));
+        assert!(is_valid("animation-timing-function", "steps(2, end)"));
+        assert!(!is_valid("transition-property", "width, none"));
+        assert!(is_valid("content", "counter(a) \". \""));
+        assert!(is_valid("counter-reset", "a 2 b"));
+        assert!(!is_valid("counter-reset", "a 2 3"));
+        assert!(!is_valid("counter-increment", "1.5"));
+        assert!(is_valid("list-style-type", "upper-roman"));
+        assert!(!is_valid("list-style-type", "unknown"));
+        assert!(!is_valid("container-name", "none a"));
+        assert!(is_valid("display", "inherit"));
+        assert!(is_valid("padding-top", "REVERT"));
+    }
+
+    #[test]
+    fn validate_test() {
+        let declaration = |name: &str, value| Declaration {
+            name: name.to_string(),
+            value: parse_value(value).unwrap(),
+            important: false,
+        };
+        assert!(validate(&declaration("padding-top", "1px")));
+        assert!(validate(&declaration("PADDING-TOP", "1px")));
+        assert!(!validate(&declaration("padding-top-width", "1px")));
+        assert!(!validate(&declaration("padding-top", "solid")));
+        assert!(validate(&declaration("--anything", "solid")));
+        // A value with `var()` is valid until it is substituted.
+        let declaration = parser::parse_declaration("width: var(--x) solid").unwrap();
+        assert!(validate(&declaration));
+    }
+
+    #[test]
+    fn animation_type_test() {
+        assert_eq!(animation_type("width"), ByComputedValue);
+        assert_eq!(animation_type("display"), Discrete);
+        assert_eq!(animation_type("--x"), Discrete);
+        assert_eq!(animation_type("animation-name"), NotAnimatable);
+        assert_eq!(animation_type("unknown"), NotAnimatable);
+    }
+}
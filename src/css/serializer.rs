@@ -0,0 +1,755 @@
+//! Serializes a stylesheet back to CSS text in a canonical form.
+//!
+//! Shorthands were expanded at parse time, so they are written as their
+//! longhands, and nested rules are written as the flat rules which they
+//! were desugared into. Parsing the output gives the same stylesheet.
+//!
+//! https://drafts.csswg.org/cssom-1/#serializing-css-values
+
+use crate::css::animation::{EasingFunction, Keyframe, StepPosition};
+use crate::css::calc::CalcNode;
+use crate::css::counter::{CounterFunction, CounterStyle};
+use crate::css::font_face::{FontFaceRule, FontSource};
+use crate::css::media::*;
+use crate::css::supports::SupportsCondition;
+use crate::css::*;
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// Omits all optional white space and the last semicolon in a block.
+    pub minify: bool,
+    /// The number of spaces per nesting level in the pretty output.
+    pub indent: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            minify: false,
+            indent: 2,
+        }
+    }
+}
+
+impl Options {
+    pub fn minified() -> Options {
+        Options {
+            minify: true,
+            ..Default::default()
+        }
+    }
+}
+
+pub fn serialize(stylesheet: &Stylesheet, options: &Options) -> String {
+    let mut serializer = Serializer {
+        options,
+        out: String::new(),
+        depth: 0,
+    };
+    serializer.rules(&stylesheet.rules);
+    serializer.out
+}
+
+struct Serializer<'a> {
+    options: &'a Options,
+    out: String,
+    depth: usize,
+}
+
+impl<'a> Serializer<'a> {
+    fn minify(&self) -> bool {
+        self.options.minify
+    }
+
+    fn newline(&mut self) {
+        if !self.minify() {
+            self.out.push('\n');
+            self.out
+                .push_str(&" ".repeat(self.depth * self.options.indent));
+        }
+    }
+
+    fn rules(&mut self, rules: &[CssRule]) {
+        for (i, rule) in rules.iter().enumerate() {
+            if i > 0 {
+                // Top-level rules are separated by a blank line.
+                if self.depth == 0 && !self.minify() {
+                    self.out.push('\n');
+                }
+                self.newline();
+            }
+            self.rule(rule);
+        }
+        if self.depth == 0 && !rules.is_empty() && !self.minify() {
+            self.out.push('\n');
+        }
+    }
+
+    fn rule(&mut self, rule: &CssRule) {
+        let minify = self.minify();
+        match rule {
+            CssRule::Style(rule) => {
+                self.out
+                    .push_str(&selector_list(rule.selectors.in_source_order(), minify));
+                self.declarations(&rule.declarations);
+            }
+            CssRule::Media(media) => {
+                self.out.push_str("@media");
+                if !media.queries.0.is_empty() {
+                    self.out.push(' ');
+                    self.out.push_str(&media_query_list(&media.queries, minify));
+                }
+                self.block(&media.rules);
+            }
+            CssRule::Import(import) => {
+                self.out.push_str("@import ");
+                self.out.push_str(&string(&import.url));
+                match &import.layer {
+                    None => {}
+                    Some(None) => self.out.push_str(" layer"),
+                    Some(Some(name)) => self.out.push_str(&format!(" layer({})", name.join("."))),
+                }
+                if !import.queries.0.is_empty() {
+                    self.out.push(' ');
+                    self.out
+                        .push_str(&media_query_list(&import.queries, minify));
+                }
+                self.out.push(';');
+            }
+            CssRule::LayerStatement(names) => {
+                let names: Vec<_> = names.iter().map(|name| name.join(".")).collect();
+                self.out.push_str("@layer ");
+                self.out
+                    .push_str(&names.join(if minify { "," } else { ", " }));
+                self.out.push(';');
+            }
+            CssRule::Layer(layer) => {
+                self.out.push_str("@layer");
+                if let Some(name) = &layer.name {
+                    self.out.push(' ');
+                    self.out.push_str(&name.join("."));
+                }
+                self.block(&layer.rules);
+            }
+            CssRule::Supports(supports) => {
+                self.out.push_str("@supports ");
+                self.out
+                    .push_str(&supports_condition(&supports.condition, minify));
+                self.block(&supports.rules);
+            }
+            CssRule::Container(container) => {
+                self.out.push_str("@container ");
+                if let Some(name) = &container.name {
+                    self.out.push_str(name);
+                    self.out.push(' ');
+                }
+                self.out
+                    .push_str(&media_condition(&container.condition, minify));
+                self.block(&container.rules);
+            }
+            CssRule::Keyframes(keyframes) => {
+                self.out.push_str("@keyframes ");
+                self.out.push_str(&keyframes_name(&keyframes.name));
+                self.keyframes(&keyframes.keyframes);
+            }
+            CssRule::FontFace(font_face) => {
+                self.out.push_str("@font-face");
+                let descriptors = font_face_descriptors(font_face, minify);
+                self.declaration_block(descriptors);
+            }
+        }
+    }
+
+    fn keyframes(&mut self, keyframes: &[Keyframe]) {
+        let minify = self.minify();
+        self.out.push_str(if minify { "{" } else { " {" });
+        if keyframes.is_empty() {
+            self.out.push('}');
+            return;
+        }
+        self.depth += 1;
+        for keyframe in keyframes {
+            self.newline();
+            let offsets: Vec<_> = keyframe
+                .offsets
+                .iter()
+                .map(|offset| format!("{}%", number(*offset)))
+                .collect();
+            self.out
+                .push_str(&offsets.join(if minify { "," } else { ", " }));
+            self.declarations(&keyframe.declarations);
+        }
+        self.depth -= 1;
+        self.newline();
+        self.out.push('}');
+    }
+
+    fn block(&mut self, rules: &[CssRule]) {
+        self.out.push_str(if self.minify() { "{" } else { " {" });
+        if rules.is_empty() {
+            self.out.push('}');
+            return;
+        }
+        self.depth += 1;
+        self.newline();
+        self.rules(rules);
+        self.depth -= 1;
+        self.newline();
+        self.out.push('}');
+    }
+
+    fn declarations(&mut self, declarations: &[Declaration]) {
+        let minify = self.minify();
+        let mut texts = vec![];
+        let mut rest = declarations;
+        while !rest.is_empty() {
+            let (text, len) = declaration(rest, minify);
+            texts.push(text);
+            rest = &rest[len..];
+        }
+        self.declaration_block(texts);
+    }
+
+    /// Writes a block of declarations or descriptors.
+    fn declaration_block(&mut self, declarations: Vec<String>) {
+        let minify = self.minify();
+        self.out.push_str(if minify { "{" } else { " {" });
+        if declarations.is_empty() {
+            self.out.push('}');
+            return;
+        }
+        self.depth += 1;
+        for (i, text) in declarations.iter().enumerate() {
+            if i > 0 {
+                self.out.push(';');
+            }
+            self.newline();
+            self.out.push_str(text);
+        }
+        if !minify {
+            self.out.push(';');
+        }
+        self.depth -= 1;
+        self.newline();
+        self.out.push('}');
+    }
+}
+
+/// Serializes the first declaration of `declarations`. Returns the text and
+/// the number of declarations which it covers: the longhands of a shorthand
+/// with `var()` are written as the shorthand.
+fn declaration(declarations: &[Declaration], minify: bool) -> (String, usize) {
+    let first = &declarations[0];
+    let separator = if minify { ":" } else { ": " };
+    let important = match (first.important, minify) {
+        (false, _) => "",
+        (true, true) => "!important",
+        (true, false) => " !important",
+    };
+    if let Value::PendingSubstitution(name, tokens) = &first.value {
+        if let Some(shorthand) = shorthand::find(name) {
+            let len = shorthand.longhands.len();
+            let is_expanded = declarations.len() >= len
+                && declarations[..len].iter().zip(shorthand.longhands).all(
+                    |(declaration, longhand)| {
+                        declaration.name == *longhand
+                            && declaration.value == first.value
+                            && declaration.important == first.important
+                    },
+                );
+            if is_expanded {
+                let text = format!("{}{}{}{}", name, separator, tokens, important);
+                return (text, len);
+            }
+        }
+    }
+    let text = format!(
+        "{}{}{}{}",
+        first.name,
+        separator,
+        value(&first.value, minify),
+        important
+    );
+    (text, 1)
+}
+
+pub fn value(value: &Value, minify: bool) -> String {
+    match value {
+        Value::Keyword(keyword) => keyword.clone(),
+        Value::Number(n) => number(*n),
+        Value::Percentage(n) => format!("{}%", number(*n)),
+        Value::Length(n, unit) => format!("{}{}", number(*n), unit.name()),
+        Value::ColorValue(color) => hex_color(*color, minify),
+        Value::String(s) => string(s),
+        Value::List(values) => {
+            let mut s = String::new();
+            for (i, v) in values.iter().enumerate() {
+                match v {
+                    Value::Slash => s.push_str(if minify { "/" } else { " / " }),
+                    _ => {
+                        if i > 0 && values[i - 1] != Value::Slash {
+                            s.push(' ');
+                        }
+                        s.push_str(&self::value(v, minify));
+                    }
+                }
+            }
+            s
+        }
+        Value::CommaList(values) => values
+            .iter()
+            .map(|v| self::value(v, minify))
+            .collect::<Vec<_>>()
+            .join(if minify { "," } else { ", " }),
+        Value::Slash => "/".to_string(),
+        Value::Calc(node) => calc(node, minify),
+        Value::Time(seconds) => format!("{}s", number(*seconds)),
+        Value::Easing(easing) => easing_function(easing, minify),
+        Value::Counter(counter) => counter_function(counter, minify),
+        Value::Tokens(tokens)
+        | Value::WithVariables(tokens)
+        | Value::PendingSubstitution(_, tokens) => tokens.to_string(),
+    }
+}
+
+/// The default style, `decimal`, is omitted.
+fn counter_function(counter: &CounterFunction, minify: bool) -> String {
+    let mut args = vec![counter.name.clone()];
+    args.extend(counter.separator.iter().map(|separator| string(separator)));
+    if counter.style != CounterStyle::Decimal {
+        args.push(counter.style.name().to_string());
+    }
+    let name = if counter.separator.is_some() {
+        "counters"
+    } else {
+        "counter"
+    };
+    format!("{}({})", name, args.join(if minify { "," } else { ", " }))
+}
+
+/// Rust writes the shortest representation which reads back as the same
+/// `f32`, without an exponent.
+fn easing_function(easing: &EasingFunction, minify: bool) -> String {
+    let separator = if minify { "," } else { ", " };
+    match *easing {
+        EasingFunction::CubicBezier(x1, y1, x2, y2) => {
+            let args: Vec<_> = [x1, y1, x2, y2].iter().map(|n| number(*n)).collect();
+            format!("cubic-bezier({})", args.join(separator))
+        }
+        EasingFunction::Steps(steps, StepPosition::JumpEnd) => format!("steps({})", steps),
+        EasingFunction::Steps(steps, position) => {
+            format!("steps({}{}{})", steps, separator, position.name())
+        }
+    }
+}
+
+/// Serializes the descriptors of `@font-face`. A family name is always a
+/// string.
+fn font_face_descriptors(font_face: &FontFaceRule, minify: bool) -> Vec<String> {
+    let separator = if minify { ":" } else { ": " };
+    let comma = if minify { "," } else { ", " };
+    let sources: Vec<_> = font_face
+        .sources
+        .iter()
+        .map(|source| match source {
+            FontSource::Url { url, format: None } => format!("url({})", string(url)),
+            FontSource::Url {
+                url,
+                format: Some(format),
+            } => format!("url({}) format({})", string(url), string(format)),
+            FontSource::Local(name) => format!("local({})", string(name)),
+        })
+        .collect();
+    let mut descriptors = vec![
+        format!("font-family{}{}", separator, string(&font_face.family)),
+        format!("src{}{}", separator, sources.join(comma)),
+    ];
+    if let Some((start, end)) = font_face.weight {
+        let weight = if start == end {
+            number(start)
+        } else {
+            format!("{} {}", number(start), number(end))
+        };
+        descriptors.push(format!("font-weight{}{}", separator, weight));
+    }
+    if let Some(style) = font_face.style {
+        descriptors.push(format!("font-style{}{}", separator, style.name()));
+    }
+    if !font_face.unicode_range.is_empty() {
+        let ranges: Vec<_> = font_face
+            .unicode_range
+            .iter()
+            .map(|&(start, end)| {
+                if start == end {
+                    format!("U+{:X}", start)
+                } else {
+                    format!("U+{:X}-{:X}", start, end)
+                }
+            })
+            .collect();
+        descriptors.push(format!("unicode-range{}{}", separator, ranges.join(comma)));
+    }
+    descriptors
+}
+
+/// Returns the name of `@keyframes` as an identifier if it is one, or as a
+/// string.
+fn keyframes_name(name: &str) -> String {
+    let mut chars = name.chars();
+    let is_ident = chars
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_' || c == '-')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        && !["none", "initial", "inherit", "unset", "default"]
+            .iter()
+            .any(|keyword| keyword.eq_ignore_ascii_case(name));
+    if is_ident {
+        name.to_string()
+    } else {
+        string(name)
+    }
+}
+
+fn number(n: f32) -> String {
+    format!("{}", n)
+}
+
+fn hex_color(color: Color, minify: bool) -> String {
+    let mut channels = vec![color.r, color.g, color.b];
+    if !color.is_opaque() {
+        channels.push(color.a);
+    }
+    // `#rrggbb` is shortened to `#rgb` if each channel has two equal digits.
+    if minify && channels.iter().all(|c| c % 17 == 0) {
+        return channels
+            .iter()
+            .fold("#".to_string(), |s, c| format!("{}{:x}", s, c / 17));
+    }
+    channels
+        .iter()
+        .fold("#".to_string(), |s, c| format!("{}{:02x}", s, c))
+}
+
+fn string(s: &str) -> String {
+    // There are no escapes, so a string with `"` is put in single quotes.
+    if s.contains('"') {
+        format!("'{}'", s)
+    } else {
+        format!("\"{}\"", s)
+    }
+}
+
+fn calc(node: &CalcNode, minify: bool) -> String {
+    match node {
+        CalcNode::Min(_) | CalcNode::Max(_) | CalcNode::Clamp(..) => calc_value(node, minify),
+        _ => format!("calc({})", calc_sum(node, minify)),
+    }
+}
+
+fn calc_sum(node: &CalcNode, minify: bool) -> String {
+    // White space around `+` and `-` is required.
+    match node {
+        CalcNode::Add(a, b) => format!("{} + {}", calc_sum(a, minify), calc_product(b, minify)),
+        CalcNode::Sub(a, b) => format!("{} - {}", calc_sum(a, minify), calc_product(b, minify)),
+        _ => calc_product(node, minify),
+    }
+}
+
+fn calc_product(node: &CalcNode, minify: bool) -> String {
+    let op = |op: &str| {
+        if minify {
+            op.to_string()
+        } else {
+            format!(" {} ", op)
+        }
+    };
+    match node {
+        CalcNode::Mul(a, b) => format!(
+            "{}{}{}",
+            calc_product(a, minify),
+            op("*"),
+            calc_value(b, minify)
+        ),
+        CalcNode::Div(a, b) => format!(
+            "{}{}{}",
+            calc_product(a, minify),
+            op("/"),
+            calc_value(b, minify)
+        ),
+        _ => calc_value(node, minify),
+    }
+}
+
+fn calc_value(node: &CalcNode, minify: bool) -> String {
+    let args = |args: &[&CalcNode]| {
+        args.iter()
+            .map(|arg| calc_sum(arg, minify))
+            .collect::<Vec<_>>()
+            .join(if minify { "," } else { ", " })
+    };
+    match node {
+        CalcNode::Number(n) => number(*n),
+        CalcNode::Percentage(n) => format!("{}%", number(*n)),
+        CalcNode::Length(n, unit) => format!("{}{}", number(*n), unit.name()),
+        CalcNode::Min(nodes) => format!("min({})", args(&nodes.iter().collect::<Vec<_>>())),
+        CalcNode::Max(nodes) => format!("max({})", args(&nodes.iter().collect::<Vec<_>>())),
+        CalcNode::Clamp(min, value, max) => format!("clamp({})", args(&[min, value, max])),
+        _ => format!("({})", calc_sum(node, minify)),
+    }
+}
+
+fn selector_list<'a>(selectors: impl Iterator<Item = &'a Selector>, minify: bool) -> String {
+    selectors
+        .map(|s| selector(s, minify))
+        .collect::<Vec<_>>()
+        .join(if minify { "," } else { ", " })
+}
+
+pub fn selector(selector: &Selector, minify: bool) -> String {
+    match selector {
+        Selector::Simple(simple) => simple_selector(simple, minify),
+        Selector::Complex(ancestor, combinator, simple) => {
+            let combinator = match (combinator, minify) {
+                (Combinator::Descendant, _) => " ",
+                (Combinator::Child, true) => ">",
+                (Combinator::Child, false) => " > ",
+            };
+            format!(
+                "{}{}{}",
+                self::selector(ancestor, minify),
+                combinator,
+                simple_selector(simple, minify)
+            )
+        }
+    }
+}
+
+fn simple_selector(simple: &SimpleSelector, minify: bool) -> String {
+    let mut s = simple.tag_name.clone().unwrap_or_default();
+    if simple.nesting {
+        s.push('&');
+    }
+    if let Some(id) = &simple.id {
+        s.push('#');
+        s.push_str(id);
+    }
+    for class in &simple.classes {
+        s.push('.');
+        s.push_str(class);
+    }
+    for selectors in &simple.is {
+        s.push_str(&format!(":is({})", selector_list(selectors.iter(), minify)));
+    }
+    if s.is_empty() && simple.pseudo_element.is_none() {
+        s.push('*');
+    }
+    if let Some(pseudo_element) = simple.pseudo_element {
+        s.push_str("::");
+        s.push_str(pseudo_element.name());
+    }
+    s
+}
+
+fn media_query_list(list: &MediaQueryList, minify: bool) -> String {
+    list.0
+        .iter()
+        .map(|query| media_query(query, minify))
+        .collect::<Vec<_>>()
+        .join(if minify { "," } else { ", " })
+}
+
+fn media_query(query: &MediaQuery, minify: bool) -> String {
+    let mut parts = vec![];
+    match query.qualifier {
+        Some(Qualifier::Not) => parts.push("not".to_string()),
+        Some(Qualifier::Only) => parts.push("only".to_string()),
+        None => {}
+    }
+    parts.extend(query.media_type.clone());
+    if let Some(condition) = &query.condition {
+        if query.media_type.is_some() {
+            parts.push("and".to_string());
+        }
+        parts.push(media_condition(condition, minify));
+    }
+    parts.join(" ")
+}
+
+fn media_condition(condition: &MediaCondition, minify: bool) -> String {
+    match condition {
+        MediaCondition::Not(condition) => format!("not {}", media_in_parens(condition, minify)),
+        MediaCondition::And(conditions) => conditions
+            .iter()
+            .map(|c| media_in_parens(c, minify))
+            .collect::<Vec<_>>()
+            .join(" and "),
+        MediaCondition::Or(conditions) => conditions
+            .iter()
+            .map(|c| media_in_parens(c, minify))
+            .collect::<Vec<_>>()
+            .join(" or "),
+        MediaCondition::Feature(_) => media_in_parens(condition, minify),
+    }
+}
+
+fn media_in_parens(condition: &MediaCondition, minify: bool) -> String {
+    match condition {
+        MediaCondition::Feature(feature) => media_feature(feature, minify),
+        _ => format!("({})", media_condition(condition, minify)),
+    }
+}
+
+fn media_feature(feature: &MediaFeature, minify: bool) -> String {
+    let space = if minify { "" } else { " " };
+    match feature {
+        MediaFeature::Boolean(name) => format!("({})", name),
+        MediaFeature::Plain(name, value) => {
+            format!("({}:{}{})", name, space, media_value(value, minify))
+        }
+        MediaFeature::Range(name, comparisons) => match comparisons.as_slice() {
+            [(comparison, value)] => format!(
+                "({}{}{}{}{})",
+                name,
+                space,
+                comparison.symbol(),
+                space,
+                media_value(value, minify)
+            ),
+            // `value < name < value`, whose first comparison was flipped.
+            [(lower, lower_value), (upper, upper_value)] => format!(
+                "({}{}{}{}{}{}{}{}{})",
+                media_value(lower_value, minify),
+                space,
+                lower.flip().symbol(),
+                space,
+                name,
+                space,
+                upper.symbol(),
+                space,
+                media_value(upper_value, minify)
+            ),
+            _ => format!("({})", name),
+        },
+    }
+}
+
+fn media_value(value: &MediaValue, minify: bool) -> String {
+    match value {
+        MediaValue::Number(n) => number(*n),
+        MediaValue::Length(n, unit) => format!("{}{}", number(*n), unit.name()),
+        MediaValue::Ratio(width, height) if minify => {
+            format!("{}/{}", number(*width), number(*height))
+        }
+        MediaValue::Ratio(width, height) => format!("{} / {}", number(*width), number(*height)),
+        MediaValue::Resolution(dppx) => format!("{}dppx", number(*dppx)),
+        MediaValue::Ident(ident) => ident.clone(),
+    }
+}
+
+fn supports_condition(condition: &SupportsCondition, minify: bool) -> String {
+    match condition {
+        SupportsCondition::Not(condition) => {
+            format!("not {}", supports_in_parens(condition, minify))
+        }
+        SupportsCondition::And(conditions) => conditions
+            .iter()
+            .map(|c| supports_in_parens(c, minify))
+            .collect::<Vec<_>>()
+            .join(" and "),
+        SupportsCondition::Or(conditions) => conditions
+            .iter()
+            .map(|c| supports_in_parens(c, minify))
+            .collect::<Vec<_>>()
+            .join(" or "),
+        _ => supports_in_parens(condition, minify),
+    }
+}
+
+fn supports_in_parens(condition: &SupportsCondition, minify: bool) -> String {
+    match condition {
+        SupportsCondition::Declaration(name, value) if minify => format!("({}:{})", name, value),
+        SupportsCondition::Declaration(name, value) => format!("({}: {})", name, value),
+        SupportsCondition::Selector(selector) => format!("selector({})", selector),
+        SupportsCondition::General(text) => text.clone(),
+        _ => format!("({})", supports_condition(condition, minify)),
+    }
+}
+
+impl fmt::Display for Stylesheet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serialize(self, &Options::default()))
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", value(self, false))
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", selector(self, false))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::css::parser::parse_stylesheet;
+
+    const STYLESHEET: &str = r#"
+        @import "base.css" layer(base.reset) screen and (min-width: 400px);
+        @import 'say "hi".css' layer;
+        @layer base, theme.dark;
+        #foo, p, .a.b, * { color: #102030; background-color: rgba(0, 0, 0, 50%) }
+        div > p .c, :is(p, &.d) { width: calc(100% - (2px + 1em) * 2) !important; height: min(10px, 5vw) }
+        div { margin: 1px auto; font: italic bold 12px / 1.5 "Helvetica Neue", serif }
+        span { --x: 1px  2px; --y: "a" calc( 1 ); padding: var(--x) !important; border-top-width: var(--x, 0) }
+        div { background: none no-repeat, red; width: clamp(1px, 2%, 3.5e2px) }
+        @media not print and (max-width: 0.5in), (400px < width <= 800px) or (not (color)) {
+          @layer { div { height: 1px } }
+          @supports ((display: grid) and (not selector(a > b))) or f(x) { p { width: 0 } }
+        }
+        @media { div { color: transparent } }
+        @layer theme { div { } }
+        .x { .y { width: 1px } & .z { height: 1px } }
+        @keyframes slide { from, 50% { margin-left: 1px } to { animation-timing-function: steps(2, start) } }
+        @keyframes "a b" { }
+        div { animation: slide 200ms cubic-bezier(0.1, 0.2, 0.3, 1) infinite, x 1s steps(3) }
+        ol > li::marker, ::marker { content: counters(list-item, ".") " " counter(x, lower-roman) }
+        @font-face { font-family: Open  Sans; src: local("Open Sans"), url(a.ttf) format("truetype"); font-weight: 700 100; unicode-range: u+0-7f, U+4?? }
+        @font-face { font-family: "x"; src: url("b c.otf"); font-style: italic; font-weight: bold }
+        .card { container: card main / inline-size }
+        @container card (400px < width) and (not (orientation: portrait)) { @container (min-height: 0) { p { width: 1px } } }
+    "#;
+
+    #[test]
+    fn round_trip_test() {
+        let stylesheet = parse_stylesheet(STYLESHEET).unwrap();
+        for options in &[Options::default(), Options::minified()] {
+            let text = serialize(&stylesheet, options);
+            assert_eq!(parse_stylesheet(&text).unwrap(), stylesheet, "{}", text);
+            assert_eq!(serialize(&parse_stylesheet(&text).unwrap(), options), text);
+        }
+    }
+
+    #[test]
+    fn serialize_test() {
+        let stylesheet = parse_stylesheet(
+            "p, #a { color: #ffffff; margin-top: 1px !important } \
+             @media print { div { } }",
+        )
+        .unwrap();
+        assert_eq!(
+            serialize(&stylesheet, &Options::default()),
+            "p, #a {\n  color: #ffffff;\n  margin-top: 1px !important;\n}\n\n\
+             @media print {\n  div {}\n}\n"
+        );
+        assert_eq!(
+            serialize(&stylesheet, &Options::minified()),
+            "p,#a{color:#fff;margin-top:1px!important}@media print{div{}}"
+        );
+    }
+}
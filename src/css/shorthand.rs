@@ -0,0 +1,1022 @@
+//! Shorthand properties, which are expanded into their longhands at parse
+//! time.
+//!
+//! https://drafts.csswg.org/css-cascade-4/#shorthand
+
+use crate::css::animation::EasingFunction;
+use crate::css::calc::CalcType;
+use crate::css::container::{container_names, ContainerType};
+use crate::css::property::CssWideKeyword;
+use crate::css::{Color, Declaration, Value};
+use log::*;
+use std::slice;
+
+pub struct Shorthand {
+    pub name: &'static str,
+    pub longhands: &'static [&'static str],
+    /// Returns the values of the longhands in the same order as `longhands`,
+    /// or `None` if the value is invalid.
+    parse: fn(&Value) -> Option<Vec<Value>>,
+}
+
+const SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
+
+static SHORTHANDS: [Shorthand; 11] = [
+    Shorthand {
+        name: "margin",
+        longhands: &["margin-top", "margin-right", "margin-bottom", "margin-left"],
+        parse: |value| sides(value, |v| is_length_percentage(v) || is_keyword(v, "auto")),
+    },
+    Shorthand {
+        name: "padding",
+        longhands: &[
+            "padding-top",
+            "padding-right",
+            "padding-bottom",
+            "padding-left",
+        ],
+        parse: |value| sides(value, is_length_percentage),
+    },
+    Shorthand {
+        name: "border-width",
+        longhands: &[
+            "border-top-width",
+            "border-right-width",
+            "border-bottom-width",
+            "border-left-width",
+        ],
+        parse: |value| sides(value, is_line_width),
+    },
+    Shorthand {
+        name: "border-style",
+        longhands: &[
+            "border-top-style",
+            "border-right-style",
+            "border-bottom-style",
+            "border-left-style",
+        ],
+        parse: |value| sides(value, is_line_style),
+    },
+    Shorthand {
+        name: "border-color",
+        longhands: &[
+            "border-top-color",
+            "border-right-color",
+            "border-bottom-color",
+            "border-left-color",
+        ],
+        parse: |value| sides(value, is_color),
+    },
+    Shorthand {
+        name: "border",
+        longhands: &[
+            "border-top-width",
+            "border-right-width",
+            "border-bottom-width",
+            "border-left-width",
+            "border-top-style",
+            "border-right-style",
+            "border-bottom-style",
+            "border-left-style",
+            "border-top-color",
+            "border-right-color",
+            "border-bottom-color",
+            "border-left-color",
+        ],
+        parse: border,
+    },
+    Shorthand {
+        name: "background",
+        longhands: &[
+            "background-color",
+            "background-image",
+            "background-repeat",
+            "background-attachment",
+            "background-position",
+            "background-size",
+        ],
+        parse: background,
+    },
+    Shorthand {
+        name: "font",
+        longhands: &[
+            "font-style",
+            "font-variant",
+            "font-weight",
+            "font-stretch",
+            "font-size",
+            "line-height",
+            "font-family",
+        ],
+        parse: font,
+    },
+    Shorthand {
+        name: "animation",
+        longhands: &[
+            "animation-name",
+            "animation-duration",
+            "animation-timing-function",
+            "animation-delay",
+            "animation-iteration-count",
+            "animation-direction",
+            "animation-fill-mode",
+            "animation-play-state",
+        ],
+        parse: animation,
+    },
+    Shorthand {
+        name: "transition",
+        longhands: &[
+            "transition-property",
+            "transition-duration",
+            "transition-timing-function",
+            "transition-delay",
+        ],
+        parse: transition,
+    },
+    Shorthand {
+        name: "container",
+        longhands: &["container-name", "container-type"],
+        parse: container,
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Shorthand> {
+    SHORTHANDS.iter().find(|shorthand| shorthand.name == name)
+}
+
+pub fn shorthands() -> impl Iterator<Item = &'static Shorthand> {
+    SHORTHANDS.iter()
+}
+
+impl Shorthand {
+    /// Returns the longhand declarations, or `None` if `value` is invalid
+    /// for this shorthand. A CSS-wide keyword sets all the longhands.
+    pub fn expand(&self, value: &Value) -> Option<Vec<Declaration>> {
+        let values = match CssWideKeyword::from_value(value) {
+            Some(_) => vec![value.clone(); self.longhands.len()],
+            None => (self.parse)(value)?,
+        };
+        debug_assert_eq!(values.len(), self.longhands.len());
+        Some(
+            self.longhands
+                .iter()
+                .zip(values)
+                .map(|(name, value)| Declaration {
+                    name: name.to_string(),
+                    value,
+                    important: false,
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Replaces a shorthand declaration with its longhands. Other declarations
+/// are returned as is.
+///
+/// An invalid shorthand declaration is dropped with a warning. If the value
+/// contains `var()`, it can be expanded only after substitution, so each
+/// longhand gets a pending-substitution value.
+pub fn expand(declaration: Declaration) -> Vec<Declaration> {
+    let shorthand = match find(&declaration.name) {
+        Some(shorthand) => shorthand,
+        None => return vec![declaration],
+    };
+    if let Value::WithVariables(ref tokens) = declaration.value {
+        return shorthand
+            .longhands
+            .iter()
+            .map(|name| Declaration {
+                name: name.to_string(),
+                value: Value::PendingSubstitution(shorthand.name.to_string(), tokens.clone()),
+                important: declaration.important,
+            })
+            .collect();
+    }
+    match shorthand.expand(&declaration.value) {
+        Some(mut longhands) => {
+            for longhand in &mut longhands {
+                longhand.important = declaration.important;
+            }
+            longhands
+        }
+        None => {
+            warn!(
+                "Ignored an invalid value for {}: {:?}",
+                shorthand.name, declaration.value
+            );
+            vec![]
+        }
+    }
+}
+
+/// Returns the space separated components of `value`.
+pub(crate) fn components(value: &Value) -> Option<&[Value]> {
+    match value {
+        Value::List(values) => Some(values),
+        Value::CommaList(_) => None,
+        _ => Some(slice::from_ref(value)),
+    }
+}
+
+fn list(values: &[Value]) -> Value {
+    match values {
+        [value] => value.clone(),
+        _ => Value::List(values.to_vec()),
+    }
+}
+
+fn keyword(name: &str) -> Value {
+    Value::Keyword(name.to_string())
+}
+
+pub(crate) fn is_keyword(value: &Value, name: &str) -> bool {
+    match value {
+        Value::Keyword(keyword) => keyword.eq_ignore_ascii_case(name),
+        _ => false,
+    }
+}
+
+pub(crate) fn is_one_of(value: &Value, names: &[&str]) -> bool {
+    names.iter().any(|name| is_keyword(value, name))
+}
+
+pub(crate) fn is_length_percentage(value: &Value) -> bool {
+    match value {
+        Value::Length(..) | Value::Percentage(_) | Value::Number(0.0) => true,
+        Value::Calc(node) => matches!(
+            node.calc_type(),
+            Some(CalcType::Length) | Some(CalcType::Percentage) | Some(CalcType::LengthPercentage)
+        ),
+        _ => false,
+    }
+}
+
+pub(crate) fn is_color(value: &Value) -> bool {
+    matches!(value, Value::ColorValue(_)) || is_keyword(value, "currentcolor")
+}
+
+pub(crate) fn is_line_width(value: &Value) -> bool {
+    (is_length_percentage(value) && !matches!(value, Value::Percentage(_)))
+        || is_one_of(value, &["thin", "medium", "thick"])
+}
+
+pub(crate) fn is_line_style(value: &Value) -> bool {
+    is_one_of(
+        value,
+        &[
+            "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset",
+            "outset",
+        ],
+    )
+}
+
+/// Expands one to four values into top, right, bottom and left.
+fn sides(value: &Value, is_valid: fn(&Value) -> bool) -> Option<Vec<Value>> {
+    let values = components(value)?;
+    if !values.iter().all(is_valid) {
+        return None;
+    }
+    let (top, right, bottom, left) = match values {
+        [top] => (top, top, top, top),
+        [top, right] => (top, right, top, right),
+        [top, right, bottom] => (top, right, bottom, right),
+        [top, right, bottom, left] => (top, right, bottom, left),
+        _ => return None,
+    };
+    Some(vec![
+        top.clone(),
+        right.clone(),
+        bottom.clone(),
+        left.clone(),
+    ])
+}
+
+// https://drafts.csswg.org/css-backgrounds-3/#propdef-border
+fn border(value: &Value) -> Option<Vec<Value>> {
+    let (mut width, mut style, mut color) = (None, None, None);
+    for value in components(value)? {
+        let slot = if is_line_width(value) {
+            &mut width
+        } else if is_line_style(value) {
+            &mut style
+        } else if is_color(value) {
+            &mut color
+        } else {
+            return None;
+        };
+        if slot.replace(value.clone()).is_some() {
+            return None;
+        }
+    }
+    let width = width.unwrap_or_else(|| keyword("medium"));
+    let style = style.unwrap_or_else(|| keyword("none"));
+    let color = color.unwrap_or_else(|| keyword("currentcolor"));
+    Some(
+        SIDES
+            .iter()
+            .map(|_| width.clone())
+            .chain(SIDES.iter().map(|_| style.clone()))
+            .chain(SIDES.iter().map(|_| color.clone()))
+            .collect(),
+    )
+}
+
+// https://drafts.csswg.org/css-backgrounds-3/#propdef-background
+fn background(value: &Value) -> Option<Vec<Value>> {
+    let layers = match value {
+        Value::CommaList(layers) => layers.as_slice(),
+        _ => slice::from_ref(value),
+    };
+    let mut color = None;
+    let mut longhands = vec![vec![]; 5];
+    for (i, layer) in layers.iter().enumerate() {
+        let (layer_color, values) = background_layer(layer)?;
+        if layer_color.is_some() {
+            // Only the final layer may have a color.
+            if i + 1 < layers.len() {
+                return None;
+            }
+            color = layer_color;
+        }
+        for (longhand, value) in longhands.iter_mut().zip(values) {
+            longhand.push(value);
+        }
+    }
+    let mut values = vec![color.unwrap_or(Value::ColorValue(Color::TRANSPARENT))];
+    values.extend(longhands.into_iter().map(|mut layers| {
+        if layers.len() == 1 {
+            layers.pop().unwrap()
+        } else {
+            Value::CommaList(layers)
+        }
+    }));
+    Some(values)
+}
+
+/// Returns the color and the other longhands of a background layer.
+fn background_layer(layer: &Value) -> Option<(Option<Value>, Vec<Value>)> {
+    let values = components(layer)?;
+    let (mut color, mut image, mut repeat, mut attachment, mut position, mut size) =
+        (None, None, None, None, None, None);
+    let mut i = 0;
+    while i < values.len() {
+        let value = &values[i];
+        let is_position = is_background_position(value);
+        let (slot, n) = if is_position {
+            (
+                &mut position,
+                count(&values[i..], 2, is_background_position),
+            )
+        } else if is_one_of(value, &["repeat-x", "repeat-y"]) {
+            (&mut repeat, 1)
+        } else if is_background_repeat(value) {
+            (&mut repeat, count(&values[i..], 2, is_background_repeat))
+        } else if is_one_of(value, &["scroll", "fixed", "local"]) {
+            (&mut attachment, 1)
+        } else if is_keyword(value, "none") {
+            (&mut image, 1)
+        } else if is_color(value) {
+            (&mut color, 1)
+        } else {
+            return None;
+        };
+        if slot.replace(list(&values[i..i + n])).is_some() {
+            return None;
+        }
+        i += n;
+
+        // The size follows the position after a slash.
+        if is_position && values.get(i) == Some(&Value::Slash) {
+            let n = count(&values[i + 1..], 2, is_background_size);
+            if n == 0 {
+                return None;
+            }
+            size = Some(list(&values[i + 1..i + 1 + n]));
+            i += 1 + n;
+        }
+    }
+    Some((
+        color,
+        vec![
+            image.unwrap_or_else(|| keyword("none")),
+            repeat.unwrap_or_else(|| keyword("repeat")),
+            attachment.unwrap_or_else(|| keyword("scroll")),
+            position.unwrap_or_else(|| {
+                Value::List(vec![Value::Percentage(0.0), Value::Percentage(0.0)])
+            }),
+            size.unwrap_or_else(|| keyword("auto")),
+        ],
+    ))
+}
+
+/// Counts the leading values (at most `max`) which satisfy `predicate`.
+fn count(values: &[Value], max: usize, predicate: fn(&Value) -> bool) -> usize {
+    values.iter().take(max).take_while(|v| predicate(v)).count()
+}
+
+pub(crate) fn is_background_position(value: &Value) -> bool {
+    is_length_percentage(value) || is_one_of(value, &["left", "center", "right", "top", "bottom"])
+}
+
+pub(crate) fn is_background_repeat(value: &Value) -> bool {
+    is_one_of(value, &["repeat", "space", "round", "no-repeat"])
+}
+
+pub(crate) fn is_background_size(value: &Value) -> bool {
+    is_length_percentage(value) || is_one_of(value, &["auto", "cover", "contain"])
+}
+
+// https://drafts.csswg.org/css-fonts-4/#font-prop
+fn font(value: &Value) -> Option<Vec<Value>> {
+    let (first, other_families) = match value {
+        Value::CommaList(values) => (&values[0], &values[1..]),
+        _ => (value, &[][..]),
+    };
+    let values = components(first)?;
+
+    // [ <font-style> || <font-variant-css2> || <font-weight> || <font-stretch-css3> ]?
+    // `normal` can be any of them.
+    let (mut style, mut variant, mut weight, mut stretch) = (None, None, None, None);
+    let mut i = 0;
+    loop {
+        let value = values.get(i)?;
+        let slot = if is_keyword(value, "normal") {
+            IntoIterator::into_iter([&mut style, &mut variant, &mut weight, &mut stretch])
+                .find(|slot| slot.is_none())?
+        } else if is_one_of(value, &["italic", "oblique"]) {
+            &mut style
+        } else if is_keyword(value, "small-caps") {
+            &mut variant
+        } else if is_font_weight(value) {
+            &mut weight
+        } else if is_font_stretch(value) {
+            &mut stretch
+        } else {
+            break;
+        };
+        if slot.replace(value.clone()).is_some() {
+            return None;
+        }
+        i += 1;
+    }
+
+    let size = values.get(i).filter(|v| is_font_size(v))?.clone();
+    i += 1;
+    let line_height = if values.get(i) == Some(&Value::Slash) {
+        i += 2;
+        values.get(i - 1).filter(|v| is_line_height(v))?.clone()
+    } else {
+        keyword("normal")
+    };
+
+    let first_family = list(values.get(i..).filter(|v| !v.is_empty())?);
+    let families: Vec<_> = Some(first_family)
+        .into_iter()
+        .chain(other_families.iter().cloned())
+        .collect();
+    if !families.iter().all(is_font_family) {
+        return None;
+    }
+    let family = match families.len() {
+        1 => families[0].clone(),
+        _ => Value::CommaList(families),
+    };
+
+    let normal = || keyword("normal");
+    Some(vec![
+        style.unwrap_or_else(normal),
+        variant.unwrap_or_else(normal),
+        weight.unwrap_or_else(normal),
+        stretch.unwrap_or_else(normal),
+        size,
+        line_height,
+        family,
+    ])
+}
+
+// https://drafts.csswg.org/css-animations-1/#animation
+fn animation(value: &Value) -> Option<Vec<Value>> {
+    comma_separated(value, 8, single_animation)
+}
+
+/// Expands each comma separated item with `parse`, and returns the lists
+/// of the `n` longhands.
+fn comma_separated(
+    value: &Value,
+    n: usize,
+    parse: fn(&Value) -> Option<Vec<Value>>,
+) -> Option<Vec<Value>> {
+    let items = match value {
+        Value::CommaList(items) => items.as_slice(),
+        _ => slice::from_ref(value),
+    };
+    let mut longhands = vec![vec![]; n];
+    for item in items {
+        for (longhand, value) in longhands.iter_mut().zip(parse(item)?) {
+            longhand.push(value);
+        }
+    }
+    Some(
+        longhands
+            .into_iter()
+            .map(|mut values| {
+                if values.len() == 1 {
+                    values.pop().unwrap()
+                } else {
+                    Value::CommaList(values)
+                }
+            })
+            .collect(),
+    )
+}
+
+fn single_animation(value: &Value) -> Option<Vec<Value>> {
+    let (mut name, mut duration, mut easing, mut delay) = (None, None, None, None);
+    let (mut count, mut direction, mut fill_mode, mut play_state) = (None, None, None, None);
+    for value in components(value)? {
+        // The first time is the duration, and the second one is the delay.
+        // A keyword is the name only if it is not a keyword of the other
+        // longhands, which are not already set.
+        let slot = if let Value::Time(_) = value {
+            if duration.is_none() {
+                &mut duration
+            } else {
+                &mut delay
+            }
+        } else if EasingFunction::from_value(value).is_some() {
+            &mut easing
+        } else if is_keyword(value, "infinite") || matches!(value, Value::Number(n) if *n >= 0.0) {
+            &mut count
+        } else if direction.is_none()
+            && is_one_of(
+                value,
+                &["normal", "reverse", "alternate", "alternate-reverse"],
+            )
+        {
+            &mut direction
+        } else if fill_mode.is_none()
+            && is_one_of(value, &["none", "forwards", "backwards", "both"])
+        {
+            &mut fill_mode
+        } else if play_state.is_none() && is_one_of(value, &["running", "paused"]) {
+            &mut play_state
+        } else if let Value::Keyword(_) | Value::String(_) = value {
+            &mut name
+        } else {
+            return None;
+        };
+        if slot.replace(value.clone()).is_some() {
+            return None;
+        }
+    }
+    Some(vec![
+        name.unwrap_or_else(|| keyword("none")),
+        duration.unwrap_or(Value::Time(0.0)),
+        easing.unwrap_or_else(|| keyword("ease")),
+        delay.unwrap_or(Value::Time(0.0)),
+        count.unwrap_or(Value::Number(1.0)),
+        direction.unwrap_or_else(|| keyword("normal")),
+        fill_mode.unwrap_or_else(|| keyword("none")),
+        play_state.unwrap_or_else(|| keyword("running")),
+    ])
+}
+
+// https://drafts.csswg.org/css-transitions-1/#transition-shorthand-property
+fn transition(value: &Value) -> Option<Vec<Value>> {
+    let longhands = comma_separated(value, 4, single_transition)?;
+    // `none` is valid only as the only item.
+    if let Value::CommaList(properties) = &longhands[0] {
+        if properties
+            .iter()
+            .any(|property| is_keyword(property, "none"))
+        {
+            return None;
+        }
+    }
+    Some(longhands)
+}
+
+fn single_transition(value: &Value) -> Option<Vec<Value>> {
+    let (mut property, mut duration, mut easing, mut delay) = (None, None, None, None);
+    for value in components(value)? {
+        // The first time is the duration, and the second one is the delay.
+        let slot = if let Value::Time(_) = value {
+            if duration.is_none() {
+                &mut duration
+            } else {
+                &mut delay
+            }
+        } else if EasingFunction::from_value(value).is_some() {
+            &mut easing
+        } else if let Value::Keyword(_) = value {
+            &mut property
+        } else {
+            return None;
+        };
+        if slot.replace(value.clone()).is_some() {
+            return None;
+        }
+    }
+    Some(vec![
+        property.unwrap_or_else(|| keyword("all")),
+        duration.unwrap_or(Value::Time(0.0)),
+        easing.unwrap_or_else(|| keyword("ease")),
+        delay.unwrap_or(Value::Time(0.0)),
+    ])
+}
+
+// <'container-name'> [ / <'container-type'> ]?
+fn container(value: &Value) -> Option<Vec<Value>> {
+    let values = components(value)?;
+    let (names, container_type) = match values.iter().position(|v| *v == Value::Slash) {
+        Some(i) => match &values[i + 1..] {
+            [container_type] => (&values[..i], container_type.clone()),
+            _ => return None,
+        },
+        None => (values, keyword("normal")),
+    };
+    if names.is_empty() {
+        return None;
+    }
+    let names = list(names);
+    container_names(&names)?;
+    ContainerType::from_value(&container_type)?;
+    Some(vec![names, container_type])
+}
+
+pub(crate) fn is_font_weight(value: &Value) -> bool {
+    match value {
+        Value::Number(n) => (1.0..=1000.0).contains(n),
+        _ => is_one_of(value, &["bold", "bolder", "lighter"]),
+    }
+}
+
+pub(crate) fn is_font_stretch(value: &Value) -> bool {
+    is_one_of(
+        value,
+        &[
+            "ultra-condensed",
+            "extra-condensed",
+            "condensed",
+            "semi-condensed",
+            "semi-expanded",
+            "expanded",
+            "extra-expanded",
+            "ultra-expanded",
+        ],
+    )
+}
+
+pub(crate) fn is_font_size(value: &Value) -> bool {
+    is_length_percentage(value)
+        || is_one_of(
+            value,
+            &[
+                "xx-small",
+                "x-small",
+                "small",
+                "medium",
+                "large",
+                "x-large",
+                "xx-large",
+                "xxx-large",
+                "larger",
+                "smaller",
+            ],
+        )
+}
+
+pub(crate) fn is_line_height(value: &Value) -> bool {
+    is_length_percentage(value) || matches!(value, Value::Number(_)) || is_keyword(value, "normal")
+}
+
+/// A family name is a string or a sequence of identifiers.
+pub(crate) fn is_font_family(value: &Value) -> bool {
+    match value {
+        Value::String(_) | Value::Keyword(_) => true,
+        Value::List(values) => values.iter().all(|v| matches!(v, Value::Keyword(_))),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::css::parser::parse_value;
+    use crate::css::Unit;
+
+    fn expand_str(name: &str, value: &str) -> Vec<(String, Value)> {
+        expand(Declaration {
+            name: name.to_string(),
+            value: parse_value(value).unwrap(),
+            important: false,
+        })
+        .into_iter()
+        .map(|d| (d.name, d.value))
+        .collect()
+    }
+
+    fn px(n: f32) -> Value {
+        Value::Length(n, Unit::Px)
+    }
+
+    fn pairs(names: &[&str], values: Vec<Value>) -> Vec<(String, Value)> {
+        names.iter().map(|s| s.to_string()).zip(values).collect()
+    }
+
+    #[test]
+    fn sides_test() {
+        let margin = ["margin-top", "margin-right", "margin-bottom", "margin-left"];
+        assert_eq!(
+            expand_str("margin", "1px"),
+            pairs(&margin, vec![px(1.0), px(1.0), px(1.0), px(1.0)])
+        );
+        assert_eq!(
+            expand_str("margin", "1px auto"),
+            pairs(
+                &margin,
+                vec![px(1.0), keyword("auto"), px(1.0), keyword("auto")]
+            )
+        );
+        assert_eq!(
+            expand_str("margin", "1px 2px 3px"),
+            pairs(&margin, vec![px(1.0), px(2.0), px(3.0), px(2.0)])
+        );
+        assert_eq!(
+            expand_str("margin", "1px 2px 3px 4px"),
+            pairs(&margin, vec![px(1.0), px(2.0), px(3.0), px(4.0)])
+        );
+        assert_eq!(expand_str("margin", "1px 2px 3px 4px 5px"), vec![]);
+        assert_eq!(expand_str("padding", "auto"), vec![]);
+        assert_eq!(expand_str("border-style", "solid red"), vec![]);
+        assert_eq!(
+            expand_str("padding", "inherit"),
+            pairs(
+                &[
+                    "padding-top",
+                    "padding-right",
+                    "padding-bottom",
+                    "padding-left"
+                ],
+                vec![keyword("inherit"); 4]
+            )
+        );
+        // Not a shorthand.
+        assert_eq!(
+            expand_str("margin-top", "1px"),
+            vec![("margin-top".to_string(), px(1.0))]
+        );
+    }
+
+    #[test]
+    fn border_test() {
+        let expanded = expand_str("border", "#000 1px solid");
+        assert_eq!(expanded.len(), 12);
+        assert_eq!(expanded[0], ("border-top-width".to_string(), px(1.0)));
+        assert_eq!(
+            expanded[5],
+            ("border-right-style".to_string(), keyword("solid"))
+        );
+        assert_eq!(
+            expanded[11],
+            ("border-left-color".to_string(), Value::color((0, 0, 0)))
+        );
+
+        let expanded = expand_str("border", "dashed");
+        assert_eq!(expanded[0].1, keyword("medium"));
+        assert_eq!(expanded[8].1, keyword("currentcolor"));
+
+        assert_eq!(expand_str("border", "1px 2px"), vec![]);
+    }
+
+    #[test]
+    fn background_test() {
+        let names = [
+            "background-color",
+            "background-image",
+            "background-repeat",
+            "background-attachment",
+            "background-position",
+            "background-size",
+        ];
+        assert_eq!(
+            expand_str("background", "red"),
+            pairs(
+                &names,
+                vec![
+                    Value::color((255, 0, 0)),
+                    keyword("none"),
+                    keyword("repeat"),
+                    keyword("scroll"),
+                    Value::List(vec![Value::Percentage(0.0), Value::Percentage(0.0)]),
+                    keyword("auto"),
+                ]
+            )
+        );
+        assert_eq!(
+            expand_str(
+                "background",
+                "none center / 10px cover no-repeat fixed #fff"
+            ),
+            pairs(
+                &names,
+                vec![
+                    Value::color((255, 255, 255)),
+                    keyword("none"),
+                    keyword("no-repeat"),
+                    keyword("fixed"),
+                    keyword("center"),
+                    Value::List(vec![px(10.0), keyword("cover")]),
+                ]
+            )
+        );
+
+        let expanded = expand_str("background", "repeat-x, left top blue");
+        assert_eq!(expanded[0].1, Value::color((0, 0, 255)));
+        assert_eq!(
+            expanded[2].1,
+            Value::CommaList(vec![keyword("repeat-x"), keyword("repeat")])
+        );
+        // Only the final layer can have a color.
+        assert_eq!(expand_str("background", "red, none"), vec![]);
+    }
+
+    #[test]
+    fn font_test() {
+        let names = [
+            "font-style",
+            "font-variant",
+            "font-weight",
+            "font-stretch",
+            "font-size",
+            "line-height",
+            "font-family",
+        ];
+        assert_eq!(
+            expand_str(
+                "font",
+                "italic bold 12px/1.5 \"Helvetica Neue\", sans-serif"
+            ),
+            pairs(
+                &names,
+                vec![
+                    keyword("italic"),
+                    keyword("normal"),
+                    keyword("bold"),
+                    keyword("normal"),
+                    px(12.0),
+                    Value::Number(1.5),
+                    Value::CommaList(vec![
+                        Value::String("Helvetica Neue".to_string()),
+                        keyword("sans-serif"),
+                    ]),
+                ]
+            )
+        );
+        assert_eq!(
+            expand_str("font", "normal 700 2em Times New Roman"),
+            pairs(
+                &names,
+                vec![
+                    keyword("normal"),
+                    keyword("normal"),
+                    Value::Number(700.0),
+                    keyword("normal"),
+                    Value::Length(2.0, Unit::Em),
+                    keyword("normal"),
+                    Value::List(vec![keyword("Times"), keyword("New"), keyword("Roman")]),
+                ]
+            )
+        );
+        // The size and the family are required.
+        assert_eq!(expand_str("font", "bold serif"), vec![]);
+        assert_eq!(expand_str("font", "12px"), vec![]);
+    }
+
+    #[test]
+    fn animation_test() {
+        let names = [
+            "animation-name",
+            "animation-duration",
+            "animation-timing-function",
+            "animation-delay",
+            "animation-iteration-count",
+            "animation-direction",
+            "animation-fill-mode",
+            "animation-play-state",
+        ];
+        assert_eq!(
+            expand_str("animation", "1s slide"),
+            pairs(
+                &names,
+                vec![
+                    keyword("slide"),
+                    Value::Time(1.0),
+                    keyword("ease"),
+                    Value::Time(0.0),
+                    Value::Number(1.0),
+                    keyword("normal"),
+                    keyword("none"),
+                    keyword("running"),
+                ]
+            )
+        );
+        let expanded = expand_str(
+            "animation",
+            "fade 200ms steps(4, start) 1s infinite alternate both, none 2s",
+        );
+        assert_eq!(
+            expanded[0].1,
+            Value::CommaList(vec![keyword("fade"), keyword("none")])
+        );
+        assert_eq!(
+            expanded[1].1,
+            Value::CommaList(vec![Value::Time(0.2), Value::Time(2.0)])
+        );
+        assert_eq!(
+            expanded[2].1,
+            Value::CommaList(vec![
+                Value::Easing(EasingFunction::Steps(
+                    4,
+                    crate::css::animation::StepPosition::JumpStart
+                )),
+                keyword("ease"),
+            ])
+        );
+        assert_eq!(
+            expanded[4].1,
+            Value::CommaList(vec![keyword("infinite"), Value::Number(1.0)])
+        );
+        // `none` is a name if the fill mode is already set.
+        let expanded = expand_str("animation", "forwards none");
+        assert_eq!(expanded[0].1, keyword("none"));
+        assert_eq!(expanded[6].1, keyword("forwards"));
+        assert_eq!(expand_str("animation", "1s 2s 3s"), vec![]);
+        assert_eq!(expand_str("animation", "a b"), vec![]);
+    }
+
+    #[test]
+    fn transition_test() {
+        let names = [
+            "transition-property",
+            "transition-duration",
+            "transition-timing-function",
+            "transition-delay",
+        ];
+        assert_eq!(
+            expand_str("transition", "1s"),
+            pairs(
+                &names,
+                vec![
+                    keyword("all"),
+                    Value::Time(1.0),
+                    keyword("ease"),
+                    Value::Time(0.0),
+                ]
+            )
+        );
+        assert_eq!(
+            expand_str("transition", "width 1s linear 2s, color 500ms"),
+            pairs(
+                &names,
+                vec![
+                    Value::CommaList(vec![keyword("width"), keyword("color")]),
+                    Value::CommaList(vec![Value::Time(1.0), Value::Time(0.5)]),
+                    Value::CommaList(vec![keyword("linear"), keyword("ease")]),
+                    Value::CommaList(vec![Value::Time(2.0), Value::Time(0.0)]),
+                ]
+            )
+        );
+        assert_eq!(expand_str("transition", "none").len(), 4);
+        assert_eq!(expand_str("transition", "none 1s, width 1s"), vec![]);
+        assert_eq!(expand_str("transition", "width height 1s"), vec![]);
+    }
+
+    #[test]
+    fn container_test() {
+        let names = ["container-name", "container-type"];
+        assert_eq!(
+            expand_str("container", "card"),
+            pairs(&names, vec![keyword("card"), keyword("normal")])
+        );
+        assert_eq!(
+            expand_str("container", "a b / inline-size"),
+            pairs(
+                &names,
+                vec![
+                    Value::List(vec![keyword("a"), keyword("b")]),
+                    keyword("inline-size")
+                ]
+            )
+        );
+        assert_eq!(
+            expand_str("container", "none/size"),
+            pairs(&names, vec![keyword("none"), keyword("size")])
+        );
+        assert_eq!(expand_str("container", "a / block-size"), vec![]);
+        assert_eq!(expand_str("container", "a / size size"), vec![]);
+        assert_eq!(expand_str("container", "a or"), vec![]);
+    }
+}
@@ -0,0 +1,90 @@
+//! `@supports`, which is evaluated by serval's own parser, so that a
+//! stylesheet can detect what serval supports.
+//!
+//! https://drafts.csswg.org/css-conditional-3/#at-supports
+
+use crate::css::{parser, property, shorthand, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SupportsCondition {
+    Not(Box<SupportsCondition>),
+    And(Vec<SupportsCondition>),
+    Or(Vec<SupportsCondition>),
+    /// `(name: value)`. The value is kept as written.
+    Declaration(String, String),
+    /// `selector(selector)`. The selector is kept as written.
+    Selector(String),
+    /// Anything else in parentheses, or an unknown function, which is never
+    /// supported.
+    ///
+    /// https://drafts.csswg.org/mediaqueries-4/#typedef-general-enclosed
+    General(String),
+}
+
+impl SupportsCondition {
+    pub fn matches(&self) -> bool {
+        match self {
+            SupportsCondition::Not(condition) => !condition.matches(),
+            SupportsCondition::And(conditions) => conditions.iter().all(|c| c.matches()),
+            SupportsCondition::Or(conditions) => conditions.iter().any(|c| c.matches()),
+            SupportsCondition::Declaration(name, value) => supports_declaration(name, value),
+            SupportsCondition::Selector(selector) => parser::parse_selectors(selector).is_ok(),
+            SupportsCondition::General(_) => false,
+        }
+    }
+}
+
+/// Returns true if the property is known and the value is valid for it.
+fn supports_declaration(name: &str, value: &str) -> bool {
+    if !property::is_supported(name) {
+        return false;
+    }
+    let declaration = match parser::parse_declaration(&format!("{}: {}", name, value)) {
+        Ok(declaration) => declaration,
+        Err(_) => return false,
+    };
+    match (
+        &declaration.value,
+        shorthand::find(&name.to_ascii_lowercase()),
+    ) {
+        // A value with var() is valid until it is substituted.
+        (Value::WithVariables(_), _) => true,
+        (value, Some(shorthand)) => shorthand.expand(value).is_some(),
+        (value, None) => property::find(name).is_none_or(|p| p.is_valid(value)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::css::parser::parse_supports_condition;
+
+    fn supports(condition: &str) -> bool {
+        parse_supports_condition(condition).unwrap().matches()
+    }
+
+    #[test]
+    fn matches_test() {
+        assert!(supports("(width: 10px)"));
+        assert!(supports("(WIDTH : calc(1px + 2%))"));
+        assert!(supports("(margin: 1px auto)"));
+        assert!(supports("(--anything: 1 \"a\" b)"));
+        assert!(supports("(color: var(--x))"));
+        assert!(!supports("(unknown: 10px)"));
+        assert!(!supports("(margin: 1px solid)"));
+        assert!(!supports("(display: flex)"));
+        assert!(supports("(display: list-item)"));
+        assert!(supports("not (unknown: 1px)"));
+        assert!(supports("(width: 1px) and (height: 1px)"));
+        assert!(!supports("(width: 1px) and (unknown: 1px)"));
+        assert!(supports("(unknown: 1px) or (height: 1px)"));
+        assert!(supports(
+            "((unknown: 1px) or (height: 1px)) and (not (x: 1))"
+        ));
+        assert!(supports("selector(div.foo#bar, p)"));
+        assert!(supports("selector(div > p :is(.a, &))"));
+        assert!(!supports("selector(div + p)"));
+        assert!(!supports("selector(a#b#c)"));
+        assert!(!supports("unknown(width: 1px)"));
+        assert!(!supports("(width 1px)"));
+    }
+}
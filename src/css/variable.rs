@@ -0,0 +1,404 @@
+// https://drafts.csswg.org/css-variables/
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// A token of an unparsed value, such as the value of a custom property.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Ident(String),
+    /// A function name. The opening parenthesis is part of the token.
+    Function(String),
+    Hash(String),
+    /// A number, a percentage or a dimension, as written.
+    Number(String),
+    String(String),
+    Whitespace,
+    Comma,
+    OpenParen,
+    CloseParen,
+    Delim(char),
+}
+
+impl Token {
+    fn is_var(&self) -> bool {
+        matches!(self, Token::Function(name) if name.eq_ignore_ascii_case("var"))
+    }
+
+    /// Returns true if `self` followed by `next` would be read back as a
+    /// different sequence of tokens.
+    fn would_merge_with(&self, next: &Token) -> bool {
+        matches!(
+            (self, next),
+            (Token::Ident(_), Token::Ident(_))
+                | (Token::Ident(_), Token::Function(_))
+                | (Token::Ident(_), Token::Number(_))
+                | (Token::Ident(_), Token::OpenParen)
+                | (Token::Hash(_), Token::Ident(_))
+                | (Token::Hash(_), Token::Number(_))
+                | (Token::Number(_), Token::Ident(_))
+                | (Token::Number(_), Token::Function(_))
+                | (Token::Number(_), Token::Number(_))
+                | (Token::Number(_), Token::Delim('%'))
+        )
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(s) | Token::Number(s) => write!(f, "{}", s),
+            Token::Function(name) => write!(f, "{}(", name),
+            Token::Hash(s) => write!(f, "#{}", s),
+            Token::String(s) => write!(f, "\"{}\"", s),
+            Token::Whitespace => write!(f, " "),
+            Token::Comma => write!(f, ","),
+            Token::OpenParen => write!(f, "("),
+            Token::CloseParen => write!(f, ")"),
+            Token::Delim(c) => write!(f, "{}", c),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TokenStream {
+    pub tokens: Vec<Token>,
+}
+
+impl fmt::Display for TokenStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in &self.tokens {
+            write!(f, "{}", token)?;
+        }
+        Ok(())
+    }
+}
+
+impl TokenStream {
+    /// Leading and trailing white space is not part of a value.
+    pub fn new(mut tokens: Vec<Token>) -> TokenStream {
+        while tokens.last() == Some(&Token::Whitespace) {
+            tokens.pop();
+        }
+        let start = tokens.len() - skip_whitespace(&tokens).len();
+        tokens.drain(..start);
+        TokenStream { tokens }
+    }
+
+    pub fn has_references(&self) -> bool {
+        self.tokens.iter().any(Token::is_var)
+    }
+
+    /// Returns the names of all custom properties referenced by `var()`,
+    /// including the ones in fallbacks.
+    fn references(&self) -> Vec<&str> {
+        self.tokens
+            .iter()
+            .enumerate()
+            .filter(|(_, token)| token.is_var())
+            .filter_map(
+                |(i, _)| match skip_whitespace(&self.tokens[i + 1..]).first() {
+                    Some(Token::Ident(name)) => Some(name.as_str()),
+                    _ => None,
+                },
+            )
+            .collect()
+    }
+
+    /// Replaces every `var()` with the value of the referenced custom
+    /// property, or its fallback. `lookup` returns `None` for a custom
+    /// property which is not defined or is invalid.
+    ///
+    /// Returns `None` if a reference cannot be substituted.
+    pub fn substitute<'a>(
+        &self,
+        lookup: &dyn Fn(&str) -> Option<&'a TokenStream>,
+    ) -> Option<TokenStream> {
+        let mut output = Vec::new();
+        substitute(&self.tokens, lookup, &mut output)?;
+        Some(TokenStream { tokens: output })
+    }
+}
+
+fn substitute<'a>(
+    mut tokens: &[Token],
+    lookup: &dyn Fn(&str) -> Option<&'a TokenStream>,
+    output: &mut Vec<Token>,
+) -> Option<()> {
+    while let Some((token, rest)) = tokens.split_first() {
+        if !token.is_var() {
+            append(output, std::slice::from_ref(token));
+            tokens = rest;
+            continue;
+        }
+        let end = matching_close_paren(rest)?;
+        let (name, fallback) = var_arguments(&rest[..end])?;
+        match (lookup(name), fallback) {
+            (Some(value), _) => append(output, &value.tokens),
+            (None, Some(fallback)) => {
+                let mut substituted = Vec::new();
+                substitute(fallback, lookup, &mut substituted)?;
+                append(output, &TokenStream::new(substituted).tokens);
+            }
+            (None, None) => return None,
+        }
+        tokens = &rest[end + 1..];
+    }
+    Some(())
+}
+
+/// Appends `tokens`, separating them from the preceding token if they would
+/// otherwise be read back as one token.
+fn append(output: &mut Vec<Token>, tokens: &[Token]) {
+    if let (Some(last), Some(first)) = (output.last(), tokens.first()) {
+        if last.would_merge_with(first) {
+            output.push(Token::Whitespace);
+        }
+    }
+    output.extend_from_slice(tokens);
+}
+
+/// Returns the index of the parenthesis which closes an already opened one.
+fn matching_close_paren(tokens: &[Token]) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Function(_) | Token::OpenParen => depth += 1,
+            Token::CloseParen if depth == 0 => return Some(i),
+            Token::CloseParen => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits the arguments of `var( <custom-property-name> [, <fallback>]? )`.
+fn var_arguments(tokens: &[Token]) -> Option<(&str, Option<&[Token]>)> {
+    let (name, rest) = match skip_whitespace(tokens).split_first() {
+        Some((Token::Ident(name), rest)) if name.starts_with("--") => (name.as_str(), rest),
+        _ => return None,
+    };
+    match skip_whitespace(rest).split_first() {
+        None => Some((name, None)),
+        Some((Token::Comma, fallback)) => Some((name, Some(fallback))),
+        Some(_) => None,
+    }
+}
+
+fn skip_whitespace(tokens: &[Token]) -> &[Token] {
+    let start = tokens
+        .iter()
+        .position(|token| *token != Token::Whitespace)
+        .unwrap_or(tokens.len());
+    &tokens[start..]
+}
+
+/// Resolves `var()` references between custom properties.
+///
+/// Every custom property which is part of a dependency cycle is invalid at
+/// computed-value time and is left out of the result, as is every custom
+/// property that references an invalid one without a usable fallback.
+pub fn resolve_custom_properties(
+    properties: &HashMap<String, TokenStream>,
+) -> HashMap<String, TokenStream> {
+    let cyclic = find_cycles(properties);
+    let mut resolved = HashMap::new();
+    let mut invalid = HashSet::new();
+    for name in properties.keys() {
+        resolve(name, properties, &cyclic, &mut resolved, &mut invalid);
+    }
+    resolved
+}
+
+fn resolve(
+    name: &str,
+    properties: &HashMap<String, TokenStream>,
+    cyclic: &HashSet<&str>,
+    resolved: &mut HashMap<String, TokenStream>,
+    invalid: &mut HashSet<String>,
+) {
+    if resolved.contains_key(name) || invalid.contains(name) {
+        return;
+    }
+    let value = match properties.get(name) {
+        Some(value) if !cyclic.contains(name) => value,
+        _ => {
+            invalid.insert(name.to_string());
+            return;
+        }
+    };
+    // Properties outside of cycles form a DAG, so this terminates.
+    for reference in value.references() {
+        resolve(reference, properties, cyclic, resolved, invalid);
+    }
+    match value.substitute(&|name| resolved.get(name)) {
+        Some(value) => {
+            resolved.insert(name.to_string(), value);
+        }
+        None => {
+            invalid.insert(name.to_string());
+        }
+    }
+}
+
+/// Returns the custom properties which are part of a dependency cycle,
+/// using Tarjan's strongly connected components algorithm.
+fn find_cycles(properties: &HashMap<String, TokenStream>) -> HashSet<&str> {
+    struct Tarjan<'a> {
+        properties: &'a HashMap<String, TokenStream>,
+        index: HashMap<&'a str, usize>,
+        low_link: HashMap<&'a str, usize>,
+        stack: Vec<&'a str>,
+        on_stack: HashSet<&'a str>,
+        cyclic: HashSet<&'a str>,
+    }
+
+    impl<'a> Tarjan<'a> {
+        fn visit(&mut self, name: &'a str) {
+            let index = self.index.len();
+            self.index.insert(name, index);
+            self.low_link.insert(name, index);
+            self.stack.push(name);
+            self.on_stack.insert(name);
+
+            let references = self.properties[name].references();
+            for reference in &references {
+                let (reference, _) = match self.properties.get_key_value(*reference) {
+                    Some(entry) => entry,
+                    None => continue,
+                };
+                if !self.index.contains_key(reference.as_str()) {
+                    self.visit(reference);
+                    let low_link = self.low_link[reference.as_str()].min(self.low_link[name]);
+                    self.low_link.insert(name, low_link);
+                } else if self.on_stack.contains(reference.as_str()) {
+                    let low_link = self.index[reference.as_str()].min(self.low_link[name]);
+                    self.low_link.insert(name, low_link);
+                }
+            }
+
+            if self.low_link[name] == self.index[name] {
+                let mut component = Vec::new();
+                loop {
+                    let member = self.stack.pop().unwrap();
+                    self.on_stack.remove(member);
+                    component.push(member);
+                    if member == name {
+                        break;
+                    }
+                }
+                if component.len() > 1 || references.contains(&name) {
+                    self.cyclic.extend(component);
+                }
+            }
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        properties,
+        index: HashMap::new(),
+        low_link: HashMap::new(),
+        stack: Vec::new(),
+        on_stack: HashSet::new(),
+        cyclic: HashSet::new(),
+    };
+    for name in properties.keys() {
+        if !tarjan.index.contains_key(name.as_str()) {
+            tarjan.visit(name);
+        }
+    }
+    tarjan.cyclic
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::css::parser;
+    use combine::Parser;
+    use maplit::hashmap;
+
+    fn tokens(s: &str) -> TokenStream {
+        parser::token_stream().parse(s).unwrap().0
+    }
+
+    fn custom_properties(properties: &[(&str, &str)]) -> HashMap<String, TokenStream> {
+        properties
+            .iter()
+            .map(|(name, value)| (name.to_string(), tokens(value)))
+            .collect()
+    }
+
+    #[test]
+    fn substitute_test() {
+        let properties = custom_properties(&[
+            ("--width", "10px"),
+            ("--color", "#ff0000"),
+            ("--number", "10"),
+        ]);
+        let substitute = |s: &str| {
+            tokens(s)
+                .substitute(&|name| properties.get(name))
+                .map(|tokens| tokens.to_string())
+        };
+
+        assert_eq!(substitute("var(--width)"), Some("10px".to_string()));
+        assert_eq!(
+            substitute("calc(var( --width ) * 2)"),
+            Some("calc(10px * 2)".to_string())
+        );
+        assert_eq!(
+            substitute("var(--width) var(--color)"),
+            Some("10px #ff0000".to_string())
+        );
+        assert_eq!(substitute("var(--undefined)"), None);
+        assert_eq!(substitute("var(--undefined, 1em)"), Some("1em".to_string()));
+        assert_eq!(
+            substitute("var(--undefined, var(--width))"),
+            Some("10px".to_string())
+        );
+        assert_eq!(substitute("var(--undefined,)"), Some("".to_string()));
+        assert_eq!(substitute("var(width)"), None);
+        // Tokens are never concatenated into a different token.
+        assert_eq!(substitute("var(--number)px"), Some("10 px".to_string()));
+    }
+
+    #[test]
+    fn resolve_custom_properties_test() {
+        let resolved = resolve_custom_properties(&custom_properties(&[
+            ("--a", "var(--b)"),
+            ("--b", "1px"),
+            ("--c", "var(--undefined)"),
+            ("--d", "var(--c, 2px)"),
+        ]));
+        assert_eq!(
+            resolved,
+            hashmap! {
+                "--a".to_string() => tokens("1px"),
+                "--b".to_string() => tokens("1px"),
+                "--d".to_string() => tokens("2px"),
+            }
+        );
+    }
+
+    #[test]
+    fn cycle_test() {
+        let resolved = resolve_custom_properties(&custom_properties(&[
+            ("--self", "var(--self)"),
+            ("--a", "var(--b, 1px)"),
+            ("--b", "var(--c, 2px)"),
+            ("--c", "var(--a, 3px)"),
+            ("--d", "var(--a, 4px)"),
+            ("--e", "var(--a)"),
+            ("--f", "5px"),
+        ]));
+        // Fallbacks don't break a cycle. A property which only refers to a
+        // cycle can use its fallback.
+        assert_eq!(
+            resolved,
+            hashmap! {
+                "--d".to_string() => tokens("4px"),
+                "--f".to_string() => tokens("5px"),
+            }
+        );
+    }
+}
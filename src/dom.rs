@@ -13,11 +13,7 @@ type AttrMap = BTreeMap<String, String>;
 
 impl Node {
     pub fn element(tag_name: String, attrs: AttrMap, children: Vec<Node>) -> Node {
-        Node::Element(ElementData {
-            tag_name,
-            attrs,
-            children,
-        })
+        Node::Element(ElementData::new(tag_name, attrs, children))
     }
 
     pub fn children(&self) -> &[Node] {
@@ -35,14 +31,116 @@ impl Node {
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Debug, Default)]
+/// An element. Its attributes and children change only through the
+/// mutation methods, which set the dirty bits that `style::restyle_tree`
+/// takes.
+#[derive(Clone, Debug, Default)]
 pub struct ElementData {
-    pub tag_name: String,
-    pub attrs: AttrMap,
-    pub children: Vec<Node>,
+    tag_name: String,
+    attrs: AttrMap,
+    children: Vec<Node>,
+    changes: Changes,
+}
+
+/// Elements are equal if their contents are, whether or not they have been
+/// styled since they changed.
+impl PartialEq for ElementData {
+    fn eq(&self, other: &ElementData) -> bool {
+        self.tag_name == other.tag_name
+            && self.attrs == other.attrs
+            && self.children == other.children
+    }
+}
+
+impl Eq for ElementData {}
+
+/// The changes to an element since it was last styled.
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct Changes {
+    /// The attributes before the first change, or `None` if they have not
+    /// changed.
+    pub old_attrs: Option<AttrMap>,
+    /// The children which have been inserted and removed, in order.
+    pub child_changes: Vec<ChildChange>,
+}
+
+/// The insertion or the removal of the child at an index.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ChildChange {
+    Inserted(usize),
+    Removed(usize),
 }
 
 impl ElementData {
+    pub fn new(tag_name: String, attrs: AttrMap, children: Vec<Node>) -> ElementData {
+        ElementData {
+            tag_name,
+            attrs,
+            children,
+            changes: Changes::default(),
+        }
+    }
+
+    pub fn tag_name(&self) -> &str {
+        &self.tag_name
+    }
+
+    pub fn attrs(&self) -> &AttrMap {
+        &self.attrs
+    }
+
+    pub fn children(&self) -> &[Node] {
+        &self.children
+    }
+
+    /// Returns the child at `index` if it is an element, which can be
+    /// changed through its own mutation methods.
+    pub fn child_element_mut(&mut self, index: usize) -> Option<&mut ElementData> {
+        match &mut self.children[index] {
+            Node::Element(data) => Some(data),
+            Node::Text(_) => None,
+        }
+    }
+
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        self.save_attrs();
+        self.attrs.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn remove_attribute(&mut self, name: &str) -> Option<String> {
+        self.save_attrs();
+        self.attrs.remove(name)
+    }
+
+    fn save_attrs(&mut self) {
+        if self.changes.old_attrs.is_none() {
+            self.changes.old_attrs = Some(self.attrs.clone());
+        }
+    }
+
+    pub fn insert_child(&mut self, index: usize, child: Node) {
+        self.children.insert(index, child);
+        self.changes
+            .child_changes
+            .push(ChildChange::Inserted(index));
+    }
+
+    pub fn remove_child(&mut self, index: usize) -> Node {
+        let child = self.children.remove(index);
+        self.changes.child_changes.push(ChildChange::Removed(index));
+        child
+    }
+
+    /// Whether the element has changed since it was last styled.
+    pub fn is_dirty(&self) -> bool {
+        self.changes != Changes::default()
+    }
+
+    /// Returns the changes, and clears them.
+    pub fn take_changes(&mut self) -> Changes {
+        std::mem::take(&mut self.changes)
+    }
+
     pub fn id(&self) -> Option<&str> {
         self.attrs.get("id").map(|s| s.as_str())
     }
@@ -105,4 +203,40 @@ mod test {
         );
     }
 
+    #[test]
+    fn changes_test() {
+        let mut div = ElementData::new(
+            "div".to_string(),
+            btreemap! { "class".to_string() => "a".to_string() },
+            vec![],
+        );
+        assert!(!div.is_dirty());
+        div.set_attribute("class", "b");
+        div.set_attribute("id", "c");
+        assert_eq!(div.remove_attribute("class"), Some("b".to_string()));
+        div.insert_child(0, Node::Text("hello".to_string()));
+        div.insert_child(0, Node::Text("world".to_string()));
+        assert_eq!(div.remove_child(1), Node::Text("hello".to_string()));
+        assert!(div.is_dirty());
+        // The attributes are the ones before the first change.
+        assert_eq!(
+            div.take_changes(),
+            Changes {
+                old_attrs: Some(btreemap! { "class".to_string() => "a".to_string() }),
+                child_changes: vec![
+                    ChildChange::Inserted(0),
+                    ChildChange::Inserted(0),
+                    ChildChange::Removed(1)
+                ],
+            }
+        );
+        assert!(!div.is_dirty());
+        assert_eq!(format!("{}", Node::Element(div.clone())), "(div id=c)");
+
+        // The changes don't matter to equality.
+        let mut changed = div.clone();
+        changed.set_attribute("id", "c");
+        assert!(changed.is_dirty());
+        assert_eq!(changed, div);
+    }
 }
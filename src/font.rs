@@ -0,0 +1,848 @@
+//! Fonts and font matching.
+//!
+//! The installed fonts are the TrueType and OpenType files in a font
+//! directory, not the fonts of the system, so that the result doesn't depend
+//! on the machine. `@font-face` adds web fonts from local files.
+//!
+//! https://drafts.csswg.org/css-fonts-4/#font-matching-algorithm
+
+pub mod sfnt;
+
+use crate::css::font_face::{FontFaceRule, FontSource, FontStyle};
+use crate::css::{self, Value};
+use crate::dom::Node;
+use crate::prelude::*;
+use crate::style::computed_style::TextStyle;
+use crate::style::StyledNode;
+use log::*;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A font file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Font {
+    pub path: PathBuf,
+    pub info: sfnt::FontInfo,
+}
+
+impl Font {
+    pub fn load(path: impl AsRef<Path>) -> Result<Font> {
+        let path = path.as_ref();
+        let error = |message: String| -> failure::Error {
+            EngineError::FontError {
+                path: path.display().to_string(),
+                message,
+            }
+            .into()
+        };
+        let data = fs::read(path).map_err(|e| error(e.to_string()))?;
+        let info = sfnt::parse(&data).map_err(|e| error(e.to_string()))?;
+        Ok(Font {
+            path: path.to_path_buf(),
+            info,
+        })
+    }
+
+    /// Returns true if the font has a glyph for `c`.
+    pub fn has_char(&self, c: char) -> bool {
+        let c = c as u32;
+        self.info
+            .coverage
+            .binary_search_by(|&(start, end)| {
+                if end < c {
+                    std::cmp::Ordering::Less
+                } else if start > c {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+}
+
+/// The installed fonts.
+#[derive(Debug, Default)]
+pub struct FontRegistry {
+    fonts: Vec<Font>,
+}
+
+impl FontRegistry {
+    /// A registry without fonts.
+    pub fn new() -> FontRegistry {
+        Default::default()
+    }
+
+    /// Loads the `.ttf` and `.otf` files in `dir` and its subdirectories, in
+    /// the order of their paths. A file which fails to load is skipped.
+    pub fn scan(dir: impl AsRef<Path>) -> Result<FontRegistry> {
+        fn font_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    font_files(&path, files)?;
+                } else if path.extension().is_some_and(|extension| {
+                    extension.eq_ignore_ascii_case("ttf") || extension.eq_ignore_ascii_case("otf")
+                }) {
+                    files.push(path);
+                }
+            }
+            Ok(())
+        }
+
+        let dir = dir.as_ref();
+        let mut files = vec![];
+        font_files(dir, &mut files).map_err(|e| EngineError::FontError {
+            path: dir.display().to_string(),
+            message: e.to_string(),
+        })?;
+        files.sort();
+        let mut registry = FontRegistry::new();
+        for file in files {
+            match Font::load(&file) {
+                Ok(font) => registry.fonts.push(font),
+                Err(e) => warn!("{}", e),
+            }
+        }
+        Ok(registry)
+    }
+
+    pub fn fonts(&self) -> &[Font] {
+        &self.fonts
+    }
+
+    /// Finds a font by its full name or PostScript name for `local()`.
+    pub fn find_local(&self, name: &str) -> Option<&Font> {
+        self.fonts.iter().find(|font| {
+            font.info.full_name.eq_ignore_ascii_case(name)
+                || font.info.postscript_name.eq_ignore_ascii_case(name)
+        })
+    }
+}
+
+/// A font face which font matching chooses from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Face {
+    pub family: String,
+    pub font: Font,
+    /// The range of weights.
+    pub weight: (f32, f32),
+    /// The width in percent, which is always the one of the font file.
+    pub stretch: f32,
+    pub style: FontStyle,
+    /// Empty for all code points.
+    pub unicode_range: Vec<(u32, u32)>,
+}
+
+impl Face {
+    fn from_rule(rule: &FontFaceRule, registry: &FontRegistry) -> Option<Face> {
+        // The first source which loads is used.
+        let font = rule
+            .sources
+            .iter()
+            .filter(|source| source.is_supported())
+            .find_map(|source| match source {
+                FontSource::Url { url, .. } => Font::load(url).map_err(|e| warn!("{}", e)).ok(),
+                FontSource::Local(name) => {
+                    let font = registry.find_local(name).cloned();
+                    if font.is_none() {
+                        warn!("No installed font is named {:?}", name);
+                    }
+                    font
+                }
+            })?;
+        Some(Face {
+            family: rule.family.clone(),
+            weight: rule.weight.unwrap_or((font.info.weight, font.info.weight)),
+            stretch: font.info.stretch,
+            style: rule.style.unwrap_or(font.info.style),
+            unicode_range: rule.unicode_range.clone(),
+            font,
+        })
+    }
+
+    fn from_installed(font: &Font) -> Face {
+        Face {
+            family: font.info.family.clone(),
+            weight: (font.info.weight, font.info.weight),
+            stretch: font.info.stretch,
+            style: font.info.style,
+            unicode_range: vec![],
+            font: font.clone(),
+        }
+    }
+
+    /// Returns true if the face is used for `c`: `c` is in `unicode-range`
+    /// and the font has a glyph for it.
+    pub fn has_char(&self, c: char) -> bool {
+        let code_point = c as u32;
+        (self.unicode_range.is_empty()
+            || self
+                .unicode_range
+                .iter()
+                .any(|&(start, end)| start <= code_point && code_point <= end))
+            && self.font.has_char(c)
+    }
+
+    fn has_same_descriptors(&self, other: &Face) -> bool {
+        self.family.eq_ignore_ascii_case(&other.family)
+            && self.weight == other.weight
+            && self.stretch == other.stretch
+            && self.style == other.style
+    }
+}
+
+impl fmt::Display for Face {
+    /// e.g. `Open Sans 100-700 italic (fonts/OpenSans.ttf)`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (start, end) = self.weight;
+        write!(f, "{} {}", self.family, start)?;
+        if start != end {
+            write!(f, "-{}", end)?;
+        }
+        write!(f, " {} ({})", self.style.name(), self.font.path.display())
+    }
+}
+
+/// The faces which are available to a document: the `@font-face` rules
+/// and the installed fonts.
+#[derive(Debug, Default)]
+pub struct FontSet {
+    /// From the lowest precedence. Installed faces come first, so a web
+    /// font with the same descriptors wins.
+    faces: Vec<Face>,
+}
+
+impl FontSet {
+    /// `rules` are from the lowest precedence, as `style::font_face_rules`
+    /// returns them. A rule whose sources all fail to load is ignored.
+    ///
+    /// A family which `@font-face` defines hides the installed fonts of the
+    /// same family name.
+    pub fn new(registry: &FontRegistry, rules: &[&FontFaceRule]) -> FontSet {
+        let web_faces: Vec<_> = rules
+            .iter()
+            .filter_map(|rule| {
+                let face = Face::from_rule(rule, registry);
+                if face.is_none() {
+                    warn!("No source of @font-face for {:?} loads", rule.family);
+                }
+                face
+            })
+            .collect();
+        let installed_faces = registry
+            .fonts()
+            .iter()
+            .filter(|font| {
+                !rules
+                    .iter()
+                    .any(|rule| rule.family.eq_ignore_ascii_case(&font.info.family))
+            })
+            .map(Face::from_installed);
+        FontSet {
+            faces: installed_faces.chain(web_faces).collect(),
+        }
+    }
+
+    pub fn faces(&self) -> &[Face] {
+        &self.faces
+    }
+
+    /// Returns the face which renders `c` in the font of `font`, or `None`
+    /// if no face has a glyph for it.
+    ///
+    /// Each named family in the list is tried in order. The faces of a
+    /// family are narrowed to the ones with the closest width, style and
+    /// weight, and one of them which has `c` in its `unicode-range` is used. A
+    /// generic family, or the end of the list, falls back to any face with
+    /// a glyph for `c`.
+    pub fn match_char(&self, font: &FontSpec, c: char) -> Option<&Face> {
+        for family in &font.families {
+            let name = match family {
+                FontFamily::Named(name) => name,
+                FontFamily::Generic(_) => break,
+            };
+            let faces: Vec<_> = self
+                .faces
+                .iter()
+                .filter(|face| face.family.eq_ignore_ascii_case(name))
+                .collect();
+            if let Some(face) = closest_faces(&faces, font)
+                .into_iter()
+                .rev()
+                .find(|face| face.has_char(c))
+            {
+                return Some(face);
+            }
+        }
+        let faces: Vec<_> = self.faces.iter().filter(|face| face.has_char(c)).collect();
+        closest_faces(&faces, font).pop()
+    }
+
+    /// Splits `text` into runs which are rendered by the same face.
+    pub fn runs<'a>(&'a self, font: &FontSpec, text: &str) -> Vec<TextRun<'a>> {
+        let mut runs: Vec<TextRun<'a>> = vec![];
+        for c in text.chars() {
+            let face = self.match_char(font, c);
+            match runs.last_mut() {
+                Some(run) if is_same_face(run.face, face) => run.text.push(c),
+                _ => runs.push(TextRun {
+                    text: c.to_string(),
+                    face,
+                }),
+            }
+        }
+        runs
+    }
+}
+
+fn is_same_face(a: Option<&Face>, b: Option<&Face>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => std::ptr::eq(a, b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Returns the faces with the width, the style and the weight closest to
+/// `font`, in the order of `faces`. They share their descriptors, and differ
+/// only in `unicode-range`.
+///
+/// https://drafts.csswg.org/css-fonts-4/#font-style-matching
+fn closest_faces<'a>(faces: &[&'a Face], font: &FontSpec) -> Vec<&'a Face> {
+    let stretch = match faces.iter().map(|face| face.stretch).min_by(|&a, &b| {
+        stretch_distance(a, font.stretch)
+            .partial_cmp(&stretch_distance(b, font.stretch))
+            .unwrap()
+    }) {
+        Some(stretch) => stretch,
+        None => return vec![],
+    };
+    let faces: Vec<_> = faces
+        .iter()
+        .filter(|face| face.stretch == stretch)
+        .cloned()
+        .collect();
+    let styles = match font.style {
+        FontStyle::Italic => [FontStyle::Italic, FontStyle::Oblique, FontStyle::Normal],
+        FontStyle::Oblique => [FontStyle::Oblique, FontStyle::Italic, FontStyle::Normal],
+        FontStyle::Normal => [FontStyle::Normal, FontStyle::Oblique, FontStyle::Italic],
+    };
+    let style = match styles
+        .iter()
+        .find(|&&style| faces.iter().any(|face| face.style == style))
+    {
+        Some(&style) => style,
+        None => return vec![],
+    };
+    let faces = faces.iter().filter(|face| face.style == style);
+    // The last face wins a tie.
+    let best = match faces.clone().min_by(|a, b| {
+        weight_distance(a.weight, font.weight)
+            .partial_cmp(&weight_distance(b.weight, font.weight))
+            .unwrap()
+            .then(std::cmp::Ordering::Greater)
+    }) {
+        Some(best) => best,
+        None => return vec![],
+    };
+    faces
+        .filter(|face| face.has_same_descriptors(best))
+        .cloned()
+        .collect()
+}
+
+/// Returns how far a width is from the desired width, in the same way as
+/// `weight_distance`. Narrower widths are tried first for a desired width up
+/// to 100%, and wider widths first otherwise.
+///
+/// https://drafts.csswg.org/css-fonts-4/#font-stretch-matching
+fn stretch_distance(stretch: f32, desired: f32) -> (u8, f32) {
+    if stretch == desired {
+        (0, 0.0)
+    } else if (desired <= 100.0) == (stretch < desired) {
+        (1, (stretch - desired).abs())
+    } else {
+        (2, (stretch - desired).abs())
+    }
+}
+
+/// Returns how far a range of weights is from the desired weight. Smaller is
+/// closer: the first element is the order of the search direction, and the
+/// second is the distance in the direction.
+///
+/// https://drafts.csswg.org/css-fonts-4/#font-weight-matching
+fn weight_distance((start, end): (f32, f32), desired: f32) -> (u8, f32) {
+    if start <= desired && desired <= end {
+        (0, 0.0)
+    } else if (400.0..=500.0).contains(&desired) {
+        // Heavier weights up to 500, then lighter weights, then heavier
+        // weights over 500.
+        if desired < start && start <= 500.0 {
+            (1, start - desired)
+        } else if end < desired {
+            (2, desired - end)
+        } else {
+            (3, start - desired)
+        }
+    } else if desired < 400.0 {
+        if end < desired {
+            (1, desired - end)
+        } else {
+            (2, start - desired)
+        }
+    } else if desired < start {
+        (1, start - desired)
+    } else {
+        (2, desired - end)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FontFamily {
+    Named(String),
+    /// e.g. `serif` or `monospace`.
+    Generic(String),
+}
+
+const GENERIC_FAMILIES: &[&str] = &[
+    "serif",
+    "sans-serif",
+    "monospace",
+    "cursive",
+    "fantasy",
+    "system-ui",
+];
+
+/// Returns the family list in a value of `font-family`. A family name in
+/// identifiers is the identifiers joined by a space.
+pub fn font_families(value: &Value) -> Vec<FontFamily> {
+    let items = match value {
+        Value::CommaList(items) => items.as_slice(),
+        _ => std::slice::from_ref(value),
+    };
+    items
+        .iter()
+        .filter_map(|item| match item {
+            Value::String(name) => Some(FontFamily::Named(name.clone())),
+            Value::Keyword(name) => Some(
+                if GENERIC_FAMILIES
+                    .iter()
+                    .any(|generic| generic.eq_ignore_ascii_case(name))
+                {
+                    FontFamily::Generic(name.to_ascii_lowercase())
+                } else {
+                    FontFamily::Named(name.clone())
+                },
+            ),
+            Value::List(names) => {
+                let names: Option<Vec<_>> = names
+                    .iter()
+                    .map(|name| match name {
+                        Value::Keyword(name) => Some(name.as_str()),
+                        _ => None,
+                    })
+                    .collect();
+                names.map(|names| FontFamily::Named(names.join(" ")))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The font properties of an element which font matching uses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontSpec {
+    pub families: Vec<FontFamily>,
+    pub weight: f32,
+    /// `font-stretch` in percent.
+    pub stretch: f32,
+    pub style: FontStyle,
+}
+
+impl Default for FontSpec {
+    fn default() -> FontSpec {
+        FontSpec {
+            families: vec![],
+            weight: 400.0,
+            stretch: 100.0,
+            style: FontStyle::Normal,
+        }
+    }
+}
+
+impl From<&TextStyle> for FontSpec {
+    fn from(style: &TextStyle) -> FontSpec {
+        FontSpec {
+            families: style.font_family.clone(),
+            weight: style.font_weight.0,
+            stretch: style.font_stretch.0,
+            style: style.font_style,
+        }
+    }
+}
+
+/// https://drafts.csswg.org/css-fonts-4/#relative-weights
+pub(crate) fn bolder(weight: f32) -> f32 {
+    if weight < 350.0 {
+        400.0
+    } else if weight < 550.0 {
+        700.0
+    } else if weight < 900.0 {
+        900.0
+    } else {
+        weight
+    }
+}
+
+pub(crate) fn lighter(weight: f32) -> f32 {
+    if weight < 100.0 {
+        weight
+    } else if weight < 550.0 {
+        100.0
+    } else if weight < 750.0 {
+        400.0
+    } else {
+        700.0
+    }
+}
+
+/// A part of a text which is rendered by a face, or by none if no face has
+/// glyphs for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextRun<'a> {
+    pub text: String,
+    pub face: Option<&'a Face>,
+}
+
+impl fmt::Display for TextRun<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.face {
+            Some(face) => write!(f, "{:?} {}", self.text, face),
+            None => write!(f, "{:?} (no font)", self.text),
+        }
+    }
+}
+
+/// Returns the text runs of the text nodes in a style tree, in tree order.
+pub fn text_runs<'a>(root: &StyledNode<'_>, fonts: &'a FontSet) -> Vec<TextRun<'a>> {
+    fn collect<'a>(node: &StyledNode<'_>, fonts: &'a FontSet, runs: &mut Vec<TextRun<'a>>) {
+        match node.node {
+            Node::Text(text) => runs.extend(fonts.runs(&FontSpec::from(&*node.style.text), text)),
+            Node::Element(_) => {
+                for child in &node.children {
+                    collect(child, fonts, runs);
+                }
+            }
+        }
+    }
+    let mut runs = vec![];
+    collect(root, fonts, &mut runs);
+    runs
+}
+
+/// Loads the fonts of `stylesheets` in `env` and the fonts in `font_dir`,
+/// and returns the text runs of `html`.
+pub fn match_fonts(
+    html: &str,
+    stylesheets: &[&css::Stylesheet],
+    env: &css::media::Environment,
+    font_dir: Option<&Path>,
+) -> Result<String> {
+    let node = crate::parse_html(html)?;
+    let style_tree = crate::style::style_tree(&node, stylesheets, env);
+    let registry = match font_dir {
+        Some(dir) => FontRegistry::scan(dir)?,
+        None => FontRegistry::new(),
+    };
+    let fonts = FontSet::new(&registry, &crate::style::font_face_rules(stylesheets, env));
+    Ok(text_runs(&style_tree, &fonts)
+        .iter()
+        .map(|run| format!("{}\n", run))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::css::parser::{parse_stylesheet, parse_value};
+
+    /// Creates font files in a new temporary directory.
+    fn create_fonts(name: &str, fonts: &[(&str, Vec<u8>)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("serval-{}-{}", name, std::process::id()));
+        for (file, data) in fonts {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        }
+        dir
+    }
+
+    fn face(weight: (f32, f32), style: FontStyle) -> Face {
+        Face {
+            family: "a".to_string(),
+            font: Font {
+                path: PathBuf::new(),
+                info: sfnt::FontInfo {
+                    family: "a".to_string(),
+                    full_name: String::new(),
+                    postscript_name: String::new(),
+                    weight: weight.0,
+                    stretch: 100.0,
+                    style,
+                    coverage: vec![(0, 0x10_FFFF)],
+                },
+            },
+            weight,
+            stretch: 100.0,
+            style,
+            unicode_range: vec![],
+        }
+    }
+
+    #[test]
+    fn closest_faces_test() {
+        let faces = [
+            face((300.0, 300.0), FontStyle::Normal),
+            face((600.0, 600.0), FontStyle::Normal),
+            face((900.0, 900.0), FontStyle::Normal),
+            face((100.0, 200.0), FontStyle::Oblique),
+            face((700.0, 700.0), FontStyle::Italic),
+        ];
+        let faces: Vec<_> = faces.iter().collect();
+        let spec = |weight, style| FontSpec {
+            weight,
+            style,
+            ..Default::default()
+        };
+        let closest = |weight, style| closest_faces(&faces, &spec(weight, style))[0].weight.0;
+
+        // 400 to 500: heavier up to 500, then lighter, then heavier.
+        assert_eq!(closest(400.0, FontStyle::Normal), 300.0);
+        assert_eq!(closest(500.0, FontStyle::Normal), 300.0);
+        // Under 400: lighter first. Over 500: heavier first.
+        assert_eq!(closest(250.0, FontStyle::Normal), 300.0);
+        assert_eq!(closest(350.0, FontStyle::Normal), 300.0);
+        assert_eq!(closest(650.0, FontStyle::Normal), 900.0);
+        assert_eq!(closest(950.0, FontStyle::Normal), 900.0);
+        // Italic falls back to oblique before normal, and normal to oblique
+        // before italic.
+        assert_eq!(closest(400.0, FontStyle::Italic), 700.0);
+        assert_eq!(closest(150.0, FontStyle::Oblique), 100.0);
+        let faces = faces[3..].to_vec();
+        assert_eq!(
+            closest_faces(&faces, &spec(400.0, FontStyle::Normal))[0].style,
+            FontStyle::Oblique
+        );
+
+        // The width is matched before the style: narrower widths first up to
+        // 100%, and wider ones first over it.
+        let faces: Vec<_> = [75.0, 87.5, 125.0]
+            .iter()
+            .map(|&stretch| Face {
+                stretch,
+                ..face((400.0, 400.0), FontStyle::Italic)
+            })
+            .chain(Some(face((400.0, 400.0), FontStyle::Normal)))
+            .collect();
+        let faces: Vec<_> = faces.iter().collect();
+        let closest = |stretch| {
+            let font = FontSpec {
+                stretch,
+                style: FontStyle::Italic,
+                ..Default::default()
+            };
+            let faces = closest_faces(&faces, &font);
+            (faces[0].stretch, faces[0].style)
+        };
+        assert_eq!(closest(100.0), (100.0, FontStyle::Normal));
+        assert_eq!(closest(80.0), (75.0, FontStyle::Italic));
+        assert_eq!(closest(70.0), (75.0, FontStyle::Italic));
+        assert_eq!(closest(112.5), (125.0, FontStyle::Italic));
+        assert_eq!(closest(150.0), (125.0, FontStyle::Italic));
+    }
+
+    #[test]
+    fn font_families_test() {
+        let families = |s| font_families(&parse_value(s).unwrap());
+        assert_eq!(
+            families("\"Helvetica Neue\", Open  Sans, serif, 'monospace'"),
+            vec![
+                FontFamily::Named("Helvetica Neue".to_string()),
+                FontFamily::Named("Open Sans".to_string()),
+                FontFamily::Generic("serif".to_string()),
+                FontFamily::Named("monospace".to_string()),
+            ]
+        );
+        assert_eq!(bolder(500.0), 700.0);
+        assert_eq!(lighter(500.0), 100.0);
+    }
+
+    #[test]
+    fn match_fonts_test() {
+        let ascii = &[(0x20, 0x7E)];
+        let dir = create_fonts(
+            "fonts",
+            &[
+                (
+                    "installed/Serif-Regular.ttf",
+                    sfnt::build(
+                        "Serif",
+                        "Regular",
+                        400,
+                        FontStyle::Normal,
+                        &[(0x20, 0x7E), (0x3B1, 0x3C9)],
+                    ),
+                ),
+                (
+                    "installed/Hidden-Regular.otf",
+                    sfnt::build("Web", "Regular", 400, FontStyle::Normal, ascii),
+                ),
+                ("installed/broken.ttf", b"broken".to_vec()),
+                ("installed/notes.txt", vec![]),
+                (
+                    "web/web-bold.ttf",
+                    sfnt::build("W", "Bold", 700, FontStyle::Normal, ascii),
+                ),
+                (
+                    "web/web-italic.ttf",
+                    sfnt::build("W", "Italic", 400, FontStyle::Italic, ascii),
+                ),
+                (
+                    "web/digits.ttf",
+                    sfnt::build("D", "Regular", 400, FontStyle::Normal, &[(0x30, 0x39)]),
+                ),
+            ],
+        );
+        let registry = FontRegistry::scan(dir.join("installed")).unwrap();
+        assert_eq!(registry.fonts().len(), 2);
+
+        let url = |file: &str| dir.join("web").join(file).display().to_string();
+        let stylesheet = parse_stylesheet(&format!(
+            "@font-face {{ font-family: Web; src: url({bold}) format('woff2'), url({bold}) }} \
+             @font-face {{ font-family: Web; src: url(missing.ttf), url({italic}) }} \
+             @font-face {{ font-family: Web; src: url({digits}); font-weight: 700; \
+                           unicode-range: U+30-39 }} \
+             @font-face {{ font-family: Local; src: local(Serif-Regular) }} \
+             @font-face {{ font-family: Nothing; src: local(Missing) }}",
+            bold = url("web-bold.ttf"),
+            italic = url("web-italic.ttf"),
+            digits = url("digits.ttf"),
+        ))
+        .unwrap();
+        let rules = crate::style::font_face_rules(&[&stylesheet], &Default::default());
+        let fonts = FontSet::new(&registry, &rules);
+        // The installed family "Web" is hidden, and "Nothing" has no font.
+        assert_eq!(fonts.faces().len(), 5);
+
+        let spec = |families: &str, weight, style| FontSpec {
+            families: font_families(&parse_value(families).unwrap()),
+            weight,
+            style,
+            ..Default::default()
+        };
+        let runs = |spec: &FontSpec, text| {
+            fonts
+                .runs(spec, text)
+                .into_iter()
+                .map(|run| {
+                    (
+                        run.text,
+                        run.face.map(|face| face.font.info.full_name.clone()),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let run = |text: &str, name: Option<&str>| (text.to_string(), name.map(str::to_string));
+
+        // The digits come from the face with `unicode-range`, and Greek falls
+        // back to the installed font.
+        assert_eq!(
+            runs(&spec("Web, serif", 700.0, FontStyle::Normal), "a1 αβ"),
+            vec![
+                run("a", Some("W Bold")),
+                run("1", Some("D Regular")),
+                run(" ", Some("W Bold")),
+                run("αβ", Some("Serif Regular")),
+            ]
+        );
+        // The style is matched before the weight, so the normal faces of 700
+        // are closer than the italic face of 400.
+        assert_eq!(
+            runs(&spec("Web", 400.0, FontStyle::Normal), "a1"),
+            vec![run("a", Some("W Bold")), run("1", Some("D Regular"))]
+        );
+        assert_eq!(
+            runs(&spec("Web", 700.0, FontStyle::Italic), "a1"),
+            vec![run("a1", Some("W Italic"))]
+        );
+        // `local()` uses an installed font, and a missing family falls back.
+        assert_eq!(
+            runs(
+                &spec("Nothing, Local", 400.0, FontStyle::Normal),
+                "a\u{1F600}"
+            ),
+            vec![run("a", Some("Serif Regular")), run("\u{1F600}", None)]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn text_runs_test() {
+        let dir = create_fonts(
+            "text-runs",
+            &[
+                (
+                    "Serif-Regular.ttf",
+                    sfnt::build("Serif", "Regular", 400, FontStyle::Normal, &[(0x20, 0x7E)]),
+                ),
+                (
+                    "Serif-Bold.ttf",
+                    sfnt::build("Serif", "Bold", 700, FontStyle::Normal, &[(0x20, 0x7E)]),
+                ),
+                (
+                    "Serif-BoldItalic.ttf",
+                    sfnt::build(
+                        "Serif",
+                        "Bold Italic",
+                        700,
+                        FontStyle::Italic,
+                        &[(0x20, 0x7E)],
+                    ),
+                ),
+            ],
+        );
+        let stylesheet = parse_stylesheet(
+            "div { font: bold 12px Serif } .i { font-style: italic } .l { font-weight: lighter }",
+        )
+        .unwrap();
+        let text = match_fonts(
+            "(body \"a\" (div \"b\" (p class=i \"c\") (p class=l \"d\")))",
+            &[&stylesheet],
+            &Default::default(),
+            Some(&dir),
+        )
+        .unwrap();
+        let line = |text: &str, file: &str, weight, style| {
+            format!(
+                "{:?} Serif {} {} ({})\n",
+                text,
+                weight,
+                style,
+                dir.join(file).display()
+            )
+        };
+        assert_eq!(
+            text,
+            [
+                line("a", "Serif-Regular.ttf", 400, "normal"),
+                line("b", "Serif-Bold.ttf", 700, "normal"),
+                line("c", "Serif-BoldItalic.ttf", 700, "italic"),
+                line("d", "Serif-Regular.ttf", 400, "normal"),
+            ]
+            .concat()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,455 @@
+//! Reads what font matching needs from a TrueType or OpenType file: the
+//! names, the weight, the style and the characters which it maps to glyphs.
+//! Glyph outlines are not read.
+//!
+//! https://learn.microsoft.com/en-us/typography/opentype/spec/otff
+
+use crate::css::font_face::FontStyle;
+use crate::prelude::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontInfo {
+    /// The typographic family name, or the family name if there is none.
+    pub family: String,
+    pub full_name: String,
+    pub postscript_name: String,
+    /// `usWeightClass` of the OS/2 table, or 400 or 700 from `head`.
+    pub weight: f32,
+    /// The width in percent, from `usWidthClass` of the OS/2 table.
+    pub stretch: f32,
+    pub style: FontStyle,
+    /// The sorted and disjoint ranges of the code points with glyphs.
+    pub coverage: Vec<(u32, u32)>,
+}
+
+const NAME_FAMILY: u16 = 1;
+const NAME_SUBFAMILY: u16 = 2;
+const NAME_FULL: u16 = 4;
+const NAME_POSTSCRIPT: u16 = 6;
+const NAME_TYPOGRAPHIC_FAMILY: u16 = 16;
+
+pub fn parse(data: &[u8]) -> Result<FontInfo> {
+    match read_u32(data, 0)? {
+        0x0001_0000 | 0x4F54_544F | 0x7472_7565 => {}
+        0x7474_6366 => return Err(failure::err_msg("font collections are not supported")),
+        _ => return Err(failure::err_msg("not a TrueType or OpenType font")),
+    }
+    let tables = tables(data)?;
+    let table = |tag: &[u8; 4]| {
+        tables
+            .iter()
+            .find(|(t, _)| t == tag)
+            .map(|&(_, table)| table)
+    };
+
+    let name = table(b"name").ok_or_else(|| failure::err_msg("no name table"))?;
+    let names = names(name)?;
+    let get = |id| {
+        names
+            .iter()
+            .find(|(name_id, _)| *name_id == id)
+            .map(|(_, s)| s.clone())
+    };
+    let family = get(NAME_TYPOGRAPHIC_FAMILY)
+        .or_else(|| get(NAME_FAMILY))
+        .ok_or_else(|| failure::err_msg("no family name"))?;
+    let full_name = get(NAME_FULL).unwrap_or_else(|| {
+        let subfamily = get(NAME_SUBFAMILY).unwrap_or_default();
+        format!("{} {}", family, subfamily).trim_end().to_string()
+    });
+    let postscript_name = get(NAME_POSTSCRIPT).unwrap_or_default();
+
+    // The OS/2 table wins over `macStyle` of the head table.
+    let (weight, stretch, style) = match table(b"OS/2") {
+        Some(os2) => {
+            let fs_selection = read_u16(os2, 62)?;
+            let style = if fs_selection & 1 != 0 {
+                FontStyle::Italic
+            } else if fs_selection & (1 << 9) != 0 {
+                FontStyle::Oblique
+            } else {
+                FontStyle::Normal
+            };
+            const WIDTHS: [f32; 9] = [50.0, 62.5, 75.0, 87.5, 100.0, 112.5, 125.0, 150.0, 200.0];
+            let width = read_u16(os2, 6)?.clamp(1, 9);
+            (
+                f32::from(read_u16(os2, 4)?.clamp(1, 1000)),
+                WIDTHS[width as usize - 1],
+                style,
+            )
+        }
+        None => {
+            let mac_style = match table(b"head") {
+                Some(head) => read_u16(head, 44)?,
+                None => 0,
+            };
+            let weight = if mac_style & 1 != 0 { 700.0 } else { 400.0 };
+            let style = if mac_style & 2 != 0 {
+                FontStyle::Italic
+            } else {
+                FontStyle::Normal
+            };
+            (weight, 100.0, style)
+        }
+    };
+
+    let cmap = table(b"cmap").ok_or_else(|| failure::err_msg("no cmap table"))?;
+    Ok(FontInfo {
+        family,
+        full_name,
+        postscript_name,
+        weight,
+        stretch,
+        style,
+        coverage: cmap_coverage(cmap)?,
+    })
+}
+
+/// Returns the tables by tag.
+fn tables(data: &[u8]) -> Result<Vec<([u8; 4], &[u8])>> {
+    let count = read_u16(data, 4)? as usize;
+    let mut tables = Vec::with_capacity(count);
+    for i in 0..count {
+        let record = 12 + i * 16;
+        let tag = slice(data, record, 4)?;
+        let offset = read_u32(data, record + 8)? as usize;
+        let length = read_u32(data, record + 12)? as usize;
+        tables.push((
+            [tag[0], tag[1], tag[2], tag[3]],
+            slice(data, offset, length)?,
+        ));
+    }
+    Ok(tables)
+}
+
+/// Returns the names by name ID. A Windows name in US English is preferred,
+/// then any Windows name, then a Unicode name and then a Macintosh name.
+fn names(table: &[u8]) -> Result<Vec<(u16, String)>> {
+    let count = read_u16(table, 2)? as usize;
+    let storage = read_u16(table, 4)? as usize;
+    let mut names: Vec<(u16, u8, String)> = vec![];
+    for i in 0..count {
+        let record = 6 + i * 12;
+        let platform = read_u16(table, record)?;
+        let encoding = read_u16(table, record + 2)?;
+        let language = read_u16(table, record + 4)?;
+        let name_id = read_u16(table, record + 6)?;
+        let length = read_u16(table, record + 8)? as usize;
+        let offset = read_u16(table, record + 10)? as usize;
+        let bytes = slice(table, storage + offset, length)?;
+        let (rank, name) = match (platform, encoding) {
+            (3, 1) | (3, 10) => (if language == 0x409 { 0 } else { 1 }, utf16_be(bytes)),
+            (0, _) => (2, utf16_be(bytes)),
+            // Mac Roman, which is read as ASCII.
+            (1, 0) => (3, Some(bytes.iter().map(|&b| b as char).collect())),
+            _ => continue,
+        };
+        let name = match name {
+            Some(name) if !name.is_empty() => name,
+            _ => continue,
+        };
+        match names.iter_mut().find(|(id, _, _)| *id == name_id) {
+            Some(entry) if rank < entry.1 => *entry = (name_id, rank, name),
+            Some(_) => {}
+            None => names.push((name_id, rank, name)),
+        }
+    }
+    Ok(names
+        .into_iter()
+        .map(|(name_id, _, name)| (name_id, name))
+        .collect())
+}
+
+fn utf16_be(bytes: &[u8]) -> Option<String> {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16(&units).ok()
+}
+
+/// Returns the code points which the best Unicode subtable of a cmap table
+/// maps to glyphs other than `.notdef`. A format 12 subtable is preferred
+/// to a format 4 one, because it covers all planes.
+fn cmap_coverage(table: &[u8]) -> Result<Vec<(u32, u32)>> {
+    let count = read_u16(table, 2)? as usize;
+    let mut best: Option<(u16, &[u8])> = None;
+    for i in 0..count {
+        let record = 4 + i * 8;
+        let platform = read_u16(table, record)?;
+        let encoding = read_u16(table, record + 2)?;
+        if !(platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10))) {
+            continue;
+        }
+        let offset = read_u32(table, record + 4)? as usize;
+        let subtable = slice(table, offset, table.len().saturating_sub(offset))?;
+        let format = read_u16(subtable, 0)?;
+        let better = match best {
+            None => format == 4 || format == 12,
+            Some((best_format, _)) => format == 12 && best_format == 4,
+        };
+        if better {
+            best = Some((format, subtable));
+        }
+    }
+    let mut ranges = match best {
+        Some((4, subtable)) => format4_coverage(subtable)?,
+        Some((_, subtable)) => format12_coverage(subtable)?,
+        None => return Err(failure::err_msg("no Unicode cmap subtable")),
+    };
+    ranges.sort();
+    let mut merged: Vec<(u32, u32)> = vec![];
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    Ok(merged)
+}
+
+/// https://learn.microsoft.com/en-us/typography/opentype/spec/cmap#format-4-segment-mapping-to-delta-values
+fn format4_coverage(subtable: &[u8]) -> Result<Vec<(u32, u32)>> {
+    let segments = read_u16(subtable, 6)? as usize / 2;
+    let end_codes = 14;
+    let start_codes = end_codes + segments * 2 + 2;
+    let deltas = start_codes + segments * 2;
+    let range_offsets = deltas + segments * 2;
+    let mut ranges = vec![];
+    for i in 0..segments {
+        let end = read_u16(subtable, end_codes + i * 2)?;
+        let start = read_u16(subtable, start_codes + i * 2)?;
+        let delta = read_u16(subtable, deltas + i * 2)?;
+        let range_offset = read_u16(subtable, range_offsets + i * 2)? as usize;
+        if start > end {
+            continue;
+        }
+        for c in start..=end {
+            // The last segment only maps 0xFFFF to `.notdef`.
+            if c == 0xFFFF {
+                continue;
+            }
+            let glyph = if range_offset == 0 {
+                c.wrapping_add(delta)
+            } else {
+                let offset = range_offsets + i * 2 + range_offset + (c - start) as usize * 2;
+                match read_u16(subtable, offset)? {
+                    0 => 0,
+                    glyph => glyph.wrapping_add(delta),
+                }
+            };
+            if glyph != 0 {
+                ranges.push((u32::from(c), u32::from(c)));
+            }
+        }
+    }
+    Ok(ranges)
+}
+
+/// https://learn.microsoft.com/en-us/typography/opentype/spec/cmap#format-12-segmented-coverage
+fn format12_coverage(subtable: &[u8]) -> Result<Vec<(u32, u32)>> {
+    let groups = read_u32(subtable, 12)? as usize;
+    // `numGroups` is not trusted: a corrupt one ends at the end of the
+    // subtable.
+    let mut ranges = Vec::with_capacity(groups.min(subtable.len() / 12));
+    for i in 0..groups {
+        let group = 16 + i * 12;
+        let mut start = read_u32(subtable, group)?;
+        let end = read_u32(subtable, group + 4)?.min(0x10_FFFF);
+        if read_u32(subtable, group + 8)? == 0 {
+            start = start.saturating_add(1);
+        }
+        // A start beyond Unicode is after `end`, so the group is dropped.
+        if start <= end {
+            ranges.push((start, end));
+        }
+    }
+    Ok(ranges)
+}
+
+fn slice(data: &[u8], offset: usize, length: usize) -> Result<&[u8]> {
+    offset
+        .checked_add(length)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| failure::err_msg("unexpected end of data"))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = slice(data, offset, 2)?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = slice(data, offset, 4)?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Builds a minimal TrueType file of normal width with a name table, an
+/// OS/2 table and a format 12 cmap table which maps `coverage`.
+#[cfg(test)]
+pub(crate) fn build(
+    family: &str,
+    subfamily: &str,
+    weight: u16,
+    style: FontStyle,
+    coverage: &[(u32, u32)],
+) -> Vec<u8> {
+    fn push_u16(data: &mut Vec<u8>, n: u16) {
+        data.extend_from_slice(&n.to_be_bytes());
+    }
+    fn push_u32(data: &mut Vec<u8>, n: u32) {
+        data.extend_from_slice(&n.to_be_bytes());
+    }
+
+    let mut os2 = vec![0; 78];
+    os2[4..6].copy_from_slice(&weight.to_be_bytes());
+    os2[6..8].copy_from_slice(&5u16.to_be_bytes());
+    let fs_selection: u16 = match style {
+        FontStyle::Normal => 1 << 6,
+        FontStyle::Italic => 1,
+        FontStyle::Oblique => 1 << 9,
+    };
+    os2[62..64].copy_from_slice(&fs_selection.to_be_bytes());
+
+    let mut cmap = vec![];
+    push_u16(&mut cmap, 0);
+    push_u16(&mut cmap, 1);
+    push_u16(&mut cmap, 3);
+    push_u16(&mut cmap, 10);
+    push_u32(&mut cmap, 12);
+    push_u16(&mut cmap, 12);
+    push_u16(&mut cmap, 0);
+    push_u32(&mut cmap, 16 + coverage.len() as u32 * 12);
+    push_u32(&mut cmap, 0);
+    push_u32(&mut cmap, coverage.len() as u32);
+    let mut glyph = 1;
+    for &(start, end) in coverage {
+        push_u32(&mut cmap, start);
+        push_u32(&mut cmap, end);
+        push_u32(&mut cmap, glyph);
+        glyph += end - start + 1;
+    }
+
+    let full_name = format!("{} {}", family, subfamily);
+    let postscript_name = format!("{}-{}", family, subfamily).replace(' ', "");
+    let strings = [
+        (NAME_FAMILY, family),
+        (NAME_SUBFAMILY, subfamily),
+        (NAME_FULL, full_name.as_str()),
+        (NAME_POSTSCRIPT, postscript_name.as_str()),
+    ];
+    let mut name = vec![];
+    let mut storage = vec![];
+    push_u16(&mut name, 0);
+    push_u16(&mut name, strings.len() as u16);
+    push_u16(&mut name, 6 + strings.len() as u16 * 12);
+    for (id, s) in &strings {
+        let bytes: Vec<u8> = s.encode_utf16().flat_map(|u| u.to_be_bytes()).collect();
+        for &n in &[3, 1, 0x409, *id, bytes.len() as u16, storage.len() as u16] {
+            push_u16(&mut name, n);
+        }
+        storage.extend(bytes);
+    }
+    name.extend(storage);
+
+    // The tables are sorted by tag.
+    let tables: [(&[u8; 4], Vec<u8>); 3] = [(b"OS/2", os2), (b"cmap", cmap), (b"name", name)];
+    let mut data = vec![];
+    push_u32(&mut data, 0x0001_0000);
+    push_u16(&mut data, tables.len() as u16);
+    data.extend_from_slice(&[0; 6]);
+    let mut offset = 12 + tables.len() * 16;
+    for (tag, table) in &tables {
+        data.extend_from_slice(*tag);
+        push_u32(&mut data, 0);
+        push_u32(&mut data, offset as u32);
+        push_u32(&mut data, table.len() as u32);
+        offset += table.len().div_ceil(4) * 4;
+    }
+    for (_, table) in &tables {
+        data.extend_from_slice(table);
+        data.resize(data.len().div_ceil(4) * 4, 0);
+    }
+    data
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let data = build(
+            "Test Sans",
+            "Bold Italic",
+            700,
+            FontStyle::Italic,
+            &[(0x41, 0x5A), (0x5B, 0x60), (0x1F600, 0x1F600)],
+        );
+        assert_eq!(
+            parse(&data).unwrap(),
+            FontInfo {
+                family: "Test Sans".to_string(),
+                full_name: "Test Sans Bold Italic".to_string(),
+                postscript_name: "TestSans-BoldItalic".to_string(),
+                weight: 700.0,
+                stretch: 100.0,
+                style: FontStyle::Italic,
+                coverage: vec![(0x41, 0x60), (0x1F600, 0x1F600)],
+            }
+        );
+
+        assert!(parse(b"ttcf\0\x01\0\0").is_err());
+        assert!(parse(b"not a font").is_err());
+        // A truncated file is an error, not a panic.
+        assert!(parse(&data[..data.len() - 10]).is_err());
+
+        // The cmap table is the second one, and its subtable follows the
+        // header and the encoding record.
+        let subtable = read_u32(&data, 12 + 16 + 8).unwrap() as usize + 12;
+        let patched = |words: &[(usize, u32)]| {
+            let mut data = data.clone();
+            for &(offset, n) in words {
+                let offset = subtable + offset;
+                data[offset..offset + 4].copy_from_slice(&n.to_be_bytes());
+            }
+            data
+        };
+        // A huge `numGroups` runs into the end of the table.
+        assert!(parse(&patched(&[(12, 0xFFFF_FFF0)])).is_err());
+        // The first group starts at the largest code and maps it to
+        // `.notdef`.
+        assert_eq!(
+            parse(&patched(&[(16, u32::MAX), (24, 0)]))
+                .unwrap()
+                .coverage,
+            vec![(0x5B, 0x60), (0x1F600, 0x1F600)]
+        );
+    }
+
+    #[test]
+    fn format4_coverage_test() {
+        // Two segments and the final one: 'a'-'c' by delta, 'x'-'z' by the
+        // glyph array, where 'y' has no glyph.
+        let words: &[u16] = &[
+            4, 0, 0, 6, 0, 0, 0, // header with segCountX2 = 6
+            0x63, 0x7A, 0xFFFF, // endCode
+            0,      // reservedPad
+            0x61, 0x78, 0xFFFF, // startCode
+            0xFFA0, 0, 1, // idDelta
+            0, 4, 0, // idRangeOffset
+            5, 0, 6, // glyphIdArray
+        ];
+        let subtable: Vec<u8> = words.iter().flat_map(|w| w.to_be_bytes()).collect();
+        assert_eq!(
+            format4_coverage(&subtable).unwrap(),
+            vec![
+                (0x61, 0x61),
+                (0x62, 0x62),
+                (0x63, 0x63),
+                (0x78, 0x78),
+                (0x7A, 0x7A)
+            ]
+        );
+    }
+}
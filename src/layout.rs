@@ -1,5 +1,11 @@
 use crate::css;
-use crate::style::{Display, StyledNode};
+use crate::css::container::{ContainerSize, ContainerType};
+use crate::css::media;
+use crate::dom::Node;
+use crate::style;
+use crate::style::computed_style::{LengthPercentage, LengthPercentageAuto};
+use crate::style::container::ContainerSizes;
+use crate::style::{Display, Marker, StyledNode};
 
 use crate::prelude::*;
 
@@ -38,6 +44,48 @@ impl std::fmt::Display for Rect {
     }
 }
 
+/// The initial containing block. Viewport-percentage lengths refer to its
+/// size.
+#[derive(Clone, Copy, Debug)]
+pub struct Viewport {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Viewport {
+            width: 800.0,
+            height: 800.0,
+        }
+    }
+}
+
+impl From<&media::Environment> for Viewport {
+    fn from(env: &media::Environment) -> Self {
+        Viewport {
+            width: env.width,
+            height: env.height,
+        }
+    }
+}
+
+impl Viewport {
+    fn containing_block(&self) -> Dimensions {
+        Dimensions {
+            content: Rect {
+                x: 0.0,
+                y: 0.0,
+                width: self.width,
+                // The height of a containing block is used as the offset of
+                // the next child in block layout. See `layout_block_children`.
+                height: 0.0,
+            },
+            ..Default::default()
+        }
+    }
+}
+
 // TODO: Rename this?
 // LayoutBox => BoxNode,
 // Dimensions => LayoutBox?
@@ -106,6 +154,8 @@ pub struct LayoutBox<'a> {
     pub(crate) dimensions: Dimensions,
     pub(crate) box_type: BoxType<'a>,
     pub(crate) children: Vec<LayoutBox<'a>>,
+    /// The size of a query container once it is laid out.
+    container_size: Option<ContainerSize>,
 }
 
 impl std::fmt::Display for LayoutBox<'_> {
@@ -138,12 +188,22 @@ impl<'a> LayoutBox<'a> {
     fn new(style_node: &'a StyledNode<'a>) -> LayoutBox<'a> {
         LayoutBox {
             box_type: match style_node.display() {
-                Display::Block => BoxType::BlockNode(style_node),
+                Display::Block | Display::ListItem => BoxType::BlockNode(style_node),
                 Display::Inline => BoxType::InlineNode(style_node),
                 Display::None => unreachable!(),
             },
             dimensions: Default::default(),
             children: Default::default(),
+            container_size: None,
+        }
+    }
+
+    fn new_marker(marker: &'a Marker) -> LayoutBox<'a> {
+        LayoutBox {
+            box_type: BoxType::MarkerBox(marker),
+            dimensions: Default::default(),
+            children: Default::default(),
+            container_size: None,
         }
     }
 
@@ -152,6 +212,7 @@ impl<'a> LayoutBox<'a> {
             box_type: BoxType::AnonymousBlock,
             dimensions: Default::default(),
             children: Default::default(),
+            container_size: None,
         }
     }
 
@@ -168,24 +229,78 @@ impl<'a> LayoutBox<'a> {
                 }
                 self.children.last_mut().unwrap()
             }
+            BoxType::MarkerBox(_) => unreachable!("A marker has no children"),
         }
     }
 
-    pub fn layout(&mut self, containing_block: &Dimensions) {
+    /// Lays out this box as the root box of a document.
+    pub fn layout_root(&mut self, viewport: &Viewport) {
+        let context = css::LengthContext::new(viewport.width, viewport.height);
+        self.layout(
+            &viewport.containing_block(),
+            &css::LengthContext {
+                // `rem` refers to the font size of the root element.
+                root_font_size: self.get_style_node().style.text.font_size,
+                ..context
+            },
+        );
+    }
+
+    /// `context` is the length context of the parent box.
+    pub fn layout(&mut self, containing_block: &Dimensions, context: &css::LengthContext) {
         debug!("layout: {}", self);
         match self.box_type {
-            BoxType::BlockNode(_) => self.layout_block(containing_block),
+            BoxType::BlockNode(_) => self.layout_block(containing_block, context),
             // TODO: Implement this.
             // See https://www.w3.org/TR/css-inline-3/
             // https://drafts.csswg.org/css-inline-3/
             BoxType::InlineNode(_) => unimplemented!(),
             BoxType::AnonymousBlock => unimplemented!(),
+            BoxType::MarkerBox(marker) => self.layout_marker(marker, containing_block, context),
+        }
+    }
+
+    /// Lays out a marker as a line of text. Text isn't shaped yet, so each
+    /// character is as wide as `1ch` and the line is `1em` high. An outside
+    /// marker is put before the start of the list item's content, out of
+    /// the flow.
+    fn layout_marker(
+        &mut self,
+        marker: &Marker,
+        containing_block: &Dimensions,
+        context: &css::LengthContext,
+    ) {
+        debug!("layout_marker: {}", self);
+        let context = css::LengthContext {
+            font_size: marker.style.text.font_size,
+            ..*context
+        };
+        let d = &mut self.dimensions;
+        d.content.width = marker.text.chars().count() as f32 * context.font_size / 2.0;
+        d.content.height = context.font_size;
+        d.content.x = if marker.outside {
+            containing_block.content.x - d.content.width
+        } else {
+            containing_block.content.x
+        };
+        d.content.y = containing_block.content.y + containing_block.content.height;
+    }
+
+    fn is_out_of_flow(&self) -> bool {
+        match self.box_type {
+            BoxType::MarkerBox(marker) => marker.outside,
+            _ => false,
         }
     }
 
     // https://limpet.net/mbrubeck/2014/09/17/toy-layout-engine-6-block.html
-    fn layout_block(&mut self, containing_block: &Dimensions) {
+    fn layout_block(&mut self, containing_block: &Dimensions, context: &css::LengthContext) {
         debug!("layout_block: {}", self);
+        let context = css::LengthContext {
+            font_size: self.get_style_node().style.text.font_size,
+            ..*context
+        };
+
         // Child width can depend on parent width, so we need to calculate
         // this box's width before laying out its children.
         self.calculate_block_width(containing_block);
@@ -194,107 +309,128 @@ impl<'a> LayoutBox<'a> {
         self.calculate_block_position(containing_block);
 
         // Recursively lay out the children of this box.
-        self.layout_block_children();
+        self.layout_block_children(&context);
 
         // Parent height can depend on child height, so `calculate_height`
         // must be called *after* the children are laid out.
         self.calculate_block_height();
+
+        let content = &self.dimensions.content;
+        self.container_size = match self.container_type() {
+            ContainerType::Normal => None,
+            ContainerType::Size => Some(ContainerSize {
+                width: content.width,
+                height: Some(content.height),
+                context,
+            }),
+            ContainerType::InlineSize => Some(ContainerSize {
+                width: content.width,
+                height: None,
+                context,
+            }),
+        };
+    }
+
+    fn container_type(&self) -> ContainerType {
+        self.get_style_node().style.box_style.container_type
+    }
+
+    /// Adds the sizes of the query containers in this tree to `sizes`.
+    /// Returns true if any of them is new.
+    fn collect_container_sizes(&self, sizes: &mut ContainerSizes) -> bool {
+        let mut added = false;
+        if let (BoxType::BlockNode(style_node), Some(size)) = (&self.box_type, self.container_size)
+        {
+            added |= sizes.insert(style_node.node, size);
+        }
+        for child in &self.children {
+            added |= child.collect_container_sizes(sizes);
+        }
+        added
     }
 
     fn get_style_node(&self) -> &'a StyledNode<'a> {
         match self.box_type {
             BoxType::BlockNode(node) | BoxType::InlineNode(node) => node,
             BoxType::AnonymousBlock => unreachable!("Anonymous block has no style node"),
+            BoxType::MarkerBox(_) => unreachable!("A marker has no style node"),
         }
     }
 
     fn calculate_block_width(&mut self, containing_block: &Dimensions) {
         debug!("calculate_block_width: {}", self);
-        let style = self.get_style_node();
-        let width = style.value("width").unwrap_or(&css::Value::keyword_auto());
-
-        let zero = css::Value::length_zero();
+        let style = &self.get_style_node().style;
+        // Percentages of horizontal properties refer to the width of the
+        // containing block. `auto` is `None`.
+        let basis = containing_block.content.width;
+        let width = style.position.width.resolve(basis);
 
-        let mut margin_left = style.lookup("margin-left", "margin", zero);
-        let mut margin_right = style.lookup("margin-right", "margin", zero);
+        let mut margin_left = style.box_style.margin_left.resolve(basis);
+        let mut margin_right = style.box_style.margin_right.resolve(basis);
 
-        let border_left = style.lookup("border-left-width", "border-width", zero);
-        let border_right = style.lookup("border-right-width", "border-width", zero);
+        let border_left = style.border.border_left_width;
+        let border_right = style.border.border_right_width;
 
-        let padding_left = style.lookup("padding-left", "padding", zero);
-        let padding_right = style.lookup("padding-right", "padding", zero);
+        let padding_left = style.box_style.padding_left.resolve(basis);
+        let padding_right = style.box_style.padding_right.resolve(basis);
 
-        let total: f32 = [
-            margin_left,
-            margin_right,
-            border_left,
-            border_right,
-            padding_left,
-            padding_right,
-            width,
-        ]
-        .iter()
-        .map(|v| v.to_px())
-        .sum();
+        let total = margin_left.unwrap_or(0.0)
+            + margin_right.unwrap_or(0.0)
+            + border_left
+            + border_right
+            + padding_left
+            + padding_right
+            + width.unwrap_or(0.0);
 
         // println!("total: {}", total);
 
-        let auto = css::Value::keyword_auto();
-        if width != auto && total > containing_block.content.width {
-            if margin_left == auto {
-                margin_left = zero;
-            }
-            if margin_right == auto {
-                margin_right = zero;
-            }
+        if width.is_some() && total > basis {
+            margin_left = margin_left.or(Some(0.0));
+            margin_right = margin_right.or(Some(0.0));
         }
 
-        let underflow = containing_block.content.width - total;
+        let underflow = basis - total;
 
         // println!("underflow: {}", underflow);
 
         let d = &mut self.dimensions;
-        d.padding.left = padding_left.to_px();
-        d.padding.right = padding_right.to_px();
-        d.border.left = border_left.to_px();
-        d.border.right = border_right.to_px();
-
-        match (width == auto, margin_left == auto, margin_right == auto) {
-            (false, false, false) => {
-                d.content.width = width.to_px();
-                d.margin.left = margin_left.to_px();
-                d.margin.right = margin_right.to_px() + underflow;
+        d.padding.left = padding_left;
+        d.padding.right = padding_right;
+        d.border.left = border_left;
+        d.border.right = border_right;
+
+        match (width, margin_left, margin_right) {
+            (Some(width), Some(margin_left), Some(margin_right)) => {
+                d.content.width = width;
+                d.margin.left = margin_left;
+                d.margin.right = margin_right + underflow;
             }
-            (false, false, true) => {
-                d.content.width = width.to_px();
-                d.margin.left = margin_left.to_px();
+            (Some(width), Some(margin_left), None) => {
+                d.content.width = width;
+                d.margin.left = margin_left;
                 d.margin.right = underflow;
             }
-            (false, true, false) => {
-                d.content.width = width.to_px();
+            (Some(width), None, Some(margin_right)) => {
+                d.content.width = width;
                 d.margin.left = underflow;
-                d.margin.right = margin_right.to_px();
+                d.margin.right = margin_right;
             }
-            (false, true, true) => {
-                d.content.width = width.to_px();
+            (Some(width), None, None) => {
+                d.content.width = width;
                 d.margin.left = underflow / 2.0;
                 d.margin.right = underflow / 2.0;
             }
-            (true, _, _) => {
-                if margin_left == auto {
-                    margin_left = zero;
-                }
-                if margin_right == auto {
-                    margin_right = zero;
-                }
+            (None, margin_left, margin_right) => {
+                let margin_left = margin_left.unwrap_or(0.0);
+                let margin_right = margin_right.unwrap_or(0.0);
                 if underflow >= 0.0 {
                     d.content.width = underflow;
-                    d.margin.left = margin_left.to_px();
-                    d.margin.right = margin_right.to_px();
+                    d.margin.left = margin_left;
+                    d.margin.right = margin_right;
                 } else {
                     d.content.width = 0.0;
-                    d.margin.left = margin_left.to_px();
-                    d.margin.right = margin_right.to_px() + underflow;
+                    d.margin.left = margin_left;
+                    d.margin.right = margin_right + underflow;
                 }
             }
         }
@@ -306,25 +442,21 @@ impl<'a> LayoutBox<'a> {
 
     fn calculate_block_position(&mut self, containing_block: &Dimensions) {
         debug!("calculate_block_position: {}", self);
-        let style = self.get_style_node();
+        let style = &self.get_style_node().style;
         let d = &mut self.dimensions;
 
-        let zero = css::Value::length_zero();
+        // Percentages of vertical margins and paddings also refer to the
+        // width of the containing block.
+        let basis = containing_block.content.width;
 
-        d.margin.top = style.lookup("margin-top", "margin", zero).to_px();
-        d.margin.bottom = style.lookup("margin-bottom", "margin", zero).to_px();
+        d.margin.top = style.box_style.margin_top.resolve(basis).unwrap_or(0.0);
+        d.margin.bottom = style.box_style.margin_bottom.resolve(basis).unwrap_or(0.0);
 
-        d.border.top = style
-            .lookup("border-top-width", "border-width", zero)
-            .to_px();
-        d.border.bottom = style
-            .lookup("border-bottom-width", "border-width", zero)
-            .to_px();
+        d.border.top = style.border.border_top_width;
+        d.border.bottom = style.border.border_bottom_width;
 
-        d.padding.top = style.lookup("padding-top-width", "padding", zero).to_px();
-        d.padding.bottom = style
-            .lookup("padding-bottom-width", "padding", zero)
-            .to_px();
+        d.padding.top = style.box_style.padding_top.resolve(basis);
+        d.padding.bottom = style.box_style.padding_bottom.resolve(basis);
 
         d.content.x = containing_block.content.x + d.margin.left + d.border.left + d.padding.left;
 
@@ -348,19 +480,32 @@ impl<'a> LayoutBox<'a> {
             + d.padding.top;
     }
 
-    fn layout_block_children(&mut self) {
+    fn layout_block_children(&mut self, context: &css::LengthContext) {
         debug!("layout_block_children: {}", self);
         let d = &mut self.dimensions;
         for child in &mut self.children {
-            child.layout(d);
+            child.layout(d, context);
+            if child.is_out_of_flow() {
+                continue;
+            }
             d.content.height += child.dimensions.margin_box().height;
             debug!("d.content.height => : {}", d.content.height);
         }
     }
 
     fn calculate_block_height(&mut self) {
-        if let Some(css::Value::Length(h, css::Unit::Px)) = self.get_style_node().value("height") {
-            self.dimensions.content.height = *h;
+        // The height of a containing block depends on its content, so
+        // percentage heights behave as `auto`.
+        if let LengthPercentageAuto::LengthPercentage(LengthPercentage::Length(height)) =
+            self.get_style_node().style.position.height
+        {
+            self.dimensions.content.height = height;
+            return;
+        }
+        // Size containment: a size container is laid out as if it had no
+        // content.
+        if self.container_type() == ContainerType::Size {
+            self.dimensions.content.height = 0.0;
         }
     }
 }
@@ -369,6 +514,8 @@ pub(crate) enum BoxType<'a> {
     BlockNode(&'a StyledNode<'a>),
     InlineNode(&'a StyledNode<'a>),
     AnonymousBlock,
+    /// The `::marker` of a list item, which is the first child of its box.
+    MarkerBox(&'a Marker),
 }
 
 impl std::fmt::Display for BoxType<'_> {
@@ -379,15 +526,24 @@ impl std::fmt::Display for BoxType<'_> {
                 write!(f, "{}(inline)", style_node.node.simple_name())
             }
             BoxType::AnonymousBlock => write!(f, "(anonymous)"),
+            BoxType::MarkerBox(marker) => write!(
+                f,
+                "::marker({}) {:?}",
+                if marker.outside { "outside" } else { "inside" },
+                marker.text
+            ),
         }
     }
 }
 
 pub fn build_layout_tree<'a>(style_node: &'a StyledNode<'a>) -> LayoutBox<'a> {
     let mut root = LayoutBox::new(style_node);
+    if let Some(marker) = &style_node.marker {
+        root.children.push(LayoutBox::new_marker(marker));
+    }
     for child in &style_node.children {
         match child.display() {
-            Display::Block => root.children.push(build_layout_tree(child)),
+            Display::Block | Display::ListItem => root.children.push(build_layout_tree(child)),
             Display::Inline => root
                 .get_inline_container()
                 .children
@@ -400,51 +556,64 @@ pub fn build_layout_tree<'a>(style_node: &'a StyledNode<'a>) -> LayoutBox<'a> {
     root
 }
 
-pub fn dump_layout(html: &str, stylesheet: &str) -> Result<String> {
+/// Styles the tree, interleaved with layout, so that the conditions of
+/// `@container` rules are evaluated against the sizes of their containers.
+///
+/// Each pass styles the descendants of the containers whose sizes are
+/// known, and lays out the tree to find the sizes of the containers which
+/// it reaches. The size of a container doesn't depend on its descendants,
+/// so it is final once known. Styling ends when no container lacks a size,
+/// or a pass finds no new one, e.g. because the rest are not displayed.
+pub fn style_tree_with_containers<'a>(
+    root: &'a Node,
+    stylesheets: &[&'a css::Stylesheet],
+    env: &media::Environment,
+) -> StyledNode<'a> {
+    let viewport = Viewport::from(env);
+    let mut sizes = ContainerSizes::new();
+    loop {
+        let (style_tree, complete) =
+            style::style_tree_with_container_sizes(root, stylesheets, env, &sizes);
+        if complete {
+            return style_tree;
+        }
+        let mut layout_tree = build_layout_tree(&style_tree);
+        layout_tree.layout_root(&viewport);
+        let added = layout_tree.collect_container_sizes(&mut sizes);
+        drop(layout_tree);
+        if !added {
+            return style_tree;
+        }
+    }
+}
+
+pub fn dump_layout(
+    html: &str,
+    stylesheets: &[&css::Stylesheet],
+    env: &media::Environment,
+) -> Result<String> {
     debug!("parsing html:\n{}", html);
     let node = crate::dom::parser::parse_html(html)?;
     debug!("parsed: {:?}", node);
 
-    debug!("parsing stylesheet:\n{}", stylesheet);
-    let stylesheet = css::parser::parse_stylesheet(&stylesheet)?;
-    debug!("parsed: {:?}", stylesheet);
-
-    let style_tree = crate::style::style_tree(&node, &stylesheet);
+    let style_tree = style_tree_with_containers(&node, stylesheets, env);
     let mut layout_tree = build_layout_tree(&style_tree);
-    let viewport = Dimensions {
-        content: Rect {
-            x: 0.0,
-            y: 0.0,
-            width: 800.0,
-            height: 0.0,
-        },
-        ..Default::default()
-    };
-    layout_tree.layout(&viewport);
+    layout_tree.layout_root(&Viewport::from(env));
     Ok(format!("{:#}", layout_tree))
 }
 
-pub fn dump_layout_as_json(html: &str, stylesheet: &str) -> Result<String> {
+pub fn dump_layout_as_json(
+    html: &str,
+    stylesheets: &[&css::Stylesheet],
+    env: &media::Environment,
+) -> Result<String> {
     debug!("parsing html:\n{}", html);
     let node = crate::dom::parser::parse_html(html)?;
     debug!("parsed: {:?}", node);
 
-    debug!("parsing stylesheet:\n{}", stylesheet);
-    let stylesheet = css::parser::parse_stylesheet(&stylesheet)?;
-    debug!("parsed: {:?}", stylesheet);
-
-    let style_tree = crate::style::style_tree(&node, &stylesheet);
+    let style_tree = style_tree_with_containers(&node, stylesheets, env);
     let mut layout_tree = build_layout_tree(&style_tree);
-    let viewport = Dimensions {
-        content: Rect {
-            x: 0.0,
-            y: 0.0,
-            width: 800.0,
-            height: 0.0,
-        },
-        ..Default::default()
-    };
-    layout_tree.layout(&viewport);
+    layout_tree.layout_root(&Viewport::from(env));
     // TODO: json
     Ok(format!("{:#}", layout_tree))
 }
@@ -455,9 +624,8 @@ mod test {
     use crate::css;
     use crate::dom;
     use crate::style;
-    use crate::style::StyledNode;
+    use crate::style::{ComputedStyle, StyledNode};
     use combine::*;
-    use maplit::*;
 
     #[test]
     fn calculate_block_width_test() {
@@ -478,14 +646,15 @@ mod test {
             (expected_width, expected_margin_left, expected_margin_right): (f32, f32, f32),
         ) {
             let node = dom::Node::Element(Default::default());
+            let mut style = ComputedStyle::initial();
+            assert!(style.set("display", &keyword("block")));
+            assert!(style.set("width", &width));
+            assert!(style.set("margin-left", &margin_left));
+            assert!(style.set("margin-right", &margin_right));
             let style_node = StyledNode {
                 node: &node,
-                css_specified_values: hashmap! {
-                    "display".to_string() => keyword("block"),
-                    "width".to_string() => width,
-                    "margin-left".to_string() => margin_left,
-                    "margin-right".to_string() => margin_right,
-                },
+                style,
+                marker: None,
                 children: vec![],
             };
             let mut layout_box = LayoutBox::new(&style_node);
@@ -535,16 +704,7 @@ mod test {
 
     fn layout<'a>(style_tree: &'a style::StyledNode<'a>) -> LayoutBox<'a> {
         let mut layout_tree = build_layout_tree(style_tree);
-        let window = Dimensions {
-            content: Rect {
-                x: 0.0,
-                y: 0.0,
-                width: 800.0,
-                height: 0.0,
-            },
-            ..Default::default()
-        };
-        layout_tree.layout(&window);
+        layout_tree.layout_root(&Viewport::default());
         layout_tree
     }
 
@@ -558,7 +718,7 @@ mod test {
             .parse("* { display: block } div { margin: 10px }")
             .unwrap()
             .0;
-        let style_tree = style::style_tree(&node, &stylesheet);
+        let style_tree = style::style_tree(&node, &[&stylesheet], &Default::default());
         let layout_tree = layout(&style_tree);
         // assert_eq!(format!("{:#}", layout_tree), "layouttree-dayo");
 
@@ -576,9 +736,57 @@ mod test {
         assert_eq!(layout_tree.children[1].dimensions.content.y, 30.0);
     }
 
+    #[test]
+    fn relative_length_layout_test() {
+        let node = dom::parser::node().parse("(div (div (div)))").unwrap().0;
+        let stylesheet = css::parser::stylesheet()
+            .parse("* { display: block } div { padding: 1em; width: 50% }")
+            .unwrap()
+            .0;
+        let style_tree = style::style_tree(&node, &[&stylesheet], &Default::default());
+        let layout_tree = layout(&style_tree);
+
+        // The width of the root is 50% of the viewport.
+        assert_eq!(layout_tree.dimensions.content.width, 400.0);
+        // The default font size is 16px.
+        assert_eq!(layout_tree.dimensions.padding.left, 16.0);
+        assert_eq!(layout_tree.children[0].dimensions.content.width, 200.0);
+        assert_eq!(
+            layout_tree.children[0].children[0].dimensions.content.width,
+            100.0
+        );
+    }
+
+    #[test]
+    fn calc_layout_test() {
+        let node = dom::parser::node().parse("(div (div))").unwrap().0;
+        let stylesheet = css::parser::stylesheet()
+            .parse(
+                "* { display: block } div { width: calc(50% - 2 * 10px); margin: calc(10px / 2); padding: min(5%, 1em) }",
+            )
+            .unwrap()
+            .0;
+        let style_tree = style::style_tree(&node, &[&stylesheet], &Default::default());
+        let layout_tree = layout(&style_tree);
+
+        assert_eq!(layout_tree.dimensions.content.width, 380.0);
+        assert_eq!(layout_tree.dimensions.margin.left, 5.0);
+        assert_eq!(layout_tree.dimensions.padding.left, 16.0);
+        let child = &layout_tree.children[0].dimensions;
+        assert_eq!(child.content.width, 170.0);
+        // min(5% of 380px, 16px)
+        assert_eq!(child.padding.left, 16.0);
+        assert_eq!(child.margin.top, 5.0);
+    }
+
     fn assert_layout_dump(html: &str, css: &str, expected: &str) -> Result<()> {
         assert_eq!(
-            dump_layout(html.trim(), css.trim())?.trim(),
+            dump_layout(
+                html.trim(),
+                &[&css::parser::parse_stylesheet(css)?],
+                &Default::default()
+            )?
+            .trim(),
             expected.trim()
         );
         Ok(())
@@ -605,4 +813,112 @@ div(block) (12, 12) [776x144] (padding: 12, border: 0, margin: 0)
         assert_layout_dump(html, css, layout).unwrap();
     }
 
+    #[test]
+    fn marker_layout_test() {
+        let html = r"(ol (li) (li class=inside))";
+        let css = r"
+ol {
+  display: block;
+  padding-left: 40px;
+  counter-reset: list-item;
+  list-style-type: decimal
+}
+li {
+  display: list-item
+}
+.inside {
+  list-style-position: inside
+}
+.inside::marker {
+  font-size: 20px
+}
+";
+        // An outside marker doesn't take space in its list item.
+        let layout = r#"
+ol(block) (40, 0) [760x20] (padding: 0 0 0 40, border: 0, margin: 0)
+  li(block) (40, 0) [760x0] (padding: 0, border: 0, margin: 0)
+    ::marker(outside) "1. " (16, 0) [24x16] (padding: 0, border: 0, margin: 0)
+  li(block) (40, 0) [760x20] (padding: 0, border: 0, margin: 0)
+    ::marker(inside) "2. " (40, 0) [30x20] (padding: 0, border: 0, margin: 0)
+"#;
+        assert_layout_dump(html, css, layout).unwrap();
+    }
+
+    #[test]
+    fn container_query_layout_test() {
+        let html = r"(div (div class=card (p)) (div class=narrow (div class=card (p))))";
+        let css = r"
+* {
+  display: block
+}
+.card {
+  container: card / inline-size
+}
+.narrow {
+  width: 300px
+}
+p {
+  height: 10px
+}
+@container card (width > 400px) {
+  p {
+    height: 20px
+  }
+}
+";
+        let layout = r"
+div(block) (0, 0) [800x30] (padding: 0, border: 0, margin: 0)
+  div(block) (0, 0) [800x20] (padding: 0, border: 0, margin: 0)
+    p(block) (0, 0) [800x20] (padding: 0, border: 0, margin: 0)
+  div(block) (0, 20) [300x10] (padding: 0, border: 0, margin: 0 500 0 0)
+    div(block) (0, 20) [300x10] (padding: 0, border: 0, margin: 0)
+      p(block) (0, 20) [300x10] (padding: 0, border: 0, margin: 0)
+";
+        assert_layout_dump(html, css, layout).unwrap();
+    }
+
+    #[test]
+    fn nested_container_query_layout_test() {
+        // The inner container is styled after the outer one is laid out, and
+        // the paragraph after the inner one. A size container doesn't get
+        // its height from its content.
+        let html = r"(div class=outer (div class=inner (p)))";
+        let css = r"
+* {
+  display: block
+}
+.outer {
+  container-type: size;
+  height: 100px
+}
+.inner {
+  container-type: size;
+  width: 50%
+}
+p {
+  height: 10px
+}
+@container (height >= 100px) {
+  .inner {
+    width: 25%
+  }
+}
+@container (width < 15em) {
+  p {
+    width: 1px
+  }
+}
+@container (height > 0px) {
+  p {
+    height: 5px
+  }
+}
+";
+        let layout = r"
+div(block) (0, 0) [800x100] (padding: 0, border: 0, margin: 0)
+  div(block) (0, 0) [200x0] (padding: 0, border: 0, margin: 0 600 0 0)
+    p(block) (0, 0) [1x10] (padding: 0, border: 0, margin: 0 199 0 0)
+";
+        assert_layout_dump(html, css, layout).unwrap();
+    }
 }
@@ -3,10 +3,11 @@ pub mod prelude;
 
 pub mod css;
 pub mod dom;
+pub mod font;
 pub mod layout;
 pub mod paint;
 pub mod style;
 
 pub use crate::dom::parser::parse_html;
 pub use crate::layout::dump_layout;
-pub use crate::paint::paint_and_save;
+pub use crate::paint::{paint_and_save, paint_transitions_and_save};
@@ -1,9 +1,12 @@
 use failure;
 use loggerv;
 use serval;
+use serval::css;
+use serval::css::media;
 
 use std::fs;
 use std::io::prelude::*;
+use std::path::Path;
 use structopt::StructOpt;
 
 type Result<T> = std::result::Result<T, failure::Error>;
@@ -12,6 +15,26 @@ type Result<T> = std::result::Result<T, failure::Error>;
 struct Opt {
     #[structopt(short = "v", parse(from_occurrences))]
     verbose: u64,
+    /// Applies the built-in user-agent stylesheet
+    #[structopt(long = "user-agent-stylesheet")]
+    user_agent_stylesheet: bool,
+    /// A stylesheet of the user origin
+    #[structopt(long = "user-stylesheet")]
+    user_stylesheet: Option<String>,
+    /// The size of the viewport, e.g. 375x667
+    #[structopt(long = "viewport", parse(try_from_str = "parse_viewport"))]
+    viewport: Option<(f32, f32)>,
+    /// The preferred color scheme: light or dark
+    #[structopt(long = "color-scheme")]
+    color_scheme: Option<media::ColorScheme>,
+    /// A directory of TrueType and OpenType fonts, which are the only
+    /// installed fonts
+    #[structopt(long = "font-dir")]
+    font_dir: Option<String>,
+    /// The number of threads which style the tree. It is the number of CPUs
+    /// by default
+    #[structopt(long = "threads")]
+    threads: Option<usize>,
     #[structopt(subcommand)]
     cmd: Command,
 }
@@ -22,6 +45,29 @@ enum Command {
     ParseHtml { html: String },
     #[structopt(name = "layout")]
     Layout { html: String, stylesheet: String },
+    /// Shows the rules and declarations which style the elements matching a
+    /// selector
+    #[structopt(name = "explain-style")]
+    ExplainStyle {
+        html: String,
+        stylesheet: String,
+        selector: String,
+    },
+    /// Prints a stylesheet in the canonical form
+    #[structopt(name = "fmt-css")]
+    FmtCss {
+        stylesheet: String,
+        /// Omits all optional white space
+        #[structopt(long = "minify")]
+        minify: bool,
+    },
+    /// Shows the supported properties with their values, initial values,
+    /// inheritance and animation types
+    #[structopt(name = "properties")]
+    Properties,
+    /// Shows the font faces which render the text of a document
+    #[structopt(name = "match-fonts")]
+    MatchFonts { html: String, stylesheet: String },
     #[structopt(name = "paint")]
     Paint {
         html: String,
@@ -29,42 +75,221 @@ enum Command {
         output_file: String,
         #[structopt(name = "format")]
         format: String,
+        /// The time of the animation timeline to render, e.g. 1.5s or 200ms
+        #[structopt(
+            long = "time",
+            default_value = "0s",
+            parse(try_from_str = "parse_time")
+        )]
+        time: f32,
+        /// Renders this many frames from --time into numbered files, e.g.
+        /// out-0000.png, out-0001.png and so on
+        #[structopt(long = "frames")]
+        frames: Option<usize>,
+        /// The number of frames per second of --frames
+        #[structopt(long = "fps", default_value = "30", parse(try_from_str = "parse_fps"))]
+        fps: f32,
+        /// The html before a change at --time, which starts the transitions
+        /// to <html>
+        #[structopt(long = "from")]
+        from: Option<String>,
     },
 }
 
+fn read_to_string(file: &str) -> Result<String> {
+    let mut f = fs::File::open(file)?;
+    let mut s = String::new();
+    f.read_to_string(&mut s)?;
+    Ok(s)
+}
+
+fn parse_viewport(s: &str) -> Result<(f32, f32)> {
+    let mut size = s.splitn(2, 'x').map(str::parse::<f32>);
+    match (size.next(), size.next()) {
+        (Some(Ok(width)), Some(Ok(height))) if width > 0.0 && height > 0.0 => Ok((width, height)),
+        _ => Err(failure::err_msg(format!(
+            "invalid viewport size: {} (expected WIDTHxHEIGHT)",
+            s
+        ))),
+    }
+}
+
+fn parse_time(s: &str) -> Result<f32> {
+    match css::parser::parse_value(s) {
+        Ok(css::Value::Time(seconds)) => Ok(seconds),
+        _ => Err(failure::err_msg(format!(
+            "invalid time: {} (expected e.g. 1.5s or 200ms)",
+            s
+        ))),
+    }
+}
+
+fn parse_fps(s: &str) -> Result<f32> {
+    match s.parse::<f32>() {
+        Ok(fps) if fps > 0.0 && fps.is_finite() => Ok(fps),
+        _ => Err(failure::err_msg(format!(
+            "invalid frame rate: {} (expected a positive number)",
+            s
+        ))),
+    }
+}
+
+/// Returns `file` with the frame number before its extension, e.g.
+/// `out-0001.png`.
+fn frame_file(file: &str, frame: usize) -> String {
+    let path = Path::new(file);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{:04}.{}", stem, frame, extension.to_string_lossy()),
+        None => format!("{}-{:04}", stem, frame),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// Returns the environment which media queries are evaluated against.
+fn environment(opt: &Opt) -> media::Environment {
+    let mut env = media::Environment::default();
+    if let Some((width, height)) = opt.viewport {
+        env.width = width;
+        env.height = height;
+    }
+    if let Some(color_scheme) = opt.color_scheme {
+        env.color_scheme = color_scheme;
+    }
+    env
+}
+
+/// Loads the user stylesheet, if any, and the author stylesheet, with the
+/// stylesheets which they import.
+fn load_stylesheets(opt: &Opt, author_stylesheet: &str) -> Result<Vec<css::Stylesheet>> {
+    let mut stylesheets = Vec::new();
+    if let Some(file) = &opt.user_stylesheet {
+        let mut stylesheet = css::import::load_stylesheet(file)?;
+        stylesheet.origin = css::Origin::User;
+        stylesheets.push(stylesheet);
+    }
+    stylesheets.push(css::import::load_stylesheet(author_stylesheet)?);
+    Ok(stylesheets)
+}
+
 fn main() -> Result<()> {
     let opt = Opt::from_args();
     loggerv::init_with_verbosity(opt.verbose).unwrap();
-    match opt.cmd {
+    if let Some(threads) = opt.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()?;
+    }
+    let user_agent_stylesheet = if opt.user_agent_stylesheet {
+        Some(css::user_agent_stylesheet())
+    } else {
+        None
+    };
+    let env = environment(&opt);
+    match &opt.cmd {
         Command::ParseHtml { html } => {
-            let mut f = fs::File::open(html)?;
-            let mut s = String::new();
-            f.read_to_string(&mut s)?;
-            println!("{:#}", serval::parse_html(&s)?);
+            println!("{:#}", serval::parse_html(&read_to_string(html)?)?);
         }
         Command::Layout { html, stylesheet } => {
-            let mut f = fs::File::open(html)?;
-            let mut html = String::new();
-            f.read_to_string(&mut html)?;
-
-            let mut f = fs::File::open(stylesheet)?;
-            let mut stylesheet = String::new();
-            f.read_to_string(&mut stylesheet)?;
-            println!("{}", serval::dump_layout(&html, &stylesheet)?);
+            let html = read_to_string(html)?;
+            let stylesheets = load_stylesheets(&opt, stylesheet)?;
+            let stylesheets: Vec<_> = user_agent_stylesheet
+                .into_iter()
+                .chain(stylesheets.iter())
+                .collect();
+            println!("{}", serval::dump_layout(&html, &stylesheets, &env)?);
+        }
+        Command::ExplainStyle {
+            html,
+            stylesheet,
+            selector,
+        } => {
+            let html = read_to_string(html)?;
+            let stylesheets = load_stylesheets(&opt, stylesheet)?;
+            let stylesheets: Vec<_> = user_agent_stylesheet
+                .into_iter()
+                .chain(stylesheets.iter())
+                .collect();
+            let text = serval::style::explain::explain_style(&html, &stylesheets, &env, selector)?;
+            print!("{}", text);
+        }
+        Command::FmtCss { stylesheet, minify } => {
+            let stylesheet = css::parser::parse_stylesheet(&read_to_string(stylesheet)?)?;
+            let options = if *minify {
+                css::serializer::Options::minified()
+            } else {
+                Default::default()
+            };
+            let text = css::serializer::serialize(&stylesheet, &options);
+            println!("{}", text.trim_end());
+        }
+        Command::Properties => {
+            print!("{}", css::property::documentation());
+        }
+        Command::MatchFonts { html, stylesheet } => {
+            let html = read_to_string(html)?;
+            let stylesheets = load_stylesheets(&opt, stylesheet)?;
+            let stylesheets: Vec<_> = user_agent_stylesheet
+                .into_iter()
+                .chain(stylesheets.iter())
+                .collect();
+            let font_dir = opt.font_dir.as_ref().map(Path::new);
+            print!(
+                "{}",
+                serval::font::match_fonts(&html, &stylesheets, &env, font_dir)?
+            );
         }
         Command::Paint {
             html,
             stylesheet,
             output_file,
             format,
+            time,
+            frames,
+            fps,
+            from,
         } => {
-            let mut f = fs::File::open(html)?;
-            let mut html = String::new();
-            f.read_to_string(&mut html)?;
-            let mut f = fs::File::open(stylesheet)?;
-            let mut stylesheet = String::new();
-            f.read_to_string(&mut stylesheet)?;
-            serval::paint_and_save(&html, &stylesheet, output_file, &format)?;
+            let html = read_to_string(html)?;
+            let stylesheets = load_stylesheets(&opt, stylesheet)?;
+            let stylesheets: Vec<_> = user_agent_stylesheet
+                .into_iter()
+                .chain(stylesheets.iter())
+                .collect();
+            let frames: Vec<_> = match frames {
+                None => vec![(
+                    media::Environment {
+                        time: *time,
+                        ..env.clone()
+                    },
+                    output_file.clone(),
+                )],
+                Some(frames) => (0..*frames)
+                    .map(|frame| {
+                        let env = media::Environment {
+                            time: time + frame as f32 / fps,
+                            ..env.clone()
+                        };
+                        (env, frame_file(output_file, frame))
+                    })
+                    .collect(),
+            };
+            match from {
+                None => {
+                    for (env, file) in &frames {
+                        serval::paint_and_save(&html, &stylesheets, env, file, format)?;
+                    }
+                }
+                Some(from) => {
+                    let from = read_to_string(from)?;
+                    serval::paint_transitions_and_save(
+                        &from,
+                        &html,
+                        &stylesheets,
+                        &frames,
+                        format,
+                    )?;
+                }
+            }
         }
     }
     Ok(())
@@ -2,6 +2,9 @@ use crate::css;
 use crate::css::Color;
 use crate::layout::*;
 use crate::prelude::*;
+use crate::style::computed_style::ComputedColor;
+use crate::style::transition::Transitions;
+use crate::style::{ComputedStyle, StyledNode};
 use log::*;
 use std::path::Path;
 
@@ -28,7 +31,7 @@ fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox<'_>) {
 }
 
 fn render_background(list: &mut DisplayList, layout_box: &LayoutBox<'_>) {
-    if let Some(color) = get_color(layout_box, "background") {
+    if let Some(color) = get_color(layout_box, |style| style.background.background_color) {
         list.push(DisplayCommand::SolidColor(
             color,
             layout_box.dimensions.border_box(),
@@ -37,69 +40,72 @@ fn render_background(list: &mut DisplayList, layout_box: &LayoutBox<'_>) {
 }
 
 fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox<'_>) {
-    let color = match get_color(layout_box, "border-color") {
-        Some(color) => color,
-        _ => return,
-    };
-
     let d = &layout_box.dimensions;
     let border_box = d.border_box();
 
+    let mut render_border = |color: fn(&ComputedStyle) -> ComputedColor, rect| {
+        if let Some(color) = get_color(layout_box, color) {
+            list.push(DisplayCommand::SolidColor(color, rect));
+        }
+    };
+
     // Left border
-    list.push(DisplayCommand::SolidColor(
-        color,
+    render_border(
+        |style| style.border.border_left_color,
         Rect {
             x: border_box.x,
             y: border_box.y,
             width: d.border.left,
             height: border_box.height,
         },
-    ));
+    );
 
     // Right border
-    list.push(DisplayCommand::SolidColor(
-        color,
+    render_border(
+        |style| style.border.border_right_color,
         Rect {
             x: border_box.x + border_box.width - d.border.right,
             y: border_box.y,
             width: d.border.right,
             height: border_box.height,
         },
-    ));
+    );
 
     // Top border
-    list.push(DisplayCommand::SolidColor(
-        color,
+    render_border(
+        |style| style.border.border_top_color,
         Rect {
             x: border_box.x,
             y: border_box.y,
             width: border_box.width,
             height: d.border.top,
         },
-    ));
+    );
 
     // Bottom border
-    list.push(DisplayCommand::SolidColor(
-        color,
+    render_border(
+        |style| style.border.border_bottom_color,
         Rect {
             x: border_box.x,
             y: border_box.y + border_box.height - d.border.bottom,
             width: border_box.width,
             height: d.border.bottom,
         },
-    ));
+    );
 }
 
-fn get_color(layout_box: &'_ LayoutBox<'_>, name: &str) -> Option<Color> {
-    match layout_box.box_type {
-        BoxType::BlockNode(style_node) | BoxType::InlineNode(style_node) => {
-            match style_node.value(name) {
-                Some(css::Value::ColorValue(color)) => Some(*color),
-                _ => None,
-            }
-        }
-        BoxType::AnonymousBlock => None,
-    }
+/// Returns the used value of the `color` of a box, or `None` if it is
+/// transparent.
+fn get_color(
+    layout_box: &'_ LayoutBox<'_>,
+    color: impl Fn(&ComputedStyle) -> ComputedColor,
+) -> Option<Color> {
+    let style = match layout_box.box_type {
+        BoxType::BlockNode(style_node) | BoxType::InlineNode(style_node) => &style_node.style,
+        BoxType::MarkerBox(marker) => &marker.style,
+        BoxType::AnonymousBlock => return None,
+    };
+    Some(style.color(color(style))).filter(|color| color.a > 0)
 }
 
 trait Canvas {
@@ -124,7 +130,7 @@ struct PixelCanvas {
 
 impl PixelCanvas {
     fn new(width: usize, height: usize) -> Self {
-        let white = Color { r: 0, g: 0, b: 0 };
+        let white = Color::rgb(0, 0, 0);
         PixelCanvas {
             pixels: vec![white; width * height],
             width,
@@ -148,7 +154,8 @@ impl Canvas for PixelCanvas {
                 let y1 = self.clamp(rect.y + rect.height);
                 for y in y0..y1 {
                     for x in x0..x1 {
-                        self.pixels[x + y * self.width] = *color;
+                        let pixel = &mut self.pixels[x + y * self.width];
+                        *pixel = color.over(*pixel);
                     }
                 }
             }
@@ -161,7 +168,7 @@ impl Canvas for PixelCanvas {
         let (w, h) = (self.width as u32, self.height as u32);
         let img = image::ImageBuffer::from_fn(w, h, move |x, y| {
             let color = self.pixels[(y * w + x) as usize];
-            image::Pixel::from_channels(color.r, color.g, color.b, 255)
+            image::Pixel::from_channels(color.r, color.g, color.b, color.a)
         });
         image::ImageRgba8(img).save(file)?;
         Ok(())
@@ -190,11 +197,11 @@ impl Canvas for WebCanvas {
             DisplayCommand::SolidColor(color, rect) => {
                 debug!("painting: color: {:?}, rect: {:?}", color, rect);
                 self.commands.push(format!(
-                    "ctx.fillStyle = 'rgb({},{},{},{})';",
+                    "ctx.fillStyle = 'rgba({},{},{},{})';",
                     color.r,
                     color.g,
                     color.b,
-                    255, // TODO: Use color.alpha
+                    f32::from(color.a) / 255.0,
                 ));
                 self.commands.push(format!(
                     "ctx.fillRect({}, {}, {}, {});",
@@ -228,31 +235,57 @@ const ctx = canvas.getContext('2d');
 
 pub fn paint_and_save(
     html: &str,
-    stylesheet: &str,
+    stylesheets: &[&css::Stylesheet],
+    env: &css::media::Environment,
     output_file: impl AsRef<Path>,
     format: &str,
 ) -> Result<()> {
     let node = crate::dom::parser::parse_html(html)?;
     debug!("parsed html: {:?}", node);
-    let stylesheet = css::parser::parse_stylesheet(&stylesheet)?;
-    debug!("parsed stylesheet: {:?}", stylesheet);
+    let style_tree = style_tree_with_containers(&node, stylesheets, env);
+    paint_style_tree_and_save(&style_tree, env, output_file, format)
+}
 
-    let style_tree = crate::style::style_tree(&node, &stylesheet);
-    let mut layout_tree = build_layout_tree(&style_tree);
-    let viewport = Dimensions {
-        content: Rect {
-            x: 0.0,
-            y: 0.0,
-            width: 800.0,
-            height: 0.0,
-        },
-        ..Default::default()
-    };
-    layout_tree.layout(&viewport);
+/// Paints the frames of `html` after a change from `from_html`, which
+/// happens at the time of the first frame. Each frame is painted at the
+/// time of its environment, with the transitions which the change started.
+///
+/// `@container` rules don't apply, as transitions are styled in a single
+/// pass.
+pub fn paint_transitions_and_save(
+    from_html: &str,
+    html: &str,
+    stylesheets: &[&css::Stylesheet],
+    frames: &[(css::media::Environment, impl AsRef<Path>)],
+    format: &str,
+) -> Result<()> {
+    let from_node = crate::dom::parser::parse_html(from_html)?;
+    let node = crate::dom::parser::parse_html(html)?;
+    debug!("parsed html: {:?}", node);
+    let mut transitions = Transitions::new();
+    if let Some((env, _)) = frames.first() {
+        transitions.style_tree(&from_node, stylesheets, env);
+    }
+    for (env, output_file) in frames {
+        let style_tree = transitions.style_tree(&node, stylesheets, env);
+        paint_style_tree_and_save(&style_tree, env, output_file, format)?;
+    }
+    Ok(())
+}
+
+fn paint_style_tree_and_save(
+    style_tree: &StyledNode<'_>,
+    env: &css::media::Environment,
+    output_file: impl AsRef<Path>,
+    format: &str,
+) -> Result<()> {
+    let mut layout_tree = build_layout_tree(style_tree);
+    let viewport = Viewport::from(env);
+    layout_tree.layout_root(&viewport);
 
     let rect = Rect {
-        width: 800.0,
-        height: 800.0,
+        width: viewport.width,
+        height: viewport.height,
         ..Default::default()
     };
 
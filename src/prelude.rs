@@ -11,12 +11,37 @@ impl Clamp for f32 {
     }
 }
 
+/// A position in the input of a parser.
+pub trait InputPosition {
+    /// Returns the line and the column, both starting at 1, or `None` if
+    /// the input does not track them.
+    fn line_column(&self) -> Option<(usize, usize)>;
+}
+
+impl InputPosition for combine::stream::PointerOffset {
+    fn line_column(&self) -> Option<(usize, usize)> {
+        None
+    }
+}
+
+impl InputPosition for combine::stream::state::SourcePosition {
+    fn line_column(&self) -> Option<(usize, usize)> {
+        Some((self.line as usize, self.column as usize))
+    }
+}
+
 #[derive(Fail, Debug)]
 pub enum EngineError {
     #[fail(display = "Parse Error: {}", error)]
     ParseError {
         error: combine::error::StringStreamError,
     },
+    /// `chain` is the files from the top-level stylesheet to the file which
+    /// failed to be imported.
+    #[fail(display = "Import Error: {}: {}", chain, message)]
+    ImportError { chain: String, message: String },
+    #[fail(display = "Font Error: {}: {}", path, message)]
+    FontError { path: String, message: String },
 }
 
 impl From<combine::error::StringStreamError> for EngineError {
@@ -47,7 +72,7 @@ macro_rules! def_parser {
         parser! {
             pub fn $parser[I]()(I) -> $type
             where
-                [I: Stream<Item = char>]
+                [I: Stream<Item = char>, I::Position: $crate::prelude::InputPosition]
                 $body
         }
     };
@@ -55,7 +80,7 @@ macro_rules! def_parser {
         parser! {
             fn $parser[I]()(I) -> $type
             where
-                [I: Stream<Item = char>]
+                [I: Stream<Item = char>, I::Position: $crate::prelude::InputPosition]
                 $body
         }
     };
@@ -3,116 +3,912 @@ use super::css;
 // use super::dom::{ElementData, Node, NodeType};
 use super::dom;
 use super::dom::Node;
+use crate::css::animation::KeyframesRule;
+use crate::css::container::ContainerRule;
+use crate::css::font_face::FontFaceRule;
+use crate::css::media;
+use crate::css::property::{self, CssWideKeyword};
+use crate::css::variable;
+use crate::prelude::*;
+use crate::style::container::{ContainerSizes, QueryContainers};
+use crate::style::selector_map::{Ancestors, RuleMap};
+use crate::style::sharing::{SharedStyle, SharingCache, SharingStats};
+use log::*;
+use rayon::prelude::*;
 use std::collections::HashMap;
+use std::sync::Arc;
 
-pub type CssPropertyMap = HashMap<String, css::Value>;
+pub mod animation;
+pub mod computed;
+pub mod computed_style;
+pub mod container;
+pub mod counter;
+pub mod explain;
+mod invalidation;
+pub mod restyle;
+mod selector_map;
+mod sharing;
+pub mod transition;
 
-#[derive(PartialEq)]
-pub enum Display {
-    Inline,
-    Block,
-    None,
-}
+pub use self::computed_style::{ComputedStyle, Display};
+pub use self::restyle::{restyle_tree, StyleCache};
 
+pub type CssPropertyMap = HashMap<String, css::Value>;
+
+#[derive(Debug, PartialEq)]
 pub struct StyledNode<'a> {
     pub node: &'a Node,
-    pub css_specified_values: CssPropertyMap,
+    /// The computed values. A text node inherits the inherited properties
+    /// of its parent, and has the initial values of the others.
+    pub style: ComputedStyle,
+    /// The `::marker` of a list item.
+    pub marker: Option<Marker>,
     pub children: Vec<StyledNode<'a>>,
 }
 
-impl<'a> StyledNode<'a> {
-    pub fn value(&'a self, name: &str) -> Option<&'a css::Value> {
-        self.css_specified_values.get(name)
+/// The `::marker` pseudo-element of a list item.
+#[derive(Debug, PartialEq)]
+pub struct Marker {
+    pub style: ComputedStyle,
+    /// The generated content, e.g. `1. ` or `• `.
+    pub text: String,
+    /// Whether `list-style-position` of the list item is `outside`.
+    pub outside: bool,
+}
+
+impl Marker {
+    pub fn value(&self, name: &str) -> Option<css::Value> {
+        self.style.value(name)
     }
+}
 
-    pub fn lookup(
-        &'a self,
-        name: &str,
-        fallback_name: &str,
-        default: &'a css::Value,
-    ) -> &'a css::Value {
-        self.value(name)
-            .unwrap_or_else(|| self.value(fallback_name).unwrap_or_else(|| default))
+impl<'a> StyledNode<'a> {
+    pub fn value(&self, name: &str) -> Option<css::Value> {
+        self.style.value(name)
     }
 
     pub fn display(&self) -> Display {
-        match self.value("display") {
-            Some(css::Value::Keyword(s)) => match s.as_str() {
-                "block" => Display::Block,
-                "none" => Display::None,
-                _ => Display::Inline,
-            },
-            _ => Display::Inline,
+        self.style.box_style.display
+    }
+}
+
+/// Styles the tree with `stylesheets`, which are given in the order of
+/// appearance. Their origins determine their precedence. Media queries are
+/// evaluated against `env`.
+///
+/// No query container has a size without layout, so the conditions of
+/// `@container` rules are never true. `layout::style_tree_with_containers`
+/// interleaves styling with layout to evaluate them.
+pub fn style_tree<'a>(
+    root: &'a Node,
+    stylesheets: &[&'a css::Stylesheet],
+    env: &media::Environment,
+) -> StyledNode<'a> {
+    let cascade = cascade(stylesheets, env);
+    style_root(root, &cascade, &mut StyleState::new(None))
+}
+
+/// Styles the tree as `style_tree` does, but with the `sizes` of the query
+/// containers which have been laid out. The descendants of a container whose
+/// size is unknown are not styled, so it has no children yet.
+///
+/// Returns the tree, and whether it is complete, i.e. no container lacks a
+/// size.
+pub fn style_tree_with_container_sizes<'a>(
+    root: &'a Node,
+    stylesheets: &[&'a css::Stylesheet],
+    env: &media::Environment,
+    sizes: &ContainerSizes,
+) -> (StyledNode<'a>, bool) {
+    let cascade = cascade(stylesheets, env);
+    // Sizes don't matter without `@container` rules.
+    let sizes = if cascade.rules.iter().any(|rule| !rule.containers.is_empty()) {
+        Some(sizes)
+    } else {
+        None
+    };
+    let mut state = StyleState::new(sizes);
+    let styled = style_root(root, &cascade, &mut state);
+    (styled, !state.containers.incomplete)
+}
+
+/// The state of a style pass at an element. `ancestors` are the ancestor
+/// elements of the element, from the root, and `containers` are the query
+/// containers among them. `stats` counts how often siblings share styles.
+#[derive(Clone)]
+struct StyleState<'a, 's> {
+    ancestors: Ancestors<'a>,
+    containers: QueryContainers<'s>,
+    stats: SharingStats,
+}
+
+impl<'a, 's> StyleState<'a, 's> {
+    fn new(sizes: Option<&'s ContainerSizes>) -> StyleState<'a, 's> {
+        StyleState {
+            ancestors: Ancestors::new(),
+            containers: QueryContainers::new(sizes),
+            stats: SharingStats::default(),
         }
     }
+
+    /// Returns a copy of the state for styling a subtree on another thread,
+    /// without the results so far.
+    fn fork(&self) -> StyleState<'a, 's> {
+        let mut state = self.clone();
+        state.stats = SharingStats::default();
+        state.containers.incomplete = false;
+        state
+    }
+
+    /// Adds the results of a forked state.
+    fn join(&mut self, stats: SharingStats, incomplete: bool) {
+        self.stats += stats;
+        self.containers.incomplete |= incomplete;
+    }
+}
+
+/// Styles the tree from `root`, and then generates the text of the
+/// markers, which depends on the counters in tree order.
+fn style_root<'a>(
+    root: &'a Node,
+    cascade: &Cascade<'_>,
+    state: &mut StyleState<'a, '_>,
+) -> StyledNode<'a> {
+    let mut styled = match root {
+        Node::Element(data) => {
+            let shared = computed_style(data, cascade, None, &cascade.context, state);
+            styled_element(root, data, shared, cascade, state)
+        }
+        Node::Text(_) => styled_text(root, None),
+    };
+    generate_markers(&mut styled, 0, &mut counter::Counters::new());
+    info!(
+        "style sharing: {} hits, {} misses",
+        state.stats.hits, state.stats.misses
+    );
+    styled
 }
 
-pub fn style_tree<'a>(root: &'a Node, stylesheet: &'a css::Stylesheet) -> StyledNode<'a> {
+/// A text node inherits the inherited properties of its parent.
+fn styled_text<'a>(node: &'a Node, parent_style: Option<&ComputedStyle>) -> StyledNode<'a> {
     StyledNode {
-        node: root,
-        css_specified_values: match root {
-            Node::Element(data) => css_specified_values(data, stylesheet),
-            Node::Text(_) => HashMap::new(),
+        node,
+        style: match parent_style {
+            Some(parent_style) => ComputedStyle::inherit_from(parent_style),
+            None => ComputedStyle::initial(),
         },
-        children: root
-            .children()
-            .iter()
-            .map(|child| style_tree(child, stylesheet))
-            .collect(),
+        marker: None,
+        children: vec![],
     }
 }
 
-fn css_specified_values(elem: &dom::ElementData, stylesheet: &css::Stylesheet) -> CssPropertyMap {
-    let mut values = HashMap::new();
-    let mut rules = matching_rules(elem, stylesheet);
+/// Returns the styled node of `data` with its computed style, and styles
+/// its children. The text of the marker is left empty for
+/// `generate_markers`.
+fn styled_element<'a>(
+    node: &'a Node,
+    data: &'a dom::ElementData,
+    shared: SharedStyle,
+    cascade: &Cascade<'_>,
+    state: &mut StyleState<'a, '_>,
+) -> StyledNode<'a> {
+    let SharedStyle {
+        style,
+        context,
+        marker_style,
+    } = shared;
+    let mut styled = StyledNode {
+        node,
+        marker: marker(&style, marker_style),
+        style,
+        children: vec![],
+    };
+
+    let display = styled.display();
+    let is_block = display == Display::Block || display == Display::ListItem;
+    let depth = state.ancestors.len();
+    if state.containers.enter(node, &styled.style, is_block, depth) {
+        state.ancestors.push(data);
+        styled.children = styled_children(node, &styled.style, &context, cascade, state);
+        state.ancestors.pop();
+    }
+    state.containers.leave(depth);
+    styled
+}
 
-    // Go through the rules from lowest to highest specificity.
-    rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
-    for (_, rule) in rules {
-        for declaration in &rule.declarations {
-            values.insert(declaration.name.clone(), declaration.value.clone());
+/// Returns the marker of an element whose style is `style`, if it is a list
+/// item. The text is left empty for `generate_markers`.
+fn marker(style: &ComputedStyle, marker_style: Option<ComputedStyle>) -> Option<Marker> {
+    marker_style.map(|marker_style| Marker {
+        style: marker_style,
+        text: String::new(),
+        outside: match &style.text.list_style_position {
+            css::Value::Keyword(keyword) => !keyword.eq_ignore_ascii_case("inside"),
+            _ => true,
+        },
+    })
+}
+
+/// Styles the children of `node`, whose style is `parent_style` and whose
+/// children have the length context `context`.
+///
+/// The children themselves are styled in chunks of `sharing::CHUNK_SIZE` in
+/// parallel, and in order within a chunk, so that they can share the styles
+/// of their previous siblings in the chunk. The chunks don't depend on the
+/// number of threads, so neither do the styles which are shared. Then the
+/// subtrees are styled in parallel, each with a fork of `state`.
+fn styled_children<'a>(
+    node: &'a Node,
+    parent_style: &ComputedStyle,
+    context: &css::LengthContext,
+    cascade: &Cascade<'_>,
+    state: &mut StyleState<'a, '_>,
+) -> Vec<StyledNode<'a>> {
+    let chunks: Vec<_> = node
+        .children()
+        .par_chunks(sharing::CHUNK_SIZE)
+        .map(|chunk| {
+            let mut sharing = SharingCache::new();
+            let mut stats = SharingStats::default();
+            let shared: Vec<_> = chunk
+                .iter()
+                .map(|child| match child {
+                    Node::Element(data) => Some(match sharing.get(data, &mut stats) {
+                        Some(shared) => shared,
+                        None => {
+                            let shared =
+                                computed_style(data, cascade, Some(parent_style), context, state);
+                            sharing.insert(data, &shared);
+                            shared
+                        }
+                    }),
+                    Node::Text(_) => None,
+                })
+                .collect();
+            (shared, stats)
+        })
+        .collect();
+    let mut shared = Vec::with_capacity(node.children().len());
+    for (chunk, stats) in chunks {
+        shared.extend(chunk);
+        state.stats += stats;
+    }
+    let results: Vec<_> = node
+        .children()
+        .par_iter()
+        .zip(shared)
+        .map_with(state.fork(), |state, (child, shared)| {
+            let styled = match (child, shared) {
+                (Node::Element(data), Some(shared)) => {
+                    styled_element(child, data, shared, cascade, state)
+                }
+                _ => styled_text(child, Some(parent_style)),
+            };
+            // The state is reused for the following siblings.
+            let stats = std::mem::take(&mut state.stats);
+            let incomplete = std::mem::replace(&mut state.containers.incomplete, false);
+            (styled, stats, incomplete)
+        })
+        .collect();
+    results
+        .into_iter()
+        .map(|(styled, stats, incomplete)| {
+            state.join(stats, incomplete);
+            styled
+        })
+        .collect()
+}
+
+/// Updates `counters` in tree order, and generates the text of the markers.
+/// `depth` is the depth of `styled` in the tree.
+fn generate_markers(styled: &mut StyledNode<'_>, depth: usize, counters: &mut counter::Counters) {
+    if let Node::Text(_) = styled.node {
+        return;
+    }
+    // An element without a box, and its descendants, don't affect counters.
+    let mut hidden_counters = counter::Counters::new();
+    let display = styled.display();
+    let counters = if display == Display::None {
+        &mut hidden_counters
+    } else {
+        counters
+    };
+    counters.update(&styled.style, depth, display == Display::ListItem);
+    if let Some(marker) = styled.marker.take() {
+        styled.marker = counters
+            .marker_text(&styled.style, &marker.style)
+            .map(|text| Marker { text, ..marker });
+    }
+    for child in &mut styled.children {
+        generate_markers(child, depth + 1, counters);
+    }
+    counters.leave(depth);
+}
+
+/// Runs the cascade for `data`, and for its `::marker` if it is a list item.
+fn computed_style(
+    data: &dom::ElementData,
+    cascade: &Cascade<'_>,
+    parent_style: Option<&ComputedStyle>,
+    parent_context: &css::LengthContext,
+    state: &StyleState<'_, '_>,
+) -> SharedStyle {
+    let no_transitions = CssPropertyMap::new();
+    transitioned_style(
+        data,
+        cascade,
+        parent_style,
+        parent_context,
+        state,
+        &no_transitions,
+    )
+}
+
+/// Runs the cascade as `computed_style` does, with the values of the
+/// running transitions of `data`, which override all declarations.
+///
+/// https://drafts.csswg.org/css-cascade-4/#cascade-origin
+fn transitioned_style(
+    data: &dom::ElementData,
+    cascade: &Cascade<'_>,
+    parent_style: Option<&ComputedStyle>,
+    parent_context: &css::LengthContext,
+    state: &StyleState<'_, '_>,
+    transition_values: &CssPropertyMap,
+) -> SharedStyle {
+    let StyleState {
+        ancestors,
+        containers,
+        ..
+    } = state;
+    let mut values = css_specified_values(data, ancestors, containers, cascade, None);
+    values.extend(transition_values.clone());
+    let custom_properties = substitute_variables(
+        &mut values,
+        parent_style.map(|parent_style| &parent_style.custom_properties),
+    );
+    let (style, context) =
+        computed::compute_values(&values, custom_properties, parent_style, parent_context);
+    let marker_style = if style.box_style.display == Display::ListItem {
+        let mut marker_values = css_specified_values(
+            data,
+            ancestors,
+            containers,
+            cascade,
+            Some(css::PseudoElement::Marker),
+        );
+        let custom_properties =
+            substitute_variables(&mut marker_values, Some(&style.custom_properties));
+        let (marker_style, _) =
+            computed::compute_values(&marker_values, custom_properties, Some(&style), &context);
+        Some(marker_style)
+    } else {
+        None
+    };
+    SharedStyle {
+        style,
+        context,
+        marker_style,
+    }
+}
+
+/// Resolves custom properties, which are inherited by default, and
+/// substitutes `var()` in the other properties. The custom properties are
+/// removed from `values` and returned. They are the ones of the parent if
+/// the element declares none.
+///
+/// A property whose value is invalid after substitution is invalid at
+/// computed-value time, and is removed.
+fn substitute_variables(
+    values: &mut CssPropertyMap,
+    parent_custom_properties: Option<&Arc<HashMap<String, variable::TokenStream>>>,
+) -> Arc<HashMap<String, variable::TokenStream>> {
+    let declared: HashMap<_, _> = values
+        .iter()
+        .filter_map(|(name, value)| match value {
+            css::Value::Tokens(tokens) => Some((name.clone(), tokens.clone())),
+            _ => None,
+        })
+        .collect();
+    let custom_properties = match parent_custom_properties {
+        Some(parent) if declared.is_empty() => parent.clone(),
+        _ => {
+            let mut custom_properties = parent_custom_properties
+                .map(|parent| (**parent).clone())
+                .unwrap_or_default();
+            custom_properties.extend(declared);
+            Arc::new(variable::resolve_custom_properties(&custom_properties))
+        }
+    };
+
+    values.retain(|name, value| {
+        let (tokens, shorthand) = match value {
+            css::Value::Tokens(_) => return false,
+            css::Value::WithVariables(tokens) => (tokens, None),
+            css::Value::PendingSubstitution(shorthand, tokens) => (tokens, Some(shorthand)),
+            _ => return true,
+        };
+        let substituted = tokens
+            .substitute(&|name| custom_properties.get(name))
+            .ok_or_else(|| failure::err_msg("undefined custom property"))
+            .and_then(|tokens| css::parser::parse_value(&tokens.to_string()))
+            .and_then(|value| match shorthand {
+                Some(shorthand) => longhand_value(shorthand, name, &value),
+                None if property::find(name).is_some_and(|p| p.is_valid(&value)) => Ok(value),
+                None => Err(failure::err_msg(format!("invalid value {:?}", value))),
+            });
+        match substituted {
+            Ok(substituted) => {
+                *value = substituted;
+                true
+            }
+            Err(e) => {
+                warn!("{} is invalid at computed-value time: {}", name, e);
+                false
+            }
         }
+    });
+    custom_properties
+}
+
+/// Expands a shorthand after `var()` substitution, and returns the value of
+/// one of its longhands.
+fn longhand_value(shorthand: &str, longhand: &str, value: &css::Value) -> Result<css::Value> {
+    css::shorthand::find(shorthand)
+        .and_then(|shorthand| shorthand.expand(value))
+        .and_then(|declarations| declarations.into_iter().find(|d| d.name == longhand))
+        .map(|declaration| declaration.value)
+        .ok_or_else(|| failure::err_msg(format!("invalid value for {}", shorthand)))
+}
+
+/// A style rule which applies in the environment, with its origin and the
+/// order of its layer.
+struct CascadeRule<'a> {
+    rule: &'a css::Rule,
+    origin: css::Origin,
+    /// Unlayered rules have the highest order in their origin.
+    layer: usize,
+    /// The `@container` rules which the rule is in, from the outermost. The
+    /// rule applies only if all of their conditions are true.
+    containers: Vec<&'a ContainerRule>,
+}
+
+/// The rules which apply in an environment.
+struct Cascade<'a> {
+    /// The style rules in the order of appearance.
+    rules: Vec<CascadeRule<'a>>,
+    /// `rules` by the identifiers which their selectors require.
+    rule_map: RuleMap,
+    /// `@keyframes` by name. If there are more than one with the same name,
+    /// the one with the highest precedence wins, as a style rule does.
+    keyframes: HashMap<&'a str, &'a KeyframesRule>,
+    /// `@font-face` in the order of precedence, from the lowest.
+    font_faces: Vec<&'a FontFaceRule>,
+    /// The time which animations are sampled at.
+    time: f32,
+    /// The length context of the viewport, which the root element inherits.
+    context: css::LengthContext,
+}
+
+/// A rule which `collect_rules` collects.
+enum CollectedRule<'a> {
+    /// A style rule, with the `@container` rules which it is in.
+    Style(&'a css::Rule, Vec<&'a ContainerRule>),
+    Keyframes(&'a KeyframesRule),
+    FontFace(&'a FontFaceRule),
+}
+
+/// Collects the rules which apply in `env`.
+fn cascade<'a>(stylesheets: &[&'a css::Stylesheet], env: &media::Environment) -> Cascade<'a> {
+    let mut cascade = Cascade {
+        rules: Vec::new(),
+        rule_map: RuleMap::default(),
+        keyframes: HashMap::new(),
+        font_faces: Vec::new(),
+        time: env.time,
+        context: css::LengthContext::new(env.width, env.height),
+    };
+    // Layers are shared by the stylesheets of the same origin.
+    for &origin in &[
+        css::Origin::UserAgent,
+        css::Origin::User,
+        css::Origin::Author,
+    ] {
+        let mut layers = LayerTree::new();
+        let mut rules = Vec::new();
+        for stylesheet in stylesheets.iter().filter(|s| s.origin == origin) {
+            collect_rules(
+                &stylesheet.rules,
+                env,
+                LayerTree::ROOT,
+                &[],
+                &mut layers,
+                &mut rules,
+            );
+        }
+        let order = layers.order();
+        let mut keyframes = Vec::new();
+        let mut font_faces = Vec::new();
+        for (layer, rule) in rules {
+            match rule {
+                CollectedRule::Style(rule, containers) => cascade.rules.push(CascadeRule {
+                    rule,
+                    origin,
+                    layer: order[layer],
+                    containers,
+                }),
+                CollectedRule::Keyframes(rule) => keyframes.push((order[layer], rule)),
+                CollectedRule::FontFace(rule) => font_faces.push((order[layer], rule)),
+            }
+        }
+        // A later rule wins a tie, so the sort must be stable.
+        keyframes.sort_by_key(|&(layer, _)| layer);
+        cascade.keyframes.extend(
+            keyframes
+                .into_iter()
+                .map(|(_, rule)| (rule.name.as_str(), rule)),
+        );
+        font_faces.sort_by_key(|&(layer, _)| layer);
+        cascade
+            .font_faces
+            .extend(font_faces.into_iter().map(|(_, rule)| rule));
+    }
+    cascade.rule_map = RuleMap::new(&cascade.rules);
+    cascade
+}
+
+/// Returns the `@font-face` rules which apply in `env`, from the lowest
+/// precedence to the highest.
+pub fn font_face_rules<'a>(
+    stylesheets: &[&'a css::Stylesheet],
+    env: &media::Environment,
+) -> Vec<&'a FontFaceRule> {
+    cascade(stylesheets, env).font_faces
+}
+
+/// Collects the style rules, `@keyframes` and `@font-face` in `rules` with the ids of
+/// their layers. `layer` is the layer which `rules` are in, and `containers`
+/// are the `@container` rules which they are in.
+///
+/// `@keyframes` and `@font-face` in `@container` are global, as they are in
+/// `@media` which matches.
+fn collect_rules<'a>(
+    rules: &'a [css::CssRule],
+    env: &media::Environment,
+    layer: usize,
+    containers: &[&'a ContainerRule],
+    layers: &mut LayerTree,
+    collected: &mut Vec<(usize, CollectedRule<'a>)>,
+) {
+    for rule in rules {
+        match rule {
+            css::CssRule::Style(rule) => {
+                collected.push((layer, CollectedRule::Style(rule, containers.to_vec())))
+            }
+            css::CssRule::Keyframes(rule) => {
+                collected.push((layer, CollectedRule::Keyframes(rule)))
+            }
+            css::CssRule::FontFace(rule) => collected.push((layer, CollectedRule::FontFace(rule))),
+            css::CssRule::Media(media) => {
+                if media.queries.matches(env) {
+                    collect_rules(&media.rules, env, layer, containers, layers, collected);
+                }
+            }
+            css::CssRule::Import(import) => {
+                if import.queries.matches(env) {
+                    let layer = match &import.layer {
+                        None => layer,
+                        Some(name) => layers.add(layer, name.as_ref()),
+                    };
+                    collect_rules(&import.rules, env, layer, containers, layers, collected);
+                }
+            }
+            css::CssRule::LayerStatement(names) => {
+                for name in names {
+                    layers.add(layer, Some(name));
+                }
+            }
+            css::CssRule::Layer(block) => {
+                let layer = layers.add(layer, block.name.as_ref());
+                collect_rules(&block.rules, env, layer, containers, layers, collected);
+            }
+            css::CssRule::Supports(supports) => {
+                if supports.condition.matches() {
+                    collect_rules(&supports.rules, env, layer, containers, layers, collected);
+                }
+            }
+            css::CssRule::Container(container) => {
+                let mut containers = containers.to_vec();
+                containers.push(container);
+                collect_rules(&container.rules, env, layer, &containers, layers, collected);
+            }
+        }
+    }
+}
+
+/// The tree of cascade layers of an origin. A layer is identified by its
+/// index in `children`.
+///
+/// https://drafts.csswg.org/css-cascade-5/#layer-ordering
+struct LayerTree {
+    /// The named (`Some`) and anonymous (`None`) sub-layers of each layer,
+    /// in the order of their first declarations.
+    children: Vec<Vec<(Option<String>, usize)>>,
+}
+
+impl LayerTree {
+    /// The implicit outermost layer, which holds unlayered rules.
+    const ROOT: usize = 0;
+
+    fn new() -> LayerTree {
+        LayerTree {
+            children: vec![vec![]],
+        }
+    }
+
+    /// Returns the layer `name` in `parent`, declaring it if it is new. An
+    /// anonymous layer (`None`) is always new.
+    fn add(&mut self, parent: usize, name: Option<&css::LayerName>) -> usize {
+        match name {
+            Some(name) => name
+                .iter()
+                .fold(parent, |parent, name| self.add_child(parent, Some(name))),
+            None => self.add_child(parent, None),
+        }
+    }
+
+    fn add_child(&mut self, parent: usize, name: Option<&str>) -> usize {
+        if name.is_some() {
+            if let Some(&(_, layer)) = self.children[parent]
+                .iter()
+                .find(|(child, _)| child.as_deref() == name)
+            {
+                return layer;
+            }
+        }
+        let layer = self.children.len();
+        self.children.push(vec![]);
+        self.children[parent].push((name.map(|name| name.to_string()), layer));
+        layer
     }
+
+    /// Returns the order of each layer. Sub-layers come before the rules
+    /// directly in their parent layer, so the tree is ordered in post-order.
+    fn order(&self) -> Vec<usize> {
+        fn visit(tree: &LayerTree, layer: usize, order: &mut Vec<usize>, next: &mut usize) {
+            for &(_, child) in &tree.children[layer] {
+                visit(tree, child, order, next);
+            }
+            order[layer] = *next;
+            *next += 1;
+        }
+        let mut order = vec![0; self.children.len()];
+        visit(self, LayerTree::ROOT, &mut order, &mut 0);
+        order
+    }
+}
+
+/// Returns the cascaded values of `elem`, or of its `pseudo_element`.
+///
+/// Only the rules which `cascade.rule_map` finds for `elem` and its
+/// `ancestors` are matched.
+fn css_specified_values(
+    elem: &dom::ElementData,
+    ancestors: &Ancestors<'_>,
+    containers: &QueryContainers<'_>,
+    cascade: &Cascade<'_>,
+    pseudo_element: Option<css::PseudoElement>,
+) -> CssPropertyMap {
+    let matched_rules: Vec<_> = cascade
+        .rule_map
+        .candidates(elem, &ancestors.filter)
+        .into_iter()
+        .filter_map(|index| {
+            let rule = &cascade.rules[index];
+            match_rule(elem, ancestors, containers, pseudo_element, rule)
+        })
+        .collect();
+    let declarations: Vec<_> = cascaded_declarations(&matched_rules)
+        .into_iter()
+        .map(|(index, declaration)| (matched_rules[index].1.origin, declaration))
+        .collect();
+    let mut values = HashMap::new();
+    // Important declarations come after normal ones.
+    let (normal, important): (Vec<_>, Vec<_>) =
+        (0..declarations.len()).partition(|&i| !declarations[i].1.important);
+    for i in normal {
+        let name = declarations[i].1.name.clone();
+        values.insert(name, declared_value(&declarations, i));
+    }
+    let important: CssPropertyMap = important
+        .into_iter()
+        .map(|i| {
+            (
+                declarations[i].1.name.clone(),
+                declared_value(&declarations, i),
+            )
+        })
+        .collect();
+
+    // Animations override normal declarations, but not important ones.
+    //
+    // https://drafts.csswg.org/css-cascade-4/#cascade-origin
+    if !cascade.keyframes.is_empty() {
+        let mut cascaded = values.clone();
+        cascaded.extend(important.clone());
+        values.extend(animation::animated_values(
+            &cascaded,
+            &cascade.keyframes,
+            cascade.time,
+        ));
+    }
+    values.extend(important);
     values
 }
 
-type MatchedRule<'a> = (css::Specifity, &'a css::Rule);
+/// Returns the value of the `index`th of `declarations`, which are in the
+/// order of precedence with their origins. `revert` rolls back to the value
+/// which the property would have without the declarations of its origin. It
+/// is left as is if there is no such value.
+///
+/// https://drafts.csswg.org/css-cascade-4/#default
+fn declared_value(declarations: &[(css::Origin, &css::Declaration)], index: usize) -> css::Value {
+    let (origin, declaration) = declarations[index];
+    if CssWideKeyword::from_value(&declaration.value) != Some(CssWideKeyword::Revert) {
+        return declaration.value.clone();
+    }
+    match declarations
+        .iter()
+        .rposition(|(other_origin, other)| *other_origin < origin && other.name == declaration.name)
+    {
+        Some(index) => declared_value(declarations, index),
+        None => declaration.value.clone(),
+    }
+}
+
+/// Returns the declarations of `matched_rules` from lowest to highest
+/// precedence, with the index of their rule in `matched_rules`. The sort is
+/// stable, so a later declaration comes after an earlier one with the same
+/// precedence, and wins.
+fn cascaded_declarations<'a>(
+    matched_rules: &[MatchedRule<'_, 'a>],
+) -> Vec<(usize, &'a css::Declaration)> {
+    let mut declarations = Vec::new();
+    for (index, &(specifity, rule)) in matched_rules.iter().enumerate() {
+        for declaration in &rule.rule.declarations {
+            let important = declaration.important;
+            let precedence = origin_precedence(rule.origin, important);
+            let layer = layer_precedence(rule.layer, important);
+            declarations.push(((precedence, layer, specifity), index, declaration));
+        }
+    }
+    declarations.sort_by_key(|&(key, _, _)| key);
+    declarations
+        .into_iter()
+        .map(|(_, index, declaration)| (index, declaration))
+        .collect()
+}
+
+/// Returns the precedence of the origin and importance of a declaration.
+/// Important declarations win over normal ones, and reverse the order of
+/// origins.
+///
+/// https://drafts.csswg.org/css-cascade-4/#cascade-origin
+fn origin_precedence(origin: css::Origin, important: bool) -> (bool, i8) {
+    let origin = origin as i8;
+    (important, if important { -origin } else { origin })
+}
+
+/// Returns the precedence of a layer in its origin. Important declarations
+/// reverse the order of layers, so unlayered ones have the lowest precedence.
+///
+/// https://drafts.csswg.org/css-cascade-5/#cascade-layering
+fn layer_precedence(layer: usize, important: bool) -> isize {
+    if important {
+        -(layer as isize)
+    } else {
+        layer as isize
+    }
+}
+
+type MatchedRule<'r, 'a> = (css::Specifity, &'r CascadeRule<'a>);
 
-fn matching_rules<'a>(
-    elem: &'a dom::ElementData,
-    stylesheet: &'a css::Stylesheet,
-) -> Vec<MatchedRule<'a>> {
-    stylesheet
-        .rules
+/// Returns the rules which match `elem`, or its `pseudo_element`.
+/// `containers` are the query containers among `ancestors`.
+fn matching_rules<'r, 'a>(
+    elem: &dom::ElementData,
+    ancestors: &[&dom::ElementData],
+    containers: &QueryContainers<'_>,
+    pseudo_element: Option<css::PseudoElement>,
+    rules: &'r [CascadeRule<'a>],
+) -> Vec<MatchedRule<'r, 'a>> {
+    rules
         .iter()
-        .filter_map(|rule| match_rule(elem, rule))
+        .filter_map(|rule| match_rule(elem, ancestors, containers, pseudo_element, rule))
         .collect()
 }
 
-fn match_rule<'a>(elem: &dom::ElementData, rule: &'a css::Rule) -> Option<MatchedRule<'a>> {
-    match_selectors(elem, &rule.selectors).map(|selector| (selector.specifity(), rule))
+fn match_rule<'r, 'a>(
+    elem: &dom::ElementData,
+    ancestors: &[&dom::ElementData],
+    containers: &QueryContainers<'_>,
+    pseudo_element: Option<css::PseudoElement>,
+    rule: &'r CascadeRule<'a>,
+) -> Option<MatchedRule<'r, 'a>> {
+    if !containers.matches(&rule.containers) {
+        return None;
+    }
+    match_selectors(elem, ancestors, pseudo_element, &rule.rule.selectors)
+        .map(|selector| (selector.specifity(), rule))
 }
 
 fn match_selectors<'a>(
     elem: &dom::ElementData,
+    ancestors: &[&dom::ElementData],
+    pseudo_element: Option<css::PseudoElement>,
     sorted_selectors: &'a css::SortedSelectors,
 ) -> Option<&'a css::Selector> {
     // Find the first (most specific) matching selector.
     sorted_selectors
         .selectors
         .iter()
-        .find(|selector| matches(elem, *selector))
+        .find(|selector| matches_pseudo_element(elem, ancestors, selector, pseudo_element))
+}
+
+/// `ancestors` are the ancestor elements of `elem`, from the root.
+fn matches(
+    elem: &dom::ElementData,
+    ancestors: &[&dom::ElementData],
+    selector: &css::Selector,
+) -> bool {
+    matches_pseudo_element(elem, ancestors, selector, None)
 }
 
-fn matches(elem: &dom::ElementData, selector: &css::Selector) -> bool {
+/// Returns true if `selector` matches `pseudo_element` of `elem`, or `elem`
+/// itself if it is `None`. Only the last compound selector may have a
+/// pseudo-element.
+fn matches_pseudo_element(
+    elem: &dom::ElementData,
+    ancestors: &[&dom::ElementData],
+    selector: &css::Selector,
+    pseudo_element: Option<css::PseudoElement>,
+) -> bool {
     match selector {
-        css::Selector::Simple(simple_selector) => matches_simple_selector(elem, simple_selector),
+        css::Selector::Simple(simple_selector) => {
+            simple_selector.pseudo_element == pseudo_element
+                && matches_simple_selector(elem, ancestors, simple_selector)
+        }
+        css::Selector::Complex(ancestor, combinator, simple_selector) => {
+            if simple_selector.pseudo_element != pseudo_element
+                || !matches_simple_selector(elem, ancestors, simple_selector)
+            {
+                return false;
+            }
+            match combinator {
+                css::Combinator::Child => match ancestors.split_last() {
+                    Some((parent, rest)) => matches(parent, rest, ancestor),
+                    None => false,
+                },
+                css::Combinator::Descendant => (0..ancestors.len())
+                    .rev()
+                    .any(|i| matches(ancestors[i], &ancestors[..i], ancestor)),
+            }
+        }
     }
 }
 
-fn matches_simple_selector(elem: &dom::ElementData, selector: &css::SimpleSelector) -> bool {
+/// The pseudo-element of `selector` is checked by `matches_pseudo_element`.
+fn matches_simple_selector(
+    elem: &dom::ElementData,
+    ancestors: &[&dom::ElementData],
+    selector: &css::SimpleSelector,
+) -> bool {
     // Check type selector
-    if !selector.tag_name.iter().all(|name| elem.tag_name == *name) {
+    if !selector
+        .tag_name
+        .iter()
+        .all(|name| elem.tag_name() == *name)
+    {
         return false;
     }
 
@@ -131,6 +927,20 @@ fn matches_simple_selector(elem: &dom::ElementData, selector: &css::SimpleSelect
         return false;
     }
 
+    // `&` at the top level matches the root element.
+    if selector.nesting && !ancestors.is_empty() {
+        return false;
+    }
+
+    // Check :is()
+    if !selector.is.iter().all(|selectors| {
+        selectors
+            .iter()
+            .any(|selector| matches(elem, ancestors, selector))
+    }) {
+        return false;
+    }
+
     // We didn't find any non-matching selector components.
     true
 }
@@ -149,79 +959,81 @@ mod test {
             ..Default::default()
         };
 
-        let div_elem = dom::ElementData {
-            tag_name: "div".to_string(),
-            ..Default::default()
-        };
+        let div_elem = dom::ElementData::new("div".to_string(), Default::default(), vec![]);
 
-        let p_elem = dom::ElementData {
-            tag_name: "p".to_string(),
-            ..Default::default()
-        };
+        let p_elem = dom::ElementData::new("p".to_string(), Default::default(), vec![]);
 
-        assert!(matches_simple_selector(&div_elem, &div_selector));
-        assert!(!matches_simple_selector(&p_elem, &div_selector));
+        assert!(matches_simple_selector(&div_elem, &[], &div_selector));
+        assert!(!matches_simple_selector(&p_elem, &[], &div_selector));
 
         let class_foo_selector = css::SimpleSelector {
             classes: btreeset! { "foo".to_string() },
             ..Default::default()
         };
 
-        assert!(!matches_simple_selector(&div_elem, &class_foo_selector));
-        assert!(!matches_simple_selector(&p_elem, &class_foo_selector));
+        assert!(!matches_simple_selector(
+            &div_elem,
+            &[],
+            &class_foo_selector
+        ));
+        assert!(!matches_simple_selector(&p_elem, &[], &class_foo_selector));
 
-        let div_class_foo_elem = dom::ElementData {
-            tag_name: "div".to_string(),
-            attrs: btreemap! {
+        let div_class_foo_elem = dom::ElementData::new(
+            "div".to_string(),
+            btreemap! {
                 "class".to_string() => "foo".to_string()
             },
-            ..Default::default()
-        };
+            vec![],
+        );
 
-        let div_class_bar_elem = dom::ElementData {
-            tag_name: "div".to_string(),
-            attrs: btreemap! {
+        let div_class_bar_elem = dom::ElementData::new(
+            "div".to_string(),
+            btreemap! {
                 "class".to_string() => "bar".to_string()
             },
-            ..Default::default()
-        };
+            vec![],
+        );
 
-        let div_class_foo_bar_elem = dom::ElementData {
-            tag_name: "div".to_string(),
-            attrs: btreemap! {
+        let div_class_foo_bar_elem = dom::ElementData::new(
+            "div".to_string(),
+            btreemap! {
                 "class".to_string() => "foo bar".to_string()
             },
-            ..Default::default()
-        };
+            vec![],
+        );
 
         assert!(matches_simple_selector(
             &div_class_foo_elem,
+            &[],
             &class_foo_selector
         ));
 
         assert!(!matches_simple_selector(
             &div_class_bar_elem,
+            &[],
             &class_foo_selector
         ));
 
         assert!(matches_simple_selector(
             &div_class_foo_bar_elem,
+            &[],
             &class_foo_selector
         ));
 
         let universal_selector: css::SimpleSelector = Default::default();
-        assert!(matches_simple_selector(&div_elem, &universal_selector));
+        assert!(matches_simple_selector(&div_elem, &[], &universal_selector));
 
-        let div_id_foo_elem = dom::ElementData {
-            tag_name: "div".to_string(),
-            attrs: btreemap! {
+        let div_id_foo_elem = dom::ElementData::new(
+            "div".to_string(),
+            btreemap! {
                 "id".to_string() => "foo".to_string()
             },
-            ..Default::default()
-        };
+            vec![],
+        );
 
         assert!(matches_simple_selector(
             &div_id_foo_elem,
+            &[],
             &css::SimpleSelector {
                 id: Some("foo".to_string()),
                 ..Default::default()
@@ -230,23 +1042,25 @@ mod test {
 
         assert!(!matches_simple_selector(
             &div_id_foo_elem,
+            &[],
             &css::SimpleSelector {
                 id: Some("xxx".to_string()),
                 ..Default::default()
             }
         ));
 
-        let div_id_foo_class1_class2_elem = dom::ElementData {
-            tag_name: "div".to_string(),
-            attrs: btreemap! {
+        let div_id_foo_class1_class2_elem = dom::ElementData::new(
+            "div".to_string(),
+            btreemap! {
                 "id".to_string() => "foo".to_string(),
                 "class".to_string() => "class1 class2".to_string(),
             },
-            ..Default::default()
-        };
+            vec![],
+        );
 
         assert!(matches_simple_selector(
             &div_id_foo_class1_class2_elem,
+            &[],
             &css::SimpleSelector {
                 id: Some("foo".to_string()),
                 classes: btreeset! {"class1".to_string()},
@@ -256,6 +1070,7 @@ mod test {
 
         assert!(!matches_simple_selector(
             &div_id_foo_class1_class2_elem,
+            &[],
             &css::SimpleSelector {
                 id: Some("foo".to_string()),
                 classes: btreeset! {"class1 classxx".to_string()},
@@ -266,14 +1081,13 @@ mod test {
 
     #[test]
     fn match_selectors_test() {
-        let div = dom::ElementData {
-            tag_name: "div".to_string(),
-            ..Default::default()
-        };
+        let div = dom::ElementData::new("div".to_string(), Default::default(), vec![]);
 
-        assert!(match_selectors(&div, &css::SortedSelectors::new(vec![])).is_none());
+        assert!(match_selectors(&div, &[], None, &css::SortedSelectors::new(vec![])).is_none());
         assert!(match_selectors(
             &div,
+            &[],
+            None,
             &css::SortedSelectors::new(vec![css::Selector::id("XXX")]),
         )
         .is_none());
@@ -281,23 +1095,27 @@ mod test {
         assert_eq!(
             match_selectors(
                 &div,
+                &[],
+                None,
                 &css::SortedSelectors::new(vec![css::Selector::universal()]),
             ),
             Some(&css::Selector::universal())
         );
 
-        let elem = dom::ElementData {
-            tag_name: "div".to_string(),
-            attrs: btreemap! {
+        let elem = dom::ElementData::new(
+            "div".to_string(),
+            btreemap! {
                 "id".to_string() => "foo".to_string(),
                 "class".to_string() => "class1 class2".to_string(),
             },
-            ..Default::default()
-        };
+            vec![],
+        );
 
         assert_eq!(
             match_selectors(
                 &elem,
+                &[],
+                None,
                 &css::SortedSelectors::new(vec![
                     css::Selector::tag("div"),
                     css::Selector::class(&["class1"]),
@@ -311,6 +1129,8 @@ mod test {
         assert_eq!(
             match_selectors(
                 &elem,
+                &[],
+                None,
                 &css::SortedSelectors::new(vec![
                     css::Selector::tag("div"),
                     css::Selector::class(&["class1"]),
@@ -323,6 +1143,8 @@ mod test {
         assert_eq!(
             match_selectors(
                 &elem,
+                &[],
+                None,
                 &css::SortedSelectors::new(vec![
                     css::Selector::class(&["class1"]),
                     css::Selector::class(&["class1", "class2"]),
@@ -337,31 +1159,34 @@ mod test {
     #[test]
     fn matching_rules_test() {
         let stylesheet = css::Stylesheet {
+            origin: css::Origin::Author,
             rules: vec![
-                css::Rule {
+                css::CssRule::Style(css::Rule {
                     selectors: css::SortedSelectors::new(vec![css::Selector::tag("div")]),
                     declarations: vec![css::Declaration::color((0, 0, 0))],
-                },
-                css::Rule {
+                    location: Default::default(),
+                }),
+                css::CssRule::Style(css::Rule {
                     selectors: css::SortedSelectors::new(vec![css::Selector::tag("foo")]),
                     declarations: vec![css::Declaration::color((1, 1, 1))],
-                },
-                css::Rule {
+                    location: Default::default(),
+                }),
+                css::CssRule::Style(css::Rule {
                     selectors: css::SortedSelectors::new(vec![css::Selector::tag("div")]),
                     declarations: vec![css::Declaration::color((2, 2, 2))],
-                },
+                    location: Default::default(),
+                }),
             ],
         };
 
-        let div = dom::ElementData {
-            tag_name: "div".to_string(),
-            ..Default::default()
-        };
+        let div = dom::ElementData::new("div".to_string(), Default::default(), vec![]);
 
-        let matched_declarations = matching_rules(&div, &stylesheet)
-            .into_iter()
-            .map(|(_speficity, rule)| &rule.declarations)
-            .collect::<Vec<_>>();
+        let rules = cascade(&[&stylesheet], &Default::default()).rules;
+        let matched_declarations =
+            matching_rules(&div, &[], &QueryContainers::new(None), None, &rules)
+                .into_iter()
+                .map(|(_speficity, rule)| &rule.rule.declarations)
+                .collect::<Vec<_>>();
 
         assert_eq!(
             matched_declarations,
@@ -375,38 +1200,347 @@ mod test {
     #[test]
     fn css_specified_values_test() {
         let stylesheet = css::Stylesheet {
+            origin: css::Origin::Author,
             rules: vec![
-                css::Rule {
+                css::CssRule::Style(css::Rule {
                     selectors: css::SortedSelectors::new(vec![css::Selector::tag("div")]),
                     declarations: vec![css::Declaration::color((0, 0, 0))],
-                },
-                css::Rule {
+                    location: Default::default(),
+                }),
+                css::CssRule::Style(css::Rule {
                     selectors: css::SortedSelectors::new(vec![css::Selector::id("foo")]),
                     declarations: vec![css::Declaration::color((1, 1, 1))],
-                },
-                css::Rule {
+                    location: Default::default(),
+                }),
+                css::CssRule::Style(css::Rule {
                     selectors: css::SortedSelectors::new(vec![css::Selector::id("foo")]),
                     declarations: vec![css::Declaration::color((2, 2, 2))],
-                },
-                css::Rule {
+                    location: Default::default(),
+                }),
+                css::CssRule::Style(css::Rule {
                     selectors: css::SortedSelectors::new(vec![css::Selector::tag("div")]),
                     declarations: vec![css::Declaration::color((3, 3, 3))],
-                },
+                    location: Default::default(),
+                }),
             ],
         };
 
-        let div = dom::ElementData {
-            tag_name: "div".to_string(),
-            attrs: btreemap! {
+        let div = dom::ElementData::new(
+            "div".to_string(),
+            btreemap! {
                 "id".to_string() => "foo".to_string()
             },
-            ..Default::default()
-        };
+            vec![],
+        );
 
-        let values = css_specified_values(&div, &stylesheet);
+        let values = css_specified_values(
+            &div,
+            &Ancestors::new(),
+            &QueryContainers::new(None),
+            &cascade(&[&stylesheet], &Default::default()),
+            None,
+        );
         assert_eq!(
             values,
             hashmap! { "color".to_string() => css::Value::color((2, 2, 2)) }
         );
     }
+
+    #[test]
+    fn cascade_origin_test() {
+        let node = dom::parser::parse_html("(div (p id=foo) (head))").unwrap();
+        let mut user = css::parser::parse_stylesheet(
+            "div { color: #010101 !important; width: 1px } p { display: inline }",
+        )
+        .unwrap();
+        user.origin = css::Origin::User;
+        let author = css::parser::parse_stylesheet(
+            "div { color: #030303; width: 2px !important } \
+             #foo { color: #040404 } p { color: #050505 !important }",
+        )
+        .unwrap();
+        let style_tree = style_tree(
+            &node,
+            &[css::user_agent_stylesheet(), &user, &author],
+            &Default::default(),
+        );
+
+        let div = &style_tree;
+        let p = &div.children[0];
+        let head = &div.children[1];
+        let px = |n| css::Value::Length(n, css::Unit::Px);
+
+        // Important user declarations win over normal author declarations.
+        assert_eq!(div.value("color"), Some(css::Value::color((1, 1, 1))));
+        // Important author declarations win over normal user declarations.
+        assert_eq!(div.value("width"), Some(px(2.0)));
+        assert!(div.display() == Display::Block);
+        // Importance comes before specificity.
+        assert_eq!(p.value("color"), Some(css::Value::color((5, 5, 5))));
+        // User declarations win over user-agent declarations.
+        assert!(p.display() == Display::Inline);
+        assert!(head.display() == Display::None);
+    }
+
+    #[test]
+    fn computed_values_test() {
+        let node = dom::parser::parse_html("(body (div (p)))").unwrap();
+        let mut user_agent =
+            css::parser::parse_stylesheet("div { display: block; margin-top: 1px }").unwrap();
+        user_agent.origin = css::Origin::UserAgent;
+        let author = css::parser::parse_stylesheet(
+            "body { color: #ff0000; font-size: 10px } \
+             div { font-size: 2em; display: inline; display: revert; margin-top: 2px } \
+             div { margin-top: revert } \
+             p { color: #0000ff; color: revert; width: 2em; margin-top: inherit }",
+        )
+        .unwrap();
+        let style_tree = style_tree(&node, &[&user_agent, &author], &Default::default());
+
+        let div = &style_tree.children[0];
+        let p = &div.children[0];
+        let px = |n| css::Value::Length(n, css::Unit::Px);
+
+        // `color` is inherited. `revert` without a declaration in a lower
+        // origin is `unset`.
+        assert_eq!(p.value("color"), Some(css::Value::color((255, 0, 0))));
+        // The computed font size is inherited, and `em` refers to it.
+        assert_eq!(p.value("font-size"), Some(px(20.0)));
+        assert_eq!(p.value("width"), Some(px(40.0)));
+        // `revert` rolls back to the user-agent origin.
+        assert!(div.display() == Display::Block);
+        assert_eq!(div.value("margin-top"), Some(px(1.0)));
+        assert_eq!(p.value("margin-top"), Some(px(1.0)));
+        // A property which is not inherited has its initial value.
+        assert!(p.display() == Display::Inline);
+    }
+
+    #[test]
+    fn property_name_case_test() {
+        let node = dom::parser::parse_html("(div)").unwrap();
+        let stylesheet =
+            css::parser::parse_stylesheet("div { WIDTH: 100px; Margin: 10px; --X: 1px }").unwrap();
+        let style_tree = style_tree(&node, &[&stylesheet], &Default::default());
+        let px = |n| Some(css::Value::Length(n, css::Unit::Px));
+
+        assert_eq!(style_tree.value("width"), px(100.0));
+        assert_eq!(style_tree.value("margin-left"), px(10.0));
+        // Custom property names are case-sensitive.
+        assert!(style_tree.value("--x").is_none());
+        assert!(style_tree.value("--X").is_some());
+    }
+
+    #[test]
+    fn media_rule_test() {
+        let node = dom::parser::parse_html("(div)").unwrap();
+        let stylesheet = css::parser::parse_stylesheet(
+            "div { width: 1px } \
+             @media (max-width: 400px) { div { width: 2px } } \
+             @media (prefers-color-scheme: dark) { @media print { div { height: 3px } } }",
+        )
+        .unwrap();
+        let width = |env: &media::Environment| {
+            let style_tree = style_tree(&node, &[&stylesheet], env);
+            style_tree.value("width")
+        };
+        let px = |n| Some(css::Value::Length(n, css::Unit::Px));
+
+        assert_eq!(width(&Default::default()), px(1.0));
+        let narrow = media::Environment {
+            width: 375.0,
+            color_scheme: media::ColorScheme::Dark,
+            ..Default::default()
+        };
+        assert_eq!(width(&narrow), px(2.0));
+        assert_eq!(
+            style_tree(&node, &[&stylesheet], &narrow).value("height"),
+            Some(css::Value::keyword_auto().clone())
+        );
+    }
+
+    #[test]
+    fn layer_test() {
+        let node = dom::parser::parse_html("(div (p) (span))").unwrap();
+        let stylesheet = css::parser::parse_stylesheet(
+            "@layer base, theme; \
+             div { width: 1px } \
+             @layer theme { div { width: 2px; height: 2px } p { color: #020202 !important } } \
+             @layer base { div { height: 3px } #x, div { margin-top: 3px } } \
+             @layer base.reset { div { margin-top: 4px; margin-left: 4px } } \
+             @layer base { div { margin-left: 5px } p { color: #050505 !important } } \
+             @layer { span { width: 6px } } @layer { span { width: 7px } } \
+             p { color: #080808 !important }",
+        )
+        .unwrap();
+        let style_tree = style_tree(&node, &[&stylesheet], &Default::default());
+
+        let div = &style_tree;
+        let p = &div.children[0];
+        let span = &div.children[1];
+        let px = |n| css::Value::Length(n, css::Unit::Px);
+
+        // Unlayered declarations win over layered ones.
+        assert_eq!(div.value("width"), Some(px(1.0)));
+        // The order of layers is the order of their first declarations.
+        assert_eq!(div.value("height"), Some(px(2.0)));
+        // Sub-layers come before the rules directly in their parent layer,
+        // whatever their specificity.
+        assert_eq!(div.value("margin-top"), Some(px(3.0)));
+        assert_eq!(div.value("margin-left"), Some(px(5.0)));
+        // Every anonymous layer is a new layer.
+        assert_eq!(span.value("width"), Some(px(7.0)));
+        // Important declarations reverse the order of layers.
+        assert_eq!(p.value("color"), Some(css::Value::color((5, 5, 5))));
+    }
+
+    #[test]
+    fn nesting_test() {
+        let node = dom::parser::parse_html("(div id=x (p class=a) (p class=b))").unwrap();
+        let stylesheet = css::parser::parse_stylesheet(
+            "#y, div { .a { width: 1px } } div > p.a { width: 2px } \
+             .b { height: 3px; &.b { height: 4px } div & { height: 5px } } \
+             p { @media (min-width: 400px) { margin-top: 6px } }",
+        )
+        .unwrap();
+        let style_tree = style_tree(&node, &[&stylesheet], &Default::default());
+
+        let a = &style_tree.children[0];
+        let b = &style_tree.children[1];
+        let px = |n| css::Value::Length(n, css::Unit::Px);
+
+        // `:is()` takes the specificity of its most specific argument.
+        assert_eq!(a.value("width"), Some(px(1.0)));
+        assert_eq!(b.value("height"), Some(px(4.0)));
+        assert_eq!(a.value("margin-top"), Some(px(6.0)));
+        assert_eq!(b.value("margin-top"), Some(px(6.0)));
+        assert_eq!(style_tree.value("margin-top"), Some(px(0.0)));
+    }
+
+    #[test]
+    fn custom_property_test() {
+        let node = dom::parser::parse_html("(div (p (span)) (p id=foo))").unwrap();
+        let stylesheet = css::parser::parse_stylesheet(
+            "div { --width: 10px; --margin: var(--width); --cycle: var(--cycle) } \
+             p { --width: 20px; width: var(--width) } \
+             span { margin-left: var(--margin); width: var(--cycle, 1px); height: var(--cycle) } \
+             #foo { padding: var(--undefined, 3px) var(--width); color: var(--width); \
+                    margin: var(--width) red }",
+        )
+        .unwrap();
+        let style_tree = style_tree(&node, &[&stylesheet], &Default::default());
+
+        let div = &style_tree;
+        let p = &div.children[0];
+        let span = &p.children[0];
+        let foo = &div.children[1];
+        let px = |n| css::Value::Length(n, css::Unit::Px);
+
+        assert!(div.value("--cycle").is_none());
+        assert_eq!(p.value("width"), Some(px(20.0)));
+        // Custom properties are inherited after their references are resolved.
+        assert_eq!(span.value("margin-left"), Some(px(10.0)));
+        // A custom property in a cycle is invalid, and is not inherited. A
+        // property which refers to it is unset.
+        assert_eq!(span.value("width"), Some(px(1.0)));
+        assert_eq!(
+            span.value("height"),
+            Some(css::Value::keyword_auto().clone())
+        );
+        // A shorthand is expanded after substitution.
+        assert_eq!(foo.value("padding-top"), Some(px(3.0)));
+        assert_eq!(foo.value("padding-left"), Some(px(20.0)));
+        assert!(foo.value("padding").is_none());
+        assert_eq!(foo.value("margin-top"), Some(px(0.0)));
+        // A substituted value is validated against its property.
+        assert_eq!(foo.value("color"), Some(css::Value::color((0, 0, 0))));
+    }
+
+    #[test]
+    fn sharing_test() {
+        let node = dom::parser::parse_html(
+            "(ol (li class=a (p)) (li class=a (p)) (li class=b) (li class=a) (li id=x class=a))",
+        )
+        .unwrap();
+        let stylesheet = css::parser::parse_stylesheet(
+            "ol { list-style-type: decimal } li { display: list-item } .a { width: 1px } #x { width: 2px }",
+        )
+        .unwrap();
+        let cascade = cascade(&[&stylesheet], &Default::default());
+        let mut state = StyleState::new(None);
+        let style_tree = style_root(&node, &cascade, &mut state);
+
+        let items = &style_tree.children;
+        let px = |n| css::Value::Length(n, css::Unit::Px);
+
+        // The second and fourth items share the style of the first. The
+        // children of different parents don't share, and neither does an
+        // element with an id.
+        assert!(Arc::ptr_eq(
+            &items[0].style.box_style,
+            &items[1].style.box_style
+        ));
+        assert!(Arc::ptr_eq(
+            &items[0].style.box_style,
+            &items[3].style.box_style
+        ));
+        assert_eq!(state.stats, SharingStats { hits: 2, misses: 4 });
+        assert_eq!(
+            items[2].value("width"),
+            Some(css::Value::keyword_auto().clone())
+        );
+        assert_eq!(items[3].value("width"), Some(px(1.0)));
+        assert_eq!(items[4].value("width"), Some(px(2.0)));
+        // Counters are not shared.
+        let marker = |i: usize| items[i].marker.as_ref().unwrap().text.clone();
+        assert_eq!(marker(0), "1. ");
+        assert_eq!(marker(1), "2. ");
+        assert_eq!(marker(3), "4. ");
+    }
+
+    #[test]
+    fn parallel_test() {
+        let item = "(li class=a (p class=b) (p id=x))";
+        let list = format!("(ol class=c {})", vec![item; 20].join(" "));
+        // A flat list is cascaded in several chunks.
+        let flat_list = format!("(ol class=c {})", vec![item; 100].join(" "));
+        let html = format!(
+            "(body {} (div class=hidden {}) {})",
+            vec![list.as_str(); 20].join(" "),
+            list,
+            flat_list
+        );
+        let node = dom::parser::parse_html(&html).unwrap();
+        let stylesheet = css::parser::parse_stylesheet(
+            "ol { counter-reset: list-item; list-style-type: decimal } \
+             li { display: list-item } .hidden { display: none } \
+             ol > .a { width: 1px } .c p.b { height: 2px } li #x { counter-increment: list-item 2 } \
+             li::marker { content: counters(list-item, \".\") }",
+        )
+        .unwrap();
+        let style_tree_with_threads = |threads| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+                .install(|| style_tree(&node, &[&stylesheet], &Default::default()))
+        };
+
+        let sequential = style_tree_with_threads(1);
+        assert_eq!(
+            sequential.children[0].children[1]
+                .marker
+                .as_ref()
+                .unwrap()
+                .text,
+            "4"
+        );
+        let flat_list = &sequential.children[21];
+        assert_eq!(flat_list.children.len(), 100);
+        assert!(Arc::ptr_eq(
+            &flat_list.children[0].style.box_style,
+            &flat_list.children[sharing::CHUNK_SIZE - 1].style.box_style
+        ));
+        assert_eq!(flat_list.children[99].marker.as_ref().unwrap().text, "298");
+        assert!(sequential == style_tree_with_threads(4));
+    }
 }
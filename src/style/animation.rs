@@ -0,0 +1,335 @@
+//! Samples CSS animations at a time of the document timeline.
+//!
+//! The timeline is a virtual clock given by `media::Environment::time`, and
+//! every animation starts at time 0. Animation properties and keyframe
+//! values with `var()` are not substituted yet when animations are sampled,
+//! so they are ignored.
+//!
+//! https://drafts.csswg.org/css-animations-1/
+
+use super::CssPropertyMap;
+use crate::css::animation::{interpolate, EasingFunction, KeyframesRule};
+use crate::css::property::{self, AnimationType};
+use crate::css::Value;
+use std::collections::HashMap;
+use std::slice;
+
+/// Returns the values of the animated properties of an element at `time`.
+/// `values` are the cascaded values of the element, which give the
+/// animations and the values of missing `from` and `to` keyframes. If more
+/// than one animation animates a property, the last one wins.
+pub(super) fn animated_values(
+    values: &CssPropertyMap,
+    keyframes: &HashMap<&str, &KeyframesRule>,
+    time: f32,
+) -> CssPropertyMap {
+    let mut animated = HashMap::new();
+    let names = match values.get("animation-name") {
+        Some(names) => list_items(names),
+        None => return animated,
+    };
+    for (i, name) in names.iter().enumerate() {
+        let rule = match name {
+            Value::Keyword(name) | Value::String(name) => keyframes.get(name.as_str()),
+            _ => None,
+        };
+        let rule = match rule {
+            Some(rule) => rule,
+            None => continue,
+        };
+        // A longhand with fewer items than `animation-name` repeats them.
+        let property = |name: &str| {
+            values
+                .get(name)
+                .map(list_items)
+                .filter(|items| !items.is_empty())
+                .map(|items| &items[i % items.len()])
+        };
+        let timing = Timing {
+            duration: match property("animation-duration") {
+                Some(Value::Time(duration)) if *duration >= 0.0 => *duration,
+                _ => 0.0,
+            },
+            delay: match property("animation-delay") {
+                Some(Value::Time(delay)) => *delay,
+                _ => 0.0,
+            },
+            iterations: match property("animation-iteration-count") {
+                Some(Value::Number(n)) if *n >= 0.0 => *n,
+                Some(Value::Keyword(k)) if k.eq_ignore_ascii_case("infinite") => f32::INFINITY,
+                _ => 1.0,
+            },
+            direction: keyword(property("animation-direction")).unwrap_or("normal"),
+            fill_mode: keyword(property("animation-fill-mode")).unwrap_or("none"),
+            paused: keyword(property("animation-play-state")) == Some("paused"),
+        };
+        let progress = match timing.iteration_progress(time) {
+            Some(progress) => progress,
+            None => continue,
+        };
+        let easing = property("animation-timing-function")
+            .and_then(EasingFunction::from_value)
+            .unwrap_or(EasingFunction::EASE);
+        animated.extend(sample(rule, progress, values, easing));
+    }
+    animated
+}
+
+/// Returns the comma separated items of a value.
+pub(super) fn list_items(value: &Value) -> &[Value] {
+    match value {
+        Value::CommaList(items) => items,
+        _ => slice::from_ref(value),
+    }
+}
+
+fn keyword(value: Option<&Value>) -> Option<&str> {
+    match value {
+        Some(Value::Keyword(keyword)) => Some(keyword),
+        _ => None,
+    }
+}
+
+/// The timing of an animation, from its longhands.
+struct Timing<'a> {
+    /// The duration of an iteration in seconds.
+    duration: f32,
+    delay: f32,
+    /// It may be infinite.
+    iterations: f32,
+    direction: &'a str,
+    fill_mode: &'a str,
+    /// A paused animation stays where it is at time 0.
+    paused: bool,
+}
+
+impl<'a> Timing<'a> {
+    /// Returns the progress in the current iteration, from 0 to 1, or `None`
+    /// if the animation has no effect at `time`.
+    ///
+    /// https://drafts.csswg.org/web-animations-1/#core-animation-model-calculations
+    fn iteration_progress(&self, time: f32) -> Option<f32> {
+        let time = if self.paused { 0.0 } else { time };
+        let local_time = time - self.delay;
+        let active_duration = if self.duration == 0.0 {
+            0.0
+        } else {
+            self.duration * self.iterations
+        };
+        let fills = |mode: &str| self.fill_mode == mode || self.fill_mode == "both";
+
+        let overall_progress = if local_time < 0.0 {
+            if !fills("backwards") {
+                return None;
+            }
+            0.0
+        } else if local_time >= active_duration {
+            if !fills("forwards") {
+                return None;
+            }
+            self.iterations
+        } else {
+            local_time / self.duration
+        };
+        let mut iteration = overall_progress.floor();
+        let mut progress = overall_progress - iteration;
+        // The end of the last iteration is its 100%, not the next one's 0%.
+        if progress == 0.0 && overall_progress > 0.0 && local_time >= active_duration {
+            iteration -= 1.0;
+            progress = 1.0;
+        }
+
+        let reversed = match self.direction {
+            "reverse" => true,
+            "alternate" => iteration % 2.0 == 1.0,
+            "alternate-reverse" => iteration % 2.0 == 0.0,
+            _ => false,
+        };
+        Some(if reversed { 1.0 - progress } else { progress })
+    }
+}
+
+/// A keyframe of a property.
+struct PropertyKeyframe<'a> {
+    /// From 0 to 1.
+    offset: f32,
+    value: &'a Value,
+    /// The easing to the next keyframe.
+    easing: EasingFunction,
+}
+
+/// Returns the values of the properties of `rule` at `progress`.
+///
+/// `values` give the values of missing `from` and `to` keyframes. If a
+/// property has no value, its first and last keyframes extend to the ends.
+fn sample(
+    rule: &KeyframesRule,
+    progress: f32,
+    values: &CssPropertyMap,
+    easing: EasingFunction,
+) -> Vec<(String, Value)> {
+    let mut properties: Vec<(&str, Vec<PropertyKeyframe<'_>>)> = Vec::new();
+    for keyframe in &rule.keyframes {
+        let keyframe_easing = keyframe
+            .declarations
+            .iter()
+            .rev()
+            .find(|declaration| declaration.name == "animation-timing-function")
+            .and_then(|declaration| EasingFunction::from_value(&declaration.value))
+            .unwrap_or(easing);
+        for declaration in &keyframe.declarations {
+            if property::animation_type(&declaration.name) == AnimationType::NotAnimatable
+                || has_variables(&declaration.value)
+            {
+                continue;
+            }
+            let index = match properties
+                .iter()
+                .position(|(name, _)| *name == declaration.name)
+            {
+                Some(index) => index,
+                None => {
+                    properties.push((&declaration.name, vec![]));
+                    properties.len() - 1
+                }
+            };
+            for &offset in &keyframe.offsets {
+                properties[index].1.push(PropertyKeyframe {
+                    offset: offset / 100.0,
+                    value: &declaration.value,
+                    easing: keyframe_easing,
+                });
+            }
+        }
+    }
+
+    let mut sampled = Vec::new();
+    for (name, mut keyframes) in properties {
+        // A later keyframe with the same offset wins.
+        keyframes.sort_by(|a, b| a.offset.partial_cmp(&b.offset).unwrap());
+        keyframes.reverse();
+        keyframes.dedup_by(|a, b| a.offset == b.offset);
+        keyframes.reverse();
+        if let Some(value) = values.get(name).filter(|value| !has_variables(value)) {
+            if keyframes[0].offset > 0.0 {
+                keyframes.insert(
+                    0,
+                    PropertyKeyframe {
+                        offset: 0.0,
+                        value,
+                        easing,
+                    },
+                );
+            }
+            if keyframes[keyframes.len() - 1].offset < 1.0 {
+                keyframes.push(PropertyKeyframe {
+                    offset: 1.0,
+                    value,
+                    easing,
+                });
+            }
+        }
+        let animation_type = property::animation_type(name);
+        sampled.push((
+            name.to_string(),
+            keyframes_value(&keyframes, progress, animation_type),
+        ));
+    }
+    sampled
+}
+
+/// Returns the value between the keyframes around `progress`.
+fn keyframes_value(
+    keyframes: &[PropertyKeyframe<'_>],
+    progress: f32,
+    animation_type: AnimationType,
+) -> Value {
+    let next = keyframes
+        .iter()
+        .position(|keyframe| keyframe.offset > progress)
+        .unwrap_or(keyframes.len());
+    if next == 0 {
+        return keyframes[0].value.clone();
+    }
+    if next == keyframes.len() {
+        return keyframes[next - 1].value.clone();
+    }
+    let (from, to) = (&keyframes[next - 1], &keyframes[next]);
+    let local_progress = (progress - from.offset) / (to.offset - from.offset);
+    let progress = from.easing.apply(local_progress);
+    match animation_type {
+        AnimationType::Discrete if progress < 0.5 => from.value.clone(),
+        AnimationType::Discrete => to.value.clone(),
+        _ => interpolate(from.value, to.value, progress),
+    }
+}
+
+fn has_variables(value: &Value) -> bool {
+    matches!(
+        value,
+        Value::WithVariables(_) | Value::PendingSubstitution(..)
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::css::media::Environment;
+    use crate::css::parser::parse_stylesheet;
+    use crate::css::{Color, Unit, Value};
+    use crate::dom::parser::parse_html;
+    use crate::style::style_tree;
+
+    fn value_at(stylesheet: &str, name: &str, time: f32) -> Option<Value> {
+        let node = parse_html("(div)").unwrap();
+        let stylesheet = parse_stylesheet(stylesheet).unwrap();
+        let env = Environment {
+            time,
+            ..Default::default()
+        };
+        style_tree(&node, &[&stylesheet], &env).value(name)
+    }
+
+    fn px(n: f32) -> Option<Value> {
+        Some(Value::Length(n, Unit::Px))
+    }
+
+    #[test]
+    fn animation_test() {
+        let stylesheet = "@keyframes grow { from { width: 0px } to { width: 100px } } \
+                          div { width: 10px; animation: grow 2s linear 1s }";
+        assert_eq!(value_at(stylesheet, "width", 0.5), px(10.0));
+        assert_eq!(value_at(stylesheet, "width", 1.0), px(0.0));
+        assert_eq!(value_at(stylesheet, "width", 1.5), px(25.0));
+        assert_eq!(value_at(stylesheet, "width", 2.5), px(75.0));
+        // Without `animation-fill-mode`, the animation has no effect after
+        // it ends.
+        assert_eq!(value_at(stylesheet, "width", 3.0), px(10.0));
+
+        // The missing `from` keyframe is the value of the element.
+        let stylesheet = "@keyframes a { 50% { height: 20px } } \
+                          div { height: 10px; animation: a 1s linear infinite alternate }";
+        assert_eq!(value_at(stylesheet, "height", 0.25), px(15.0));
+        assert_eq!(value_at(stylesheet, "height", 0.75), px(15.0));
+        assert_eq!(value_at(stylesheet, "height", 1.5), px(20.0));
+
+        let stylesheet = "@keyframes fade { to { color: #ffffff } } \
+                          div { color: #000000; animation: fade 1s steps(2) forwards }";
+        let gray = |n| Some(Value::ColorValue(Color::rgb(n, n, n)));
+        assert_eq!(value_at(stylesheet, "color", 0.4), gray(0));
+        assert_eq!(value_at(stylesheet, "color", 0.6), gray(128));
+        assert_eq!(value_at(stylesheet, "color", 5.0), gray(255));
+
+        // Important declarations win over animations.
+        let stylesheet = "@keyframes grow { to { width: 100px } } \
+                          div { width: 10px !important; animation: grow 1s linear }";
+        assert_eq!(value_at(stylesheet, "width", 0.5), px(10.0));
+
+        // Per-keyframe easing.
+        let stylesheet = "@keyframes a { \
+                            from { width: 0px; animation-timing-function: step-end } \
+                            to { width: 100px } \
+                          } \
+                          div { animation: a 1s linear both paused }";
+        assert_eq!(value_at(stylesheet, "width", 0.9), px(0.0));
+    }
+}
@@ -0,0 +1,397 @@
+//! The computed-value phase, which turns the cascaded values of an element
+//! into its `ComputedStyle`. Every supported property gets a value: CSS-wide
+//! keywords are resolved, a property which is not set inherits the value of
+//! the parent if it is inherited, and gets its initial value otherwise.
+//! Relative values are made absolute where they can be without layout.
+//!
+//! https://drafts.csswg.org/css-cascade-4/#defaulting
+
+use super::computed_style::{ComputedStyle, ComputedValue, StyleGroup};
+use super::CssPropertyMap;
+use crate::css::property::{self, CssWideKeyword, Property};
+use crate::css::variable::TokenStream;
+use crate::css::{LengthContext, Unit, Value};
+use crate::font::{bolder, lighter};
+use log::*;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Returns the computed style of an element from its `cascaded` values, in
+/// which `var()` is substituted, and its resolved `custom_properties`.
+/// `parent` is the computed style of the parent, if any, and
+/// `parent_context` is its length context, which relative values in
+/// `font-size` refer to.
+///
+/// A group of properties which the element declares none of is shared with
+/// the parent or the initial style, without computing it.
+///
+/// Returns the length context of the element too.
+pub(super) fn compute_values(
+    cascaded: &CssPropertyMap,
+    custom_properties: Arc<HashMap<String, TokenStream>>,
+    parent: Option<&ComputedStyle>,
+    parent_context: &LengthContext,
+) -> (ComputedStyle, LengthContext) {
+    // Other lengths refer to `font-size`, so it comes first.
+    let font_size = match parent {
+        Some(parent) if !cascaded.contains_key("font-size") => parent.text.font_size,
+        _ => Computer::new(cascaded, parent, parent_context, *parent_context).get("font-size"),
+    };
+    let context = LengthContext {
+        font_size,
+        // `rem` refers to the font size of the root element.
+        root_font_size: match parent {
+            Some(_) => parent_context.root_font_size,
+            None => font_size,
+        },
+        ..*parent_context
+    };
+    let computer = Computer::new(cascaded, parent, parent_context, context);
+    let initial = ComputedStyle::initial();
+    let style = ComputedStyle {
+        box_style: computer.group(parent.map(|p| &p.box_style), &initial.box_style),
+        position: computer.group(parent.map(|p| &p.position), &initial.position),
+        border: computer.group(parent.map(|p| &p.border), &initial.border),
+        background: computer.group(parent.map(|p| &p.background), &initial.background),
+        text: computer.group(parent.map(|p| &p.text), &initial.text),
+        custom_properties,
+    };
+    (style, context)
+}
+
+/// Computes the values of the properties of an element.
+#[derive(Clone, Copy)]
+pub(super) struct Computer<'a> {
+    cascaded: &'a CssPropertyMap,
+    parent: Option<&'a ComputedStyle>,
+    parent_context: &'a LengthContext,
+    /// The length context of the element.
+    context: LengthContext,
+}
+
+impl<'a> Computer<'a> {
+    pub(super) fn new(
+        cascaded: &'a CssPropertyMap,
+        parent: Option<&'a ComputedStyle>,
+        parent_context: &'a LengthContext,
+        context: LengthContext,
+    ) -> Computer<'a> {
+        Computer {
+            cascaded,
+            parent,
+            parent_context,
+            context,
+        }
+    }
+
+    /// Returns a group of properties, which is shared if the element
+    /// declares none of them.
+    fn group<G: StyleGroup>(&self, parent: Option<&Arc<G>>, initial: &Arc<G>) -> Arc<G> {
+        if G::PROPERTIES
+            .iter()
+            .any(|name| self.cascaded.contains_key(*name))
+        {
+            Arc::new(G::compute(self))
+        } else if G::INHERITED {
+            parent.unwrap_or(initial).clone()
+        } else {
+            initial.clone()
+        }
+    }
+
+    /// Returns the computed value of a property. A value which is not of
+    /// type `T` is invalid at computed-value time, where the property is
+    /// `unset`.
+    pub(super) fn get<T: ComputedValue>(&self, name: &str) -> T {
+        let value = self.value(name);
+        T::from_value(&value).unwrap_or_else(|| {
+            warn!("{} is invalid at computed-value time: {:?}", name, value);
+            let cascaded = CssPropertyMap::new();
+            let unset = Computer {
+                cascaded: &cascaded,
+                ..*self
+            };
+            T::from_value(&unset.value(name)).expect("invalid default value")
+        })
+    }
+
+    fn value(&self, name: &str) -> Value {
+        let (value, computed) = specified_value(name, self.cascaded, self.parent);
+        if computed {
+            return value.into_owned();
+        }
+        match name {
+            "font-size" => compute_font_size(&value, self.parent_context),
+            // `color: currentcolor` is the same as `inherit`.
+            "color" if is_current_color(&value) => {
+                default_value(property::find("color").unwrap(), true, self.parent).into_owned()
+            }
+            "font-weight" => compute_font_weight(&value, self.parent),
+            "line-height" => match *value {
+                Value::Percentage(n) => Value::Length(n * self.context.font_size / 100.0, Unit::Px),
+                _ => value.to_computed(&self.context),
+            },
+            name if name.starts_with("border-") && name.ends_with("-width") => {
+                let style = name.replace("-width", "-style");
+                let style = specified_value(&style, self.cascaded, self.parent).0;
+                compute_border_width(&value, &style, &self.context)
+            }
+            _ => to_computed(&value, &self.context),
+        }
+    }
+}
+
+/// Returns the value of a property after defaulting, and true if it is the
+/// value of the parent, which is computed already.
+///
+/// https://drafts.csswg.org/css-cascade-4/#defaulting-keywords
+fn specified_value<'v>(
+    name: &str,
+    cascaded: &'v CssPropertyMap,
+    parent: Option<&ComputedStyle>,
+) -> (Cow<'v, Value>, bool) {
+    let property = property::find(name).expect("unknown property");
+    let value = cascaded.get(name);
+    let inherit = match value.map(|value| (value, CssWideKeyword::from_value(value))) {
+        Some((value, None)) => return (Cow::Borrowed(value), false),
+        Some((_, Some(CssWideKeyword::Initial))) => false,
+        Some((_, Some(CssWideKeyword::Inherit))) => true,
+        // `revert` is left only if there is no value to roll back to, where
+        // it is the same as `unset`.
+        _ => property.inherited,
+    };
+    let value = default_value(property, inherit, parent);
+    (value, inherit && parent.is_some())
+}
+
+/// Returns the value of the parent if `inherit` is true and there is a
+/// parent, and the initial value otherwise.
+fn default_value(
+    property: &Property,
+    inherit: bool,
+    parent: Option<&ComputedStyle>,
+) -> Cow<'static, Value> {
+    match parent
+        .filter(|_| inherit)
+        .and_then(|parent| parent.value(property.name))
+    {
+        Some(value) => Cow::Owned(value),
+        None => Cow::Borrowed(property.initial_value()),
+    }
+}
+
+/// Lengths are converted to px in lists too.
+fn to_computed(value: &Value, context: &LengthContext) -> Value {
+    match value {
+        Value::List(values) => {
+            Value::List(values.iter().map(|v| to_computed(v, context)).collect())
+        }
+        Value::CommaList(values) => {
+            Value::CommaList(values.iter().map(|v| to_computed(v, context)).collect())
+        }
+        _ => value.to_computed(context),
+    }
+}
+
+fn is_current_color(value: &Value) -> bool {
+    matches!(value, Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("currentcolor"))
+}
+
+/// Returns the font size in px. Keywords, percentages and font-relative
+/// lengths refer to the font size of the parent.
+///
+/// https://drafts.csswg.org/css-fonts-4/#font-size-prop
+fn compute_font_size(value: &Value, parent_context: &LengthContext) -> Value {
+    const MEDIUM: f32 = LengthContext::DEFAULT_FONT_SIZE;
+    let parent = parent_context.font_size;
+    let px = match value {
+        Value::Keyword(keyword) => match keyword.to_ascii_lowercase().as_str() {
+            "xx-small" => MEDIUM * 3.0 / 5.0,
+            "x-small" => MEDIUM * 3.0 / 4.0,
+            "small" => MEDIUM * 8.0 / 9.0,
+            "medium" => MEDIUM,
+            "large" => MEDIUM * 6.0 / 5.0,
+            "x-large" => MEDIUM * 3.0 / 2.0,
+            "xx-large" => MEDIUM * 2.0,
+            "xxx-large" => MEDIUM * 3.0,
+            "larger" => parent * 1.2,
+            "smaller" => parent / 1.2,
+            _ => parent,
+        },
+        _ => value
+            .resolve_length(parent_context, parent)
+            .unwrap_or(parent),
+    };
+    Value::Length(px, Unit::Px)
+}
+
+/// Returns the weight as a number. `bolder` and `lighter` are relative to
+/// the weight of the parent.
+fn compute_font_weight(value: &Value, parent: Option<&ComputedStyle>) -> Value {
+    let parent_weight = || parent.map_or(400.0, |parent| parent.text.font_weight.0);
+    match value {
+        Value::Keyword(keyword) => Value::Number(match keyword.to_ascii_lowercase().as_str() {
+            "bold" => 700.0,
+            "bolder" => bolder(parent_weight()),
+            "lighter" => lighter(parent_weight()),
+            _ => 400.0,
+        }),
+        _ => value.clone(),
+    }
+}
+
+/// Returns the border width in px, which is 0 if the border has no style.
+///
+/// https://drafts.csswg.org/css-backgrounds-3/#border-width
+fn compute_border_width(value: &Value, style: &Value, context: &LengthContext) -> Value {
+    let has_style = match style {
+        Value::Keyword(style) => {
+            !style.eq_ignore_ascii_case("none") && !style.eq_ignore_ascii_case("hidden")
+        }
+        _ => true,
+    };
+    let px = match value {
+        _ if !has_style => 0.0,
+        Value::Keyword(keyword) => match keyword.to_ascii_lowercase().as_str() {
+            "thin" => 1.0,
+            "thick" => 5.0,
+            _ => 3.0,
+        },
+        _ => value.to_px(context, 0.0),
+    };
+    Value::Length(px, Unit::Px)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::css::parser::parse_declaration;
+    use crate::css::Color;
+    use crate::style::computed_style::{ComputedColor, Display};
+
+    type Computed = (ComputedStyle, LengthContext);
+
+    fn compute(declarations: &str, parent: Option<&Computed>) -> Computed {
+        let cascaded = declarations
+            .split(';')
+            .filter(|declaration| !declaration.trim().is_empty())
+            .map(|declaration| parse_declaration(declaration.trim()).unwrap())
+            .map(|declaration| (declaration.name, declaration.value))
+            .collect();
+        let context = match parent {
+            Some((_, context)) => *context,
+            None => LengthContext::new(800.0, 600.0),
+        };
+        compute_values(
+            &cascaded,
+            Default::default(),
+            parent.map(|(style, _)| style),
+            &context,
+        )
+    }
+
+    fn px(n: f32) -> Option<Value> {
+        Some(Value::Length(n, Unit::Px))
+    }
+
+    fn keyword(keyword: &str) -> Option<Value> {
+        Some(Value::Keyword(keyword.to_string()))
+    }
+
+    #[test]
+    fn defaulting_test() {
+        let parent = compute("color: red; width: 10px; font-size: 20px", None);
+        assert_eq!(parent.0.box_style.display, Display::Inline);
+        assert_eq!(parent.0.value("width"), px(10.0));
+
+        let child = compute("display: block", Some(&parent)).0;
+        // `color` is inherited, and `width` is not.
+        assert_eq!(child.value("color"), parent.0.value("color"));
+        assert_eq!(child.value("width"), keyword("auto"));
+
+        let child = compute("width: inherit; color: initial", Some(&parent)).0;
+        assert_eq!(child.value("width"), px(10.0));
+        assert_eq!(child.text.color, Color::rgb(0, 0, 0));
+
+        let child = compute("width: unset; color: unset", Some(&parent)).0;
+        assert_eq!(child.value("width"), keyword("auto"));
+        assert_eq!(child.value("color"), parent.0.value("color"));
+
+        // `inherit` at the root is `initial`.
+        let root = compute("color: inherit", None).0;
+        assert_eq!(root.text.color, Color::rgb(0, 0, 0));
+    }
+
+    #[test]
+    fn sharing_test() {
+        let parent = compute("color: red; width: 10px", None);
+        let initial = ComputedStyle::initial();
+        // The groups which are not declared are the initial ones.
+        assert!(!Arc::ptr_eq(&parent.0.position, &initial.position));
+        assert!(Arc::ptr_eq(&parent.0.box_style, &initial.box_style));
+
+        // Inherited groups are shared with the parent.
+        let child = compute("", Some(&parent)).0;
+        assert!(Arc::ptr_eq(&child.text, &parent.0.text));
+        assert!(Arc::ptr_eq(&child.position, &initial.position));
+        let child = compute("font-weight: bold", Some(&parent)).0;
+        assert!(!Arc::ptr_eq(&child.text, &parent.0.text));
+        assert_eq!(child.text.color, Color::rgb(255, 0, 0));
+    }
+
+    #[test]
+    fn relative_values_test() {
+        let root = compute("font-size: 20px; margin-top: 1em", None);
+        assert_eq!(root.0.value("margin-top"), px(20.0));
+
+        // Relative values in `font-size` refer to the parent's font size,
+        // and the ones in other properties refer to the element's.
+        let parent = compute("font-size: 2em; padding-left: 1em", Some(&root));
+        assert_eq!(parent.0.text.font_size, 40.0);
+        assert_eq!(parent.0.value("padding-left"), px(40.0));
+        let child = compute(
+            "font-size: 50%; width: 2rem; line-height: 150%",
+            Some(&parent),
+        )
+        .0;
+        assert_eq!(child.value("font-size"), px(20.0));
+        assert_eq!(child.value("width"), px(40.0));
+        assert_eq!(child.value("line-height"), px(30.0));
+        // The computed font size is inherited, not `2em`.
+        assert_eq!(compute("", Some(&parent)).0.text.font_size, 40.0);
+        let font_size = |declarations| compute(declarations, Some(&root)).0.text.font_size;
+        assert_eq!(font_size("font-size: larger"), 24.0);
+        assert_eq!(font_size("font-size: x-large"), 24.0);
+
+        // Percentages of widths are resolved at layout time.
+        let child = compute("width: 50%", Some(&parent)).0;
+        assert_eq!(child.value("width"), Some(Value::Percentage(50.0)));
+
+        let child = compute("font-weight: bolder", Some(&root));
+        assert_eq!(child.0.value("font-weight"), Some(Value::Number(700.0)));
+        let grandchild = compute("font-weight: lighter", Some(&child)).0;
+        assert_eq!(grandchild.value("font-weight"), Some(Value::Number(400.0)));
+    }
+
+    #[test]
+    fn border_test() {
+        let style = compute(
+            "color: red; border-top-style: solid; border-left-width: 4px; \
+             border-bottom-style: solid; border-bottom-width: thin",
+            None,
+        )
+        .0;
+        assert_eq!(style.border.border_top_width, 3.0);
+        assert_eq!(style.border.border_bottom_width, 1.0);
+        // A border without a style has no width.
+        assert_eq!(style.border.border_left_width, 0.0);
+        // `currentcolor` is kept, and its used value is `color`.
+        assert_eq!(style.value("border-top-color"), keyword("currentcolor"));
+        assert_eq!(style.border.border_top_color, ComputedColor::CurrentColor);
+        assert_eq!(
+            style.color(style.border.border_top_color),
+            Color::rgb(255, 0, 0)
+        );
+    }
+}
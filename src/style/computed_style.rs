@@ -0,0 +1,625 @@
+//! `ComputedStyle`, the computed values of an element in typed fields.
+//!
+//! The properties are grouped into structs, which elements share through
+//! `Arc`s: a group which an element declares no property of is the one of
+//! its parent if the properties are inherited, and the initial one if they
+//! are not. A group is copied only when it is modified, e.g. by a
+//! transition.
+//!
+//! Properties which nothing reads yet keep their `Value`s.
+
+use super::computed::Computer;
+use crate::css::calc::CalcNode;
+use crate::css::container::{container_names, ContainerType};
+use crate::css::font_face::FontStyle;
+use crate::css::variable::TokenStream;
+use crate::css::{Color, LengthContext, Unit, Value};
+use crate::font::{font_families, FontFamily};
+use lazy_static::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// A type of computed values, which converts from and to `Value`s.
+pub trait ComputedValue: Sized {
+    /// Returns `None` if `value` is not a computed value of this type.
+    fn from_value(value: &Value) -> Option<Self>;
+    fn to_value(&self) -> Value;
+}
+
+impl ComputedValue for Value {
+    fn from_value(value: &Value) -> Option<Value> {
+        Some(value.clone())
+    }
+
+    fn to_value(&self) -> Value {
+        self.clone()
+    }
+}
+
+/// A length in px.
+impl ComputedValue for f32 {
+    fn from_value(value: &Value) -> Option<f32> {
+        match *value {
+            Value::Length(n, Unit::Px) => Some(n),
+            // Unitless zero is a valid length.
+            Value::Number(0.0) => Some(0.0),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Length(*self, Unit::Px)
+    }
+}
+
+impl ComputedValue for Color {
+    fn from_value(value: &Value) -> Option<Color> {
+        match value {
+            Value::ColorValue(color) => Some(*color),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::ColorValue(*self)
+    }
+}
+
+/// `display`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Display {
+    Inline,
+    Block,
+    /// A block with a marker.
+    ListItem,
+    None,
+}
+
+impl ComputedValue for Display {
+    fn from_value(value: &Value) -> Option<Display> {
+        match value {
+            Value::Keyword(keyword) => match keyword.to_ascii_lowercase().as_str() {
+                "inline" => Some(Display::Inline),
+                "block" => Some(Display::Block),
+                "list-item" => Some(Display::ListItem),
+                "none" => Some(Display::None),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Keyword(
+            match self {
+                Display::Inline => "inline",
+                Display::Block => "block",
+                Display::ListItem => "list-item",
+                Display::None => "none",
+            }
+            .to_string(),
+        )
+    }
+}
+
+/// A computed `<length-percentage>`. Percentages are resolved at layout
+/// time, against a size which depends on the property.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LengthPercentage {
+    /// In px.
+    Length(f32),
+    Percentage(f32),
+    /// A `calc()` which mixes lengths in px with percentages.
+    Calc(Box<CalcNode>),
+}
+
+impl LengthPercentage {
+    pub fn resolve(&self, percentage_basis: f32) -> f32 {
+        match self {
+            LengthPercentage::Length(n) => *n,
+            LengthPercentage::Percentage(n) => n * percentage_basis / 100.0,
+            // No length in a computed `calc()` is relative, so any context
+            // will do.
+            LengthPercentage::Calc(node) => {
+                node.resolve(&LengthContext::new(0.0, 0.0), percentage_basis)
+            }
+        }
+    }
+}
+
+impl ComputedValue for LengthPercentage {
+    fn from_value(value: &Value) -> Option<LengthPercentage> {
+        match value {
+            Value::Percentage(n) => Some(LengthPercentage::Percentage(*n)),
+            Value::Calc(node) => Some(LengthPercentage::Calc(node.clone())),
+            _ => f32::from_value(value).map(LengthPercentage::Length),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match self {
+            LengthPercentage::Length(n) => n.to_value(),
+            LengthPercentage::Percentage(n) => Value::Percentage(*n),
+            LengthPercentage::Calc(node) => Value::Calc(node.clone()),
+        }
+    }
+}
+
+/// `auto | <length-percentage>`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LengthPercentageAuto {
+    Auto,
+    LengthPercentage(LengthPercentage),
+}
+
+impl LengthPercentageAuto {
+    /// Returns `None` for `auto`.
+    pub fn resolve(&self, percentage_basis: f32) -> Option<f32> {
+        match self {
+            LengthPercentageAuto::Auto => None,
+            LengthPercentageAuto::LengthPercentage(value) => Some(value.resolve(percentage_basis)),
+        }
+    }
+}
+
+impl ComputedValue for LengthPercentageAuto {
+    fn from_value(value: &Value) -> Option<LengthPercentageAuto> {
+        match value {
+            Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("auto") => {
+                Some(LengthPercentageAuto::Auto)
+            }
+            _ => LengthPercentage::from_value(value).map(LengthPercentageAuto::LengthPercentage),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match self {
+            LengthPercentageAuto::Auto => Value::keyword_auto().clone(),
+            LengthPercentageAuto::LengthPercentage(value) => value.to_value(),
+        }
+    }
+}
+
+/// The computed value of a color property other than `color`, where
+/// `currentcolor` is kept as is, so that it follows `color` at used-value
+/// time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ComputedColor {
+    CurrentColor,
+    Color(Color),
+}
+
+impl ComputedColor {
+    /// Returns the used color. `current_color` is the value of `color`.
+    pub fn resolve(self, current_color: Color) -> Color {
+        match self {
+            ComputedColor::CurrentColor => current_color,
+            ComputedColor::Color(color) => color,
+        }
+    }
+}
+
+impl ComputedValue for ComputedColor {
+    fn from_value(value: &Value) -> Option<ComputedColor> {
+        match value {
+            Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("currentcolor") => {
+                Some(ComputedColor::CurrentColor)
+            }
+            _ => Color::from_value(value).map(ComputedColor::Color),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match self {
+            ComputedColor::CurrentColor => Value::Keyword("currentcolor".to_string()),
+            ComputedColor::Color(color) => color.to_value(),
+        }
+    }
+}
+
+/// `font-weight` as a number.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FontWeight(pub f32);
+
+impl ComputedValue for FontWeight {
+    fn from_value(value: &Value) -> Option<FontWeight> {
+        match *value {
+            Value::Number(weight) => Some(FontWeight(weight)),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Number(self.0)
+    }
+}
+
+/// `font-stretch` in percent.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FontStretch(pub f32);
+
+const STRETCH_KEYWORDS: &[(&str, f32)] = &[
+    ("ultra-condensed", 50.0),
+    ("extra-condensed", 62.5),
+    ("condensed", 75.0),
+    ("semi-condensed", 87.5),
+    ("normal", 100.0),
+    ("semi-expanded", 112.5),
+    ("expanded", 125.0),
+    ("extra-expanded", 150.0),
+    ("ultra-expanded", 200.0),
+];
+
+impl ComputedValue for FontStretch {
+    fn from_value(value: &Value) -> Option<FontStretch> {
+        match value {
+            Value::Percentage(stretch) if *stretch >= 0.0 => Some(FontStretch(*stretch)),
+            Value::Keyword(keyword) => STRETCH_KEYWORDS
+                .iter()
+                .find(|(name, _)| keyword.eq_ignore_ascii_case(name))
+                .map(|&(_, stretch)| FontStretch(stretch)),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Percentage(self.0)
+    }
+}
+
+impl ComputedValue for FontStyle {
+    fn from_value(value: &Value) -> Option<FontStyle> {
+        match value {
+            Value::Keyword(keyword) => FontStyle::from_name(keyword),
+            // e.g. `oblique 10deg`
+            Value::List(values) => values.first().and_then(FontStyle::from_value),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Keyword(self.name().to_string())
+    }
+}
+
+impl ComputedValue for Vec<FontFamily> {
+    fn from_value(value: &Value) -> Option<Vec<FontFamily>> {
+        Some(font_families(value))
+    }
+
+    fn to_value(&self) -> Value {
+        let mut values: Vec<_> = self
+            .iter()
+            .map(|family| match family {
+                FontFamily::Named(name) => Value::String(name.clone()),
+                FontFamily::Generic(name) => Value::Keyword(name.clone()),
+            })
+            .collect();
+        if values.len() == 1 {
+            values.pop().unwrap()
+        } else {
+            Value::CommaList(values)
+        }
+    }
+}
+
+impl ComputedValue for ContainerType {
+    fn from_value(value: &Value) -> Option<ContainerType> {
+        ContainerType::from_value(value)
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Keyword(
+            match self {
+                ContainerType::Normal => "normal",
+                ContainerType::Size => "size",
+                ContainerType::InlineSize => "inline-size",
+            }
+            .to_string(),
+        )
+    }
+}
+
+/// The names in `container-name`.
+impl ComputedValue for Vec<String> {
+    fn from_value(value: &Value) -> Option<Vec<String>> {
+        container_names(value)
+    }
+
+    fn to_value(&self) -> Value {
+        let mut values: Vec<_> = self.iter().cloned().map(Value::Keyword).collect();
+        match values.len() {
+            0 => Value::Keyword("none".to_string()),
+            1 => values.pop().unwrap(),
+            _ => Value::List(values),
+        }
+    }
+}
+
+/// A group of properties in `ComputedStyle`.
+pub(super) trait StyleGroup: Clone {
+    /// Whether the properties are inherited.
+    const INHERITED: bool;
+    const PROPERTIES: &'static [&'static str];
+
+    fn compute(computer: &Computer<'_>) -> Self;
+    fn value(&self, name: &str) -> Option<Value>;
+    /// Returns `None` if the property is not in the group, and false if
+    /// `value` is not a computed value of it. The group is copied only if
+    /// it is shared and `value` is valid.
+    fn set(group: &mut Arc<Self>, name: &str, value: &Value) -> Option<bool>;
+}
+
+/// Declares a group of properties, with a field of the computed value of
+/// each of them.
+macro_rules! style_group {
+    (
+        $(#[$attr:meta])*
+        pub struct $name:ident, inherited: $inherited:expr, {
+            $($(#[$field_attr:meta])* $field:ident: $ty:ty = $property:literal,)*
+        }
+    ) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct $name {
+            $($(#[$field_attr])* pub $field: $ty,)*
+        }
+
+        impl StyleGroup for $name {
+            const INHERITED: bool = $inherited;
+            const PROPERTIES: &'static [&'static str] = &[$($property),*];
+
+            fn compute(computer: &Computer<'_>) -> $name {
+                $name {
+                    $($field: computer.get($property),)*
+                }
+            }
+
+            fn value(&self, name: &str) -> Option<Value> {
+                match name {
+                    $($property => Some(self.$field.to_value()),)*
+                    _ => None,
+                }
+            }
+
+            fn set(group: &mut Arc<$name>, name: &str, value: &Value) -> Option<bool> {
+                match name {
+                    $($property => Some(match ComputedValue::from_value(value) {
+                        Some(value) => {
+                            Arc::make_mut(group).$field = value;
+                            true
+                        }
+                        None => false,
+                    }),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+style_group! {
+    /// The box model, generated content and animations.
+    pub struct BoxStyle, inherited: false, {
+        display: Display = "display",
+        margin_top: LengthPercentageAuto = "margin-top",
+        margin_right: LengthPercentageAuto = "margin-right",
+        margin_bottom: LengthPercentageAuto = "margin-bottom",
+        margin_left: LengthPercentageAuto = "margin-left",
+        padding_top: LengthPercentage = "padding-top",
+        padding_right: LengthPercentage = "padding-right",
+        padding_bottom: LengthPercentage = "padding-bottom",
+        padding_left: LengthPercentage = "padding-left",
+        content: Value = "content",
+        counter_reset: Value = "counter-reset",
+        counter_increment: Value = "counter-increment",
+        counter_set: Value = "counter-set",
+        container_type: ContainerType = "container-type",
+        container_name: Vec<String> = "container-name",
+        animation_name: Value = "animation-name",
+        animation_duration: Value = "animation-duration",
+        animation_timing_function: Value = "animation-timing-function",
+        animation_delay: Value = "animation-delay",
+        animation_iteration_count: Value = "animation-iteration-count",
+        animation_direction: Value = "animation-direction",
+        animation_fill_mode: Value = "animation-fill-mode",
+        animation_play_state: Value = "animation-play-state",
+        transition_property: Value = "transition-property",
+        transition_duration: Value = "transition-duration",
+        transition_timing_function: Value = "transition-timing-function",
+        transition_delay: Value = "transition-delay",
+    }
+}
+
+style_group! {
+    /// The size of the box.
+    pub struct PositionStyle, inherited: false, {
+        width: LengthPercentageAuto = "width",
+        height: LengthPercentageAuto = "height",
+    }
+}
+
+style_group! {
+    /// Widths are in px, and 0 if the border has no style.
+    pub struct BorderStyle, inherited: false, {
+        border_top_width: f32 = "border-top-width",
+        border_right_width: f32 = "border-right-width",
+        border_bottom_width: f32 = "border-bottom-width",
+        border_left_width: f32 = "border-left-width",
+        border_top_style: Value = "border-top-style",
+        border_right_style: Value = "border-right-style",
+        border_bottom_style: Value = "border-bottom-style",
+        border_left_style: Value = "border-left-style",
+        border_top_color: ComputedColor = "border-top-color",
+        border_right_color: ComputedColor = "border-right-color",
+        border_bottom_color: ComputedColor = "border-bottom-color",
+        border_left_color: ComputedColor = "border-left-color",
+    }
+}
+
+style_group! {
+    pub struct BackgroundStyle, inherited: false, {
+        background_color: ComputedColor = "background-color",
+        background_image: Value = "background-image",
+        background_repeat: Value = "background-repeat",
+        background_attachment: Value = "background-attachment",
+        background_position: Value = "background-position",
+        background_size: Value = "background-size",
+    }
+}
+
+style_group! {
+    /// Fonts and the other inherited properties.
+    pub struct TextStyle, inherited: true, {
+        color: Color = "color",
+        font_style: FontStyle = "font-style",
+        font_variant: Value = "font-variant",
+        font_weight: FontWeight = "font-weight",
+        font_stretch: FontStretch = "font-stretch",
+        /// In px.
+        font_size: f32 = "font-size",
+        /// `normal`, a number, or a length in px.
+        line_height: Value = "line-height",
+        font_family: Vec<FontFamily> = "font-family",
+        list_style_type: Value = "list-style-type",
+        list_style_position: Value = "list-style-position",
+    }
+}
+
+/// The computed values of an element.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComputedStyle {
+    /// `box` is a keyword.
+    pub box_style: Arc<BoxStyle>,
+    pub position: Arc<PositionStyle>,
+    pub border: Arc<BorderStyle>,
+    pub background: Arc<BackgroundStyle>,
+    pub text: Arc<TextStyle>,
+    /// Custom properties, in which `var()` is resolved.
+    pub custom_properties: Arc<HashMap<String, TokenStream>>,
+}
+
+impl ComputedStyle {
+    /// Returns the style of an element which declares no property and has
+    /// no parent. Its groups are shared by every style which uses them.
+    pub fn initial() -> ComputedStyle {
+        lazy_static! {
+            static ref INITIAL: ComputedStyle = {
+                let cascaded = HashMap::new();
+                let context = LengthContext::new(0.0, 0.0);
+                let computer = Computer::new(&cascaded, None, &context, context);
+                ComputedStyle {
+                    box_style: Arc::new(BoxStyle::compute(&computer)),
+                    position: Arc::new(PositionStyle::compute(&computer)),
+                    border: Arc::new(BorderStyle::compute(&computer)),
+                    background: Arc::new(BackgroundStyle::compute(&computer)),
+                    text: Arc::new(TextStyle::compute(&computer)),
+                    custom_properties: Default::default(),
+                }
+            };
+        }
+        INITIAL.clone()
+    }
+
+    /// Returns the style of a child which declares no property, e.g. a text
+    /// node.
+    pub fn inherit_from(parent: &ComputedStyle) -> ComputedStyle {
+        ComputedStyle {
+            text: parent.text.clone(),
+            custom_properties: parent.custom_properties.clone(),
+            ..ComputedStyle::initial()
+        }
+    }
+
+    /// Returns the value of a property, or `None` if it is unknown or an
+    /// undefined custom property.
+    pub fn value(&self, name: &str) -> Option<Value> {
+        if name.starts_with("--") {
+            return self.custom_properties.get(name).cloned().map(Value::Tokens);
+        }
+        self.box_style
+            .value(name)
+            .or_else(|| self.position.value(name))
+            .or_else(|| self.border.value(name))
+            .or_else(|| self.background.value(name))
+            .or_else(|| self.text.value(name))
+    }
+
+    /// Sets the value of a property. Only the group of the property is
+    /// copied if it is shared. Returns false if `value` is not a computed
+    /// value of the property.
+    pub fn set(&mut self, name: &str, value: &Value) -> bool {
+        BoxStyle::set(&mut self.box_style, name, value)
+            .or_else(|| PositionStyle::set(&mut self.position, name, value))
+            .or_else(|| BorderStyle::set(&mut self.border, name, value))
+            .or_else(|| BackgroundStyle::set(&mut self.background, name, value))
+            .or_else(|| TextStyle::set(&mut self.text, name, value))
+            .unwrap_or(false)
+    }
+
+    /// Returns the used value of a color property other than `color`.
+    pub fn color(&self, color: ComputedColor) -> Color {
+        color.resolve(self.text.color)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::css::property;
+
+    #[test]
+    fn groups_test() {
+        // Every property is in one group, whose properties are all
+        // inherited, or all not.
+        fn check<G: StyleGroup>(names: &mut Vec<&str>) {
+            for name in G::PROPERTIES {
+                assert_eq!(property::is_inherited(name), G::INHERITED, "{}", name);
+            }
+            names.extend(G::PROPERTIES);
+        }
+        let mut names = vec![];
+        check::<BoxStyle>(&mut names);
+        check::<PositionStyle>(&mut names);
+        check::<BorderStyle>(&mut names);
+        check::<BackgroundStyle>(&mut names);
+        check::<TextStyle>(&mut names);
+        let mut properties: Vec<_> = property::properties().map(|p| p.name).collect();
+        names.sort_unstable();
+        properties.sort_unstable();
+        assert_eq!(names, properties);
+    }
+
+    #[test]
+    fn value_test() {
+        let mut style = ComputedStyle::initial();
+        assert_eq!(style.box_style.display, Display::Inline);
+        assert_eq!(style.text.font_size, 16.0);
+        assert_eq!(
+            style.value("margin-top"),
+            Some(Value::Length(0.0, Unit::Px))
+        );
+        assert_eq!(style.value("width"), Some(Value::keyword_auto().clone()));
+        assert_eq!(style.value("unknown"), None);
+
+        // A modified group is copied, and the others are still shared.
+        let initial = ComputedStyle::initial();
+        assert!(style.set("width", &Value::Percentage(50.0)));
+        assert!(!style.set("display", &Value::Keyword("flex".to_string())));
+        assert_eq!(
+            style.position.width,
+            LengthPercentageAuto::LengthPercentage(LengthPercentage::Percentage(50.0))
+        );
+        assert!(!Arc::ptr_eq(&style.position, &initial.position));
+        assert!(Arc::ptr_eq(&style.box_style, &initial.box_style));
+        assert_eq!(initial.position.width, LengthPercentageAuto::Auto);
+
+        // `currentcolor` follows `color`.
+        assert!(style.set("color", &Value::ColorValue(Color::rgb(255, 0, 0))));
+        assert_eq!(style.border.border_top_color, ComputedColor::CurrentColor);
+        assert_eq!(
+            style.color(style.border.border_top_color),
+            Color::rgb(255, 0, 0)
+        );
+    }
+}
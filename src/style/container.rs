@@ -0,0 +1,132 @@
+//! Query containers, which `@container` rules are evaluated against during a
+//! style pass, and the sizes which layout gives them.
+//!
+//! https://drafts.csswg.org/css-contain-3/#container-rule
+
+use super::ComputedStyle;
+use crate::css::container::{ContainerRule, ContainerSize, ContainerType};
+use crate::dom::Node;
+use std::collections::HashMap;
+
+/// The sizes of the query containers which have been laid out. A container
+/// is identified by the address of its node, so the sizes are only valid
+/// for the tree which they were collected from.
+#[derive(Debug, Clone, Default)]
+pub struct ContainerSizes(HashMap<usize, ContainerSize>);
+
+impl ContainerSizes {
+    pub fn new() -> ContainerSizes {
+        Default::default()
+    }
+
+    pub fn get(&self, node: &Node) -> Option<ContainerSize> {
+        self.0.get(&address(node)).cloned()
+    }
+
+    /// Returns false if the size of `node` is already known, which is kept.
+    pub fn insert(&mut self, node: &Node, size: ContainerSize) -> bool {
+        let mut inserted = false;
+        self.0.entry(address(node)).or_insert_with(|| {
+            inserted = true;
+            size
+        });
+        inserted
+    }
+}
+
+fn address(node: &Node) -> usize {
+    node as *const Node as usize
+}
+
+/// The query containers in scope during a style pass.
+#[derive(Clone)]
+pub(super) struct QueryContainers<'s> {
+    /// The ancestor containers of the element being styled, from the
+    /// outermost.
+    containers: Vec<QueryContainer>,
+    /// The sizes of the containers which have been laid out. It is `None`
+    /// if the tree is styled without layout, where no container has a size.
+    sizes: Option<&'s ContainerSizes>,
+    /// Whether the descendants of a container are left unstyled, because
+    /// its size is not known yet.
+    pub(super) incomplete: bool,
+}
+
+#[derive(Clone)]
+struct QueryContainer {
+    names: Vec<String>,
+    container_type: ContainerType,
+    /// `None` until the container is laid out.
+    size: Option<ContainerSize>,
+    /// The depth of the element in the tree.
+    depth: usize,
+}
+
+impl<'s> QueryContainers<'s> {
+    pub(super) fn new(sizes: Option<&'s ContainerSizes>) -> QueryContainers<'s> {
+        QueryContainers {
+            containers: vec![],
+            sizes,
+            incomplete: false,
+        }
+    }
+
+    /// Makes an element at `depth` a query container for its descendants if
+    /// it has a `container-type`. Only block-level boxes can be containers.
+    /// Returns false if its descendants can't be styled until its size is
+    /// known.
+    pub(super) fn enter(
+        &mut self,
+        node: &Node,
+        style: &ComputedStyle,
+        is_block: bool,
+        depth: usize,
+    ) -> bool {
+        let container_type = style.box_style.container_type;
+        if container_type == ContainerType::Normal || !is_block {
+            return true;
+        }
+        let size = self.sizes.and_then(|sizes| sizes.get(node));
+        self.containers.push(QueryContainer {
+            names: style.box_style.container_name.clone(),
+            container_type,
+            size,
+            depth,
+        });
+        if self.sizes.is_some() && size.is_none() {
+            self.incomplete = true;
+            return false;
+        }
+        true
+    }
+
+    /// Ends the scope of the container which the element at `depth` is.
+    pub(super) fn leave(&mut self, depth: usize) {
+        self.containers.retain(|container| container.depth < depth);
+    }
+
+    /// Returns true if the conditions of all `rules` are true. Each of them
+    /// queries the nearest container which has its name and can answer it.
+    /// A condition is never true if there is no such container, or its size
+    /// is unknown.
+    pub(super) fn matches(&self, rules: &[&ContainerRule]) -> bool {
+        rules.iter().all(|rule| {
+            self.containers
+                .iter()
+                .rev()
+                .find(|container| container.can_answer(rule))
+                .and_then(|container| container.size)
+                .and_then(|size| size.evaluate(&rule.condition))
+                == Some(true)
+        })
+    }
+}
+
+impl QueryContainer {
+    fn can_answer(&self, rule: &ContainerRule) -> bool {
+        rule.name
+            .as_ref()
+            .is_none_or(|name| self.names.contains(name))
+            && (self.container_type == ContainerType::Size || !rule.queries_block_axis())
+    }
+}
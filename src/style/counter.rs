@@ -0,0 +1,230 @@
+//! CSS counters, which are updated in tree order after a style pass, and
+//! the generated text of `::marker`.
+//!
+//! https://drafts.csswg.org/css-lists-3/#auto-numbering
+
+use super::ComputedStyle;
+use crate::css::counter::{counter_changes, CounterFunction, CounterStyle};
+use crate::css::Value;
+
+/// The counters in scope at an element.
+#[derive(Debug, Default)]
+pub(super) struct Counters {
+    /// From the outermost to the innermost.
+    instances: Vec<Counter>,
+}
+
+#[derive(Debug)]
+struct Counter {
+    name: String,
+    value: i32,
+    /// The depth of the element which instantiated the counter. Its scope is
+    /// the element, its following siblings and their descendants.
+    depth: usize,
+}
+
+impl Counters {
+    pub(super) fn new() -> Counters {
+        Default::default()
+    }
+
+    /// Applies `counter-reset`, `counter-increment` and `counter-set` of an
+    /// element at `depth`, in this order. A list item increments `list-item`
+    /// unless `counter-increment` mentions it.
+    pub(super) fn update(&mut self, style: &ComputedStyle, depth: usize, list_item: bool) {
+        let box_style = &style.box_style;
+        for (name, value) in counter_changes(&box_style.counter_reset, 0) {
+            self.instantiate(name, value, depth);
+        }
+        let mut increments = counter_changes(&box_style.counter_increment, 1);
+        if list_item && increments.iter().all(|(name, _)| name != "list-item") {
+            increments.push(("list-item".to_string(), 1));
+        }
+        for (name, value) in increments {
+            let counter = self.get_or_instantiate(name, depth);
+            counter.value = counter.value.wrapping_add(value);
+        }
+        for (name, value) in counter_changes(&box_style.counter_set, 0) {
+            self.get_or_instantiate(name, depth).value = value;
+        }
+    }
+
+    /// Ends the scope of the counters which the children of an element at
+    /// `depth` instantiated.
+    pub(super) fn leave(&mut self, depth: usize) {
+        self.instances.retain(|counter| counter.depth <= depth);
+    }
+
+    /// A counter replaces the one which a preceding sibling instantiated.
+    fn instantiate(&mut self, name: String, value: i32, depth: usize) {
+        self.instances
+            .retain(|counter| counter.depth != depth || counter.name != name);
+        self.instances.push(Counter { name, value, depth });
+    }
+
+    fn get_or_instantiate(&mut self, name: String, depth: usize) -> &mut Counter {
+        match self
+            .instances
+            .iter()
+            .rposition(|counter| counter.name == name)
+        {
+            Some(index) => &mut self.instances[index],
+            None => {
+                self.instantiate(name, 0, depth);
+                self.instances.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Returns the values of the counters `name`, from the outermost. A
+    /// counter which is not in scope has the value 0.
+    fn values(&self, name: &str) -> Vec<i32> {
+        let values: Vec<_> = self
+            .instances
+            .iter()
+            .filter(|counter| counter.name == name)
+            .map(|counter| counter.value)
+            .collect();
+        if values.is_empty() {
+            vec![0]
+        } else {
+            values
+        }
+    }
+
+    fn format(&self, function: &CounterFunction) -> String {
+        let values = self.values(&function.name);
+        match &function.separator {
+            None => function.style.format(*values.last().unwrap()),
+            Some(separator) => values
+                .iter()
+                .map(|&value| function.style.format(value))
+                .collect::<Vec<_>>()
+                .join(separator),
+        }
+    }
+
+    /// Returns the text of a `content` value, or `None` if it is `normal` or
+    /// `none`. Unsupported items, such as `open-quote`, are ignored.
+    pub(super) fn content(&self, value: &Value) -> Option<String> {
+        let items = match value {
+            Value::Keyword(keyword)
+                if keyword.eq_ignore_ascii_case("normal")
+                    || keyword.eq_ignore_ascii_case("none") =>
+            {
+                return None
+            }
+            Value::List(items) => items.as_slice(),
+            _ => std::slice::from_ref(value),
+        };
+        let mut text = String::new();
+        for item in items {
+            match item {
+                Value::String(s) => text.push_str(s),
+                Value::Counter(function) => text.push_str(&self.format(function)),
+                _ => {}
+            }
+        }
+        Some(text)
+    }
+
+    /// Returns the text of the marker of a list item, or `None` if it has no
+    /// marker. `content` of `::marker` wins over `list-style-type`.
+    ///
+    /// https://drafts.csswg.org/css-lists-3/#content-property
+    pub(super) fn marker_text(
+        &self,
+        style: &ComputedStyle,
+        marker_style: &ComputedStyle,
+    ) -> Option<String> {
+        match &marker_style.box_style.content {
+            Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("normal") => {}
+            content => return self.content(content),
+        }
+        let style = match &style.text.list_style_type {
+            Value::String(s) => return Some(s.clone()),
+            Value::Keyword(name) => CounterStyle::from_name(name).unwrap_or(CounterStyle::Disc),
+            _ => CounterStyle::Disc,
+        };
+        if style == CounterStyle::None {
+            return None;
+        }
+        Some(style.marker(*self.values("list-item").last().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::css::parser::parse_stylesheet;
+    use crate::dom::parser::parse_html;
+    use crate::style::{style_tree, StyledNode};
+
+    fn markers(html: &str, stylesheet: &str) -> Vec<String> {
+        fn collect(node: &StyledNode<'_>, markers: &mut Vec<String>) {
+            markers.extend(node.marker.as_ref().map(|marker| marker.text.clone()));
+            for child in &node.children {
+                collect(child, markers);
+            }
+        }
+        let node = parse_html(html).unwrap();
+        let stylesheet = parse_stylesheet(stylesheet).unwrap();
+        let mut markers = vec![];
+        collect(
+            &style_tree(&node, &[&stylesheet], &Default::default()),
+            &mut markers,
+        );
+        markers
+    }
+
+    #[test]
+    fn list_item_test() {
+        let stylesheet = "ol { counter-reset: list-item; list-style-type: lower-roman } \
+                          li { display: list-item } \
+                          .same { counter-increment: list-item 0 } \
+                          .hidden { display: none } \
+                          .set { counter-set: list-item 9 } \
+                          ol ol li::marker { content: counters(list-item, \".\") \") \" }";
+        assert_eq!(
+            markers(
+                "(ol (li) (li class=same) (li (ol (li) (li))) (li class=hidden) (li) (li class=set))",
+                stylesheet
+            ),
+            vec!["i. ", "i. ", "ii. ", "2.1) ", "2.2) ", "iii. ", "ix. "]
+        );
+
+        // `list-style-type` is inherited, and its initial value is `disc`.
+        let stylesheet = "li { display: list-item } .a { list-style-type: \"-\" } \
+                          .b { list-style-type: none }";
+        assert_eq!(
+            markers(
+                "(div (li) (div class=a (li)) (div class=b (li)))",
+                stylesheet
+            ),
+            vec!["• ", "-"]
+        );
+    }
+
+    #[test]
+    fn counter_scope_test() {
+        // A counter is in scope in the following siblings of the element
+        // which instantiates it, and their descendants.
+        let stylesheet = "div { counter-reset: c 5 } \
+                          h { display: list-item; counter-increment: c 2 } \
+                          h::marker { content: \"#\" counter(c, upper-roman) \" \" counters(c, \"/\") }";
+        assert_eq!(
+            markers(
+                "(body (div) (h) (p (h)) (section (div) (h)) (h))",
+                stylesheet
+            ),
+            vec!["#VII 7", "#IX 9", "#VII 9/7", "#XI 11"]
+        );
+        // The counter is not in scope before the element.
+        assert_eq!(
+            markers(
+                "(body (h) (div))",
+                "h { display: list-item } h::marker { content: counter(c) }"
+            ),
+            vec!["0"]
+        );
+    }
+}
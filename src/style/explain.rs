@@ -0,0 +1,378 @@
+//! Explains how the cascade styles an element, like the styles pane of a
+//! browser's developer tools: which rules match it, and which of their
+//! declarations apply.
+
+use super::container::QueryContainers;
+use super::{cascade, cascaded_declarations, match_selectors, matches, matching_rules};
+use super::{layer_precedence, origin_precedence, CascadeRule};
+use crate::css;
+use crate::css::media;
+use crate::css::property;
+use crate::dom;
+use crate::dom::Node;
+use crate::prelude::*;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// How the cascade styles an element.
+#[derive(Debug)]
+pub struct Explanation<'a> {
+    pub element: &'a dom::ElementData,
+    /// The ancestor elements of `element`, from the root.
+    pub ancestors: Vec<&'a dom::ElementData>,
+    /// The rules which match `element`, from the highest to the lowest
+    /// precedence.
+    pub rules: Vec<RuleMatch<'a>>,
+    /// The values which `element` inherits, from the nearest ancestor.
+    pub inherited: Vec<InheritedValue<'a>>,
+}
+
+#[derive(Debug)]
+pub struct RuleMatch<'a> {
+    pub rule: &'a css::Rule,
+    /// The most specific selector of `rule` which matches.
+    pub selector: &'a css::Selector,
+    pub specifity: css::Specifity,
+    pub origin: css::Origin,
+    /// The declarations of `rule`, in the order of appearance.
+    pub declarations: Vec<(&'a css::Declaration, Status)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Applied,
+    /// Overridden by a declaration of the rule at this index in
+    /// `Explanation::rules`. It may be the same rule.
+    Overridden(usize),
+}
+
+#[derive(Debug)]
+pub struct InheritedValue<'a> {
+    pub declaration: &'a css::Declaration,
+    /// The index of the ancestor in `Explanation::ancestors`.
+    pub ancestor: usize,
+    /// The rule which sets the value on the ancestor.
+    pub rule: &'a css::Rule,
+}
+
+/// Explains the styles of the elements which match any of `selectors`, in
+/// tree order. The document is not laid out, so the rules in `@container`
+/// never match.
+pub fn explain<'a>(
+    root: &'a Node,
+    stylesheets: &[&'a css::Stylesheet],
+    env: &media::Environment,
+    selectors: &[css::Selector],
+) -> Vec<Explanation<'a>> {
+    let rules = cascade(stylesheets, env).rules;
+    let mut explanations = vec![];
+    visit(root, &rules, selectors, &mut vec![], &mut explanations);
+    explanations
+}
+
+/// Parses the HTML, the stylesheets and the selector, and returns the
+/// explanations for the matching elements as text.
+pub fn explain_style(
+    html: &str,
+    stylesheets: &[&css::Stylesheet],
+    env: &media::Environment,
+    selectors: &str,
+) -> Result<String> {
+    let node = crate::dom::parser::parse_html(html)?;
+    let selectors = css::parser::parse_selectors(selectors)?;
+    let explanations = explain(&node, stylesheets, env, &selectors);
+    Ok(explanations
+        .iter()
+        .map(|explanation| explanation.to_string())
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+fn visit<'a>(
+    node: &'a Node,
+    rules: &[CascadeRule<'a>],
+    selectors: &[css::Selector],
+    ancestors: &mut Vec<&'a dom::ElementData>,
+    explanations: &mut Vec<Explanation<'a>>,
+) {
+    let elem = match node {
+        Node::Element(elem) => elem,
+        Node::Text(_) => return,
+    };
+    if selectors
+        .iter()
+        .any(|selector| matches(elem, ancestors, selector))
+    {
+        explanations.push(explain_element(elem, ancestors, rules));
+    }
+    ancestors.push(elem);
+    for child in node.children() {
+        visit(child, rules, selectors, ancestors, explanations);
+    }
+    ancestors.pop();
+}
+
+fn explain_element<'a>(
+    elem: &'a dom::ElementData,
+    ancestors: &[&'a dom::ElementData],
+    rules: &[CascadeRule<'a>],
+) -> Explanation<'a> {
+    let matched_rules = matching_rules(elem, ancestors, &QueryContainers::new(None), None, rules);
+
+    // Rules are shown from the highest precedence of their normal
+    // declarations. A later rule comes first in a tie.
+    let mut order: Vec<usize> = (0..matched_rules.len()).collect();
+    order.sort_by_key(|&index| {
+        let (specifity, rule) = matched_rules[index];
+        (
+            origin_precedence(rule.origin, false),
+            layer_precedence(rule.layer, false),
+            specifity,
+        )
+    });
+    order.reverse();
+    let mut position = vec![0; matched_rules.len()];
+    for (i, &index) in order.iter().enumerate() {
+        position[index] = i;
+    }
+
+    let winners = winning_declarations(&matched_rules);
+    let status = |declaration: &css::Declaration| {
+        let (index, winner) = winners[declaration.name.as_str()];
+        if std::ptr::eq(winner, declaration) {
+            Status::Applied
+        } else {
+            Status::Overridden(position[index])
+        }
+    };
+    let explained_rules = order
+        .iter()
+        .map(|&index| {
+            let (specifity, rule) = matched_rules[index];
+            RuleMatch {
+                rule: rule.rule,
+                selector: match_selectors(elem, ancestors, None, &rule.rule.selectors)
+                    .expect("a matched rule should have a matching selector"),
+                specifity,
+                origin: rule.origin,
+                declarations: rule
+                    .rule
+                    .declarations
+                    .iter()
+                    .map(|declaration| (declaration, status(declaration)))
+                    .collect(),
+            }
+        })
+        .collect();
+
+    Explanation {
+        element: elem,
+        ancestors: ancestors.to_vec(),
+        rules: explained_rules,
+        inherited: inherited_values(ancestors, rules, &winners),
+    }
+}
+
+/// Returns the declaration which wins the cascade for each property, with
+/// the index of its rule in `matched_rules`.
+fn winning_declarations<'a>(
+    matched_rules: &[super::MatchedRule<'_, 'a>],
+) -> BTreeMap<&'a str, (usize, &'a css::Declaration)> {
+    cascaded_declarations(matched_rules)
+        .into_iter()
+        .map(|(index, declaration)| (declaration.name.as_str(), (index, declaration)))
+        .collect()
+}
+
+/// Returns the inherited properties which an element doesn't set itself.
+/// `own` are the winning declarations of the element.
+fn inherited_values<'a>(
+    ancestors: &[&'a dom::ElementData],
+    rules: &[CascadeRule<'a>],
+    own: &BTreeMap<&'a str, (usize, &'a css::Declaration)>,
+) -> Vec<InheritedValue<'a>> {
+    let mut seen: Vec<&str> = own.keys().cloned().collect();
+    let mut inherited = vec![];
+    for (i, ancestor) in ancestors.iter().enumerate().rev() {
+        let matched_rules = matching_rules(
+            ancestor,
+            &ancestors[..i],
+            &QueryContainers::new(None),
+            None,
+            rules,
+        );
+        for (name, (index, declaration)) in winning_declarations(&matched_rules) {
+            if !property::is_inherited(name) || seen.contains(&name) {
+                continue;
+            }
+            seen.push(name);
+            inherited.push(InheritedValue {
+                declaration,
+                ancestor: i,
+                rule: matched_rules[index].1.rule,
+            });
+        }
+    }
+    inherited
+}
+
+/// Returns `tag#id.class` for an element.
+fn element_name(elem: &dom::ElementData) -> String {
+    let mut name = elem.tag_name().to_string();
+    if let Some(id) = elem.id() {
+        name.push('#');
+        name.push_str(id);
+    }
+    let mut classes: Vec<_> = elem.classes().into_iter().collect();
+    classes.sort();
+    for class in classes {
+        name.push('.');
+        name.push_str(class);
+    }
+    name
+}
+
+fn origin_name(origin: css::Origin) -> &'static str {
+    match origin {
+        css::Origin::UserAgent => "user-agent",
+        css::Origin::User => "user",
+        css::Origin::Author => "author",
+    }
+}
+
+fn declaration(declaration: &css::Declaration) -> String {
+    format!(
+        "{}: {}{};",
+        declaration.name,
+        declaration.value,
+        if declaration.important {
+            " !important"
+        } else {
+            ""
+        }
+    )
+}
+
+impl<'a> fmt::Display for Explanation<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path: Vec<_> = self
+            .ancestors
+            .iter()
+            .chain(std::iter::once(&self.element))
+            .map(|elem| element_name(elem))
+            .collect();
+        writeln!(f, "{}", path.join(" > "))?;
+        for rule in &self.rules {
+            let (a, b, c) = rule.specifity;
+            writeln!(
+                f,
+                "  {} {{  /* {}, specificity ({}, {}, {}), {} */",
+                rule.selector,
+                origin_name(rule.origin),
+                a,
+                b,
+                c,
+                rule.rule.location
+            )?;
+            for &(d, status) in &rule.declarations {
+                match status {
+                    Status::Applied => writeln!(f, "    {}", declaration(d))?,
+                    Status::Overridden(index) => writeln!(
+                        f,
+                        "    {}  /* overridden by {} ({}) */",
+                        declaration(d),
+                        self.rules[index].selector,
+                        self.rules[index].rule.location
+                    )?,
+                }
+            }
+            writeln!(f, "  }}")?;
+        }
+        for value in &self.inherited {
+            writeln!(
+                f,
+                "  {}  /* inherited from {} ({}) */",
+                declaration(value.declaration),
+                element_name(self.ancestors[value.ancestor]),
+                value.rule.location
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::css::parser::{parse_selectors, parse_stylesheet};
+    use crate::dom::parser::parse_html;
+
+    #[test]
+    fn explain_test() {
+        let root = parse_html("(div id=main class=a (p class=a) (span class=a))").unwrap();
+        let mut stylesheet = parse_stylesheet(
+            "div { --x: 1; color: #000000 }\n\
+             .a { width: 1px; height: 2px }\n\
+             p.a { width: 3px; height: 4px !important }\n\
+             p { height: 5px }",
+        )
+        .unwrap();
+        css::set_source_file(&mut stylesheet.rules, "a.css");
+        let explanations = explain(
+            &root,
+            &[&stylesheet],
+            &Default::default(),
+            &parse_selectors("p").unwrap(),
+        );
+        assert_eq!(explanations.len(), 1);
+        let explanation = &explanations[0];
+        assert_eq!(explanation.element.tag_name(), "p");
+        assert_eq!(explanation.ancestors.len(), 1);
+
+        let selectors: Vec<_> = explanation
+            .rules
+            .iter()
+            .map(|rule| (rule.selector.to_string(), rule.specifity))
+            .collect();
+        assert_eq!(
+            selectors,
+            vec![
+                ("p.a".to_string(), (0, 1, 1)),
+                (".a".to_string(), (0, 1, 0)),
+                ("p".to_string(), (0, 0, 1)),
+            ]
+        );
+        let location = &explanation.rules[1].rule.location;
+        assert_eq!(
+            (location.file.as_deref(), location.line, location.column),
+            (Some("a.css"), 2, 1)
+        );
+
+        let statuses = |index: usize| -> Vec<_> {
+            explanation.rules[index]
+                .declarations
+                .iter()
+                .map(|&(declaration, status)| (declaration.name.as_str(), status))
+                .collect()
+        };
+        assert_eq!(
+            statuses(0),
+            vec![("width", Status::Applied), ("height", Status::Applied)]
+        );
+        assert_eq!(
+            statuses(1),
+            vec![
+                ("width", Status::Overridden(0)),
+                ("height", Status::Overridden(0))
+            ]
+        );
+        assert_eq!(statuses(2), vec![("height", Status::Overridden(0))]);
+
+        let inherited: Vec<_> = explanation
+            .inherited
+            .iter()
+            .map(|value| (value.declaration.name.as_str(), value.ancestor))
+            .collect();
+        assert_eq!(inherited, vec![("--x", 0), ("color", 0)]);
+    }
+}
@@ -0,0 +1,163 @@
+//! Invalidation sets, which tell which elements a change of an attribute
+//! can restyle.
+//!
+//! A class or id which a selector requires of the element itself
+//! invalidates the element when it is added or removed. One which the
+//! selector requires of an ancestor invalidates the descendants. `:is()`
+//! requires its arguments of the element which it is in.
+//!
+//! There are no attribute selectors, so a change of another attribute
+//! invalidates nothing. Nor are there selectors which depend on siblings, so
+//! inserting or removing a child invalidates no other child.
+
+use super::CascadeRule;
+use crate::css::{Selector, SimpleSelector};
+use std::collections::{BTreeMap, HashSet};
+
+/// The classes and ids in the selectors of a cascade, by the elements which
+/// they restyle.
+#[derive(Debug, Default)]
+pub(super) struct InvalidationMap {
+    element: Identifiers,
+    descendants: Identifiers,
+}
+
+#[derive(Debug, Default)]
+struct Identifiers {
+    classes: HashSet<String>,
+    ids: HashSet<String>,
+}
+
+/// What a change restyles.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Invalidation {
+    /// The element itself.
+    pub(super) element: bool,
+    /// All of its descendants.
+    pub(super) descendants: bool,
+}
+
+impl InvalidationMap {
+    pub(super) fn new(rules: &[CascadeRule<'_>]) -> InvalidationMap {
+        let mut map = InvalidationMap::default();
+        for rule in rules {
+            for selector in &rule.rule.selectors.selectors {
+                map.add_selector(selector, true);
+            }
+        }
+        map
+    }
+
+    /// `subject` tells whether the rightmost compound of `selector` is
+    /// matched against the element itself.
+    fn add_selector(&mut self, selector: &Selector, subject: bool) {
+        match selector {
+            Selector::Simple(compound) => self.add_compound(compound, subject),
+            Selector::Complex(ancestor, _, compound) => {
+                self.add_compound(compound, subject);
+                self.add_selector(ancestor, false);
+            }
+        }
+    }
+
+    fn add_compound(&mut self, compound: &SimpleSelector, subject: bool) {
+        let identifiers = if subject {
+            &mut self.element
+        } else {
+            &mut self.descendants
+        };
+        identifiers.classes.extend(compound.classes.iter().cloned());
+        identifiers.ids.extend(compound.id.iter().cloned());
+        for selectors in &compound.is {
+            for selector in selectors {
+                self.add_selector(selector, subject);
+            }
+        }
+    }
+
+    /// Returns what changing the attributes of an element from `old` to
+    /// `new` restyles.
+    pub(super) fn invalidation(
+        &self,
+        old: &BTreeMap<String, String>,
+        new: &BTreeMap<String, String>,
+    ) -> Invalidation {
+        let classes = |attrs: &'_ BTreeMap<String, String>| -> HashSet<String> {
+            attrs
+                .get("class")
+                .map(|classes| classes.split(' ').map(str::to_string).collect())
+                .unwrap_or_default()
+        };
+        let (old_classes, new_classes) = (classes(old), classes(new));
+        let changed_classes: Vec<_> = old_classes.symmetric_difference(&new_classes).collect();
+        let changed_ids: Vec<_> = match (old.get("id"), new.get("id")) {
+            (old, new) if old == new => vec![],
+            (old, new) => old.into_iter().chain(new).collect(),
+        };
+        let invalidates = |identifiers: &Identifiers| {
+            changed_classes
+                .iter()
+                .any(|class| identifiers.classes.contains(*class))
+                || changed_ids.iter().any(|id| identifiers.ids.contains(*id))
+        };
+        Invalidation {
+            element: invalidates(&self.element),
+            descendants: invalidates(&self.descendants),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::css::parser::parse_stylesheet;
+    use crate::css::CssRule;
+
+    #[test]
+    fn invalidation_test() {
+        let stylesheet =
+            parse_stylesheet(".a { } #b .c { } .d > p { } :is(.e .f) { } div:is(#g) { }").unwrap();
+        let rules: Vec<_> = stylesheet
+            .rules
+            .iter()
+            .map(|rule| match rule {
+                CssRule::Style(rule) => CascadeRule {
+                    rule,
+                    origin: stylesheet.origin,
+                    layer: 0,
+                    containers: vec![],
+                },
+                _ => unreachable!(),
+            })
+            .collect();
+        let map = InvalidationMap::new(&rules);
+        let invalidation = |old: &str, new: &str| {
+            let attrs = |attrs: &str| -> BTreeMap<String, String> {
+                attrs
+                    .split(',')
+                    .filter(|attr| !attr.is_empty())
+                    .map(|attr| {
+                        let (name, value) = attr.split_at(attr.find('=').unwrap());
+                        (name.to_string(), value[1..].to_string())
+                    })
+                    .collect()
+            };
+            let Invalidation {
+                element,
+                descendants,
+            } = map.invalidation(&attrs(old), &attrs(new));
+            (element, descendants)
+        };
+
+        assert_eq!(invalidation("", ""), (false, false));
+        assert_eq!(invalidation("", "class=a"), (true, false));
+        assert_eq!(invalidation("class=a x", "class=x a"), (false, false));
+        assert_eq!(invalidation("class=x", "class=y"), (false, false));
+        assert_eq!(invalidation("id=b", ""), (false, true));
+        assert_eq!(invalidation("class=c", "class=d"), (true, true));
+        assert_eq!(invalidation("", "class=e"), (false, true));
+        assert_eq!(invalidation("class=f", ""), (true, false));
+        assert_eq!(invalidation("id=x", "id=g"), (true, false));
+        assert_eq!(invalidation("class=a", "class=a,title=x"), (false, false));
+    }
+}
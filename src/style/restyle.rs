@@ -0,0 +1,390 @@
+//! Incremental restyle.
+//!
+//! The computed styles of a pass are kept in a `StyleCache`, which mirrors
+//! the tree. The next pass takes the changes of the elements, marks the
+//! elements which the invalidation sets say they affect, and runs the
+//! cascade only for them. An element whose computed style changed restyles
+//! its children, which inherit from it.
+//!
+//! Inserting or removing a child inserts or removes its entry in the cache,
+//! so only a new child is styled.
+
+use super::invalidation::InvalidationMap;
+use super::sharing::SharedStyle;
+use super::{
+    cascade, computed_style, counter, generate_markers, marker, styled_text, Cascade, Display,
+    StyleState, StyledNode,
+};
+use crate::css::{self, media};
+use crate::dom::{ChildChange, ElementData, Node};
+use log::*;
+
+/// The computed styles of a tree, which are kept between style passes so
+/// that `restyle_tree` styles only the elements which changes affect, and
+/// the rules which they are computed from.
+pub struct StyleCache<'a> {
+    cascade: Cascade<'a>,
+    invalidation_map: InvalidationMap,
+    root: Option<CachedNode>,
+}
+
+impl<'a> StyleCache<'a> {
+    /// Returns an empty cache of the styles of `stylesheets` in `env`.
+    pub fn new(stylesheets: &[&'a css::Stylesheet], env: &media::Environment) -> StyleCache<'a> {
+        let cascade = cascade(stylesheets, env);
+        let invalidation_map = InvalidationMap::new(&cascade.rules);
+        StyleCache {
+            cascade,
+            invalidation_map,
+            root: None,
+        }
+    }
+}
+
+impl std::fmt::Debug for StyleCache<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StyleCache")
+            .field("root", &self.root)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Default)]
+struct CachedNode {
+    /// `None` for a text node, and for an element which has not been styled.
+    style: Option<SharedStyle>,
+    /// Whether the element has to be styled again.
+    restyle: bool,
+    /// Whether all of its descendants have to be styled again.
+    restyle_descendants: bool,
+    children: Vec<CachedNode>,
+}
+
+/// Styles the tree as `style_tree` does with the stylesheets and the
+/// environment of `cache`, but runs the cascade only for the elements which
+/// have changed since the previous pass with `cache`, or which their changes
+/// affect. A new cache styles the whole tree.
+///
+/// The changes of the elements are taken, so they are clean afterwards.
+pub fn restyle_tree<'a>(root: &'a mut Node, cache: &mut StyleCache<'_>) -> StyledNode<'a> {
+    let (styled, restyled) = restyle(root, cache);
+    info!("restyle: {} elements", restyled);
+    styled
+}
+
+/// Returns the styled tree, and the number of elements which the cascade
+/// ran for.
+fn restyle<'a>(root: &'a mut Node, cache: &mut StyleCache<'_>) -> (StyledNode<'a>, usize) {
+    let cached = cache.root.get_or_insert_with(CachedNode::default);
+    if let Node::Element(data) = root {
+        invalidate(data, Some(cached), &cache.invalidation_map);
+    }
+    let mut restyler = Restyler {
+        cascade: &cache.cascade,
+        state: StyleState::new(None),
+        restyled: 0,
+    };
+    let mut styled = restyler.node(root, cached, None, false, false);
+    generate_markers(&mut styled, 0, &mut counter::Counters::new());
+    (styled, restyler.restyled)
+}
+
+/// Takes the changes of the elements in the tree of `data`, and marks the
+/// elements which they affect in `cached`, the previous styles of the tree.
+fn invalidate(data: &mut ElementData, cached: Option<&mut CachedNode>, map: &InvalidationMap) {
+    let changes = data.take_changes();
+    let cached_children = match cached {
+        Some(cached) => {
+            if let Some(old_attrs) = &changes.old_attrs {
+                let invalidation = map.invalidation(old_attrs, data.attrs());
+                cached.restyle |= invalidation.element;
+                cached.restyle_descendants |= invalidation.descendants;
+            }
+            splice(
+                &mut cached.children,
+                &changes.child_changes,
+                data.children().len(),
+            );
+            &mut cached.children[..]
+        }
+        None => &mut [],
+    };
+    for index in 0..data.children().len() {
+        if let Some(child) = data.child_element_mut(index) {
+            invalidate(child, cached_children.get_mut(index), map);
+        }
+    }
+}
+
+/// Replays the insertions and removals of children which made them
+/// `new_len` on `cached`, their previous styles.
+fn splice(cached: &mut Vec<CachedNode>, changes: &[ChildChange], new_len: usize) {
+    let inserted = changes
+        .iter()
+        .filter(|change| matches!(change, ChildChange::Inserted(_)))
+        .count();
+    let old_len = (new_len + changes.len() - inserted).checked_sub(inserted);
+    // The children of an element which has not been styled, e.g. one in a
+    // `display: none` subtree, have no styles.
+    if Some(cached.len()) != old_len {
+        cached.clear();
+        return;
+    }
+    for change in changes {
+        match *change {
+            ChildChange::Inserted(index) => cached.insert(index, CachedNode::default()),
+            ChildChange::Removed(index) => {
+                cached.remove(index);
+            }
+        }
+    }
+}
+
+struct Restyler<'c, 'a, 's> {
+    cascade: &'c Cascade<'c>,
+    state: StyleState<'a, 's>,
+    /// The number of elements which the cascade ran for.
+    restyled: usize,
+}
+
+impl<'c, 'a, 's> Restyler<'c, 'a, 's> {
+    /// Returns the styled node of `node`, and updates `cached`. `parent` is
+    /// the style of the parent. The element is styled again if it is
+    /// marked, or `parent_changed` tells that the style of the parent
+    /// changed, or `in_invalidated_subtree` that an ancestor invalidated its
+    /// descendants.
+    fn node(
+        &mut self,
+        node: &'a Node,
+        cached: &mut CachedNode,
+        parent: Option<&SharedStyle>,
+        parent_changed: bool,
+        in_invalidated_subtree: bool,
+    ) -> StyledNode<'a> {
+        let parent_style = parent.map(|parent| &parent.style);
+        let data = match node {
+            Node::Element(data) => data,
+            Node::Text(_) => return styled_text(node, parent_style),
+        };
+        let changed =
+            if cached.style.is_none() || cached.restyle || parent_changed || in_invalidated_subtree
+            {
+                let parent_context = parent.map_or(&self.cascade.context, |parent| &parent.context);
+                let shared = computed_style(
+                    data,
+                    self.cascade,
+                    parent_style,
+                    parent_context,
+                    &self.state,
+                );
+                self.restyled += 1;
+                let changed = cached
+                    .style
+                    .as_ref()
+                    .is_none_or(|old| old.style != shared.style || old.context != shared.context);
+                cached.style = Some(shared);
+                changed
+            } else {
+                false
+            };
+        cached.restyle = false;
+        let in_invalidated_subtree =
+            in_invalidated_subtree || std::mem::take(&mut cached.restyle_descendants);
+        let shared = cached.style.clone().expect("an element has been styled");
+
+        let mut styled = StyledNode {
+            node,
+            style: shared.style.clone(),
+            marker: marker(&shared.style, shared.marker_style.clone()),
+            children: vec![],
+        };
+        let display = styled.display();
+        let is_block = display == Display::Block || display == Display::ListItem;
+        let depth = self.state.ancestors.len();
+        if self
+            .state
+            .containers
+            .enter(node, &styled.style, is_block, depth)
+        {
+            let children = node.children();
+            if cached.children.len() != children.len() {
+                cached.children = children.iter().map(|_| CachedNode::default()).collect();
+            }
+            self.state.ancestors.push(data);
+            styled.children = children
+                .iter()
+                .zip(&mut cached.children)
+                .map(|(child, cached)| {
+                    self.node(
+                        child,
+                        cached,
+                        Some(&shared),
+                        changed,
+                        in_invalidated_subtree,
+                    )
+                })
+                .collect();
+            self.state.ancestors.pop();
+        }
+        self.state.containers.leave(depth);
+        styled
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::css::parser::parse_stylesheet;
+    use crate::dom::parser::parse_html;
+    use crate::style::style_tree;
+
+    fn assert_same_styles(restyled: &StyledNode<'_>, styled: &StyledNode<'_>) {
+        assert_eq!(restyled.node.to_string(), styled.node.to_string());
+        assert_eq!(restyled.style, styled.style, "{}", styled.node);
+        assert_eq!(restyled.marker, styled.marker, "{}", styled.node);
+        assert_eq!(restyled.children.len(), styled.children.len());
+        for (restyled, styled) in restyled.children.iter().zip(&styled.children) {
+            assert_same_styles(restyled, styled);
+        }
+    }
+
+    fn element_at<'n>(node: &'n mut Node, path: &[usize]) -> &'n mut ElementData {
+        let mut data = match node {
+            Node::Element(data) => data,
+            Node::Text(_) => unreachable!(),
+        };
+        for &index in path {
+            data = data.child_element_mut(index).unwrap();
+        }
+        data
+    }
+
+    /// Returns the paths of the elements from `node`.
+    fn element_paths(node: &Node, path: &mut Vec<usize>, paths: &mut Vec<Vec<usize>>) {
+        if let Node::Element(data) = node {
+            paths.push(path.clone());
+            for (index, child) in data.children().iter().enumerate() {
+                path.push(index);
+                element_paths(child, path, paths);
+                path.pop();
+            }
+        }
+    }
+
+    /// A linear congruential generator, so that the mutations are the same
+    /// on every run.
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self, n: usize) -> usize {
+            self.0 = self
+                .0
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            ((self.0 >> 33) as usize) % n
+        }
+    }
+
+    fn mutate(root: &mut Node, random: &mut Random) {
+        const CLASSES: &[&str] = &["a", "b", "c", "d", "hide"];
+        const IDS: &[&str] = &["x", "y"];
+        const CHILDREN: &[&str] = &["(p class=c)", "(li (p class=b))", "(div id=y (p))"];
+        let mut paths = vec![];
+        element_paths(root, &mut vec![], &mut paths);
+        let elem = element_at(root, &paths[random.next(paths.len())]);
+        match random.next(7) {
+            0 => {
+                let classes: Vec<_> = (0..1 + random.next(2))
+                    .map(|_| CLASSES[random.next(CLASSES.len())])
+                    .collect();
+                elem.set_attribute("class", &classes.join(" "));
+            }
+            1 => {
+                elem.remove_attribute("class");
+            }
+            2 => elem.set_attribute("id", IDS[random.next(IDS.len())]),
+            3 => {
+                elem.remove_attribute("id");
+            }
+            4 => elem.set_attribute("title", "x"),
+            5 => {
+                let child = parse_html(CHILDREN[random.next(CHILDREN.len())]).unwrap();
+                let index = random.next(elem.children().len() + 1);
+                elem.insert_child(index, child);
+            }
+            _ => {
+                if !elem.children().is_empty() {
+                    let index = random.next(elem.children().len());
+                    elem.remove_child(index);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn random_mutations_test() {
+        let stylesheet = parse_stylesheet(
+            "ol { counter-reset: list-item; list-style-type: decimal } \
+             li { display: list-item } .hide { display: none } \
+             .a { font-size: 20px; color: #ff0000 } .b p { width: 2em } \
+             #x .c { height: 3px } .d > .a { margin-left: 4px } \
+             :is(#y .b, .c.d) { color: #00ff00 } \
+             p { --width: 1px } .c { padding-left: var(--width) } \
+             li::marker { content: counters(list-item, \".\") }",
+        )
+        .unwrap();
+        let mut root = parse_html(
+            "(body (ol class=a (li (p class=b) (p)) (li class=c (p class=d))) \
+             (div id=x (p class=c (span class=a)) (ol (li) (li class=b))))",
+        )
+        .unwrap();
+        let mut cache = StyleCache::new(&[&stylesheet], &Default::default());
+        let mut random = Random(0);
+        for _ in 0..200 {
+            for _ in 0..1 + random.next(3) {
+                mutate(&mut root, &mut random);
+            }
+            let expected = root.clone();
+            let (restyled, _) = restyle(&mut root, &mut cache);
+            let styled = style_tree(&expected, &[&stylesheet], &Default::default());
+            assert_same_styles(&restyled, &styled);
+        }
+    }
+
+    #[test]
+    fn restyle_count_test() {
+        let stylesheet =
+            parse_stylesheet(".a { color: #ff0000 } .b p { width: 1px } .c { height: 1px }")
+                .unwrap();
+        let mut root = parse_html("(body (div (p) (p)) (div (p)))").unwrap();
+        let mut cache = StyleCache::new(&[&stylesheet], &Default::default());
+        let mut restyle_count = |root: &mut Node, mutation: &dyn Fn(&mut Node)| {
+            mutation(root);
+            restyle(root, &mut cache).1
+        };
+
+        assert_eq!(restyle_count(&mut root, &|_| ()), 6);
+        assert_eq!(restyle_count(&mut root, &|_| ()), 0);
+        // No selector tests the attribute.
+        let title = |root: &mut Node| element_at(root, &[0, 0]).set_attribute("title", "x");
+        assert_eq!(restyle_count(&mut root, &title), 0);
+        // Only the element itself.
+        let c = |root: &mut Node| element_at(root, &[1, 0]).set_attribute("class", "c");
+        assert_eq!(restyle_count(&mut root, &c), 1);
+        // Only the descendants.
+        let b = |root: &mut Node| element_at(root, &[0]).set_attribute("class", "b");
+        assert_eq!(restyle_count(&mut root, &b), 2);
+        // The children inherit the changed style.
+        let a = |root: &mut Node| element_at(root, &[]).set_attribute("class", "a");
+        assert_eq!(restyle_count(&mut root, &a), 6);
+        // Only the new child.
+        let insert = |root: &mut Node| {
+            element_at(root, &[1]).insert_child(0, parse_html("(p)").unwrap());
+        };
+        assert_eq!(restyle_count(&mut root, &insert), 1);
+        let remove = |root: &mut Node| {
+            element_at(root, &[0]).remove_child(0);
+        };
+        assert_eq!(restyle_count(&mut root, &remove), 0);
+    }
+}
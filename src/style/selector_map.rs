@@ -0,0 +1,340 @@
+//! Indices which make selector matching cheaper than testing every rule
+//! against every element.
+//!
+//! `RuleMap` buckets the rules by the rightmost compound selectors of their
+//! selectors, so an element is matched only against the rules which can
+//! match its id, classes or tag name. `AncestorFilter` is a counting Bloom
+//! filter of the identifiers of the ancestors of the element being styled,
+//! which rejects most selectors whose ancestors are not there without
+//! walking up the tree.
+
+use super::CascadeRule;
+use crate::css::{Selector, SimpleSelector};
+use crate::dom::ElementData;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+/// The style rules of a cascade, by the identifiers which their selectors
+/// require of the element itself.
+#[derive(Debug, Default)]
+pub(super) struct RuleMap {
+    by_id: HashMap<String, Vec<Entry>>,
+    by_class: HashMap<String, Vec<Entry>>,
+    by_tag: HashMap<String, Vec<Entry>>,
+    /// The selectors which require none of them, e.g. `*` or `:is(a, b)`.
+    universal: Vec<Entry>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// The index of the rule in the cascade.
+    rule: usize,
+    /// The hashes of the identifiers which the ancestors of a matching
+    /// element must have.
+    ancestor_hashes: Vec<u32>,
+}
+
+impl RuleMap {
+    pub(super) fn new(rules: &[CascadeRule<'_>]) -> RuleMap {
+        let mut map = RuleMap::default();
+        for (index, rule) in rules.iter().enumerate() {
+            for selector in &rule.rule.selectors.selectors {
+                map.insert(index, selector);
+            }
+        }
+        map
+    }
+
+    /// A selector goes into the bucket of its most selective identifier: an
+    /// id, then a class, and then a tag name.
+    fn insert(&mut self, rule: usize, selector: &Selector) {
+        let entry = Entry {
+            rule,
+            ancestor_hashes: ancestor_hashes(selector),
+        };
+        let compound = match selector {
+            Selector::Simple(compound) | Selector::Complex(_, _, compound) => compound,
+        };
+        let bucket = if let Some(id) = &compound.id {
+            self.by_id.entry(id.clone()).or_default()
+        } else if let Some(class) = compound.classes.iter().next() {
+            self.by_class.entry(class.clone()).or_default()
+        } else if let Some(tag_name) = &compound.tag_name {
+            self.by_tag.entry(tag_name.clone()).or_default()
+        } else {
+            &mut self.universal
+        };
+        bucket.push(entry);
+    }
+
+    /// Returns the indices of the rules which may match `elem`, in order.
+    /// `filter` has the ancestors of `elem`.
+    pub(super) fn candidates(&self, elem: &ElementData, filter: &AncestorFilter) -> Vec<usize> {
+        let id = elem.id().and_then(|id| self.by_id.get(id));
+        let classes = elem
+            .classes()
+            .into_iter()
+            .filter_map(|class| self.by_class.get(class));
+        let tag = self.by_tag.get(elem.tag_name());
+        let mut rules: Vec<_> = std::iter::once(&self.universal)
+            .chain(id)
+            .chain(classes)
+            .chain(tag)
+            .flatten()
+            .filter(|entry| filter.might_contain_all(&entry.ancestor_hashes))
+            .map(|entry| entry.rule)
+            .collect();
+        rules.sort_unstable();
+        rules.dedup();
+        rules
+    }
+}
+
+/// Returns the hashes of the identifiers in the compound selectors of the
+/// ancestors in `selector`. A parent is an ancestor too.
+fn ancestor_hashes(selector: &Selector) -> Vec<u32> {
+    let mut hashes = vec![];
+    let mut selector = selector;
+    while let Selector::Complex(ancestor, _, _) = selector {
+        let compound = match &**ancestor {
+            Selector::Simple(compound) | Selector::Complex(_, _, compound) => compound,
+        };
+        hashes.extend(compound_hashes(compound));
+        selector = ancestor;
+    }
+    hashes
+}
+
+/// The kinds of identifiers, which are hashed with the identifiers so that
+/// e.g. `#a` and `.a` differ.
+#[derive(Hash)]
+enum Identifier {
+    Tag,
+    Id,
+    Class,
+}
+
+fn hash(kind: Identifier, name: &str) -> u32 {
+    let mut hasher = DefaultHasher::new();
+    (kind, name).hash(&mut hasher);
+    hasher.finish() as u32
+}
+
+/// `:is()` is not taken into account.
+fn compound_hashes(compound: &SimpleSelector) -> impl Iterator<Item = u32> + '_ {
+    let tag = compound
+        .tag_name
+        .iter()
+        .map(|tag_name| hash(Identifier::Tag, tag_name));
+    let id = compound.id.iter().map(|id| hash(Identifier::Id, id));
+    let classes = compound
+        .classes
+        .iter()
+        .map(|class| hash(Identifier::Class, class));
+    tag.chain(id).chain(classes)
+}
+
+fn element_hashes(elem: &ElementData) -> Vec<u32> {
+    let mut hashes = vec![hash(Identifier::Tag, elem.tag_name())];
+    hashes.extend(elem.id().map(|id| hash(Identifier::Id, id)));
+    hashes.extend(
+        elem.classes()
+            .into_iter()
+            .map(|class| hash(Identifier::Class, class)),
+    );
+    hashes
+}
+
+/// The number of bits of a hash which select a counter.
+const KEY_BITS: u32 = 12;
+const KEY_MASK: u32 = (1 << KEY_BITS) - 1;
+
+/// A counting Bloom filter of the identifiers of elements, which can remove
+/// them. It has no false negatives: if it might not contain an identifier,
+/// no element in it has the identifier.
+///
+/// Each identifier increments two of the counters, which are selected by
+/// two parts of its hash. A counter which overflows sticks at the maximum,
+/// so that removal never makes it lose an identifier.
+#[derive(Clone)]
+pub(super) struct AncestorFilter {
+    counters: Vec<u8>,
+}
+
+impl std::fmt::Debug for AncestorFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let used = self.counters.iter().filter(|&&count| count > 0).count();
+        write!(f, "AncestorFilter({} counters used)", used)
+    }
+}
+
+impl AncestorFilter {
+    pub(super) fn new() -> AncestorFilter {
+        AncestorFilter {
+            counters: vec![0; 1 << KEY_BITS],
+        }
+    }
+
+    fn keys(hash: u32) -> [usize; 2] {
+        [
+            (hash & KEY_MASK) as usize,
+            ((hash >> KEY_BITS) & KEY_MASK) as usize,
+        ]
+    }
+
+    pub(super) fn insert(&mut self, elem: &ElementData) {
+        for hash in element_hashes(elem) {
+            for &key in &AncestorFilter::keys(hash) {
+                let counter = &mut self.counters[key];
+                *counter = counter.saturating_add(1);
+            }
+        }
+    }
+
+    /// Removes an element which was inserted.
+    pub(super) fn remove(&mut self, elem: &ElementData) {
+        for hash in element_hashes(elem) {
+            for &key in &AncestorFilter::keys(hash) {
+                let counter = &mut self.counters[key];
+                debug_assert!(*counter > 0, "removed an element which was not inserted");
+                if *counter != u8::MAX {
+                    *counter -= 1;
+                }
+            }
+        }
+    }
+
+    fn might_contain(&self, hash: u32) -> bool {
+        AncestorFilter::keys(hash)
+            .iter()
+            .all(|&key| self.counters[key] > 0)
+    }
+
+    pub(super) fn might_contain_all(&self, hashes: &[u32]) -> bool {
+        hashes.iter().all(|&hash| self.might_contain(hash))
+    }
+}
+
+/// The ancestor elements of the element being styled, from the root, and a
+/// filter of them.
+#[derive(Debug, Clone)]
+pub(super) struct Ancestors<'a> {
+    elements: Vec<&'a ElementData>,
+    pub(super) filter: AncestorFilter,
+}
+
+impl<'a> Ancestors<'a> {
+    pub(super) fn new() -> Ancestors<'a> {
+        Ancestors {
+            elements: vec![],
+            filter: AncestorFilter::new(),
+        }
+    }
+
+    pub(super) fn push(&mut self, elem: &'a ElementData) {
+        self.filter.insert(elem);
+        self.elements.push(elem);
+    }
+
+    pub(super) fn pop(&mut self) {
+        if let Some(elem) = self.elements.pop() {
+            self.filter.remove(elem);
+        }
+    }
+}
+
+impl<'a> Deref for Ancestors<'a> {
+    type Target = [&'a ElementData];
+
+    fn deref(&self) -> &[&'a ElementData] {
+        &self.elements
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::css::parser::parse_stylesheet;
+    use crate::css::CssRule;
+    use crate::dom::parser::parse_html;
+    use crate::dom::Node;
+
+    fn element(html: &str) -> ElementData {
+        match parse_html(html).unwrap() {
+            Node::Element(data) => data,
+            Node::Text(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn candidates_test() {
+        let stylesheet = parse_stylesheet(
+            "* { } #a { } .b { } p { } div.b { } section p { } .c, #a.c { } :is(p) { }",
+        )
+        .unwrap();
+        let rules: Vec<_> = stylesheet
+            .rules
+            .iter()
+            .map(|rule| match rule {
+                CssRule::Style(rule) => CascadeRule {
+                    rule,
+                    origin: stylesheet.origin,
+                    layer: 0,
+                    containers: vec![],
+                },
+                _ => unreachable!(),
+            })
+            .collect();
+        let map = RuleMap::new(&rules);
+        let mut ancestors = Ancestors::new();
+        let candidates = |html: &str, ancestors: &Ancestors<'_>| {
+            map.candidates(&element(html), &ancestors.filter)
+        };
+
+        assert_eq!(candidates("(p id=a)", &ancestors), vec![0, 1, 3, 6, 7]);
+        assert_eq!(candidates("(div class=b)", &ancestors), vec![0, 2, 4, 7]);
+        assert_eq!(candidates("(span)", &ancestors), vec![0, 7]);
+
+        // `section p` needs a `section` ancestor.
+        let section = element("(section class=x)");
+        ancestors.push(&section);
+        assert_eq!(candidates("(p)", &ancestors), vec![0, 3, 5, 7]);
+        ancestors.pop();
+        assert_eq!(candidates("(p)", &ancestors), vec![0, 3, 7]);
+    }
+
+    #[test]
+    fn filter_test() {
+        let mut filter = AncestorFilter::new();
+        let div = element("(div id=a class=b)");
+        let hashes = |selector: &str| {
+            let stylesheet = parse_stylesheet(&format!("{} x {{ }}", selector)).unwrap();
+            match &stylesheet.rules[0] {
+                CssRule::Style(rule) => ancestor_hashes(&rule.selectors.selectors[0]),
+                _ => unreachable!(),
+            }
+        };
+        assert!(!filter.might_contain_all(&hashes("div")));
+        filter.insert(&div);
+        filter.insert(&div);
+        assert!(filter.might_contain_all(&hashes("div#a.b")));
+        assert!(filter.might_contain_all(&hashes("#a > .b")));
+        // An identifier of another kind is another identifier.
+        assert!(!filter.might_contain_all(&hashes("a")));
+        filter.remove(&div);
+        assert!(filter.might_contain_all(&hashes("div")));
+        filter.remove(&div);
+        assert!(!filter.might_contain_all(&hashes("div")));
+
+        // A saturated counter is never decremented.
+        for _ in 0..300 {
+            filter.insert(&div);
+        }
+        for _ in 0..300 {
+            filter.remove(&div);
+        }
+        assert!(filter.might_contain_all(&hashes("div")));
+    }
+}
@@ -0,0 +1,176 @@
+//! Sharing the computed styles of siblings.
+//!
+//! Siblings have the same parent and ancestors, so two of them with the same
+//! tag name and attributes match the same rules and inherit the same values.
+//! The cascade runs for one of them, and the other reuses its style. An
+//! element with an id is not shared: ids are unique, so it would only evict
+//! the styles which are worth sharing.
+//!
+//! Selectors depend only on the tag name and attributes of an element, and
+//! on its ancestors. A selector which depends on the siblings of an element,
+//! e.g. `:first-child`, would make siblings with the same attributes differ.
+
+use super::ComputedStyle;
+use crate::css;
+use crate::dom::ElementData;
+use std::collections::VecDeque;
+
+/// The number of siblings whose styles are kept.
+const CAPACITY: usize = 8;
+/// The number of siblings which are styled in order with one cache, while
+/// other chunks of them are styled in parallel.
+pub(super) const CHUNK_SIZE: usize = 32;
+
+/// The styles of an element which can be shared: its computed style, the
+/// length context of its children, and the style of its `::marker`.
+#[derive(Debug, Clone)]
+pub(super) struct SharedStyle {
+    pub(super) style: ComputedStyle,
+    pub(super) context: css::LengthContext,
+    pub(super) marker_style: Option<ComputedStyle>,
+}
+
+/// The styles of the most recently styled children of an element, from the
+/// most recent.
+#[derive(Debug, Default)]
+pub(super) struct SharingCache<'a> {
+    entries: VecDeque<(&'a ElementData, SharedStyle)>,
+}
+
+impl<'a> SharingCache<'a> {
+    pub(super) fn new() -> SharingCache<'a> {
+        SharingCache::default()
+    }
+
+    /// Returns the style of a sibling which `elem` can share, and counts the
+    /// hit or miss in `stats`. Returns `None` without counting if `elem` can
+    /// share no style.
+    pub(super) fn get(&self, elem: &ElementData, stats: &mut SharingStats) -> Option<SharedStyle> {
+        if elem.id().is_some() {
+            return None;
+        }
+        let shared = self
+            .entries
+            .iter()
+            .find(|(sibling, _)| same_inputs(elem, sibling))
+            .map(|(_, shared)| shared.clone());
+        if shared.is_some() {
+            stats.hits += 1;
+        } else {
+            stats.misses += 1;
+        }
+        shared
+    }
+
+    /// Keeps the style of `elem`, which `get` found no style for.
+    pub(super) fn insert(&mut self, elem: &'a ElementData, shared: &SharedStyle) {
+        if elem.id().is_some() {
+            return;
+        }
+        if self.entries.len() == CAPACITY {
+            self.entries.pop_back();
+        }
+        self.entries.push_front((elem, shared.clone()));
+    }
+}
+
+/// Returns whether siblings `a` and `b` match the same selectors. Attributes
+/// are compared as they are, except that classes are a set.
+fn same_inputs(a: &ElementData, b: &ElementData) -> bool {
+    a.tag_name() == b.tag_name()
+        && a.classes() == b.classes()
+        && attrs_but_class(a).eq(attrs_but_class(b))
+}
+
+fn attrs_but_class(elem: &ElementData) -> impl Iterator<Item = (&String, &String)> {
+    elem.attrs().iter().filter(|(name, _)| *name != "class")
+}
+
+/// The numbers of elements which shared the style of a sibling, and which
+/// could have but found none.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) struct SharingStats {
+    pub(super) hits: usize,
+    pub(super) misses: usize,
+}
+
+impl std::ops::AddAssign for SharingStats {
+    fn add_assign(&mut self, other: SharingStats) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn element(tag_name: &str, attrs: &[(&str, &str)]) -> ElementData {
+        let attrs = attrs
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        ElementData::new(tag_name.to_string(), attrs, vec![])
+    }
+
+    #[test]
+    fn same_inputs_test() {
+        let p = element("p", &[("class", "a b"), ("lang", "en")]);
+        assert!(same_inputs(
+            &p,
+            &element("p", &[("class", "b a"), ("lang", "en")])
+        ));
+        assert!(!same_inputs(
+            &p,
+            &element("div", &[("class", "a b"), ("lang", "en")])
+        ));
+        assert!(!same_inputs(
+            &p,
+            &element("p", &[("class", "a"), ("lang", "en")])
+        ));
+        assert!(!same_inputs(
+            &p,
+            &element("p", &[("class", "a b"), ("lang", "fr")])
+        ));
+        assert!(!same_inputs(&p, &element("p", &[("class", "a b")])));
+        assert!(!same_inputs(
+            &p,
+            &element("p", &[("class", "a b"), ("lang", "en"), ("title", "x")])
+        ));
+    }
+
+    #[test]
+    fn cache_test() {
+        let shared = SharedStyle {
+            style: ComputedStyle::initial(),
+            context: css::LengthContext::new(800.0, 600.0),
+            marker_style: None,
+        };
+        let classes: Vec<_> = (0..=CAPACITY).map(|i| "x".repeat(i + 1)).collect();
+        let elements: Vec<_> = classes
+            .iter()
+            .map(|class| element("p", &[("class", class)]))
+            .collect();
+        let mut cache = SharingCache::new();
+        let mut stats = SharingStats::default();
+        for elem in &elements {
+            assert!(cache.get(elem, &mut stats).is_none());
+            cache.insert(elem, &shared);
+        }
+        // The first one was evicted.
+        assert!(cache.get(&elements[0], &mut stats).is_none());
+        assert!(cache.get(&elements[1], &mut stats).is_some());
+
+        // An element with an id is neither shared nor counted.
+        let with_id = element("p", &[("id", "a")]);
+        cache.insert(&with_id, &shared);
+        assert!(cache.get(&with_id, &mut stats).is_none());
+        assert_eq!(
+            stats,
+            SharingStats {
+                hits: 1,
+                misses: CAPACITY + 2,
+            }
+        );
+    }
+}
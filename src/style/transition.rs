@@ -0,0 +1,405 @@
+//! CSS transitions, which are started by changes of style between style
+//! passes.
+//!
+//! `StyledNode`s borrow the DOM and live only as long as a style pass, so
+//! `Transitions` keeps what a transition needs from one pass to the next:
+//! the values of each element in the previous pass, and the running
+//! transitions. Elements are identified by their positions in the tree, so
+//! inserting a node before an element makes it a new element, which doesn't
+//! transition.
+//!
+//! Time is the virtual clock of `media::Environment::time`, which
+//! animations use too.
+//!
+//! https://drafts.csswg.org/css-transitions-1/
+
+use super::animation::list_items;
+use super::sharing::SharedStyle;
+use super::{
+    cascade, computed_style, counter, generate_markers, marker, styled_text, transitioned_style,
+    Cascade, ComputedStyle, CssPropertyMap, Display, StyleState, StyledNode,
+};
+use crate::css;
+use crate::css::animation::{interpolate, is_interpolable, EasingFunction};
+use crate::css::media;
+use crate::css::property::{self, AnimationType};
+use crate::css::Value;
+use crate::dom::Node;
+use std::collections::HashMap;
+
+/// The indices of the children from the root to an element.
+type ElementPath = Vec<usize>;
+
+/// The state of transitions between style passes.
+#[derive(Debug, Default)]
+pub struct Transitions {
+    elements: HashMap<ElementPath, ElementState>,
+}
+
+#[derive(Debug)]
+struct ElementState {
+    /// The style of the last style pass without transitions, i.e. the
+    /// after-change style.
+    style: ComputedStyle,
+    /// Running transitions by property.
+    running: HashMap<String, Transition>,
+}
+
+#[derive(Debug)]
+struct Transition {
+    from: Value,
+    to: Value,
+    /// The time when the transition starts, after its delay.
+    start: f32,
+    duration: f32,
+    easing: EasingFunction,
+}
+
+impl Transition {
+    fn value_at(&self, time: f32) -> Value {
+        let progress = ((time - self.start) / self.duration).clamp(0.0, 1.0);
+        interpolate(&self.from, &self.to, self.easing.apply(progress))
+    }
+
+    fn is_finished(&self, time: f32) -> bool {
+        time >= self.start + self.duration
+    }
+}
+
+/// `transition-*` of a property.
+struct TransitionTiming {
+    duration: f32,
+    delay: f32,
+    easing: EasingFunction,
+}
+
+impl Transitions {
+    pub fn new() -> Transitions {
+        Default::default()
+    }
+
+    /// Styles the tree as `style::style_tree` does at `env.time`. Properties
+    /// whose values differ from the previous call start transitions, and
+    /// the running ones give the values of their properties, which the
+    /// descendants inherit.
+    pub fn style_tree<'a>(
+        &mut self,
+        root: &'a Node,
+        stylesheets: &[&'a css::Stylesheet],
+        env: &media::Environment,
+    ) -> StyledNode<'a> {
+        let cascade = cascade(stylesheets, env);
+        let mut pass = TransitionPass {
+            cascade: &cascade,
+            state: StyleState::new(None),
+            time: env.time,
+            previous: std::mem::take(&mut self.elements),
+            elements: HashMap::new(),
+            path: vec![],
+        };
+        let mut styled = pass.node(root, None);
+        generate_markers(&mut styled, 0, &mut counter::Counters::new());
+        // Elements which are no longer in the tree are forgotten.
+        self.elements = pass.elements;
+        styled
+    }
+}
+
+/// A style pass with transitions. It runs in tree order, as the transitions
+/// of an element are known only after its after-change style.
+struct TransitionPass<'c, 'a, 's> {
+    cascade: &'c Cascade<'c>,
+    state: StyleState<'a, 's>,
+    time: f32,
+    /// The states of the elements in the previous pass.
+    previous: HashMap<ElementPath, ElementState>,
+    elements: HashMap<ElementPath, ElementState>,
+    /// The path of the current node.
+    path: ElementPath,
+}
+
+/// The styles of an element for its children. The after-change style is
+/// the style without any transitions, even of the ancestors.
+struct ParentStyles<'p> {
+    after_change: &'p SharedStyle,
+    /// The style with the transitions, which is painted.
+    current: &'p SharedStyle,
+    /// Whether the element or any ancestor has running transitions, i.e.
+    /// the two styles may differ.
+    transitioned: bool,
+}
+
+impl<'c, 'a, 's> TransitionPass<'c, 'a, 's> {
+    fn node(&mut self, node: &'a Node, parent: Option<ParentStyles<'_>>) -> StyledNode<'a> {
+        let data = match node {
+            Node::Element(data) => data,
+            Node::Text(_) => {
+                return styled_text(node, parent.map(|parent| &parent.current.style));
+            }
+        };
+        let (parent_style, parent_context) = match &parent {
+            Some(parent) => (
+                Some(&parent.after_change.style),
+                &parent.after_change.context,
+            ),
+            None => (None, &self.cascade.context),
+        };
+        let after_change = computed_style(
+            data,
+            self.cascade,
+            parent_style,
+            parent_context,
+            &self.state,
+        );
+        let previous = self.previous.remove(&self.path);
+        let (state, values) = update_element(previous, &after_change.style, self.time);
+        self.elements.insert(self.path.clone(), state);
+        // The element inherits the values of the running transitions of its
+        // parent, and `em` is relative to the current font size.
+        let transitioned =
+            !values.is_empty() || parent.as_ref().is_some_and(|parent| parent.transitioned);
+        let current = if transitioned {
+            let (parent_style, parent_context) = match &parent {
+                Some(parent) => (Some(&parent.current.style), &parent.current.context),
+                None => (None, &self.cascade.context),
+            };
+            transitioned_style(
+                data,
+                self.cascade,
+                parent_style,
+                parent_context,
+                &self.state,
+                &values,
+            )
+        } else {
+            after_change.clone()
+        };
+
+        let mut styled = StyledNode {
+            node,
+            style: current.style.clone(),
+            marker: marker(&current.style, current.marker_style.clone()),
+            children: vec![],
+        };
+        let display = styled.display();
+        let is_block = display == Display::Block || display == Display::ListItem;
+        let depth = self.state.ancestors.len();
+        if self
+            .state
+            .containers
+            .enter(node, &styled.style, is_block, depth)
+        {
+            self.state.ancestors.push(data);
+            for (i, child) in node.children().iter().enumerate() {
+                self.path.push(i);
+                let parent = ParentStyles {
+                    after_change: &after_change,
+                    current: &current,
+                    transitioned,
+                };
+                styled.children.push(self.node(child, Some(parent)));
+                self.path.pop();
+            }
+            self.state.ancestors.pop();
+        }
+        self.state.containers.leave(depth);
+        styled
+    }
+}
+
+/// Starts and samples the transitions of an element whose after-change
+/// style is `after_change`. Returns its state, and the values of its
+/// running transitions.
+fn update_element(
+    previous: Option<ElementState>,
+    after_change: &ComputedStyle,
+    time: f32,
+) -> (ElementState, CssPropertyMap) {
+    let mut running = HashMap::new();
+    // The first style of an element doesn't transition.
+    if let Some(mut previous) = previous {
+        for name in property::properties().map(|property| property.name) {
+            let timing = match transition_timing(after_change, name) {
+                Some(timing) => timing,
+                None => continue,
+            };
+            let after = &after_change.value(name).unwrap();
+            let current = previous
+                .running
+                .remove(name)
+                .filter(|transition| !transition.is_finished(time));
+            // A change during a transition starts from the current value.
+            let before = match current {
+                Some(transition) if transition.to == *after => {
+                    running.insert(name.to_string(), transition);
+                    continue;
+                }
+                Some(transition) => transition.value_at(time),
+                None => previous.style.value(name).unwrap(),
+            };
+            if before != *after && timing.duration > 0.0 && is_interpolable(&before, after) {
+                running.insert(
+                    name.to_string(),
+                    Transition {
+                        from: before,
+                        to: after.clone(),
+                        start: time + timing.delay,
+                        duration: timing.duration,
+                        easing: timing.easing,
+                    },
+                );
+            }
+        }
+    }
+    running.retain(|_, transition| !transition.is_finished(time));
+    let values = running
+        .iter()
+        .map(|(name, transition)| (name.clone(), transition.value_at(time)))
+        .collect();
+    let state = ElementState {
+        style: after_change.clone(),
+        running,
+    };
+    (state, values)
+}
+
+/// Returns the timing of the transitions of property `name`, or `None` if
+/// it doesn't transition. A later item of `transition-property` wins.
+fn transition_timing(style: &ComputedStyle, name: &str) -> Option<TransitionTiming> {
+    // Discrete properties, such as custom properties, don't transition.
+    if property::animation_type(name) != AnimationType::ByComputedValue {
+        return None;
+    }
+    let box_style = &style.box_style;
+    let properties = list_items(&box_style.transition_property);
+    let index = properties.iter().rposition(|property| match property {
+        Value::Keyword(property) => {
+            property.eq_ignore_ascii_case("all") || property.eq_ignore_ascii_case(name)
+        }
+        _ => false,
+    })?;
+    let item = |value| {
+        let items = list_items(value);
+        items.get(index % items.len().max(1))
+    };
+    Some(TransitionTiming {
+        duration: match item(&box_style.transition_duration) {
+            Some(Value::Time(duration)) if *duration >= 0.0 => *duration,
+            _ => 0.0,
+        },
+        delay: match item(&box_style.transition_delay) {
+            Some(Value::Time(delay)) => *delay,
+            _ => 0.0,
+        },
+        easing: item(&box_style.transition_timing_function)
+            .and_then(EasingFunction::from_value)
+            .unwrap_or(EasingFunction::EASE),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::css::parser::parse_stylesheet;
+    use crate::css::{Color, Unit};
+    use crate::dom::parser::parse_html;
+
+    fn at(time: f32) -> media::Environment {
+        media::Environment {
+            time,
+            ..Default::default()
+        }
+    }
+
+    fn px(n: f32) -> Option<Value> {
+        Some(Value::Length(n, Unit::Px))
+    }
+
+    #[test]
+    fn transition_test() {
+        let stylesheet = parse_stylesheet(
+            "div { width: 10px; color: #000000; display: block; \
+                   transition: width 2s linear, color 1s steps(2) 1s } \
+             .wide { width: 110px; color: #ffffff; display: inline }",
+        )
+        .unwrap();
+        let narrow = parse_html("(div (div))").unwrap();
+        let wide = parse_html("(div (div class=wide))").unwrap();
+        let mut transitions = Transitions::new();
+        let mut style = |node: &Node, time: f32, name: &str| {
+            transitions
+                .style_tree(node, &[&stylesheet], &at(time))
+                .children[0]
+                .value(name)
+        };
+
+        assert_eq!(style(&narrow, 0.0, "width"), px(10.0));
+        // The change starts a transition.
+        assert_eq!(style(&wide, 1.0, "width"), px(10.0));
+        assert_eq!(style(&wide, 1.5, "width"), px(35.0));
+        assert_eq!(style(&wide, 3.0, "width"), px(110.0));
+        assert_eq!(style(&wide, 4.0, "width"), px(110.0));
+
+        // A change during a transition starts from the current value.
+        assert_eq!(style(&narrow, 5.0, "width"), px(110.0));
+        assert_eq!(style(&narrow, 6.0, "width"), px(60.0));
+        assert_eq!(style(&wide, 6.0, "width"), px(60.0));
+        assert_eq!(style(&wide, 7.0, "width"), px(85.0));
+
+        // The delay and the easing of color.
+        let gray = |n| Some(Value::ColorValue(Color::rgb(n, n, n)));
+        let mut transitions = Transitions::new();
+        let mut style = |node: &Node, time: f32, name: &str| {
+            transitions
+                .style_tree(node, &[&stylesheet], &at(time))
+                .children[0]
+                .value(name)
+        };
+        style(&narrow, 0.0, "color");
+        assert_eq!(style(&wide, 0.0, "color"), gray(0));
+        assert_eq!(style(&wide, 1.2, "color"), gray(0));
+        assert_eq!(style(&wide, 1.6, "color"), gray(128));
+        assert_eq!(style(&wide, 2.0, "color"), gray(255));
+        // Discrete values don't transition.
+        assert_eq!(
+            style(&wide, 2.0, "display"),
+            Some(Value::Keyword("inline".to_string()))
+        );
+    }
+
+    #[test]
+    fn inherited_transition_test() {
+        let stylesheet = parse_stylesheet(
+            "div { color: #000000; font-size: 10px; \
+                   transition: color 1s linear, font-size 1s linear } \
+             .changed { color: #ffffff; font-size: 20px } \
+             span { width: 2em }",
+        )
+        .unwrap();
+        let before = parse_html("(div (p (span) \"text\"))").unwrap();
+        let after = parse_html("(div class=changed (p (span) \"text\"))").unwrap();
+        let mut transitions = Transitions::new();
+        transitions.style_tree(&before, &[&stylesheet], &at(0.0));
+        transitions.style_tree(&after, &[&stylesheet], &at(0.0));
+        let styled = transitions.style_tree(&after, &[&stylesheet], &at(0.5));
+
+        // The descendants inherit the values of the running transitions,
+        // not the after-change ones.
+        let gray = Some(Value::ColorValue(Color::rgb(128, 128, 128)));
+        let p = &styled.children[0];
+        assert_eq!(styled.value("color"), gray);
+        assert_eq!(p.value("color"), gray);
+        assert_eq!(p.children[0].value("color"), gray);
+        assert_eq!(p.children[1].value("color"), gray);
+        // `em` is relative to the current font size.
+        assert_eq!(p.children[0].value("font-size"), px(15.0));
+        assert_eq!(p.children[0].value("width"), px(30.0));
+
+        // The child doesn't transition the inherited value itself.
+        let styled = transitions.style_tree(&after, &[&stylesheet], &at(1.0));
+        assert_eq!(
+            styled.children[0].value("color"),
+            Some(Value::ColorValue(Color::rgb(255, 255, 255)))
+        );
+    }
+}